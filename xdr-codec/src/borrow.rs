@@ -0,0 +1,161 @@
+//! Zero-copy decoding from a contiguous buffer
+//!
+//! `Unpack` copies variable-length strings and opaque data out of its input. When the whole
+//! message is already in a byte slice, `UnpackBorrowed` can decode it into a value which borrows
+//! that data from the slice instead, so that decoding doesn't allocate for it. `xdrgen` generates
+//! implementations for borrowing views of generated types with `GenOptions::zero_copy`.
+use std::convert::TryInto;
+use std::io;
+use std::str;
+
+use super::{Error, ErrorKind, Result, Unpack, padding};
+
+/// Deserialization from a byte slice, borrowing from it.
+///
+/// This is `Unpack` for a contiguous input: `input` is advanced past the encoded value, and the
+/// result may borrow from the part of it which was consumed. As with `Unpack`, the number of
+/// bytes consumed is returned along with the value.
+pub trait UnpackBorrowed<'a>: Sized {
+    fn unpack_borrowed(input: &mut &'a [u8]) -> Result<(Self, usize)>;
+}
+
+macro_rules! unpack_owned {
+    ($($ty:ty),*) => {
+        $(
+            impl<'a> UnpackBorrowed<'a> for $ty {
+                #[inline]
+                fn unpack_borrowed(input: &mut &'a [u8]) -> Result<($ty, usize)> {
+                    Unpack::unpack(input)
+                }
+            }
+        )*
+    };
+}
+
+unpack_owned!(u32, i32, u64, i64, f32, f64, bool, ());
+
+impl<'a> UnpackBorrowed<'a> for &'a [u8] {
+    fn unpack_borrowed(input: &mut &'a [u8]) -> Result<(&'a [u8], usize)> {
+        unpack_opaque_borrowed(input, None)
+    }
+}
+
+impl<'a> UnpackBorrowed<'a> for &'a str {
+    fn unpack_borrowed(input: &mut &'a [u8]) -> Result<(&'a str, usize)> {
+        unpack_str_borrowed(input, None)
+    }
+}
+
+impl<'a, T: UnpackBorrowed<'a>> UnpackBorrowed<'a> for Vec<T> {
+    fn unpack_borrowed(input: &mut &'a [u8]) -> Result<(Vec<T>, usize)> {
+        unpack_flex_borrowed(input, None)
+    }
+}
+
+impl<'a, T: UnpackBorrowed<'a>> UnpackBorrowed<'a> for Option<T> {
+    fn unpack_borrowed(input: &mut &'a [u8]) -> Result<(Option<T>, usize)> {
+        let (have, mut sz): (bool, _) = Unpack::unpack(input)?;
+        let ret = if have {
+            let (v, osz) = T::unpack_borrowed(input)?;
+            sz += osz;
+            Some(v)
+        } else {
+            None
+        };
+        Ok((ret, sz))
+    }
+}
+
+impl<'a, T: UnpackBorrowed<'a>> UnpackBorrowed<'a> for Box<T> {
+    fn unpack_borrowed(input: &mut &'a [u8]) -> Result<(Box<T>, usize)> {
+        let (b, sz) = T::unpack_borrowed(input)?;
+        Ok((Box::new(b), sz))
+    }
+}
+
+// Take `len` bytes, and their padding, from the front of `input`
+pub(crate) fn take<'a>(input: &mut &'a [u8], len: usize) -> Result<&'a [u8]> {
+    // Check `len` on its own first, since a hostile one can overflow once padded
+    let eof = || io::Error::new(io::ErrorKind::UnexpectedEof, "failed to fill whole buffer").into();
+    if input.len() < len {
+        return Err(eof());
+    }
+    let padded = len.checked_add(padding(len).len()).ok_or_else(eof)?;
+    if input.len() < padded {
+        return Err(eof());
+    }
+    let (data, rest) = input.split_at(padded);
+    *input = rest;
+    Ok(&data[..len])
+}
+
+/// Unpack variable-length opaque data of at most `maxsz` bytes, borrowing it from `input`.
+pub fn unpack_opaque_borrowed<'a>(input: &mut &'a [u8], maxsz: Option<usize>) -> Result<(&'a [u8], usize)> {
+    let (len, sz): (usize, _) = Unpack::unpack(input)?;
+
+    if maxsz.is_some_and(|m| len > m) {
+        bail!(ErrorKind::InvalidLen(maxsz.unwrap()));
+    }
+    let data = take(input, len)?;
+
+    Ok((data, sz + len + padding(len).len()))
+}
+
+/// Unpack a string of at most `maxsz` bytes, borrowing it from `input`.
+pub fn unpack_str_borrowed<'a>(input: &mut &'a [u8], maxsz: Option<usize>) -> Result<(&'a str, usize)> {
+    let (v, sz) = unpack_opaque_borrowed(input, maxsz)?;
+
+    match str::from_utf8(v) {
+        Ok(s) => Ok((s, sz)),
+        // Fail the same way as `unpack_string`
+        Err(_) => Err(Error::from(String::from_utf8(v.to_vec()).unwrap_err())),
+    }
+}
+
+/// Unpack a variable-length array of at most `maxsz` elements, with the elements borrowing from
+/// `input`.
+pub fn unpack_flex_borrowed<'a, T: UnpackBorrowed<'a>>(
+    input: &mut &'a [u8],
+    maxsz: Option<usize>,
+) -> Result<(Vec<T>, usize)> {
+    let (elems, mut sz): (usize, _) = Unpack::unpack(input)?;
+
+    if maxsz.is_some_and(|m| elems > m) {
+        bail!(ErrorKind::InvalidLen(maxsz.unwrap()));
+    }
+    // Every element takes at least 4 bytes, which bounds how much a bad length can allocate
+    let mut out = Vec::with_capacity(elems.min(input.len() / 4));
+
+    for _ in 0..elems {
+        let (e, esz) = T::unpack_borrowed(input)?;
+        out.push(e);
+        sz += esz;
+    }
+
+    Ok((out, sz))
+}
+
+/// Unpack a fixed-length array of `N` elements, with the elements borrowing from `input`.
+pub fn unpack_array_borrowed<'a, T: UnpackBorrowed<'a>, const N: usize>(
+    input: &mut &'a [u8],
+) -> Result<([T; N], usize)> {
+    let mut sz = 0;
+    let mut out = Vec::with_capacity(N);
+
+    for _ in 0..N {
+        let (e, esz) = T::unpack_borrowed(input)?;
+        out.push(e);
+        sz += esz;
+    }
+
+    match out.try_into() {
+        Ok(arr) => Ok((arr, sz)),
+        Err(_) => unreachable!("array of {} elements", N),
+    }
+}
+
+/// Unpack a `T` from the start of `buf`, borrowing from it.
+pub fn unpack_borrowed<'a, T: UnpackBorrowed<'a>>(buf: &'a [u8]) -> Result<T> {
+    let mut input = buf;
+    T::unpack_borrowed(&mut input).map(|(v, _)| v)
+}
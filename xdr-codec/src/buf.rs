@@ -0,0 +1,135 @@
+//! Incremental decoding from `bytes::Buf` buffers
+//!
+//! Network code often accumulates incoming bytes in a buffer such as a `BytesMut` or a
+//! `VecDeque<u8>`, and wants to decode a message as soon as enough bytes are available.
+//! `unpack_from_buf` attempts to decode a value directly from the buffer's chunks, without first
+//! copying them into a contiguous buffer. If the buffer doesn't yet contain a complete encoding it
+//! returns `Ok(None)` and leaves the buffer untouched, so it can simply be retried once more data
+//! has arrived.
+use std::cmp::min;
+use std::io::{self, IoSlice, Read};
+
+use bytes::Buf;
+
+use super::{Error, ErrorKind, Result, Unpack};
+
+// Number of buffer chunks first asked for, doubled until they cover the whole buffer.
+const CHUNKS: usize = 64;
+
+/// Read-only view of the chunks of a `Buf`.
+///
+/// This implements `Read` without consuming anything from the underlying buffer.
+#[derive(Debug)]
+pub struct BufPeek<'a> {
+    chunks: &'a [IoSlice<'a>],
+    idx: usize, // current chunk
+    off: usize, // offset into current chunk
+    consumed: usize, // total bytes read
+}
+
+impl<'a> BufPeek<'a> {
+    fn new(chunks: &'a [IoSlice<'a>]) -> BufPeek<'a> {
+        BufPeek {
+            chunks,
+            idx: 0,
+            off: 0,
+            consumed: 0,
+        }
+    }
+}
+
+impl<'a> Read for BufPeek<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.idx < self.chunks.len() && self.off == self.chunks[self.idx].len() {
+            self.idx += 1;
+            self.off = 0;
+        }
+        if self.idx == self.chunks.len() {
+            return Ok(0);
+        }
+
+        let chunk = &self.chunks[self.idx][self.off..];
+        let len = min(chunk.len(), buf.len());
+
+        buf[..len].copy_from_slice(&chunk[..len]);
+        self.off += len;
+        self.consumed += len;
+
+        Ok(len)
+    }
+}
+
+/// Attempt to unpack a value from a `Buf`.
+///
+/// Returns `Ok(Some(value))` and advances `buf` past the encoded value if it contains a complete
+/// encoding, or `Ok(None)` without touching `buf` if more bytes are needed. Malformed input is
+/// still an error.
+///
+/// The value is decoded from the chunks given by `Buf::chunks_vectored`, however many there are.
+/// Some buffers expose fewer than they have: only the first if they don't implement it, or a
+/// fixed number for `Take`. An encoding which goes beyond those is an error, rather than waiting
+/// for bytes which are already there.
+pub fn unpack_from_buf<T, B>(buf: &mut B) -> Result<Option<T>>
+where
+    B: Buf,
+    T: for<'a> Unpack<BufPeek<'a>>,
+{
+    let res = {
+        let mut chunks = vec![IoSlice::new(&[]); CHUNKS];
+        let mut n = buf.chunks_vectored(&mut chunks);
+        let mut exposed: usize = chunks[..n].iter().map(|c| c.len()).sum();
+        // A full set of chunks may not be all of them
+        while n == chunks.len() && exposed < buf.remaining() {
+            chunks.resize(chunks.len() * 2, IoSlice::new(&[]));
+            n = buf.chunks_vectored(&mut chunks);
+            exposed = chunks[..n].iter().map(|c| c.len()).sum();
+        }
+
+        match peek(&chunks[..n]) {
+            Err(ref err) if is_eof(err) && exposed < buf.remaining() => {
+                Err(format!("buffer exposes {} of its {} bytes with chunks_vectored", exposed, buf.remaining()).into())
+            }
+            res => res,
+        }
+    };
+
+    match res {
+        Ok((v, consumed)) => {
+            buf.advance(consumed);
+            Ok(Some(v))
+        }
+        Err(ref err) if is_eof(err) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+// Unpack from `chunks`, returning the value and the number of bytes it took
+fn peek<T: for<'a> Unpack<BufPeek<'a>>>(chunks: &[IoSlice]) -> Result<(T, usize)> {
+    let mut input = BufPeek::new(chunks);
+
+    T::unpack(&mut input).map(|(v, _)| (v, input.consumed))
+}
+
+fn is_eof(err: &Error) -> bool {
+    match err {
+        Error(ErrorKind::IOError(err), _) => err.kind() == io::ErrorKind::UnexpectedEof,
+        _ => false,
+    }
+}
+
+/// Method form of `unpack_from_buf`.
+///
+/// This is implemented for every type which can be unpacked, including all xdrgen generated
+/// types, so that they can be decoded with `Foo::unpack_from_buf(&mut buf)`.
+pub trait UnpackBuf: Sized {
+    fn unpack_from_buf<B: Buf>(buf: &mut B) -> Result<Option<Self>>;
+}
+
+impl<T> UnpackBuf for T
+where
+    T: for<'a> Unpack<BufPeek<'a>>,
+{
+    fn unpack_from_buf<B: Buf>(buf: &mut B) -> Result<Option<T>> {
+        unpack_from_buf(buf)
+    }
+}
@@ -0,0 +1,51 @@
+//! Unpacking which only accepts canonical encodings
+//!
+//! The unpacking helpers at the top level of the crate skip the padding after opaque data and
+//! strings without looking at it, so values which differ only in their padding bytes decode the
+//! same. Protocols which sign or hash messages need each value to have exactly one encoding. These
+//! helpers are the same as the ones without the `_canonical` suffix, but fail with `NonCanonical`
+//! unless the padding is zero. xdrgen uses them for `GenOptions::canonical`.
+use super::{Error, ErrorKind, Read, Result, Unpack, padding, read_bytes};
+
+// Read `len` bytes of opaque data and their padding, which has to be zero
+pub(crate) fn read_opaque<In: Read>(input: &mut In, len: usize) -> Result<(Vec<u8>, usize)> {
+    let mut out = Vec::new();
+    let sz = read_bytes(input, len, &mut out)?;
+
+    let p = padding(len).len();
+    let mut pad = Vec::new();
+    read_bytes(input, p, &mut pad)?;
+    if pad.iter().any(|b| *b != 0) {
+        return Err(Error::noncanonical(format!("padding {:02x?} isn't zero", pad)));
+    }
+
+    Ok((out, sz + p))
+}
+
+/// Unpack a (perhaps) length-limited opaque array, which has to have zero padding.
+pub fn unpack_opaque_flex_canonical<In: Read>(input: &mut In, maxsz: Option<usize>) -> Result<(Vec<u8>, usize)> {
+    let (len, sz): (usize, _) = Unpack::unpack(input)?;
+
+    if maxsz.is_some_and(|m| len > m) {
+        bail!(ErrorKind::InvalidLen(maxsz.unwrap()));
+    }
+    let (v, osz) = read_opaque(input, len)?;
+
+    Ok((v, sz + osz))
+}
+
+/// Unpack a (perhaps) length-limited string, which has to have zero padding.
+pub fn unpack_string_canonical<In: Read>(input: &mut In, maxsz: Option<usize>) -> Result<(String, usize)> {
+    let (v, sz) = unpack_opaque_flex_canonical(input, maxsz)?;
+
+    String::from_utf8(v).map_err(Error::from).map(|s| (s, sz))
+}
+
+/// Unpack fixed-size opaque data into a new array, which has to have zero padding.
+pub fn unpack_opaque_fixed_canonical<In: Read, const N: usize>(input: &mut In) -> Result<([u8; N], usize)> {
+    let (v, sz) = read_opaque(input, N)?;
+    let mut bytes = [0; N];
+    bytes.copy_from_slice(&v);
+
+    Ok((bytes, sz))
+}
@@ -0,0 +1,181 @@
+//! `tokio_util::codec` support, for using XDR messages with `Framed` streams.
+//!
+//! `RecordCodec` sends each message as one record, with the record marking described in the
+//! `record` module, so it interoperates with `XdrRecordReader` and `XdrRecordWriter` and with
+//! other implementations of XDR-RPC over TCP. `Decoder` and `Encoder` are re-exported so that
+//! generated code and its users don't need their own dependency on `tokio-util`.
+use std::fmt::{self, Debug, Formatter};
+use std::io::{self, Cursor};
+use std::marker::PhantomData;
+
+use bytes::{BufMut, BytesMut};
+pub use tokio_util::codec::{Decoder, Encoder};
+
+use super::record::LAST_REC;
+use super::{Error, Pack, Unpack};
+
+// Largest fragment which can be sent.
+const MAX_FRAGMENT: usize = !LAST_REC as usize;
+
+/// Default limit on the size of a record, for codecs which don't set one with `max_record`.
+pub const DEFAULT_MAX_RECORD: usize = 4 << 20;
+
+// Most space reserved ahead of a record's contents arriving, so that a header claiming a huge
+// record doesn't allocate it all up front.
+const MAX_RESERVE: usize = 64 << 10;
+
+/// Encoder and decoder of `T` messages, one per record.
+///
+/// Decoding waits until a complete record has arrived, returning `Ok(None)` until then, and
+/// fails if the record doesn't contain exactly one `T`. A record larger than the limit set with
+/// `max_record`, `DEFAULT_MAX_RECORD` unless set, fails as soon as its fragment headers show it's
+/// too large, without waiting for the rest of it. Encoding sends each message as a single fragment, unless it's too large for
+/// one.
+pub struct RecordCodec<T> {
+    max_record: usize,
+    fragment: usize,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> RecordCodec<T> {
+    pub fn new() -> RecordCodec<T> {
+        RecordCodec {
+            max_record: DEFAULT_MAX_RECORD,
+            fragment: MAX_FRAGMENT,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Fail with `ErrorKind::MessageTooLarge` for records of more than `max` bytes, not counting
+    /// fragment headers, in either direction.
+    pub fn max_record(mut self, max: usize) -> RecordCodec<T> {
+        self.max_record = max;
+        self
+    }
+
+    // Split encoded messages into fragments of at most `max` bytes, rather than the largest
+    // possible, so that tests don't need gigabytes of data.
+    #[cfg(test)]
+    pub(crate) fn fragment(mut self, max: usize) -> RecordCodec<T> {
+        self.fragment = max;
+        self
+    }
+}
+
+impl<T> Default for RecordCodec<T> {
+    fn default() -> RecordCodec<T> {
+        RecordCodec::new()
+    }
+}
+
+impl<T> Clone for RecordCodec<T> {
+    fn clone(&self) -> RecordCodec<T> {
+        RecordCodec {
+            max_record: self.max_record,
+            fragment: self.fragment,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T> Debug for RecordCodec<T> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("RecordCodec").field("max_record", &self.max_record).finish()
+    }
+}
+
+fn header(buf: &[u8]) -> (usize, bool) {
+    let hdr = u32::from_be_bytes([buf[0], buf[1], buf[2], buf[3]]);
+    ((hdr & !LAST_REC) as usize, hdr & LAST_REC != 0)
+}
+
+impl<T: Unpack<Cursor<Vec<u8>>>> Decoder for RecordCodec<T> {
+    type Item = T;
+    type Error = Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<T>, Error> {
+        // Find the end of the record from the fragment headers, without consuming anything
+        let mut end = 0;
+        let mut len = 0;
+        loop {
+            if src.len() < end + 4 {
+                return Ok(None);
+            }
+            let (frag, last) = header(&src[end..]);
+            len += frag;
+            if len > self.max_record {
+                return Err(Error::messagetoolarge(self.max_record));
+            }
+            end += 4 + frag;
+            if last {
+                break;
+            }
+        }
+        if src.len() < end {
+            src.reserve((end - src.len()).min(MAX_RESERVE));
+            return Ok(None);
+        }
+
+        let rec = src.split_to(end);
+        let mut body = Vec::with_capacity(len);
+        let mut rec = &rec[..];
+        while !rec.is_empty() {
+            let (frag, _) = header(rec);
+            body.extend_from_slice(&rec[4..4 + frag]);
+            rec = &rec[4 + frag..];
+        }
+
+        let mut cur = Cursor::new(body);
+        let (v, sz) = T::unpack(&mut cur)?;
+        if sz < len {
+            return Err(format!("record has {} bytes after the message", len - sz).into());
+        }
+        Ok(Some(v))
+    }
+
+    fn decode_eof(&mut self, src: &mut BytesMut) -> Result<Option<T>, Error> {
+        match self.decode(src)? {
+            Some(v) => Ok(Some(v)),
+            None if src.is_empty() => Ok(None),
+            None => Err(io::Error::new(io::ErrorKind::UnexpectedEof, "truncated record").into()),
+        }
+    }
+}
+
+impl<T> Encoder<T> for RecordCodec<T>
+where
+    T: for<'a> Pack<bytes::buf::Writer<&'a mut BytesMut>>,
+{
+    type Error = Error;
+
+    fn encode(&mut self, item: T, dst: &mut BytesMut) -> Result<(), Error> {
+        // Pack after a placeholder header, which is filled in once the size is known
+        let start = dst.len();
+        dst.put_u32(0);
+        let sz = match item.pack(&mut (&mut *dst).writer()) {
+            Ok(sz) => sz,
+            Err(e) => {
+                dst.truncate(start);
+                return Err(e);
+            }
+        };
+        if sz > self.max_record {
+            dst.truncate(start);
+            return Err(Error::messagetoolarge(self.max_record));
+        }
+        if sz <= self.fragment {
+            dst[start..start + 4].copy_from_slice(&(sz as u32 | LAST_REC).to_be_bytes());
+        } else {
+            // Too large for the size field of one header, so give each fragment its own
+            let body = dst.split_off(start + 4);
+            dst.truncate(start);
+            let mut frags = body.chunks(self.fragment).peekable();
+            while let Some(frag) = frags.next() {
+                let last = if frags.peek().is_none() { LAST_REC } else { 0 };
+                dst.put_u32(frag.len() as u32 | last);
+                dst.extend_from_slice(frag);
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,110 @@
+//! CRC checksums of packed output
+//!
+//! Protocols which append a checksum to each message need the CRC of its encoding. `CrcWriter`
+//! computes it as the bytes are written, so packing into it gives both the encoding and the
+//! checksum in one pass. xdrgen generates `pack_with_checksum` methods using it with
+//! `GenOptions::checksum`.
+use std::io::{self, Write};
+
+use super::{Pack, Result};
+
+/// CRC-32 algorithms.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Crc {
+    /// CRC-32 of IEEE 802.3, as used by zlib, gzip and PNG
+    Crc32,
+    /// CRC-32C (Castagnoli), as used by iSCSI and SCTP
+    Crc32c,
+}
+
+// Lookup tables for the reflected polynomials
+const fn table(poly: u32) -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ poly } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+static CRC32: [u32; 256] = table(0xedb8_8320);
+static CRC32C: [u32; 256] = table(0x82f6_3b78);
+
+impl Crc {
+    fn table(self) -> &'static [u32; 256] {
+        match self {
+            Crc::Crc32 => &CRC32,
+            Crc::Crc32c => &CRC32C,
+        }
+    }
+
+    /// The checksum of `bytes`.
+    pub fn checksum(self, bytes: &[u8]) -> u32 {
+        let mut w = CrcWriter::new(self, io::sink());
+        w.update(bytes);
+        w.checksum()
+    }
+}
+
+/// Writer which computes the CRC of everything written through it to `inner`.
+#[derive(Debug)]
+pub struct CrcWriter<W> {
+    inner: W,
+    table: &'static [u32; 256],
+    crc: u32,
+}
+
+impl<W> CrcWriter<W> {
+    pub fn new(algo: Crc, inner: W) -> CrcWriter<W> {
+        CrcWriter {
+            inner,
+            table: algo.table(),
+            crc: !0,
+        }
+    }
+
+    fn update(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.crc = self.table[((self.crc ^ *b as u32) & 0xff) as usize] ^ (self.crc >> 8);
+        }
+    }
+
+    /// Checksum of the bytes written so far.
+    pub fn checksum(&self) -> u32 {
+        !self.crc
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: Write> Write for CrcWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let sz = self.inner.write(buf)?;
+        self.update(&buf[..sz]);
+        Ok(sz)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Pack `val` into `out`, returning the number of bytes written and their CRC.
+pub fn pack_with_crc<Out, T>(val: &T, algo: Crc, out: &mut Out) -> Result<(usize, u32)>
+where
+    Out: Write,
+    T: for<'a> Pack<CrcWriter<&'a mut Out>> + ?Sized,
+{
+    let mut w = CrcWriter::new(algo, out);
+    let sz = val.pack(&mut w)?;
+    Ok((sz, w.checksum()))
+}
@@ -17,6 +17,30 @@ error_chain! {
             description("invalid array len")
             display("invalid array len: '{}'", v)
         }
+        MessageTooLarge(v: usize) {
+            description("message too large")
+            display("message larger than limit: '{}'", v)
+        }
+        Panicked(v: String) {
+            description("panic while decoding")
+            display("panic while decoding: {}", v)
+        }
+        NotLatin1(c: char) {
+            description("character not in Latin-1")
+            display("character not in Latin-1: {:?}", c)
+        }
+        InvalidHeader(v: String) {
+            description("invalid message header")
+            display("invalid message header: {}", v)
+        }
+        NonCanonical(v: String) {
+            description("non-canonical encoding")
+            display("non-canonical encoding: {}", v)
+        }
+        SpecMismatch(expected: u64, found: u64) {
+            description("message from a different specification")
+            display("message from a different specification: fingerprint {:016x}, expected {:016x}", found, expected)
+        }
     }
 }
 
@@ -34,4 +58,20 @@ impl Error {
     pub fn invalidlen(v: usize) -> Error {
         ErrorKind::InvalidLen(v).into()
     }
+
+    pub fn messagetoolarge(v: usize) -> Error {
+        ErrorKind::MessageTooLarge(v).into()
+    }
+
+    pub fn panicked(v: String) -> Error {
+        ErrorKind::Panicked(v).into()
+    }
+
+    pub fn invalidheader<S: Into<String>>(v: S) -> Error {
+        ErrorKind::InvalidHeader(v.into()).into()
+    }
+
+    pub fn noncanonical<S: Into<String>>(v: S) -> Error {
+        ErrorKind::NonCanonical(v.into()).into()
+    }
 }
@@ -0,0 +1,94 @@
+//! Caller-controlled flushing while packing.
+//!
+//! Packing a very large message into a buffered writer normally only hands data to the underlying
+//! stream when the buffer fills or the caller flushes at the end. `PolicyWriter` wraps a writer and
+//! consults a `FlushPolicy` after each write, so that a large message can be streamed out in
+//! pieces of a controlled size.
+use std::io::{self, Write};
+
+use super::{Pack, Result};
+
+/// Decide when to flush the underlying writer.
+pub trait FlushPolicy {
+    /// Called after each write with the number of bytes written since the last flush. Returns
+    /// true if the writer should be flushed now.
+    fn should_flush(&mut self, pending: usize) -> bool;
+}
+
+/// Never flush during packing. This is the same as packing directly.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NeverFlush;
+
+impl FlushPolicy for NeverFlush {
+    fn should_flush(&mut self, _pending: usize) -> bool {
+        false
+    }
+}
+
+/// Flush once at least the given number of bytes have been written.
+#[derive(Debug, Clone, Copy)]
+pub struct FlushEvery(pub usize);
+
+impl FlushPolicy for FlushEvery {
+    fn should_flush(&mut self, pending: usize) -> bool {
+        pending >= self.0
+    }
+}
+
+impl<P: FlushPolicy + ?Sized> FlushPolicy for &mut P {
+    fn should_flush(&mut self, pending: usize) -> bool {
+        (**self).should_flush(pending)
+    }
+}
+
+/// Writer which flushes according to a `FlushPolicy`.
+#[derive(Debug)]
+pub struct PolicyWriter<W, P> {
+    inner: W,
+    policy: P,
+    pending: usize,
+}
+
+impl<W: Write, P: FlushPolicy> PolicyWriter<W, P> {
+    pub fn new(inner: W, policy: P) -> PolicyWriter<W, P> {
+        PolicyWriter {
+            inner,
+            policy,
+            pending: 0,
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: Write, P: FlushPolicy> Write for PolicyWriter<W, P> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.pending += n;
+
+        if self.policy.should_flush(self.pending) {
+            self.flush()?;
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.pending = 0;
+        self.inner.flush()
+    }
+}
+
+/// Pack `val` into `out`, flushing according to `policy`.
+///
+/// The final partial chunk is not flushed, matching `pack`. Returns the number of bytes packed.
+pub fn pack_with_policy<Out, P, T>(val: &T, out: &mut Out, policy: P) -> Result<usize>
+where
+    Out: Write,
+    P: FlushPolicy,
+    T: for<'a> Pack<PolicyWriter<&'a mut Out, P>>,
+{
+    let mut out = PolicyWriter::new(out, policy);
+    val.pack(&mut out)
+}
@@ -0,0 +1,54 @@
+//! Stable fingerprints of values, computed from their packed encoding.
+use std::io::{self, Write};
+
+use super::{Pack, Result};
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// Writer which computes the 64-bit FNV-1a hash of everything written to it.
+///
+/// FNV-1a is fully specified, so unlike `std::collections::hash_map::DefaultHasher` the result
+/// is the same on every platform and Rust version. It's not a cryptographic hash.
+#[derive(Debug, Clone)]
+pub struct Fnv64(u64);
+
+impl Fnv64 {
+    pub fn new() -> Fnv64 {
+        Fnv64(FNV_OFFSET)
+    }
+
+    /// Hash of the bytes written so far.
+    pub fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+impl Default for Fnv64 {
+    fn default() -> Fnv64 {
+        Fnv64::new()
+    }
+}
+
+impl Write for Fnv64 {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for b in buf {
+            self.0 = (self.0 ^ *b as u64).wrapping_mul(FNV_PRIME);
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Hash the packed encoding of `val`.
+///
+/// Values with the same encoding have the same hash, regardless of how they're represented in
+/// memory. This fails if `val` can't be packed, for example because it exceeds a bound.
+pub fn content_hash<T: Pack<Fnv64> + ?Sized>(val: &T) -> Result<u64> {
+    let mut hasher = Fnv64::new();
+    val.pack(&mut hasher)?;
+    Ok(hasher.finish())
+}
@@ -0,0 +1,49 @@
+//! Self-describing message headers
+//!
+//! With `GenOptions::self_describing`, xdrgen prefixes each top-level message with a header
+//! identifying the specification it was generated from, which `Unpack` checks before decoding the
+//! rest, so that peers built from incompatible specifications fail clearly rather than decoding
+//! garbage. The header is 16 bytes: `HEADER_MAGIC`, `HEADER_VERSION`, and a 64-bit fingerprint of
+//! the specification.
+//!
+//! XDR is big-endian, so the magic number also tags the byte order: one which reads byte-swapped
+//! came from an encoder which wrote it little-endian.
+use super::{Error, ErrorKind, Pack, Read, Result, Unpack, Write};
+
+/// The first word of a header, "XDRh".
+pub const HEADER_MAGIC: u32 = 0x5844_5268;
+
+/// The version of the header format.
+pub const HEADER_VERSION: u32 = 1;
+
+/// The packed size of a header.
+pub const HEADER_SIZE: usize = 16;
+
+/// Pack a header for a message of the specification with fingerprint `spec_hash`.
+pub fn pack_header<Out: Write>(spec_hash: u64, out: &mut Out) -> Result<usize> {
+    Ok(HEADER_MAGIC.pack(out)? + HEADER_VERSION.pack(out)? + spec_hash.pack(out)?)
+}
+
+/// Unpack a header, checking that it's for a message of the specification with fingerprint
+/// `spec_hash`.
+pub fn unpack_header<In: Read>(spec_hash: u64, input: &mut In) -> Result<usize> {
+    let (magic, msz): (u32, _) = Unpack::unpack(input)?;
+    if magic == HEADER_MAGIC.swap_bytes() {
+        return Err(Error::invalidheader("byte-swapped magic number, from a little-endian encoder"));
+    }
+    if magic != HEADER_MAGIC {
+        return Err(Error::invalidheader(format!("magic number {:#010x}", magic)));
+    }
+
+    let (version, vsz): (u32, _) = Unpack::unpack(input)?;
+    if version != HEADER_VERSION {
+        return Err(Error::invalidheader(format!("unsupported version {}", version)));
+    }
+
+    let (hash, hsz): (u64, _) = Unpack::unpack(input)?;
+    if hash != spec_hash {
+        bail!(ErrorKind::SpecMismatch(spec_hash, hash));
+    }
+
+    Ok(msz + vsz + hsz)
+}
@@ -0,0 +1,506 @@
+//! Minimal JSON support, without serde
+//!
+//! This is enough to represent decoded XDR values as JSON text and read them back, for
+//! xdrgen's generated `to_json`/`from_json` methods. Structs are objects, enums are the name of
+//! their value, and unions are the label of their arm, as an object `{"label": value}` if the
+//! arm isn't void. Opaque data is a hex string, and optional values are `null` when absent.
+//!
+//! Numbers are kept as text, so 64-bit integers survive the round trip, although other JSON
+//! implementations may not read them exactly. Floating point values which can't be represented
+//! in JSON are written as `null`, and read back as NaN.
+use std::convert::TryFrom;
+use std::fmt::{self, Display, Write};
+
+use super::{Error, Result};
+
+/// Limit on nesting of arrays and objects when parsing.
+const MAX_DEPTH: usize = 128;
+
+/// A JSON value.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    /// A number, as it appears in the JSON text
+    Number(String),
+    String(String),
+    Array(Vec<Json>),
+    /// Object members, in order
+    Object(Vec<(String, Json)>),
+}
+
+fn err<T>(what: String) -> Result<T> {
+    Err(Error::from(format!("json: {}", what)))
+}
+
+impl Json {
+    /// Parse JSON text, which must contain exactly one value.
+    pub fn parse(text: &str) -> Result<Json> {
+        let mut p = Parser { text, pos: 0 };
+        let ret = p.value(0)?;
+        p.skip_ws();
+        if p.pos != text.len() {
+            return err(format!("trailing characters at {}", p.pos));
+        }
+        Ok(ret)
+    }
+
+    /// Opaque data as a hex string.
+    pub fn hex(bytes: &[u8]) -> Json {
+        let mut s = String::with_capacity(bytes.len() * 2);
+        for b in bytes {
+            let _ = write!(s, "{:02x}", b);
+        }
+        Json::String(s)
+    }
+
+    /// Opaque data from a hex string.
+    pub fn unhex(&self) -> Result<Vec<u8>> {
+        let s = self.as_str()?;
+        if s.len() % 2 != 0 || !s.is_ascii() {
+            return err(format!("bad hex string {:?}", s));
+        }
+        (0..s.len())
+            .step_by(2)
+            .map(|i| match u8::from_str_radix(&s[i..i + 2], 16) {
+                Ok(b) => Ok(b),
+                Err(_) => err(format!("bad hex string {:?}", s)),
+            })
+            .collect()
+    }
+
+    /// Fixed-size opaque data from a hex string.
+    pub fn unhex_array<const N: usize>(&self) -> Result<[u8; N]> {
+        let bytes = self.unhex()?;
+        let len = bytes.len();
+        <[u8; N]>::try_from(bytes).map_err(|_| Error::invalidlen(len))
+    }
+
+    pub fn as_str(&self) -> Result<&str> {
+        match self {
+            Json::String(s) => Ok(s),
+            v => err(format!("expected string, got {}", v)),
+        }
+    }
+
+    pub fn as_array(&self) -> Result<&[Json]> {
+        match self {
+            Json::Array(a) => Ok(a),
+            v => err(format!("expected array, got {}", v)),
+        }
+    }
+
+    /// The member `name` of an object.
+    pub fn field(&self, name: &str) -> Result<&Json> {
+        match self {
+            Json::Object(members) => match members.iter().find(|(n, _)| n == name) {
+                Some((_, v)) => Ok(v),
+                None => err(format!("missing field {:?}", name)),
+            },
+            v => err(format!("expected object, got {}", v)),
+        }
+    }
+
+    /// The label of a union arm, and its value unless the arm is void.
+    pub fn tag(&self) -> Result<(&str, Option<&Json>)> {
+        match self {
+            Json::String(s) => Ok((s, None)),
+            Json::Object(members) if members.len() == 1 => Ok((&members[0].0, Some(&members[0].1))),
+            v => err(format!("expected union, got {}", v)),
+        }
+    }
+}
+
+fn write_str(f: &mut fmt::Formatter, s: &str) -> fmt::Result {
+    f.write_char('"')?;
+    for c in s.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => f.write_char(c)?,
+        }
+    }
+    f.write_char('"')
+}
+
+/// Compact JSON text.
+impl Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Json::Null => f.write_str("null"),
+            Json::Bool(b) => write!(f, "{}", b),
+            Json::Number(n) => f.write_str(n),
+            Json::String(s) => write_str(f, s),
+            Json::Array(a) => {
+                f.write_char('[')?;
+                for (i, v) in a.iter().enumerate() {
+                    if i > 0 {
+                        f.write_char(',')?;
+                    }
+                    write!(f, "{}", v)?;
+                }
+                f.write_char(']')
+            }
+            Json::Object(members) => {
+                f.write_char('{')?;
+                for (i, (n, v)) in members.iter().enumerate() {
+                    if i > 0 {
+                        f.write_char(',')?;
+                    }
+                    write_str(f, n)?;
+                    write!(f, ":{}", v)?;
+                }
+                f.write_char('}')
+            }
+        }
+    }
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<u8> {
+        self.text.as_bytes().get(self.pos).cloned()
+    }
+
+    fn skip_ws(&mut self) {
+        while let Some(b' ') | Some(b'\t') | Some(b'\n') | Some(b'\r') = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, c: u8) -> Result<()> {
+        self.skip_ws();
+        if self.peek() != Some(c) {
+            return err(format!("expected '{}' at {}", c as char, self.pos));
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn literal(&mut self, word: &str, val: Json) -> Result<Json> {
+        if !self.text[self.pos..].starts_with(word) {
+            return err(format!("unexpected character at {}", self.pos));
+        }
+        self.pos += word.len();
+        Ok(val)
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Json> {
+        if depth > MAX_DEPTH {
+            return err("nesting too deep".to_string());
+        }
+        self.skip_ws();
+        match self.peek() {
+            None => err("unexpected end of input".to_string()),
+            Some(b'n') => self.literal("null", Json::Null),
+            Some(b't') => self.literal("true", Json::Bool(true)),
+            Some(b'f') => self.literal("false", Json::Bool(false)),
+            Some(b'"') => Ok(Json::String(self.string()?)),
+            Some(b'[') => {
+                self.pos += 1;
+                let mut ret = Vec::new();
+                self.skip_ws();
+                if self.peek() == Some(b']') {
+                    self.pos += 1;
+                    return Ok(Json::Array(ret));
+                }
+                loop {
+                    ret.push(self.value(depth + 1)?);
+                    self.skip_ws();
+                    match self.peek() {
+                        Some(b',') => self.pos += 1,
+                        _ => break,
+                    }
+                }
+                self.expect(b']')?;
+                Ok(Json::Array(ret))
+            }
+            Some(b'{') => {
+                self.pos += 1;
+                let mut ret = Vec::new();
+                self.skip_ws();
+                if self.peek() == Some(b'}') {
+                    self.pos += 1;
+                    return Ok(Json::Object(ret));
+                }
+                loop {
+                    self.skip_ws();
+                    if self.peek() != Some(b'"') {
+                        return err(format!("expected member name at {}", self.pos));
+                    }
+                    let name = self.string()?;
+                    self.expect(b':')?;
+                    ret.push((name, self.value(depth + 1)?));
+                    self.skip_ws();
+                    match self.peek() {
+                        Some(b',') => self.pos += 1,
+                        _ => break,
+                    }
+                }
+                self.expect(b'}')?;
+                Ok(Json::Object(ret))
+            }
+            Some(b'-') | Some(b'0'..=b'9') => self.number(),
+            Some(_) => err(format!("unexpected character at {}", self.pos)),
+        }
+    }
+
+    fn number(&mut self) -> Result<Json> {
+        let start = self.pos;
+        let digits = |p: &mut Parser| {
+            let start = p.pos;
+            while let Some(b'0'..=b'9') = p.peek() {
+                p.pos += 1;
+            }
+            p.pos > start
+        };
+
+        if self.peek() == Some(b'-') {
+            self.pos += 1;
+        }
+        let mut ok = digits(self);
+        if ok && self.peek() == Some(b'.') {
+            self.pos += 1;
+            ok = digits(self);
+        }
+        if ok && (self.peek() == Some(b'e') || self.peek() == Some(b'E')) {
+            self.pos += 1;
+            if let Some(b'+') | Some(b'-') = self.peek() {
+                self.pos += 1;
+            }
+            ok = digits(self);
+        }
+        if !ok {
+            return err(format!("bad number at {}", start));
+        }
+        Ok(Json::Number(self.text[start..self.pos].to_string()))
+    }
+
+    fn hex4(&mut self) -> Result<u32> {
+        let hex = self.text.get(self.pos..self.pos + 4);
+        match hex.and_then(|h| u32::from_str_radix(h, 16).ok()) {
+            Some(v) => {
+                self.pos += 4;
+                Ok(v)
+            }
+            None => err(format!("bad unicode escape at {}", self.pos)),
+        }
+    }
+
+    fn string(&mut self) -> Result<String> {
+        self.pos += 1;
+        let mut ret = String::new();
+        loop {
+            let rest = &self.text[self.pos..];
+            let c = match rest.chars().next() {
+                None => return err("unterminated string".to_string()),
+                Some(c) => c,
+            };
+            self.pos += c.len_utf8();
+            match c {
+                '"' => return Ok(ret),
+                '\\' => {
+                    let esc = self.peek();
+                    self.pos += 1;
+                    let c = match esc {
+                        Some(b'"') => '"',
+                        Some(b'\\') => '\\',
+                        Some(b'/') => '/',
+                        Some(b'b') => '\u{8}',
+                        Some(b'f') => '\u{c}',
+                        Some(b'n') => '\n',
+                        Some(b'r') => '\r',
+                        Some(b't') => '\t',
+                        Some(b'u') => {
+                            let mut v = self.hex4()?;
+                            // Characters outside the BMP are escaped as a surrogate pair
+                            if (0xd800..0xdc00).contains(&v) && self.text[self.pos..].starts_with("\\u") {
+                                self.pos += 2;
+                                let lo = self.hex4()?;
+                                v = 0x10000 + ((v - 0xd800) << 10) + (lo.wrapping_sub(0xdc00) & 0x3ff);
+                            }
+                            match std::char::from_u32(v) {
+                                Some(c) => c,
+                                None => return err(format!("bad unicode escape at {}", self.pos)),
+                            }
+                        }
+                        _ => return err(format!("bad escape at {}", self.pos - 1)),
+                    };
+                    ret.push(c);
+                }
+                c if (c as u32) < 0x20 => return err(format!("control character in string at {}", self.pos - 1)),
+                c => ret.push(c),
+            }
+        }
+    }
+}
+
+/// Types with a JSON representation.
+pub trait ToJson {
+    fn to_json_value(&self) -> Json;
+}
+
+/// Types which can be read back from their JSON representation.
+pub trait FromJson: Sized {
+    fn from_json_value(json: &Json) -> Result<Self>;
+}
+
+/// JSON text for a value.
+pub fn to_string<T: ToJson + ?Sized>(v: &T) -> String {
+    v.to_json_value().to_string()
+}
+
+/// Parse a value from JSON text.
+pub fn from_str<T: FromJson>(text: &str) -> Result<T> {
+    T::from_json_value(&Json::parse(text)?)
+}
+
+macro_rules! json_int {
+    ($($t:ty),*) => {$(
+        impl ToJson for $t {
+            fn to_json_value(&self) -> Json {
+                Json::Number(self.to_string())
+            }
+        }
+
+        impl FromJson for $t {
+            fn from_json_value(json: &Json) -> Result<$t> {
+                match json {
+                    Json::Number(n) => match n.parse() {
+                        Ok(v) => Ok(v),
+                        Err(_) => err(format!("{} out of range for {}", n, stringify!($t))),
+                    },
+                    v => err(format!("expected number, got {}", v)),
+                }
+            }
+        }
+    )*};
+}
+
+json_int!(i32, u32, i64, u64);
+
+macro_rules! json_float {
+    ($($t:ty),*) => {$(
+        impl ToJson for $t {
+            fn to_json_value(&self) -> Json {
+                if self.is_finite() {
+                    Json::Number(self.to_string())
+                } else {
+                    Json::Null
+                }
+            }
+        }
+
+        impl FromJson for $t {
+            fn from_json_value(json: &Json) -> Result<$t> {
+                match json {
+                    Json::Null => Ok(<$t>::NAN),
+                    Json::Number(n) => match n.parse() {
+                        Ok(v) => Ok(v),
+                        Err(_) => err(format!("bad number {}", n)),
+                    },
+                    v => err(format!("expected number, got {}", v)),
+                }
+            }
+        }
+    )*};
+}
+
+json_float!(f32, f64);
+
+impl ToJson for bool {
+    fn to_json_value(&self) -> Json {
+        Json::Bool(*self)
+    }
+}
+
+impl FromJson for bool {
+    fn from_json_value(json: &Json) -> Result<bool> {
+        match json {
+            Json::Bool(b) => Ok(*b),
+            v => err(format!("expected bool, got {}", v)),
+        }
+    }
+}
+
+impl ToJson for String {
+    fn to_json_value(&self) -> Json {
+        Json::String(self.clone())
+    }
+}
+
+impl FromJson for String {
+    fn from_json_value(json: &Json) -> Result<String> {
+        json.as_str().map(String::from)
+    }
+}
+
+impl<T: ToJson> ToJson for [T] {
+    fn to_json_value(&self) -> Json {
+        Json::Array(self.iter().map(ToJson::to_json_value).collect())
+    }
+}
+
+impl<T: ToJson> ToJson for Vec<T> {
+    fn to_json_value(&self) -> Json {
+        self[..].to_json_value()
+    }
+}
+
+impl<T: FromJson> FromJson for Vec<T> {
+    fn from_json_value(json: &Json) -> Result<Vec<T>> {
+        json.as_array()?.iter().map(T::from_json_value).collect()
+    }
+}
+
+impl<T: ToJson, const N: usize> ToJson for [T; N] {
+    fn to_json_value(&self) -> Json {
+        self[..].to_json_value()
+    }
+}
+
+impl<T: FromJson, const N: usize> FromJson for [T; N] {
+    fn from_json_value(json: &Json) -> Result<[T; N]> {
+        let v = Vec::<T>::from_json_value(json)?;
+        let len = v.len();
+        <[T; N]>::try_from(v).map_err(|_| Error::invalidlen(len))
+    }
+}
+
+impl<T: ToJson> ToJson for Option<T> {
+    fn to_json_value(&self) -> Json {
+        match self {
+            None => Json::Null,
+            Some(v) => v.to_json_value(),
+        }
+    }
+}
+
+impl<T: FromJson> FromJson for Option<T> {
+    fn from_json_value(json: &Json) -> Result<Option<T>> {
+        match json {
+            Json::Null => Ok(None),
+            v => T::from_json_value(v).map(Some),
+        }
+    }
+}
+
+impl<T: ToJson + ?Sized> ToJson for Box<T> {
+    fn to_json_value(&self) -> Json {
+        (**self).to_json_value()
+    }
+}
+
+impl<T: FromJson> FromJson for Box<T> {
+    fn from_json_value(json: &Json) -> Result<Box<T>> {
+        T::from_json_value(json).map(Box::new)
+    }
+}
@@ -0,0 +1,23 @@
+//! Strings as Latin-1 (ISO 8859-1)
+//!
+//! XDR strings are nominally ASCII, but the encoding is byte-transparent, and some peers send
+//! other 8-bit text. As every byte is a Latin-1 character, decoding bytes this way can't fail,
+//! unlike UTF-8. xdrgen uses these for `GenOptions::string_encoding(StrEnc::Latin1)`.
+use std::convert::TryFrom;
+
+use super::{ErrorKind, Result};
+
+/// Decode `bytes` as Latin-1, each byte becoming the character with the same code point.
+pub fn decode(bytes: &[u8]) -> String {
+    bytes.iter().map(|&b| char::from(b)).collect()
+}
+
+/// Encode `s` as Latin-1, failing with `NotLatin1` if it has a character beyond U+00FF.
+pub fn encode(s: &str) -> Result<Vec<u8>> {
+    s.chars()
+        .map(|c| match u8::try_from(c) {
+            Ok(b) => Ok(b),
+            Err(_) => bail!(ErrorKind::NotLatin1(c)),
+        })
+        .collect()
+}
@@ -22,15 +22,77 @@
 extern crate byteorder;
 #[macro_use]
 extern crate error_chain;
+#[cfg(feature = "bytes")]
+extern crate bytes;
 
 pub use std::io::{Read, Write};
+use std::io;
 use std::ops::Deref;
 use std::cmp::min;
 use std::borrow::{Borrow, Cow};
+use std::mem::MaybeUninit;
 use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
 
 pub mod record;
 
+mod limit;
+pub use limit::LimitRead;
+
+pub mod flush;
+pub use flush::{FlushPolicy, pack_with_policy};
+
+mod validate;
+pub use validate::Validate;
+
+mod trace;
+pub use trace::{Trace, UnpackTraced};
+
+mod hash;
+pub use hash::{Fnv64, content_hash};
+
+pub mod header;
+pub use header::{pack_header, unpack_header};
+
+mod crc;
+pub use crc::{Crc, CrcWriter, pack_with_crc};
+
+pub mod metrics;
+pub use metrics::{Metrics, set_metrics};
+
+mod panic;
+pub use panic::catch_panic;
+
+mod borrow;
+pub use borrow::{UnpackBorrowed, unpack_array_borrowed, unpack_borrowed, unpack_flex_borrowed, unpack_opaque_borrowed,
+                 unpack_str_borrowed};
+
+mod canonical;
+pub use canonical::{unpack_opaque_fixed_canonical, unpack_opaque_flex_canonical, unpack_string_canonical};
+
+mod object;
+pub use object::Codec;
+
+mod skip;
+pub use skip::{skip_flex, skip_opaque_array, skip_opaque_flex};
+
+pub mod wide;
+
+pub mod latin1;
+
+pub mod resume;
+pub use resume::{Decode, Progress, Resumable};
+
+#[cfg(feature = "bytes")]
+pub mod buf;
+#[cfg(feature = "bytes")]
+pub use buf::{UnpackBuf, unpack_from_buf};
+
+#[cfg(feature = "tokio-codec")]
+pub mod codec;
+
+#[cfg(feature = "json")]
+pub mod json;
+
 mod error;
 pub use error::*;
 
@@ -152,6 +214,44 @@ pub fn pack_flex<Out: Write, T: Pack<Out>>(
     val.pack(out)
 }
 
+/// Pack a dynamically sized array of `len` elements from an iterator, with size limit check.
+///
+/// This is `pack_flex` for elements which aren't already in a slice, so they don't have to be
+/// collected into one first. The length prefix is written before any elements, so it has to be
+/// given, and it's an error for `iter` to produce a different number of elements. When there are
+/// too many, the error is before the extra ones are packed; when there are too few, it's after
+/// all of them have been. The elements can be values or references, though for references the
+/// element type `T` has to be given.
+pub fn pack_flex_iter<Out, T, I>(len: usize, iter: I, maxsz: Option<usize>, out: &mut Out) -> Result<usize>
+where
+    Out: Write,
+    T: Pack<Out>,
+    I: IntoIterator,
+    I::Item: Borrow<T>,
+{
+    if maxsz.is_some_and(|m| len > m) {
+        bail!(ErrorKind::InvalidLen(maxsz.unwrap()));
+    }
+    if len > u32::MAX as usize {
+        bail!(ErrorKind::InvalidLen(len));
+    }
+
+    let mut sz = len.pack(out)?;
+    let mut elems = 0;
+    for it in iter {
+        if elems == len {
+            bail!(ErrorKind::InvalidLen(len));
+        }
+        sz += it.borrow().pack(out)?;
+        elems += 1;
+    }
+    if elems != len {
+        bail!(ErrorKind::InvalidLen(len));
+    }
+
+    Ok(sz)
+}
+
 /// Pack a dynamically sized opaque array, with size limit check.
 ///
 /// This packs an array of packable objects, and also applies an optional size limit.
@@ -261,6 +361,34 @@ where
     Ok(rsz)
 }
 
+/// Unpack a fixed-sized array into a new array of the same size
+///
+/// This is equivalent to `unpack_array` without the need for an existing array to unpack into,
+/// so the elements don't need to be `Clone`.
+pub fn unpack_fixed<In: Read, T: Unpack<In>, const N: usize>(input: &mut In) -> Result<([T; N], usize)> {
+    // Elements are written into uninitialized memory, and dropped again if unpacking fails part way
+    let mut array: [MaybeUninit<T>; N] = unsafe { MaybeUninit::uninit().assume_init() };
+    let mut sz = 0;
+    let mut done = 0;
+
+    let res: Result<()> = array.iter_mut().try_for_each(|elem| {
+        let (e, esz) = Unpack::unpack(input)?;
+        elem.write(e);
+        sz += esz;
+        done += 1;
+        Ok(())
+    });
+    if let Err(err) = res {
+        for elem in &mut array[..done] {
+            unsafe { elem.assume_init_drop() }
+        }
+        return Err(err);
+    }
+
+    let array = unsafe { (&array as *const [MaybeUninit<T>; N] as *const [T; N]).read() };
+    Ok((array, sz))
+}
+
 /// Unpack a fixed-sized opaque array
 ///
 /// Unpack a fixed-size array of raw bytes. The results are placed in `bytes`, but the actual wire-size of
@@ -274,12 +402,9 @@ pub fn unpack_opaque_array<In: Read>(
     bytesz: usize,
 ) -> Result<usize> {
     let sz = min(bytesz, bytes.len());
-    let mut rsz = 0;
+    let mut rsz = sz;
 
-    while rsz < sz {
-        let r = input.read(&mut bytes[rsz..])?;
-        rsz += r;
-    }
+    input.read_exact(&mut bytes[..sz])?;
 
     // Fill in excess
     if sz < bytes.len() {
@@ -290,16 +415,22 @@ pub fn unpack_opaque_array<In: Read>(
 
     // Mop up unused data on the wire and padding
     let p = padding(bytesz).len();
-    if bytes.len() < bytesz + p {
-        for _ in bytes.len()..(bytesz + p) {
-            let _ = input.read_u8()?;
-            rsz += 1;
-        }
+    for _ in sz..(bytesz + p) {
+        let _ = input.read_u8()?;
+        rsz += 1;
     }
 
     Ok(rsz)
 }
 
+/// Unpack a fixed-sized opaque array into a new array of the same size
+pub fn unpack_opaque_fixed<In: Read, const N: usize>(input: &mut In) -> Result<([u8; N], usize)> {
+    let mut bytes = [0; N];
+    let sz = unpack_opaque_array(input, &mut bytes[..], N)?;
+
+    Ok((bytes, sz))
+}
+
 /// Unpack a (perhaps) length-limited array
 pub fn unpack_flex<In: Read, T: Unpack<In>>(
     input: &mut In,
@@ -346,7 +477,7 @@ pub fn unpack_opaque_flex<In: Read>(
     // let mut out = Vec::with_capacity(elems);
     let mut out = vec![];
 
-    sz += input.take(elems as u64).read_to_end(&mut out)?;
+    sz += read_bytes(input, elems, &mut out)?;
 
     let p = padding(sz);
     for _ in 0..p.len() {
@@ -357,6 +488,17 @@ pub fn unpack_opaque_flex<In: Read>(
     Ok((out, sz))
 }
 
+// Append exactly `len` bytes from `input` to `out`. A short read is an `UnexpectedEof` error, so
+// that truncated input can be distinguished from valid input.
+fn read_bytes<In: Read>(input: &mut In, len: usize, out: &mut Vec<u8>) -> Result<usize> {
+    let sz = input.by_ref().take(len as u64).read_to_end(out)?;
+
+    if sz < len {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "short opaque data").into());
+    }
+    Ok(sz)
+}
+
 /// Unpack (perhaps) length-limited string
 pub fn unpack_string<In: Read>(input: &mut In, maxsz: Option<usize>) -> Result<(String, usize)> {
     let (v, sz) = unpack_opaque_flex(input, maxsz)?;
@@ -574,6 +716,20 @@ pub fn unpack<In: Read, T: Unpack<In>>(input: &mut In) -> Result<T> {
     T::unpack(input).map(|(v, _)| v)
 }
 
+/// Type-erased decoder of a message, as in the `dispatch_map` generated by xdrgen.
+///
+/// The decoded value can be identified with `Any::type_id`, or recovered with `downcast`.
+pub type DispatchFn = fn(&mut dyn Read) -> Result<Box<dyn std::any::Any>>;
+
+/// Format bytes as lowercase hex, as `Debug` formatting of opaque fields with a `debug_hex`
+/// pragma when xdrgen derives `educe::Educe`.
+pub fn debug_hex<T: AsRef<[u8]> + ?Sized>(val: &T, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    for b in val.as_ref() {
+        write!(f, "{:02x}", b)?;
+    }
+    Ok(())
+}
+
 /// Basic unpacking trait
 ///
 /// This trait is used to unpack a type from an XDR encoded byte
@@ -704,7 +860,7 @@ impl<'a, In: Read> Unpack<In> for Opaque<'a> {
     fn unpack(input: &mut In) -> Result<(Self, usize)> {
         let (len, mut sz) = usize::unpack(input)?;
         let mut v = Vec::new();
-        sz += input.by_ref().take(len as u64).read_to_end(&mut v)?;
+        sz += read_bytes(input, len, &mut v)?;
 
         let p = padding(sz);
         for _ in 0..p.len() {
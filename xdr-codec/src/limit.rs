@@ -0,0 +1,51 @@
+//! Limit on the total number of bytes read.
+use std::io::{self, Read};
+
+/// Reader which fails once more than a fixed number of bytes have been read.
+///
+/// Unlike `Read::take`, reaching the limit is an error rather than end of file, so a truncated
+/// message can be distinguished from one which is too large. `exceeded` reports whether the limit
+/// was hit.
+#[derive(Debug)]
+pub struct LimitRead<R> {
+    inner: R,
+    remaining: usize,
+    exceeded: bool,
+}
+
+impl<R: Read> LimitRead<R> {
+    pub fn new(inner: R, limit: usize) -> LimitRead<R> {
+        LimitRead {
+            inner,
+            remaining: limit,
+            exceeded: false,
+        }
+    }
+
+    /// Return true if a read was attempted beyond the limit.
+    pub fn exceeded(&self) -> bool {
+        self.exceeded
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read> Read for LimitRead<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if self.remaining == 0 {
+            self.exceeded = true;
+            return Err(io::Error::other("message size limit exceeded"));
+        }
+
+        let len = buf.len().min(self.remaining);
+        let n = self.inner.read(&mut buf[..len])?;
+        self.remaining -= n;
+
+        Ok(n)
+    }
+}
@@ -0,0 +1,60 @@
+//! Counters for codec activity, for monitoring.
+//!
+//! When xdrgen generates code with metrics enabled, every `Pack` and `Unpack` implementation
+//! reports to the process-wide hook installed with `set_metrics`, so that bytes processed,
+//! messages decoded and decode errors can be exported to a monitoring system without
+//! instrumenting each call site. Until a hook is installed the reports cost one atomic load.
+use std::sync::OnceLock;
+
+use super::{Error, Result};
+
+/// Receiver for codec activity.
+///
+/// `ty` is the name of the XDR type being packed or unpacked. Nested types report separately, so
+/// packing a struct also reports each of its struct, union and enum fields. The hook is called
+/// from whichever threads use the codecs, so implementations typically use atomic counters.
+pub trait Metrics: Sync {
+    /// A value of type `ty` was packed into `bytes` bytes.
+    fn on_pack(&self, _ty: &str, _bytes: usize) {}
+
+    /// A value of type `ty` was unpacked from `bytes` bytes.
+    fn on_unpack(&self, _ty: &str, _bytes: usize) {}
+
+    /// Packing a value of type `ty` failed.
+    fn on_pack_error(&self, _ty: &str, _err: &Error) {}
+
+    /// Unpacking a value of type `ty` failed.
+    fn on_unpack_error(&self, _ty: &str, _err: &Error) {}
+}
+
+static METRICS: OnceLock<&'static dyn Metrics> = OnceLock::new();
+
+/// Install the hook which generated codecs report to.
+///
+/// The hook can only be set once. Returns false, leaving the existing hook in place, if one was
+/// already installed.
+pub fn set_metrics(metrics: &'static dyn Metrics) -> bool {
+    METRICS.set(metrics).is_ok()
+}
+
+/// Report the result of packing a value of type `ty`. This is called by generated code.
+pub fn packed(ty: &str, res: Result<usize>) -> Result<usize> {
+    if let Some(metrics) = METRICS.get() {
+        match &res {
+            Ok(sz) => metrics.on_pack(ty, *sz),
+            Err(err) => metrics.on_pack_error(ty, err),
+        }
+    }
+    res
+}
+
+/// Report the result of unpacking a value of type `ty`. This is called by generated code.
+pub fn unpacked<T>(ty: &str, res: Result<(T, usize)>) -> Result<(T, usize)> {
+    if let Some(metrics) = METRICS.get() {
+        match &res {
+            Ok((_, sz)) => metrics.on_unpack(ty, *sz),
+            Err(err) => metrics.on_unpack_error(ty, err),
+        }
+    }
+    res
+}
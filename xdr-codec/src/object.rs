@@ -0,0 +1,70 @@
+//! Packing and unpacking through trait objects
+//!
+//! `Pack` and `Unpack` are generic over the stream and `Unpack` returns `Self`, so neither can be
+//! used as a trait object. `Codec` is an object-safe counterpart for registries and plugins which
+//! handle messages of types they don't know statically, holding them as `Box<dyn Codec>`. xdrgen
+//! implements it for generated types with `GenOptions::dyn_codec`.
+//!
+//! Unpacking goes through a value of the type to be decoded, which works as a prototype: a
+//! registry keeps one boxed value for each kind of message, and calls `unpack_boxed` on it to
+//! decode another of the same type.
+use std::any::Any;
+use std::fmt;
+
+use super::{Read, Result, Write};
+
+/// Object-safe packing and unpacking of a value.
+pub trait Codec: Any {
+    /// Pack the value, returning the number of bytes written, as `Pack::pack`.
+    fn pack_dyn(&self, out: &mut dyn Write) -> Result<usize>;
+
+    /// Unpack a value of the same type as this one, returning it boxed with the number of bytes
+    /// consumed.
+    fn unpack_boxed(&self, input: &mut dyn Read) -> Result<(Box<dyn Codec>, usize)>;
+
+    /// Unpack a value of the same type in place of this one, returning the number of bytes
+    /// consumed. The value is unchanged if unpacking fails.
+    fn unpack_dyn(&mut self, input: &mut dyn Read) -> Result<usize>;
+
+    /// The name of the generated type.
+    fn type_name(&self) -> &'static str;
+
+    /// The value as `Any`, to identify or downcast it.
+    fn as_any(&self) -> &dyn Any;
+
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+
+    fn into_any(self: Box<Self>) -> Box<dyn Any>;
+}
+
+impl dyn Codec {
+    /// Whether the value is a `T`.
+    pub fn is<T: Codec>(&self) -> bool {
+        self.as_any().is::<T>()
+    }
+
+    /// The value as a `T`, if it is one.
+    pub fn downcast_ref<T: Codec>(&self) -> Option<&T> {
+        self.as_any().downcast_ref()
+    }
+
+    /// The value as a mutable `T`, if it is one.
+    pub fn downcast_mut<T: Codec>(&mut self) -> Option<&mut T> {
+        self.as_any_mut().downcast_mut()
+    }
+
+    /// The boxed value as a `T`, or itself back if it isn't one.
+    pub fn downcast<T: Codec>(self: Box<Self>) -> ::std::result::Result<Box<T>, Box<dyn Codec>> {
+        if self.is::<T>() {
+            Ok(self.into_any().downcast().unwrap())
+        } else {
+            Err(self)
+        }
+    }
+}
+
+impl fmt::Debug for dyn Codec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Codec({})", self.type_name())
+    }
+}
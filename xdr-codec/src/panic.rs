@@ -0,0 +1,41 @@
+//! Turning panics while decoding into errors, for fuzzing.
+//!
+//! Generated code with `catch_panics` enabled runs each `Unpack` through `catch_panic`. Built
+//! with `--cfg fuzzing`, as `cargo fuzz` does, a panic becomes `ErrorKind::Panicked`, so a fuzz
+//! target can tell a bug in a decoder from input which is merely invalid. Otherwise the
+//! decoder is called directly, so other builds are unaffected.
+use super::Result;
+
+/// Run `f`, which unpacks a value. This is called by generated code.
+#[cfg(fuzzing)]
+pub fn catch_panic<T, F: FnOnce() -> Result<T>>(f: F) -> Result<T> {
+    caught(f)
+}
+
+/// Run `f`, which unpacks a value. This is called by generated code.
+#[cfg(not(fuzzing))]
+#[inline]
+pub fn catch_panic<T, F: FnOnce() -> Result<T>>(f: F) -> Result<T> {
+    f()
+}
+
+// The panic hook still runs first, so a fuzz target needs to leave it alone, rather than
+// installing one which aborts.
+#[cfg(any(fuzzing, test))]
+pub(crate) fn caught<T, F: FnOnce() -> Result<T>>(f: F) -> Result<T> {
+    use std::panic::{AssertUnwindSafe, catch_unwind};
+
+    match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(res) => res,
+        Err(payload) => {
+            let msg = match payload.downcast::<String>() {
+                Ok(msg) => *msg,
+                Err(payload) => match payload.downcast::<&'static str>() {
+                    Ok(msg) => msg.to_string(),
+                    Err(_) => "unknown panic".to_string(),
+                },
+            };
+            Err(super::Error::panicked(msg))
+        }
+    }
+}
@@ -14,12 +14,13 @@
 //! is using record marking; both ends must agree.
 use std::io::{self, BufRead, Read, Write};
 use std::cmp::min;
+use std::marker::PhantomData;
 
 use crate::error::*;
 
-use super::{Error, pack, unpack};
+use super::{Error, Unpack, pack, unpack};
 
-const LAST_REC: u32 = 1u32 << 31;
+pub(crate) const LAST_REC: u32 = 1u32 << 31;
 
 fn mapioerr(xdrerr: Error) -> io::Error {
     match xdrerr {
@@ -28,6 +29,17 @@ fn mapioerr(xdrerr: Error) -> io::Error {
     }
 }
 
+// How reading a record ended.
+enum RecordEnd {
+    Complete,
+    // end of stream before the record started
+    Eof,
+    // end of stream after a fragment without the end of record marker
+    Unterminated,
+    // end of stream within a fragment, with this many bytes missing
+    Short(usize),
+}
+
 /// Read records from a bytestream.
 ///
 /// Reads will read up to the end of the current fragment, and not
@@ -59,10 +71,23 @@ impl<R: BufRead> XdrRecordReader<R> {
     fn nextrec(&mut self) -> io::Result<bool> {
         assert_eq!(self.consumed, self.size);
 
+        // `read_record` calls this directly rather than through `read`, so it has to retry
+        // interrupted reads itself.
+        loop {
+            match self.reader.fill_buf() {
+                Ok([]) => return Ok(true),
+                Ok(_) => break,
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => (),
+                Err(err) => return Err(err),
+            }
+        }
+
         let rechdr: u32 = match unpack(&mut self.reader) {
             Ok(v) => v,
             Err(Error(ErrorKind::IOError(ref err), _))
-                if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(true),
+                if err.kind() == io::ErrorKind::UnexpectedEof => {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "truncated record header"))
+            }
             Err(e) => return Err(mapioerr(e)),
         };
 
@@ -81,6 +106,40 @@ impl<R: BufRead> XdrRecordReader<R> {
     pub fn eor(&self) -> bool {
         self.eor
     }
+
+    /// Iterate over the records in the stream, decoding each as a `T`.
+    ///
+    /// Each record is reassembled from its fragments and must contain exactly one encoded `T`.
+    /// Unlike `XdrRecordReaderIter`, a truncated record is an error rather than the end of the
+    /// stream. The iterator ends after the first error.
+    pub fn messages<T: Unpack<io::Cursor<Vec<u8>>>>(self) -> XdrMessages<R, T> {
+        XdrMessages {
+            reader: Some(self),
+            _marker: PhantomData,
+        }
+    }
+
+    // Read the rest of the current record into `buf`.
+    fn read_record(&mut self, buf: &mut Vec<u8>) -> io::Result<RecordEnd> {
+        loop {
+            // Do we need next fragment?
+            if self.totremains() == 0 && self.nextrec()? {
+                return Ok(if buf.is_empty() { RecordEnd::Eof } else { RecordEnd::Unterminated });
+            }
+
+            let remains = self.totremains();
+            let eor = self.eor();
+
+            let sz = self.by_ref().take(remains as u64).read_to_end(buf)?;
+            if sz < remains {
+                return Ok(RecordEnd::Short(remains - sz));
+            }
+
+            if eor {
+                return Ok(RecordEnd::Complete);
+            }
+        }
+    }
 }
 
 impl<R: BufRead> Read for XdrRecordReader<R> {
@@ -135,7 +194,7 @@ impl<R: BufRead> IntoIterator for XdrRecordReader<R> {
 ///
 /// It will return an IO error once, and then end the iterator.
 /// A short read or an unterminated record will also end the iterator. It will not return a partial
-/// record.
+/// record. A truncated fragment header is an IO error.
 #[derive(Debug)]
 pub struct XdrRecordReaderIter<R: BufRead>(Option<XdrRecordReader<R>>);
 
@@ -146,29 +205,10 @@ impl<R: BufRead> Iterator for XdrRecordReaderIter<R> {
         if let Some(mut rr) = self.0.take() {
             let mut buf = Vec::new();
 
-            // loop over fragments until we get a complete record
-            loop {
-                // Do we need next fragment?
-                if rr.totremains() == 0 {
-                    match rr.nextrec() {
-                        Err(e) => return Some(Err(e)),  // IO error
-                        Ok(true) => return None,        // EOF
-                        Ok(false) => (),                // keep going
-                    }
-                }
-
-                let remains = rr.totremains();
-                let eor = rr.eor();
-
-                match rr.by_ref().take(remains as u64).read_to_end(&mut buf) {
-                    Ok(sz) if sz == remains => (),  // OK, keep going
-                    Ok(_) => return None,           // short read
-                    Err(e) => return Some(Err(e)),  // error
-                };
-
-                if eor {
-                    break;
-                }
+            match rr.read_record(&mut buf) {
+                Err(e) => return Some(Err(e)),  // IO error
+                Ok(RecordEnd::Complete) => (),
+                Ok(_) => return None,           // EOF or short read
             }
             self.0 = Some(rr);
             Some(Ok(buf))
@@ -178,6 +218,48 @@ impl<R: BufRead> Iterator for XdrRecordReaderIter<R> {
     }
 }
 
+/// Iterator over the decoded records in a stream, from `XdrRecordReader::messages`.
+#[derive(Debug)]
+pub struct XdrMessages<R: BufRead, T> {
+    reader: Option<XdrRecordReader<R>>,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<R: BufRead, T: Unpack<io::Cursor<Vec<u8>>>> Iterator for XdrMessages<R, T> {
+    type Item = Result<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut rr = self.reader.take()?;
+        let mut buf = Vec::new();
+
+        let msg = match rr.read_record(&mut buf) {
+            Err(e) => return Some(Err(e.into())),
+            Ok(RecordEnd::Complete) => None,
+            Ok(RecordEnd::Eof) => return None,
+            Ok(RecordEnd::Unterminated) => Some("unterminated record".to_string()),
+            Ok(RecordEnd::Short(missing)) => Some(format!("truncated record: {} bytes missing", missing)),
+        };
+        if let Some(msg) = msg {
+            return Some(Err(io::Error::new(io::ErrorKind::UnexpectedEof, msg).into()));
+        }
+
+        let len = buf.len();
+        let mut cur = io::Cursor::new(buf);
+        let res = T::unpack(&mut cur).and_then(|(v, sz)| {
+            if sz < len {
+                Err(format!("record has {} bytes after the message", len - sz).into())
+            } else {
+                Ok(v)
+            }
+        });
+
+        if res.is_ok() {
+            self.reader = Some(rr);
+        }
+        Some(res)
+    }
+}
+
 const WRBUF: usize = 65536;
 
 /// Write records into a bytestream.
@@ -0,0 +1,140 @@
+//! Resumable decoding from input which arrives in pieces
+//!
+//! A `Decode` implementation is fed whatever bytes are currently available, and returns
+//! `Progress::Pending` until it has seen a complete encoding. Unlike `unpack_from_buf`, which
+//! retries from the start each time, it keeps the fields it has already decoded, so only a field
+//! which is split between two pieces of input needs to be buffered.
+//!
+//! xdrgen generates decoders for structs and unions when resumable decoding is enabled. They are
+//! built from the pieces in this module: a `Field` for each member, holding either its decoder
+//! or its decoded value, and `Leaf` for members which are decoded in one go.
+use std::io::{self, Read};
+use std::mem;
+
+use super::{Error, ErrorKind, Result};
+
+/// Result of feeding input to a `Decode`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Progress<T> {
+    /// All the input was consumed without completing the value
+    Pending,
+    /// The value was completed, consuming only its own encoding
+    Complete(T),
+}
+
+/// Incremental decoder for values of type `T`.
+pub trait Decode<T> {
+    /// Consume bytes from the start of `input`, advancing it past them.
+    ///
+    /// Once the value is complete, any remaining bytes are left in `input`, and the decoder is
+    /// reset so that it can be used for the next value.
+    fn feed(&mut self, input: &mut &[u8]) -> Result<Progress<T>>;
+}
+
+/// Types with a generated resumable decoder.
+pub trait Resumable: Sized {
+    type Decoder: Decode<Self> + Default;
+}
+
+/// Input to a `Leaf` decoder's unpack function: bytes buffered from earlier calls, followed by
+/// the new input.
+pub type LeafInput<'a> = io::Chain<&'a [u8], &'a [u8]>;
+
+/// Decoder which buffers input until a value can be unpacked all at once.
+pub struct Leaf<T> {
+    buf: Vec<u8>,
+    unpack: fn(&mut LeafInput<'_>) -> Result<(T, usize)>,
+}
+
+impl<T> Leaf<T> {
+    pub fn new(unpack: fn(&mut LeafInput<'_>) -> Result<(T, usize)>) -> Leaf<T> {
+        Leaf { buf: Vec::new(), unpack }
+    }
+}
+
+impl<T> Decode<T> for Leaf<T> {
+    fn feed(&mut self, input: &mut &[u8]) -> Result<Progress<T>> {
+        let mut chain = Read::chain(&self.buf[..], *input);
+        let res = (self.unpack)(&mut chain);
+        let rest = chain.into_inner().1.len();
+
+        match res {
+            Ok((v, _)) => {
+                *input = &input[input.len() - rest..];
+                self.buf.clear();
+                Ok(Progress::Complete(v))
+            }
+            Err(Error(ErrorKind::IOError(ref e), _)) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                self.buf.extend_from_slice(input);
+                *input = &input[input.len()..];
+                Ok(Progress::Pending)
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// Decoder which converts the values of another decoder, for example into a union variant.
+pub struct Map<D, T, U> {
+    inner: D,
+    map: fn(T) -> U,
+}
+
+impl<D: Decode<T>, T, U> Map<D, T, U> {
+    pub fn new(inner: D, map: fn(T) -> U) -> Map<D, T, U> {
+        Map { inner, map }
+    }
+}
+
+impl<D: Decode<T>, T, U> Decode<U> for Map<D, T, U> {
+    fn feed(&mut self, input: &mut &[u8]) -> Result<Progress<U>> {
+        Ok(match self.inner.feed(input)? {
+            Progress::Pending => Progress::Pending,
+            Progress::Complete(v) => Progress::Complete((self.map)(v)),
+        })
+    }
+}
+
+/// A member of a struct or union being decoded: its decoder until it's complete, then its value.
+pub enum Field<T, D> {
+    Decoding(D),
+    Done(T),
+    Taken,
+}
+
+impl<T, D: Decode<T>> Field<T, D> {
+    pub fn new(decoder: D) -> Field<T, D> {
+        Field::Decoding(decoder)
+    }
+
+    /// Feed input to the member's decoder, returning true once it's complete.
+    pub fn feed(&mut self, input: &mut &[u8]) -> Result<bool> {
+        if let Field::Decoding(decoder) = self {
+            match decoder.feed(input)? {
+                Progress::Pending => return Ok(false),
+                Progress::Complete(v) => *self = Field::Done(v),
+            }
+        }
+        Ok(true)
+    }
+
+    /// The decoded value, if it's complete.
+    pub fn get(&self) -> Option<&T> {
+        match self {
+            Field::Done(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// Take the decoded value.
+    ///
+    /// # Panics
+    ///
+    /// If the value isn't complete, or has already been taken.
+    pub fn take(&mut self) -> T {
+        match mem::replace(self, Field::Taken) {
+            Field::Done(v) => v,
+            _ => panic!("field not decoded"),
+        }
+    }
+}
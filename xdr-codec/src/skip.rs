@@ -0,0 +1,51 @@
+//! Skipping encoded values without decoding them
+//!
+//! xdrgen's `GenOptions::emit_skip` generates a `skip` function for each type, which reads past
+//! a value and returns how many bytes it took, as `Unpack` would, without building the value.
+//! Opaque data and strings are read and discarded rather than collected, so skipping a value
+//! doesn't allocate for them. These are the helpers for variable-length data it uses.
+use std::io;
+
+use super::{ErrorKind, Read, Result, Unpack, padding};
+
+// Read and discard exactly `len` bytes
+fn discard<In: Read>(input: &mut In, len: usize) -> Result<usize> {
+    let sz = io::copy(&mut input.by_ref().take(len as u64), &mut io::sink())?;
+
+    if sz < len as u64 {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "short opaque data").into());
+    }
+    Ok(len)
+}
+
+/// Skip `len` bytes of opaque data and their padding.
+pub fn skip_opaque_array<In: Read>(input: &mut In, len: usize) -> Result<usize> {
+    Ok(discard(input, len)? + discard(input, padding(len).len())?)
+}
+
+/// Skip variable-length opaque data or a string of at most `maxsz` bytes.
+pub fn skip_opaque_flex<In: Read>(input: &mut In, maxsz: Option<usize>) -> Result<usize> {
+    let (len, sz): (usize, _) = Unpack::unpack(input)?;
+
+    if maxsz.is_some_and(|m| len > m) {
+        bail!(ErrorKind::InvalidLen(maxsz.unwrap()));
+    }
+    Ok(sz + skip_opaque_array(input, len)?)
+}
+
+/// Skip a variable-length array of at most `maxsz` elements, skipping each with `skip`.
+pub fn skip_flex<In, F>(input: &mut In, maxsz: Option<usize>, mut skip: F) -> Result<usize>
+where
+    In: Read,
+    F: FnMut(&mut In) -> Result<usize>,
+{
+    let (len, mut sz): (usize, _) = Unpack::unpack(input)?;
+
+    if maxsz.is_some_and(|m| len > m) {
+        bail!(ErrorKind::InvalidLen(maxsz.unwrap()));
+    }
+    for _ in 0..len {
+        sz += skip(input)?;
+    }
+    Ok(sz)
+}
@@ -1,10 +1,11 @@
 // Don't rustfmt in here to avoid trashing vec![] formatting
 #![cfg_attr(rustfmt, rustfmt_skip)]
 
-use std::io::Cursor;
+use std::io::{Cursor, Write};
 use super::{Error, ErrorKind, Pack, Unpack, Opaque,
             pack_flex, pack_opaque_flex, pack_string, pack_array, pack_opaque_array,
-            unpack_array, unpack_opaque_array, unpack_string, unpack_flex, unpack_opaque_flex};
+            unpack_array, unpack_opaque_array, unpack_string, unpack_flex, unpack_opaque_flex,
+            unpack_fixed, unpack_opaque_fixed, content_hash, Fnv64};
 
 
 #[cfg(feature = "bytecodec")]
@@ -615,6 +616,72 @@ fn basic_array() {
     }
 }
 
+#[test]
+fn float_array() {
+    {
+        let mut out = Cursor::new(Vec::new());
+        let a = [1.0f32, -2.5, 0.0, 3.5];
+
+        assert_eq!(pack_array(&a, a.len(), &mut out, None).unwrap(), 4*4);
+
+        let v = out.into_inner();
+
+        assert_eq!(v, vec![0x3f, 0x80, 0x00, 0x00,
+                           0xc0, 0x20, 0x00, 0x00,
+                           0x00, 0x00, 0x00, 0x00,
+                           0x40, 0x60, 0x00, 0x00]);
+
+        let mut input = Cursor::new(v);
+        let mut b = [0f32; 4];
+        let bsz = unpack_array(&mut input, &mut b[..], 4, None).expect("unpack_array");
+        assert_eq!(bsz, 4*4);
+        assert_eq!(a, b);
+    }
+
+    {
+        let mut out = Cursor::new(Vec::new());
+        let a = vec![1.0f64, -2.5];
+
+        assert_eq!(pack_flex(&a, None, &mut out).unwrap(), 4 + 2*8);
+
+        let v = out.into_inner();
+
+        assert_eq!(v, vec![0x00, 0x00, 0x00, 0x02,
+                           0x3f, 0xf0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                           0xc0, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]);
+
+        let mut input = Cursor::new(v);
+        let (b, bsz): (Vec<f64>, _) = unpack_flex(&mut input, None).expect("unpack_flex");
+        assert_eq!(bsz, 4 + 2*8);
+        assert_eq!(a, b);
+    }
+}
+
+#[test]
+fn fixed_array() {
+    let data = vec![0x00, 0x00, 0x00, 0x01,  0x00, 0x00, 0x00, 0x02,  0x00, 0x00, 0x00, 0x03];
+
+    let (v, sz): ([u32; 3], _) = unpack_fixed(&mut Cursor::new(data.clone())).unwrap();
+    assert_eq!(v, [1, 2, 3]);
+    assert_eq!(sz, 12);
+
+    let (v, sz): ([String; 0], _) = unpack_fixed(&mut Cursor::new(data.clone())).unwrap();
+    assert_eq!(v.len(), 0);
+    assert_eq!(sz, 0);
+
+    let res: super::Result<([u32; 4], _)> = unpack_fixed(&mut Cursor::new(data.clone()));
+    assert!(res.is_err());
+
+    // Elements already unpacked are dropped when a later one fails
+    let strs = vec![0x00, 0x00, 0x00, 0x01,  0x61, 0x00, 0x00, 0x00,  0x00, 0x00, 0x00, 0x05,  0x62];
+    let res: super::Result<([String; 2], _)> = unpack_fixed(&mut Cursor::new(strs));
+    assert!(res.is_err());
+
+    let (v, sz) = unpack_opaque_fixed::<_, 5>(&mut Cursor::new(data)).unwrap();
+    assert_eq!(v, [0, 0, 0, 1, 0]);
+    assert_eq!(sz, 8);
+}
+
 #[test]
 fn basic_opaque_array() {
     {
@@ -737,3 +804,626 @@ fn basic_option() {
         res => panic!("bad result {:?}", res),
     }
 }
+
+#[test]
+fn short_opaque() {
+    fn is_eof<T: ::std::fmt::Debug>(res: super::Result<T>) -> bool {
+        match res {
+            Err(Error(ErrorKind::IOError(ref e), _)) => e.kind() == ::std::io::ErrorKind::UnexpectedEof,
+            res => panic!("bad result {:?}", res),
+        }
+    }
+
+    // length 5, only 3 bytes of data
+    let short = vec![0x00, 0x00, 0x00, 0x05,  0x11, 0x22, 0x33];
+
+    assert!(is_eof(unpack_opaque_flex(&mut Cursor::new(short.clone()), None)));
+    assert!(is_eof(unpack_string(&mut Cursor::new(short.clone()), None)));
+    assert!(is_eof(Opaque::unpack(&mut Cursor::new(short))));
+
+    let mut b = [0u8; 4];
+    assert!(is_eof(unpack_opaque_array(&mut Cursor::new(vec![0x11, 0x22]), &mut b[..], 4)));
+}
+
+#[test]
+fn fnv_content_hash() {
+    // Reference values for FNV-1a 64
+    assert_eq!(content_hash(&()).unwrap(), 0xcbf29ce484222325);
+
+    let mut h = Fnv64::new();
+    h.write_all(&[0, 0, 0, 1, 0x61, 0, 0, 0]).unwrap();
+    assert_eq!(content_hash(&Opaque::borrowed(b"a")).unwrap(), h.finish());
+
+    let mut h = Fnv64::new();
+    h.write_all(b"a").unwrap();
+    assert_eq!(h.finish(), 0xaf63dc4c8601ec8c);
+
+    assert_ne!(content_hash(&1u32).unwrap(), content_hash(&2u32).unwrap());
+}
+
+#[test]
+fn limit_read() {
+    use super::LimitRead;
+
+    let data = vec![0x00, 0x00, 0x00, 0x01,  0x00, 0x00, 0x00, 0x02];
+
+    let mut input = LimitRead::new(Cursor::new(data.clone()), 8);
+    assert_eq!(u32::unpack(&mut input).unwrap(), (1, 4));
+    assert_eq!(u32::unpack(&mut input).unwrap(), (2, 4));
+    assert!(!input.exceeded());
+
+    let mut input = LimitRead::new(Cursor::new(data), 6);
+    assert_eq!(u32::unpack(&mut input).unwrap(), (1, 4));
+    assert!(u32::unpack(&mut input).is_err());
+    assert!(input.exceeded());
+}
+
+#[cfg(feature = "bytes")]
+#[test]
+fn unpack_buf() {
+    use std::collections::VecDeque;
+    use std::io::IoSlice;
+    use bytes::Buf;
+    use super::{UnpackBuf, unpack_from_buf};
+
+    let mut buf = VecDeque::new();
+
+    // Arrange for the ring buffer to wrap so the data is in two chunks
+    buf.extend(vec![0; 12]);
+    for _ in 0..12 {
+        buf.pop_front();
+    }
+
+    let mut packed = Vec::new();
+    0x11223344u32.pack(&mut packed).unwrap();
+    "hello".pack(&mut packed).unwrap();
+
+    for (i, b) in packed.iter().enumerate() {
+        let res: Option<u32> = unpack_from_buf(&mut buf.clone()).unwrap();
+        assert_eq!(res.is_some(), i >= 4);
+        buf.push_back(*b);
+    }
+    assert_eq!(buf.len(), 16);
+
+    assert_eq!(u32::unpack_from_buf(&mut buf).unwrap(), Some(0x11223344));
+    assert_eq!(buf.len(), 12);
+    assert_eq!(String::unpack_from_buf(&mut buf).unwrap(), Some(String::from("hello")));
+    assert_eq!(buf.len(), 0);
+    assert_eq!(String::unpack_from_buf(&mut buf).unwrap(), None);
+
+    // Incomplete input is not consumed
+    let mut buf: VecDeque<u8> = vec![0x00, 0x00, 0x00, 0x05,  0x11, 0x22].into_iter().collect();
+    assert_eq!(String::unpack_from_buf(&mut buf).unwrap(), None);
+    assert_eq!(buf.len(), 6);
+
+    // Malformed input is an error
+    let mut buf: VecDeque<u8> = vec![0x00, 0x00, 0x00, 0x02].into_iter().collect();
+    assert!(bool::unpack_from_buf(&mut buf).is_err());
+
+    // Values spread over more chunks than are first asked for
+    fn eight(b: &[u8]) -> impl Buf + '_ {
+        let p = |i: usize| &b[i.min(b.len())..(i + 1).min(b.len())];
+        p(0).chain(p(1)).chain(p(2)).chain(p(3)).chain(p(4)).chain(p(5)).chain(p(6)).chain(p(7))
+    }
+    fn chained(b: &[u8]) -> impl Buf + '_ {
+        let e = |i: usize| eight(&b[(i * 8).min(b.len())..]);
+        e(0).chain(e(1)).chain(e(2)).chain(e(3)).chain(e(4)).chain(e(5)).chain(e(6)).chain(e(7)).chain(e(8))
+    }
+    let mut packed = Vec::new();
+    (0..17u32).collect::<Vec<_>>().pack(&mut packed).unwrap();
+    assert_eq!(packed.len(), 72);
+
+    let mut buf = chained(&packed);
+    assert_eq!(buf.chunks_vectored(&mut [IoSlice::new(&[]); 64]), 64);
+    assert_eq!(Vec::<u32>::unpack_from_buf(&mut buf).unwrap(), Some((0..17).collect()));
+    assert_eq!(buf.remaining(), 0);
+
+    let mut buf = chained(&packed[..71]);
+    assert_eq!(Vec::<u32>::unpack_from_buf(&mut buf).unwrap(), None);
+    assert_eq!(buf.remaining(), 71);
+
+    // A buffer which only exposes its first chunk can't leave a complete value waiting
+    struct Chunky<'a>(&'a [u8]);
+    impl<'a> Buf for Chunky<'a> {
+        fn remaining(&self) -> usize { self.0.len() }
+        fn chunk(&self) -> &[u8] { &self.0[..self.0.len().min(4)] }
+        fn advance(&mut self, cnt: usize) { self.0 = &self.0[cnt..] }
+    }
+    assert_eq!(u32::unpack_from_buf(&mut Chunky(&packed)).unwrap(), Some(17));
+    assert!(Vec::<u32>::unpack_from_buf(&mut Chunky(&packed)).is_err());
+}
+
+#[cfg(feature = "tokio-codec")]
+#[test]
+fn record_codec() {
+    use bytes::BytesMut;
+    use super::codec::{Decoder, Encoder, RecordCodec, DEFAULT_MAX_RECORD};
+    use super::record::XdrRecordReader;
+
+    let mut codec = RecordCodec::<Vec<u32>>::new();
+    let mut out = BytesMut::new();
+    codec.encode(vec![1, 2], &mut out).unwrap();
+    codec.encode(vec![], &mut out).unwrap();
+    assert_eq!(&out[..], &[0x80, 0x00, 0x00, 0x0c,  0, 0, 0, 2,  0, 0, 0, 1,  0, 0, 0, 2,
+                           0x80, 0x00, 0x00, 0x04,  0, 0, 0, 0][..]);
+
+    // Same framing as the record reader
+    let msgs: Vec<Vec<u32>> = XdrRecordReader::new(&out[..]).messages().map(|m| m.unwrap()).collect();
+    assert_eq!(msgs, vec![vec![1, 2], vec![]]);
+
+    // Nothing is decoded until the whole record has arrived
+    let mut buf = BytesMut::new();
+    for b in &out[..15] {
+        buf.extend_from_slice(&[*b]);
+        assert_eq!(codec.decode(&mut buf).unwrap(), None);
+    }
+    buf.extend_from_slice(&out[15..]);
+    assert_eq!(codec.decode(&mut buf).unwrap(), Some(vec![1, 2]));
+    assert_eq!(codec.decode(&mut buf).unwrap(), Some(vec![]));
+    assert_eq!(codec.decode(&mut buf).unwrap(), None);
+    assert!(buf.is_empty());
+
+    // Records can be split into fragments
+    let mut buf = BytesMut::from(&[0x00, 0x00, 0x00, 0x06,  0, 0, 0, 1,  0, 0,
+                                   0x80, 0x00, 0x00, 0x02,  0, 7][..]);
+    assert_eq!(codec.decode(&mut buf).unwrap(), Some(vec![7]));
+
+    // Extra data in the record, or a record which is cut off, is an error
+    let mut buf = BytesMut::from(&[0x80, 0x00, 0x00, 0x08,  0, 0, 0, 0,  0, 0, 0, 0][..]);
+    assert!(codec.decode(&mut buf).is_err());
+    let mut buf = BytesMut::from(&[0x80, 0x00, 0x00, 0x08,  0, 0, 0, 0][..]);
+    assert_eq!(codec.decode(&mut buf).unwrap(), None);
+    assert!(codec.decode_eof(&mut buf).is_err());
+
+    // A header announcing a large record doesn't allocate space for all of it
+    let mut codec = RecordCodec::<Vec<u32>>::new().max_record(usize::MAX);
+    let mut buf = BytesMut::from(&[0xff, 0xff, 0xff, 0xff][..]);
+    assert_eq!(codec.decode(&mut buf).unwrap(), None);
+    assert!(buf.capacity() <= 128 << 10, "{}", buf.capacity());
+
+    let mut codec = RecordCodec::<Vec<u32>>::new();
+    let mut buf = BytesMut::from(&[0x80, 0x40, 0x00, 0x01][..]);
+    match codec.decode(&mut buf) {
+        Err(Error(ErrorKind::MessageTooLarge(n), _)) => assert_eq!(n, DEFAULT_MAX_RECORD),
+        other => panic!("unexpected {:?}", other),
+    }
+
+    // Oversized records fail without waiting for their contents in either direction
+    let mut codec = RecordCodec::<Vec<u32>>::new().max_record(8);
+    let mut buf = BytesMut::from(&[0x80, 0x00, 0x01, 0x00][..]);
+    match codec.decode(&mut buf) {
+        Err(Error(ErrorKind::MessageTooLarge(8), _)) => (),
+        other => panic!("unexpected {:?}", other),
+    }
+    let mut out = BytesMut::new();
+    assert!(codec.encode(vec![1, 2], &mut out).is_err());
+    assert!(out.is_empty());
+
+    // Messages too large for one fragment are split, after any already in the buffer
+    let mut codec = RecordCodec::<Vec<u32>>::new().fragment(8);
+    let mut out = BytesMut::new();
+    codec.encode(vec![], &mut out).unwrap();
+    codec.encode(vec![1, 2], &mut out).unwrap();
+    assert_eq!(&out[..], &[0x80, 0x00, 0x00, 0x04,  0, 0, 0, 0,
+                           0x00, 0x00, 0x00, 0x08,  0, 0, 0, 2,  0, 0, 0, 1,
+                           0x80, 0x00, 0x00, 0x04,  0, 0, 0, 2][..]);
+    assert_eq!(codec.decode(&mut out).unwrap(), Some(vec![]));
+    assert_eq!(codec.decode(&mut out).unwrap(), Some(vec![1, 2]));
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn json() {
+    use super::json::{Json, from_str, to_string};
+
+    let text = r#" {"a": [1, -2.5e3, true, null], "b\u00e9": "x\"\n\ud83d\ude00", "c": {}} "#;
+    let v = Json::parse(text).unwrap();
+    assert_eq!(v.field("a").unwrap().as_array().unwrap()[1], Json::Number("-2.5e3".to_string()));
+    assert_eq!(v.field("bé").unwrap().as_str().unwrap(), "x\"\n\u{1f600}");
+    assert_eq!(v.to_string(), "{\"a\":[1,-2.5e3,true,null],\"bé\":\"x\\\"\\n\u{1f600}\",\"c\":{}}");
+    assert_eq!(Json::parse(&v.to_string()).unwrap(), v);
+
+    for bad in &["", "[1,]", "{\"a\" 1}", "01x", "\"\\q\"", "\"abc", "[1] 2", "-", "1.", "nul"] {
+        assert!(Json::parse(bad).is_err(), "{:?}", bad);
+    }
+    assert!(Json::parse(&"[".repeat(1000)).is_err());
+
+    let hex = Json::hex(&[0, 0xab, 0x10]);
+    assert_eq!(hex, Json::String("00ab10".to_string()));
+    assert_eq!(hex.unhex().unwrap(), vec![0, 0xab, 0x10]);
+    assert_eq!(hex.unhex_array::<3>().unwrap(), [0, 0xab, 0x10]);
+    assert!(hex.unhex_array::<4>().is_err());
+    assert!(Json::String("abc".to_string()).unhex().is_err());
+
+    assert_eq!(to_string(&vec![Some(1u64 << 60), None]), "[1152921504606846976,null]");
+    assert_eq!(from_str::<Vec<Option<u64>>>("[1152921504606846976,null]").unwrap(), vec![Some(1 << 60), None]);
+    assert_eq!(from_str::<[i32; 2]>("[1,2]").unwrap(), [1, 2]);
+    assert!(from_str::<[i32; 2]>("[1]").is_err());
+    assert!(from_str::<u32>("-1").is_err());
+    assert!(from_str::<f64>("null").unwrap().is_nan());
+    assert_eq!(to_string(&std::f32::INFINITY), "null");
+}
+
+#[test]
+fn resumable_leaf() {
+    use super::{Decode, Progress};
+    use super::resume::Leaf;
+
+    let mut leaf = Leaf::new(|input| unpack_string(input, Some(8)));
+
+    let mut input = &[0x00, 0x00, 0x00][..];
+    assert_eq!(leaf.feed(&mut input).unwrap(), Progress::Pending);
+    assert!(input.is_empty());
+
+    let mut input = &[0x03, 0x61, 0x62, 0x63][..];
+    assert_eq!(leaf.feed(&mut input).unwrap(), Progress::Pending);
+
+    // Trailing bytes are left for the next value
+    let mut input = &[0x00, 0x11, 0x22][..];
+    assert_eq!(leaf.feed(&mut input).unwrap(), Progress::Complete(String::from("abc")));
+    assert_eq!(input, &[0x11, 0x22]);
+
+    let mut input = &[0x00, 0x00, 0x00, 0x09][..];
+    assert!(leaf.feed(&mut input).is_err());
+}
+
+// Reader and writer which fail every other call with `Interrupted`, as if a signal arrived.
+struct Interrupting<T> {
+    inner: T,
+    interrupt: bool,
+}
+
+impl<T> Interrupting<T> {
+    fn interrupt(&mut self) -> std::io::Result<()> {
+        self.interrupt = !self.interrupt;
+        if self.interrupt {
+            Err(std::io::ErrorKind::Interrupted.into())
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl<T: std::io::Read> std::io::Read for Interrupting<T> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.interrupt()?;
+        self.inner.read(buf)
+    }
+}
+
+impl<T: Write> Write for Interrupting<T> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.interrupt()?;
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[test]
+fn interrupted() {
+    let mut out = Interrupting { inner: Vec::new(), interrupt: false };
+    let sz = 7u32.pack(&mut out).unwrap()
+        + "hello".to_string().pack(&mut out).unwrap()
+        + vec![1i64, -2].pack(&mut out).unwrap()
+        + pack_opaque_flex(&[1, 2, 3], None, &mut out).unwrap();
+    assert_eq!(sz, out.inner.len());
+
+    let mut input = Interrupting { inner: Cursor::new(out.inner), interrupt: false };
+    assert_eq!(Unpack::unpack(&mut input).unwrap(), (7u32, 4));
+    assert_eq!(Unpack::unpack(&mut input).unwrap(), ("hello".to_string(), 12));
+    assert_eq!(Unpack::unpack(&mut input).unwrap(), (vec![1i64, -2], 20));
+    assert_eq!(unpack_opaque_flex(&mut input, None).unwrap(), (vec![1, 2, 3], 8));
+}
+
+#[test]
+fn metrics() {
+    use std::sync::Mutex;
+    use super::metrics::{self, Metrics};
+
+    #[derive(Default)]
+    struct Log(Mutex<Vec<String>>);
+
+    impl Metrics for Log {
+        fn on_pack(&self, ty: &str, bytes: usize) {
+            self.0.lock().unwrap().push(format!("pack {} {}", ty, bytes));
+        }
+        fn on_unpack(&self, ty: &str, bytes: usize) {
+            self.0.lock().unwrap().push(format!("unpack {} {}", ty, bytes));
+        }
+        fn on_unpack_error(&self, ty: &str, _err: &Error) {
+            self.0.lock().unwrap().push(format!("error {}", ty));
+        }
+    }
+
+    static LOG: std::sync::OnceLock<Log> = std::sync::OnceLock::new();
+    let log = LOG.get_or_init(Log::default);
+
+    assert!(metrics::set_metrics(log));
+    assert!(!metrics::set_metrics(log));
+
+    assert_eq!(metrics::packed("foo", Ok(8)).unwrap(), 8);
+    assert_eq!(metrics::unpacked("bar", Ok((1u32, 4))).unwrap(), (1, 4));
+    assert!(metrics::unpacked::<u32>("bar", Err(Error::invalidenum(3))).is_err());
+    // Pack errors have no handler, so use the default
+    assert!(metrics::packed("foo", Err(Error::invalidlen(1))).is_err());
+
+    assert_eq!(*log.0.lock().unwrap(), vec!["pack foo 8", "unpack bar 4", "error bar"]);
+}
+
+#[test]
+fn catch_panic() {
+    use super::catch_panic;
+    use super::panic::caught;
+
+    let input = [0u8, 0, 0, 1];
+    assert_eq!(catch_panic(|| u32::unpack(&mut &input[..])).unwrap(), (1, 4));
+    assert_eq!(caught(|| u32::unpack(&mut &input[..])).unwrap(), (1, 4));
+    assert!(caught(|| u32::unpack(&mut &input[..2])).is_err());
+
+    match caught::<u32, _>(|| panic!("bad index {}", 3)) {
+        Err(Error(ErrorKind::Panicked(ref msg), _)) if msg == "bad index 3" => (),
+        other => panic!("unexpected {:?}", other),
+    }
+    match caught::<u32, _>(|| panic!("static")) {
+        Err(Error(ErrorKind::Panicked(ref msg), _)) if msg == "static" => (),
+        other => panic!("unexpected {:?}", other),
+    }
+}
+
+#[test]
+fn unpack_borrowed() {
+    use super::{UnpackBorrowed, unpack_borrowed, unpack_array_borrowed, unpack_flex_borrowed, unpack_str_borrowed};
+
+    let mut out = Cursor::new(Vec::new());
+    pack_string("hello", Some(10), &mut out).unwrap();
+    pack_opaque_flex(&[1, 2, 3], None, &mut out).unwrap();
+    2u32.pack(&mut out).unwrap();
+    "a".pack(&mut out).unwrap();
+    "bc".pack(&mut out).unwrap();
+    Some(7u32).pack(&mut out).unwrap();
+    let buf = out.into_inner();
+
+    let mut input = &buf[..];
+    let (s, sz) = unpack_str_borrowed(&mut input, Some(10)).unwrap();
+    assert_eq!((s, sz), ("hello", 12));
+    // Borrowed from the buffer, not copied
+    assert_eq!(s.as_ptr(), buf[4..].as_ptr());
+    let (o, sz): (&[u8], _) = UnpackBorrowed::unpack_borrowed(&mut input).unwrap();
+    assert_eq!((o, sz), (&[1u8, 2, 3][..], 8));
+    let (v, sz): (Vec<&str>, _) = unpack_flex_borrowed(&mut input, Some(2)).unwrap();
+    assert_eq!((v, sz), (vec!["a", "bc"], 20));
+    let (n, sz): (Option<u32>, _) = UnpackBorrowed::unpack_borrowed(&mut input).unwrap();
+    assert_eq!((n, sz), (Some(7), 8));
+    assert!(input.is_empty());
+
+    let (a, sz): ([&str; 2], _) = unpack_array_borrowed(&mut &buf[24..]).unwrap();
+    assert_eq!((a, sz), (["a", "bc"], 16));
+    assert_eq!(unpack_borrowed::<&str>(&buf).unwrap(), "hello");
+
+    // Limits, truncation and bad UTF-8 fail as they do for the owned unpackers
+    match unpack_str_borrowed(&mut &buf[..], Some(4)) {
+        Err(Error(ErrorKind::InvalidLen(4), _)) => (),
+        other => panic!("unexpected {:?}", other),
+    }
+    assert!(unpack_str_borrowed(&mut &buf[..10], None).is_err());
+    let bad = [0u8, 0, 0, 1, 0xff, 0, 0, 0];
+    match unpack_str_borrowed(&mut &bad[..], None) {
+        Err(Error(ErrorKind::InvalidUtf8(_), _)) => (),
+        other => panic!("unexpected {:?}", other),
+    }
+}
+
+#[test]
+fn wide_lengths() {
+    use super::wide;
+
+    let mut out = Cursor::new(Vec::new());
+    assert_eq!(wide::pack_string("abcde", Some(5), &mut out).unwrap(), 16);
+    assert_eq!(wide::pack_flex(&[1u32, 2], None, &mut out).unwrap(), 16);
+    assert_eq!(wide::pack_opaque_flex(&[9], None, &mut out).unwrap(), 12);
+    assert!(wide::pack_string("abcdef", Some(5), &mut out).is_err());
+    let buf = out.into_inner();
+    assert_eq!(&buf[..13], &[0, 0, 0, 0, 0, 0, 0, 5, b'a', b'b', b'c', b'd', b'e']);
+
+    let mut input = Cursor::new(&buf);
+    assert_eq!(wide::unpack_string(&mut input, Some(5)).unwrap(), ("abcde".to_string(), 16));
+    assert_eq!(wide::unpack_flex::<_, u32>(&mut input, Some(2)).unwrap(), (vec![1, 2], 16));
+    assert_eq!(wide::unpack_opaque_flex(&mut input, None).unwrap(), (vec![9], 12));
+
+    let mut input = &buf[16..];
+    let (v, sz): (Vec<u32>, _) = wide::unpack_flex_borrowed(&mut input, None).unwrap();
+    assert_eq!((v, sz), (vec![1, 2], 16));
+    assert_eq!(wide::unpack_opaque_borrowed(&mut input, None).unwrap(), (&[9u8][..], 12));
+    assert_eq!(wide::unpack_str_borrowed(&mut &buf[..], None).unwrap(), ("abcde", 16));
+
+    // Bounds are checked on the full 64-bit length
+    match wide::unpack_string(&mut Cursor::new(&buf), Some(4)) {
+        Err(Error(ErrorKind::InvalidLen(4), _)) => (),
+        other => panic!("unexpected {:?}", other),
+    }
+    let huge = [0, 0, 1, 0, 0, 0, 0, 5, 0, 0, 0, 0];
+    match wide::unpack_opaque_flex(&mut Cursor::new(&huge[..]), Some(5)) {
+        Err(Error(ErrorKind::InvalidLen(5), _)) => (),
+        other => panic!("unexpected {:?}", other),
+    }
+    assert!(wide::unpack_opaque_flex(&mut Cursor::new(&huge[..]), None).is_err());
+
+    // A length which overflows once padded is an error rather than a panic
+    let max = [0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0, 0, 0, 0];
+    assert!(wide::unpack_opaque_borrowed(&mut &max[..], None).is_err());
+    assert!(wide::unpack_str_borrowed(&mut &max[..], None).is_err());
+    assert!(wide::unpack_opaque_flex(&mut Cursor::new(&max[..]), None).is_err());
+    assert!(wide::unpack_string(&mut Cursor::new(&max[..]), None).is_err());
+}
+
+#[test]
+fn debug_hex() {
+    struct Hex<'a>(&'a [u8]);
+    impl<'a> ::std::fmt::Debug for Hex<'a> {
+        fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+            super::debug_hex(self.0, f)
+        }
+    }
+    assert_eq!(format!("{:?}", Hex(&[0x0a, 0xff, 0])), "0aff00");
+    assert_eq!(format!("{:?}", Hex(&[])), "");
+}
+
+#[test]
+fn latin1() {
+    assert_eq!(super::latin1::decode(b"caf\xe9 \xff"), "caf\u{e9} \u{ff}");
+    assert_eq!(super::latin1::encode("caf\u{e9}").unwrap(), b"caf\xe9");
+    match super::latin1::encode("\u{263a}") {
+        Err(Error(ErrorKind::NotLatin1('\u{263a}'), _)) => (),
+        other => panic!("unexpected {:?}", other),
+    }
+}
+
+#[test]
+fn skip() {
+    use super::{skip_flex, skip_opaque_array, skip_opaque_flex};
+
+    // opaque<> "abcde" with padding, then 7
+    let buf = [0, 0, 0, 5, b'a', b'b', b'c', b'd', b'e', 0, 0, 0, 0, 0, 0, 7];
+    let mut input = Cursor::new(&buf[..]);
+    assert_eq!(skip_opaque_flex(&mut input, None).unwrap(), 12);
+    assert_eq!(input.position(), 12);
+    assert_eq!(i32::unpack(&mut input).unwrap(), (7, 4));
+
+    let mut input = Cursor::new(&buf[..]);
+    match skip_opaque_flex(&mut input, Some(4)) {
+        Err(Error(ErrorKind::InvalidLen(4), _)) => (),
+        other => panic!("unexpected {:?}", other),
+    }
+
+    let mut input = Cursor::new(&buf[4..]);
+    assert_eq!(skip_opaque_array(&mut input, 5).unwrap(), 8);
+    match skip_opaque_array(&mut input, 9) {
+        Err(Error(ErrorKind::IOError(_), _)) => (),
+        other => panic!("unexpected {:?}", other),
+    }
+
+    // int<> { 1, 2 }
+    let buf = [0, 0, 0, 2, 0, 0, 0, 1, 0, 0, 0, 2];
+    let mut input = Cursor::new(&buf[..]);
+    let mut seen = 0;
+    let sz = skip_flex(&mut input, Some(2), |input| {
+        seen += 1;
+        i32::unpack(input).map(|(_, sz)| sz)
+    })
+    .unwrap();
+    assert_eq!((sz, seen), (12, 2));
+}
+
+#[test]
+fn crc() {
+    use super::{Crc, CrcWriter, pack_with_crc};
+
+    // The standard check values
+    assert_eq!(Crc::Crc32.checksum(b"123456789"), 0xcbf4_3926);
+    assert_eq!(Crc::Crc32c.checksum(b"123456789"), 0xe306_9283);
+    assert_eq!(Crc::Crc32.checksum(b""), 0);
+
+    let mut w = CrcWriter::new(Crc::Crc32, Vec::new());
+    w.write_all(b"1234").unwrap();
+    w.write_all(b"56789").unwrap();
+    assert_eq!(w.checksum(), 0xcbf4_3926);
+    assert_eq!(w.into_inner(), b"123456789");
+
+    let mut out = Vec::new();
+    let (sz, crc) = pack_with_crc(&Opaque::borrowed(b"abc"), Crc::Crc32c, &mut out).unwrap();
+    assert_eq!(sz, 8);
+    assert_eq!(crc, Crc::Crc32c.checksum(&out));
+}
+
+#[test]
+fn header() {
+    use super::header::{HEADER_SIZE, pack_header, unpack_header};
+
+    let mut buf = Vec::new();
+    assert_eq!(pack_header(0x0123_4567_89ab_cdef, &mut buf).unwrap(), HEADER_SIZE);
+    assert_eq!(&buf[..8], b"XDRh\0\0\0\x01");
+    assert_eq!(unpack_header(0x0123_4567_89ab_cdef, &mut Cursor::new(&buf)).unwrap(), HEADER_SIZE);
+
+    match unpack_header(1, &mut Cursor::new(&buf)) {
+        Err(Error(ErrorKind::SpecMismatch(1, 0x0123_4567_89ab_cdef), _)) => (),
+        other => panic!("unexpected {:?}", other),
+    }
+
+    let mut swapped = buf.clone();
+    swapped[..4].reverse();
+    match unpack_header(0x0123_4567_89ab_cdef, &mut Cursor::new(&swapped)) {
+        Err(Error(ErrorKind::InvalidHeader(ref v), _)) if v.contains("byte-swapped") => (),
+        other => panic!("unexpected {:?}", other),
+    }
+
+    let mut version = buf.clone();
+    version[7] = 2;
+    match unpack_header(0x0123_4567_89ab_cdef, &mut Cursor::new(&version)) {
+        Err(Error(ErrorKind::InvalidHeader(ref v), _)) if v == "unsupported version 2" => (),
+        other => panic!("unexpected {:?}", other),
+    }
+
+    match unpack_header(0, &mut Cursor::new(&[0u8; 16])) {
+        Err(Error(ErrorKind::InvalidHeader(ref v), _)) if v == "magic number 0x00000000" => (),
+        other => panic!("unexpected {:?}", other),
+    }
+}
+
+#[test]
+fn flex_iter() {
+    use super::pack_flex_iter;
+
+    let vals = vec![0x11u32, 0x22, 0x33];
+    let mut out = Cursor::new(Vec::new());
+    assert_eq!(pack_flex_iter(3, (1..4).map(|v| v * 0x11u32), Some(4), &mut out).unwrap(), 16);
+    let mut expected = Cursor::new(Vec::new());
+    pack_flex(&vals, None, &mut expected).unwrap();
+    assert_eq!(out.get_ref(), expected.get_ref());
+
+    let mut input = Cursor::new(out.into_inner());
+    assert_eq!(unpack_flex(&mut input, None).unwrap(), (vals.clone(), 16));
+
+    // Borrowed elements
+    let mut out = Cursor::new(Vec::new());
+    pack_flex_iter::<_, u32, _>(vals.len(), &vals, None, &mut out).unwrap();
+    assert_eq!(out.get_ref(), expected.get_ref());
+
+    for (len, maxsz, err) in [(3, Some(2), 2), (2, None, 2), (4, None, 4)] {
+        let mut out = Cursor::new(Vec::new());
+        match pack_flex_iter::<_, u32, _>(len, vals.iter(), maxsz, &mut out) {
+            Err(Error(ErrorKind::InvalidLen(l), _)) if l == err => (),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+}
+
+#[test]
+fn canonical() {
+    use super::{unpack_opaque_fixed_canonical, unpack_opaque_flex_canonical, unpack_string_canonical, wide};
+
+    let good = [0, 0, 0, 3, b'a', b'b', b'c', 0];
+    let bad = [0, 0, 0, 3, b'a', b'b', b'c', 1];
+    assert_eq!(unpack_opaque_flex_canonical(&mut Cursor::new(&good), None).unwrap(), (b"abc".to_vec(), 8));
+    assert_eq!(unpack_string_canonical(&mut Cursor::new(&good), Some(3)).unwrap(), ("abc".to_string(), 8));
+    // Lenient without the suffix
+    assert_eq!(unpack_opaque_flex(&mut Cursor::new(&bad), None).unwrap(), (b"abc".to_vec(), 8));
+    match unpack_opaque_flex_canonical(&mut Cursor::new(&bad), None) {
+        Err(Error(ErrorKind::NonCanonical(ref v), _)) if v == "padding [01] isn't zero" => (),
+        other => panic!("unexpected {:?}", other),
+    }
+    match unpack_string_canonical(&mut Cursor::new(&good), Some(2)) {
+        Err(Error(ErrorKind::InvalidLen(2), _)) => (),
+        other => panic!("unexpected {:?}", other),
+    }
+    assert!(unpack_opaque_flex_canonical(&mut Cursor::new(&good[..7]), None).is_err());
+
+    assert_eq!(unpack_opaque_fixed_canonical::<_, 3>(&mut Cursor::new(&good[4..])).unwrap(), (*b"abc", 4));
+    assert!(unpack_opaque_fixed_canonical::<_, 3>(&mut Cursor::new(&bad[4..])).is_err());
+
+    let wide_bad = [0, 0, 0, 0, 0, 0, 0, 1, b'a', 0, 2, 0];
+    assert!(wide::unpack_opaque_flex(&mut Cursor::new(&wide_bad), None).is_ok());
+    assert!(wide::unpack_string_canonical(&mut Cursor::new(&wide_bad), None).is_err());
+}
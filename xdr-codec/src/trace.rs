@@ -0,0 +1,40 @@
+//! Recording a trace of decoding, for debugging interoperability problems.
+use std::fmt::Debug;
+
+use super::{Read, Result, Unpack};
+
+/// Receiver for a decode trace.
+///
+/// `field` is called as each field is decoded, with its name, the byte offset at which it started,
+/// and the decoded value. Fields of nested structs and unions are bracketed by `enter` and
+/// `leave`. Comparing the resulting log against a peer implementation's encoder usually pinpoints
+/// where the two disagree.
+///
+/// This is implemented for closures taking the same arguments as `field`.
+pub trait Trace {
+    fn field(&mut self, name: &str, offset: usize, value: &dyn Debug);
+
+    /// Start of a nested struct or union at `offset`.
+    fn enter(&mut self, _name: &str, _offset: usize) {}
+
+    /// End of the most recently entered struct or union.
+    fn leave(&mut self) {}
+}
+
+impl<F> Trace for F
+where
+    F: FnMut(&str, usize, &dyn Debug),
+{
+    fn field(&mut self, name: &str, offset: usize, value: &dyn Debug) {
+        self(name, offset, value)
+    }
+}
+
+/// Unpack while reporting each field to a `Trace`.
+///
+/// xdrgen generates implementations of this for structs and unions when tracing is enabled.
+/// `offset` is the position of the value within the overall input, so that nested values report
+/// absolute offsets.
+pub trait UnpackTraced<In: Read>: Unpack<In> {
+    fn unpack_traced(input: &mut In, offset: usize, trace: &mut dyn Trace) -> Result<(Self, usize)>;
+}
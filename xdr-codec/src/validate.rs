@@ -0,0 +1,60 @@
+//! Checking values against their specification before packing.
+use super::Result;
+
+/// Check that a value satisfies all the constraints of its XDR specification.
+///
+/// xdrgen can generate implementations of this which check the bounds on strings, opaque data
+/// and variable-length arrays, recursively through all contained values. This allows a message
+/// which was built programmatically to be checked before it's sent, rather than having `pack` fail
+/// part way through.
+pub trait Validate {
+    fn validate(&self) -> Result<()>;
+}
+
+macro_rules! always_valid {
+    ($($t:ty),*) => {
+        $(
+            impl Validate for $t {
+                #[inline]
+                fn validate(&self) -> Result<()> {
+                    Ok(())
+                }
+            }
+        )*
+    }
+}
+
+always_valid!(u8, i8, u32, i32, u64, i64, f32, f64, bool, (), usize, str, String);
+
+impl<T: Validate> Validate for [T] {
+    fn validate(&self) -> Result<()> {
+        self.iter().try_for_each(Validate::validate)
+    }
+}
+
+impl<T: Validate, const N: usize> Validate for [T; N] {
+    fn validate(&self) -> Result<()> {
+        self[..].validate()
+    }
+}
+
+impl<T: Validate> Validate for Vec<T> {
+    fn validate(&self) -> Result<()> {
+        self[..].validate()
+    }
+}
+
+impl<T: Validate> Validate for Option<T> {
+    fn validate(&self) -> Result<()> {
+        match self {
+            Some(v) => v.validate(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<T: Validate + ?Sized> Validate for Box<T> {
+    fn validate(&self) -> Result<()> {
+        (**self).validate()
+    }
+}
@@ -0,0 +1,177 @@
+//! Variable-length data with 64-bit length prefixes
+//!
+//! RFC4506 encodes the length of variable-length arrays, opaque data and strings as an
+//! `unsigned int`, limiting them to 4GiB. Some protocols extend XDR with `unsigned hyper`
+//! lengths to carry larger blobs. These helpers are the same as the ones of the same names at the
+//! top level of the crate, but with 64-bit length prefixes. xdrgen uses them for
+//! `GenOptions::length_width(LenWidth::U64)`.
+//!
+//! Lengths are checked against `maxsz` before anything is allocated, and data is read as it
+//! arrives rather than into a buffer sized from the length, so a corrupt length can't cause a
+//! large allocation by itself.
+use std::convert::TryFrom;
+use std::str;
+
+use byteorder::ReadBytesExt;
+
+use super::{Error, ErrorKind, Pack, Read, Result, Unpack, UnpackBorrowed, Write, borrow, canonical, padding, read_bytes,
+            skip};
+
+fn pack_len<Out: Write>(len: usize, maxsz: Option<usize>, out: &mut Out) -> Result<usize> {
+    if maxsz.is_some_and(|m| len > m) {
+        bail!(ErrorKind::InvalidLen(maxsz.unwrap()));
+    }
+    (len as u64).pack(out)
+}
+
+// The length prefix, checked against `maxsz` and the address space
+fn unpack_len<In: Read>(input: &mut In, maxsz: Option<usize>) -> Result<(usize, usize)> {
+    let (len, sz): (u64, _) = Unpack::unpack(input)?;
+
+    if maxsz.is_some_and(|m| len > m as u64) {
+        bail!(ErrorKind::InvalidLen(maxsz.unwrap()));
+    }
+    match usize::try_from(len) {
+        Ok(len) => Ok((len, sz)),
+        Err(_) => bail!(ErrorKind::InvalidLen(usize::MAX)),
+    }
+}
+
+/// Pack a dynamically sized array with a 64-bit length, with size limit check.
+pub fn pack_flex<Out: Write, T: Pack<Out>>(val: &[T], maxsz: Option<usize>, out: &mut Out) -> Result<usize> {
+    let mut sz = pack_len(val.len(), maxsz, out)?;
+    for it in val {
+        sz += it.pack(out)?;
+    }
+    Ok(sz)
+}
+
+/// Pack a dynamically sized opaque array with a 64-bit length, with size limit check.
+pub fn pack_opaque_flex<Out: Write>(val: &[u8], maxsz: Option<usize>, out: &mut Out) -> Result<usize> {
+    let mut sz = pack_len(val.len(), maxsz, out)?;
+
+    out.write_all(val)?;
+    sz += val.len();
+
+    let p = padding(sz);
+    if !p.is_empty() {
+        out.write_all(p)?;
+        sz += p.len();
+    }
+
+    Ok(sz)
+}
+
+/// Pack a string with a 64-bit length, with size limit check.
+pub fn pack_string<Out: Write>(val: &str, maxsz: Option<usize>, out: &mut Out) -> Result<usize> {
+    pack_opaque_flex(val.as_bytes(), maxsz, out)
+}
+
+/// Unpack a (perhaps) length-limited array with a 64-bit length.
+pub fn unpack_flex<In: Read, T: Unpack<In>>(input: &mut In, maxsz: Option<usize>) -> Result<(Vec<T>, usize)> {
+    let (elems, mut sz) = unpack_len(input, maxsz)?;
+    let mut out = vec![];
+
+    for _ in 0..elems {
+        let (e, esz) = Unpack::unpack(input)?;
+        out.push(e);
+        sz += esz;
+    }
+
+    Ok((out, sz))
+}
+
+/// Unpack a (perhaps) length-limited opaque array with a 64-bit length.
+pub fn unpack_opaque_flex<In: Read>(input: &mut In, maxsz: Option<usize>) -> Result<(Vec<u8>, usize)> {
+    let (len, mut sz) = unpack_len(input, maxsz)?;
+    let mut out = vec![];
+
+    sz += read_bytes(input, len, &mut out)?;
+
+    let p = padding(sz);
+    for _ in 0..p.len() {
+        let _ = input.read_u8()?;
+    }
+    sz += p.len();
+
+    Ok((out, sz))
+}
+
+/// Unpack a (perhaps) length-limited string with a 64-bit length.
+pub fn unpack_string<In: Read>(input: &mut In, maxsz: Option<usize>) -> Result<(String, usize)> {
+    let (v, sz) = unpack_opaque_flex(input, maxsz)?;
+
+    String::from_utf8(v).map_err(Error::from).map(|s| (s, sz))
+}
+
+/// Unpack a (perhaps) length-limited opaque array with a 64-bit length, which has to have zero
+/// padding.
+pub fn unpack_opaque_flex_canonical<In: Read>(input: &mut In, maxsz: Option<usize>) -> Result<(Vec<u8>, usize)> {
+    let (len, sz) = unpack_len(input, maxsz)?;
+    let (v, osz) = canonical::read_opaque(input, len)?;
+
+    Ok((v, sz + osz))
+}
+
+/// Unpack a (perhaps) length-limited string with a 64-bit length, which has to have zero padding.
+pub fn unpack_string_canonical<In: Read>(input: &mut In, maxsz: Option<usize>) -> Result<(String, usize)> {
+    let (v, sz) = unpack_opaque_flex_canonical(input, maxsz)?;
+
+    String::from_utf8(v).map_err(Error::from).map(|s| (s, sz))
+}
+
+/// Unpack opaque data with a 64-bit length, borrowing it from `input`.
+pub fn unpack_opaque_borrowed<'a>(input: &mut &'a [u8], maxsz: Option<usize>) -> Result<(&'a [u8], usize)> {
+    let (len, sz) = unpack_len(input, maxsz)?;
+    let data = borrow::take(input, len)?;
+
+    Ok((data, sz + len + padding(len).len()))
+}
+
+/// Unpack a string with a 64-bit length, borrowing it from `input`.
+pub fn unpack_str_borrowed<'a>(input: &mut &'a [u8], maxsz: Option<usize>) -> Result<(&'a str, usize)> {
+    let (v, sz) = unpack_opaque_borrowed(input, maxsz)?;
+
+    match str::from_utf8(v) {
+        Ok(s) => Ok((s, sz)),
+        Err(_) => Err(Error::from(String::from_utf8(v.to_vec()).unwrap_err())),
+    }
+}
+
+/// Unpack an array with a 64-bit length, with the elements borrowing from `input`.
+pub fn unpack_flex_borrowed<'a, T: UnpackBorrowed<'a>>(
+    input: &mut &'a [u8],
+    maxsz: Option<usize>,
+) -> Result<(Vec<T>, usize)> {
+    let (elems, mut sz) = unpack_len(input, maxsz)?;
+    let mut out = Vec::with_capacity(elems.min(input.len() / 4));
+
+    for _ in 0..elems {
+        let (e, esz) = T::unpack_borrowed(input)?;
+        out.push(e);
+        sz += esz;
+    }
+
+    Ok((out, sz))
+}
+
+/// Skip variable-length opaque data or a string with a 64-bit length.
+pub fn skip_opaque_flex<In: Read>(input: &mut In, maxsz: Option<usize>) -> Result<usize> {
+    let (len, sz) = unpack_len(input, maxsz)?;
+
+    Ok(sz + skip::skip_opaque_array(input, len)?)
+}
+
+/// Skip a variable-length array with a 64-bit length, skipping each element with `skip`.
+pub fn skip_flex<In, F>(input: &mut In, maxsz: Option<usize>, mut skip: F) -> Result<usize>
+where
+    In: Read,
+    F: FnMut(&mut In) -> Result<usize>,
+{
+    let (len, mut sz) = unpack_len(input, maxsz)?;
+
+    for _ in 0..len {
+        sz += skip(input)?;
+    }
+    Ok(sz)
+}
@@ -174,3 +174,66 @@ fn largerec_flush() {
         ]
     )
 }
+
+#[test]
+fn messages() {
+    // "hi" split across two fragments, then "abcd"
+    let inbuf = vec![0, 0, 0, 4,  0, 0, 0, 2,
+                     128, 0, 0, 4,  104, 105, 0, 0,
+                     128, 0, 0, 8,  0, 0, 0, 4,  97, 98, 99, 100];
+    let recread = XdrRecordReader::new(Cursor::new(inbuf));
+    let got: Vec<String> = recread.messages().map(|r| r.expect("decode error")).collect();
+    assert_eq!(got, vec!["hi".to_string(), "abcd".to_string()]);
+}
+
+#[test]
+fn messages_malformed() {
+    fn decode(inbuf: Vec<u8>) -> Vec<Result<u32, String>> {
+        XdrRecordReader::new(Cursor::new(inbuf))
+            .messages()
+            .map(|r| r.map_err(|e| e.to_string()))
+            .collect()
+    }
+
+    // truncated header
+    assert_eq!(decode(vec![128, 0, 0, 4,  0, 0, 0, 1,  128, 0]),
+               vec![Ok(1), Err("truncated record header".to_string())]);
+    // truncated fragment
+    assert_eq!(decode(vec![128, 0, 0, 8,  0, 0, 0, 1,  0]),
+               vec![Err("truncated record: 3 bytes missing".to_string())]);
+    // no end of record
+    assert_eq!(decode(vec![0, 0, 0, 4,  0, 0, 0, 1]),
+               vec![Err("unterminated record".to_string())]);
+    // bad message ends the iterator
+    assert_eq!(decode(vec![128, 0, 0, 8,  0, 0, 0, 1,  0, 0, 0, 2,  128, 0, 0, 4,  0, 0, 0, 3]),
+               vec![Err("record has 4 bytes after the message".to_string())]);
+    assert_eq!(decode(vec![128, 0, 0, 2,  0, 0,  128, 0, 0, 4,  0, 0, 0, 3]).len(), 1);
+
+    // the raw record iterator also reports truncated headers
+    let recs: Vec<_> = XdrRecordReader::new(Cursor::new(vec![128, 0, 0, 1,  99,  0, 0])).into_iter().collect();
+    assert_eq!(recs.len(), 2);
+    assert_eq!(recs[0].as_ref().unwrap(), &vec![99]);
+    assert!(recs[1].is_err());
+}
+
+// Reader which fails every other call with `Interrupted`, as if a signal arrived.
+struct Interrupting<R>(R, bool);
+
+impl<R: Read> Read for Interrupting<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.1 = !self.1;
+        if self.1 {
+            return Err(std::io::ErrorKind::Interrupted.into());
+        }
+        self.0.read(buf)
+    }
+}
+
+#[test]
+fn recread_interrupted() {
+    let inbuf = vec![0, 0, 0, 2, 1, 2, 128, 0, 0, 1, 3, 128, 0, 0, 0];
+    let input = std::io::BufReader::with_capacity(2, Interrupting(Cursor::new(inbuf), false));
+
+    let recs: Vec<_> = XdrRecordReader::new(input).into_iter().map(|r| r.unwrap()).collect();
+    assert_eq!(recs, vec![vec![1, 2, 3], vec![]]);
+}
@@ -0,0 +1,337 @@
+//! Derive xdr-codec's `Pack` and `Unpack` for hand-written Rust types.
+//!
+//! This is an alternative to generating types from a `.x` specification with xdrgen. Each type
+//! is described as the equivalent XDR definition, which goes through xdrgen's code generation,
+//! so the codecs behave exactly as they would for a generated type.
+//!
+//! ```ignore
+//! use xdr_derive::{XdrPack, XdrUnpack};
+//!
+//! #[derive(XdrPack, XdrUnpack)]
+//! struct Message {
+//!     id: u32,
+//!     #[xdr(max = 32)]
+//!     name: String,
+//!     payload: Vec<u8>,
+//!     next: Option<Box<Message>>,
+//! }
+//! ```
+//!
+//! Structs with named fields, unit structs, and tuple structs with a single `Vec`, `String` or
+//! array field are supported, as are enums without fields, which need to be `Copy`. Field types
+//! map to XDR as:
+//!
+//!   * `i32`, `u32`, `i64`, `u64`, `f32`, `f64` and `bool` are `int`, `unsigned int`, `hyper`,
+//!     `unsigned hyper`, `float`, `double` and `bool`
+//!   * `String` is `string<>`, `Vec<u8>` is `opaque<>` and `Vec<T>` is `T<>`, with the bound
+//!     given by `#[xdr(max = N)]`
+//!   * `[u8; N]` is `opaque[N]` and `[T; N]` is `T[N]`
+//!   * `Option<T>` is `T *` and `Box<T>` is `T`
+//!   * anything else is a named type, which needs its own `Pack` and `Unpack`
+//!
+//! Unions have no direct Rust equivalent, so enums with fields aren't supported; generate them
+//! from a specification instead.
+//!
+//! For small protocols, `xdr!` generates types and codecs from a specification written inline,
+//! as xdrgen would from a `.x` file with the default options:
+//!
+//! ```ignore
+//! xdr_derive::xdr! {
+//!     enum colour { RED = 1, GREEN = 2 };
+//!     struct point { int x; int y; colour c; };
+//! }
+//! ```
+//!
+//! Parse errors point at the definition which couldn't be parsed. The specification is rebuilt
+//! from Rust tokens, which loses its line breaks, so anything line-based such as `%` lines needs
+//! the specification as a string literal instead: `xdr!(r#"..."#)`. Errors in a literal are
+//! reported with their line and column within it.
+
+extern crate proc_macro;
+
+use std::fmt::Display;
+
+use proc_macro::TokenStream;
+use proc_macro2::{Delimiter, Span, TokenTree};
+use syn::spanned::Spanned;
+use syn::{Attribute, Data, DeriveInput, Expr, Fields, GenericArgument, Lit, LitStr, PathArguments, Type, UnOp};
+
+use xdrgen::{GenOptions, Symtab};
+
+/// Derive `xdr_codec::Pack`.
+#[proc_macro_derive(XdrPack, attributes(xdr))]
+pub fn derive_pack(input: TokenStream) -> TokenStream {
+    derive(input, xdrgen::pack_impl)
+}
+
+/// Derive `xdr_codec::Unpack`.
+#[proc_macro_derive(XdrUnpack, attributes(xdr))]
+pub fn derive_unpack(input: TokenStream) -> TokenStream {
+    derive(input, xdrgen::unpack_impl)
+}
+
+/// Generate types and codecs from an inline XDR specification.
+#[proc_macro]
+pub fn xdr(input: TokenStream) -> TokenStream {
+    let input = proc_macro2::TokenStream::from(input);
+
+    // The text of the specification, and the offset in it of each token with its span
+    let (text, spans, literal) = match syn::parse2::<LitStr>(input.clone()) {
+        Ok(lit) => (lit.value(), vec![(0, lit.span())], true),
+        Err(_) => {
+            let mut text = String::new();
+            let mut spans = Vec::new();
+            source(input, &mut text, &mut spans);
+            (text, spans, false)
+        }
+    };
+
+    let res = match Symtab::parse(&text) {
+        Ok(xdr) => xdrgen::expand(&xdr, &GenOptions::new())
+            .map_err(|e| syn::Error::new(Span::call_site(), format!("xdr: {}", e))),
+        Err(e) => {
+            let span = match e.offset {
+                Some(offset) => spans
+                    .iter()
+                    .find(|(start, _)| *start >= offset)
+                    .or(spans.last())
+                    .map_or(Span::call_site(), |(_, span)| *span),
+                None => Span::call_site(),
+            };
+            // Positions in text rebuilt from tokens aren't useful, unlike those in a literal
+            let msg = if literal { e.to_string() } else { e.message };
+            Err(syn::Error::new(span, format!("xdr: {}", msg)))
+        }
+    };
+
+    match res {
+        Ok(tok) => tok.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+// Rebuild the text of a specification from its tokens, recording where each token starts.
+fn source(input: proc_macro2::TokenStream, text: &mut String, spans: &mut Vec<(usize, Span)>) {
+    for tt in input {
+        spans.push((text.len(), tt.span()));
+        match tt {
+            TokenTree::Group(g) => {
+                let (open, close) = match g.delimiter() {
+                    Delimiter::Brace => ("{ ", "} "),
+                    Delimiter::Bracket => ("[ ", "] "),
+                    Delimiter::Parenthesis => ("( ", ") "),
+                    Delimiter::None => ("", ""),
+                };
+                *text += open;
+                source(g.stream(), text, spans);
+                *text += close;
+            }
+            // Negative numbers are lexed as `-` and a literal, but have to stay together
+            TokenTree::Punct(p) if p.as_char() == '-' => text.push('-'),
+            tt => {
+                *text += &tt.to_string();
+                text.push(' ');
+            }
+        }
+    }
+}
+
+fn derive<F, E>(input: TokenStream, emit: F) -> TokenStream
+where
+    F: Fn(&Symtab, &str, &GenOptions) -> Result<proc_macro2::TokenStream, E>,
+    E: Display,
+{
+    let input = syn::parse_macro_input!(input as DeriveInput);
+    let name = input.ident.to_string();
+
+    let res = definition(&input).and_then(|spec| {
+        let err = |e: &dyn Display| syn::Error::new(input.ident.span(), format!("xdr: {}: {}", spec, e));
+        let xdr: Symtab = spec.parse().map_err(|e| err(&e))?;
+        emit(&xdr, &name, &GenOptions::new()).map_err(|e| err(&e))
+    });
+
+    match res {
+        Ok(tok) => tok.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+// The XDR definition of `input`.
+fn definition(input: &DeriveInput) -> syn::Result<String> {
+    let name = &input.ident;
+    if !input.generics.params.is_empty() {
+        return Err(syn::Error::new(input.generics.span(), "xdr: generic types aren't supported"));
+    }
+
+    match &input.data {
+        Data::Struct(s) => match &s.fields {
+            Fields::Named(fields) => {
+                let mut decls = String::new();
+                for field in &fields.named {
+                    let id = field.ident.as_ref().unwrap().to_string();
+                    decls += &decl(&field.ty, &id, max(&field.attrs)?)?;
+                    decls += "; ";
+                }
+                Ok(format!("struct {} {{ {}}};", name, decls))
+            }
+            Fields::Unit => Ok(format!("struct {} {{ }};", name)),
+            Fields::Unnamed(fields) => {
+                let field = match fields.unnamed.first() {
+                    Some(field) if fields.unnamed.len() == 1 => field,
+                    _ => return Err(syn::Error::new(fields.span(), "xdr: tuple structs need exactly one field")),
+                };
+                match (&field.ty, generic(&field.ty)) {
+                    (Type::Array(_), _) | (_, Some(("Vec", Some(_)))) | (_, Some(("String", None))) => {
+                        Ok(format!("typedef {};", decl(&field.ty, &name.to_string(), max(&field.attrs)?)?))
+                    }
+                    _ => Err(syn::Error::new(
+                        field.span(),
+                        "xdr: the field of a tuple struct has to be a Vec, String or array",
+                    )),
+                }
+            }
+        },
+
+        Data::Enum(e) => {
+            let mut next = 0;
+            let mut defs = Vec::new();
+            for variant in &e.variants {
+                if !matches!(variant.fields, Fields::Unit) {
+                    return Err(syn::Error::new(
+                        variant.span(),
+                        "xdr: enums with fields aren't supported, generate unions from a specification",
+                    ));
+                }
+                if let Some((_, disc)) = &variant.discriminant {
+                    next = discriminant(disc)?;
+                }
+                defs.push(format!("{} = {}", variant.ident, next));
+                next += 1;
+            }
+            Ok(format!("enum {} {{ {} }};", name, defs.join(", ")))
+        }
+
+        Data::Union(u) => Err(syn::Error::new(u.union_token.span, "xdr: Rust unions aren't supported")),
+    }
+}
+
+// The bound from a field's `#[xdr(max = N)]` attribute, if it has one.
+fn max(attrs: &[Attribute]) -> syn::Result<Option<String>> {
+    let mut ret = None;
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("xdr")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("max") {
+                let expr: Expr = meta.value()?.parse()?;
+                ret = Some(size(&expr)?);
+                Ok(())
+            } else {
+                Err(meta.error("xdr: unknown attribute"))
+            }
+        })?;
+    }
+    Ok(ret)
+}
+
+// An array size or bound, which is a literal or the name of a constant.
+fn size(expr: &Expr) -> syn::Result<String> {
+    match expr {
+        Expr::Lit(lit) => match &lit.lit {
+            Lit::Int(v) => Ok(v.base10_digits().to_string()),
+            _ => Err(syn::Error::new(expr.span(), "xdr: size has to be an integer")),
+        },
+        Expr::Path(path) if path.path.get_ident().is_some() => Ok(path.path.get_ident().unwrap().to_string()),
+        _ => Err(syn::Error::new(expr.span(), "xdr: size has to be an integer or a constant")),
+    }
+}
+
+fn discriminant(expr: &Expr) -> syn::Result<i64> {
+    match expr {
+        Expr::Lit(lit) => match &lit.lit {
+            Lit::Int(v) => v.base10_parse(),
+            _ => Err(syn::Error::new(expr.span(), "xdr: discriminant has to be an integer")),
+        },
+        Expr::Unary(u) if matches!(u.op, UnOp::Neg(_)) => Ok(-discriminant(&u.expr)?),
+        _ => Err(syn::Error::new(expr.span(), "xdr: discriminant has to be an integer")),
+    }
+}
+
+// The name of a path type and its generic argument, if it has no more than one.
+fn generic(ty: &Type) -> Option<(&'static str, Option<&Type>)> {
+    let path = match ty {
+        Type::Path(path) if path.qself.is_none() => &path.path,
+        _ => return None,
+    };
+    let seg = path.segments.last()?;
+    let arg = match &seg.arguments {
+        PathArguments::None => None,
+        PathArguments::AngleBracketed(args) if args.args.len() == 1 => match args.args.first() {
+            Some(GenericArgument::Type(ty)) => Some(ty),
+            _ => return None,
+        },
+        _ => return None,
+    };
+    let name = seg.ident.to_string();
+    // Only the names which have to be recognized are needed
+    let name = ["Vec", "String", "Option", "Box", "u8"].iter().find(|n| **n == name);
+    match name {
+        Some(name) => Some((name, arg)),
+        None if arg.is_none() => Some(("", None)),
+        None => None,
+    }
+}
+
+// The XDR declaration of a member `name` of Rust type `ty`, with the bound `max` for variable
+// length types.
+fn decl(ty: &Type, name: &str, max: Option<String>) -> syn::Result<String> {
+    let bounded = |spec: &str, max: Option<String>| format!("{} {}<{}>", spec, name, max.unwrap_or_default());
+
+    let ret = match (ty, generic(ty)) {
+        (Type::Array(arr), _) => {
+            let len = size(&arr.len)?;
+            match generic(&arr.elem) {
+                Some(("u8", None)) => format!("opaque {}[{}]", name, len),
+                _ => format!("{} {}[{}]", spec(&arr.elem)?, name, len),
+            }
+        }
+        (_, Some(("Vec", Some(elem)))) => match generic(elem) {
+            Some(("u8", None)) => return Ok(bounded("opaque", max)),
+            _ => return Ok(bounded(&spec(elem)?, max)),
+        },
+        (_, Some(("String", None))) => return Ok(bounded("string", max)),
+        (_, Some(("Box", Some(inner)))) => return decl(inner, name, max),
+        (_, Some(("Option", Some(inner)))) => format!("{} *{}", spec(inner)?, name),
+        _ => format!("{} {}", spec(ty)?, name),
+    };
+
+    match max {
+        None => Ok(ret),
+        Some(_) => Err(syn::Error::new(ty.span(), "xdr: max only applies to Vec and String")),
+    }
+}
+
+// The XDR type specifier for `ty`, which has to be a scalar or a named type.
+fn spec(ty: &Type) -> syn::Result<String> {
+    if let Some(("Box", Some(inner))) = generic(ty) {
+        return spec(inner);
+    }
+    let name = match ty {
+        Type::Path(path) if generic(ty).is_some_and(|(_, arg)| arg.is_none()) => {
+            path.path.segments.last().unwrap().ident.to_string()
+        }
+        _ => return Err(syn::Error::new(ty.span(), "xdr: unsupported type")),
+    };
+
+    let xdr = match name.as_str() {
+        "i32" => "int",
+        "u32" => "unsigned int",
+        "i64" => "hyper",
+        "u64" => "unsigned hyper",
+        "f32" => "float",
+        "f64" => "double",
+        "u8" | "i8" | "u16" | "i16" | "usize" | "isize" | "char" => {
+            return Err(syn::Error::new(ty.span(), "xdr: type has no XDR equivalent"))
+        }
+        name => name,
+    };
+    Ok(xdr.to_string())
+}
@@ -0,0 +1,114 @@
+extern crate xdr_codec;
+extern crate xdr_derive;
+
+use xdr_codec::{pack, unpack, Error, ErrorKind, Pack};
+use xdr_derive::{XdrPack, XdrUnpack};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, XdrPack, XdrUnpack)]
+enum Colour {
+    Red = 1,
+    Green,
+    Blue = 7,
+}
+
+#[derive(Debug, Clone, PartialEq, XdrPack, XdrUnpack)]
+struct Point {
+    x: i32,
+    y: u64,
+    colour: Colour,
+}
+
+#[derive(Debug, Clone, PartialEq, XdrPack, XdrUnpack)]
+struct Hash([u8; 6]);
+
+#[derive(Debug, Clone, PartialEq, XdrPack, XdrUnpack)]
+struct Marker;
+
+#[derive(Debug, Clone, PartialEq, XdrPack, XdrUnpack)]
+struct Message {
+    id: u32,
+    #[xdr(max = 4)]
+    name: String,
+    payload: Vec<u8>,
+    points: Vec<Point>,
+    corners: [i32; 2],
+    hash: Hash,
+    marker: Marker,
+    flag: bool,
+    next: Option<Box<Message>>,
+}
+
+fn message() -> Message {
+    Message {
+        id: 1,
+        name: "ab".into(),
+        payload: vec![9],
+        points: vec![Point { x: -1, y: 2, colour: Colour::Green }],
+        corners: [3, 4],
+        hash: Hash(*b"abcdef"),
+        marker: Marker,
+        flag: true,
+        next: Some(Box::new(Message {
+            id: 2,
+            name: String::new(),
+            payload: vec![],
+            points: vec![],
+            corners: [0, 0],
+            hash: Hash([0; 6]),
+            marker: Marker,
+            flag: false,
+            next: None,
+        })),
+    }
+}
+
+#[test]
+fn encoding() {
+    let m = message();
+    let mut buf = Vec::new();
+    let sz = m.pack(&mut buf).unwrap();
+    assert_eq!(sz, buf.len());
+
+    // The same encoding as a generated type, field by field
+    let mut expected = Vec::new();
+    1u32.pack(&mut expected).unwrap();
+    "ab".to_string().pack(&mut expected).unwrap();
+    xdr_codec::pack_opaque_flex(&[9], None, &mut expected).unwrap();
+    1u32.pack(&mut expected).unwrap();
+    (-1i32).pack(&mut expected).unwrap();
+    2u64.pack(&mut expected).unwrap();
+    2i32.pack(&mut expected).unwrap();
+    3i32.pack(&mut expected).unwrap();
+    4i32.pack(&mut expected).unwrap();
+    xdr_codec::pack_opaque_array(b"abcdef", 6, &mut expected).unwrap();
+    true.pack(&mut expected).unwrap();
+    true.pack(&mut expected).unwrap();
+    expected.extend_from_slice(&[0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+    expected.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+    assert_eq!(buf, expected);
+
+    let m2: Message = unpack(&mut &buf[..]).unwrap();
+    assert_eq!(m, m2);
+}
+
+#[test]
+fn bounds() {
+    let m = Message { name: "toolong".into(), ..message() };
+    match pack(&m, &mut Vec::new()) {
+        Err(Error(ErrorKind::InvalidLen(4), _)) => (),
+        other => panic!("unexpected {:?}", other),
+    }
+}
+
+#[test]
+fn enums() {
+    let mut buf = Vec::new();
+    pack(&Colour::Blue, &mut buf).unwrap();
+    assert_eq!(buf, [0, 0, 0, 7]);
+    assert_eq!(unpack::<_, Colour>(&mut &buf[..]).unwrap(), Colour::Blue);
+
+    match unpack::<_, Colour>(&mut &[0u8, 0, 0, 3][..]) {
+        Err(Error(ErrorKind::InvalidEnum(3), _)) => (),
+        other => panic!("unexpected {:?}", other),
+    }
+}
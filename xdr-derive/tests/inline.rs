@@ -0,0 +1,74 @@
+// The generated derives and methods need crates this test doesn't depend on.
+#![cfg(not(any(
+    feature = "derive_serde",
+    feature = "derive_json_schema",
+    feature = "derive_strum_enum_string",
+    feature = "json"
+)))]
+
+extern crate xdr_codec;
+extern crate xdr_derive;
+
+use xdr_codec::{pack, unpack};
+
+mod tokens {
+    xdr_derive::xdr! {
+        const MAXPOINTS = 4;
+
+        enum colour { RED = 1, GREEN = 2, BLACK = -1 };
+
+        struct point {
+            int x;
+            int y;
+            colour c;
+        };
+
+        /* Unions work inline too */
+        union shape switch (int kind) {
+            case 0: void;
+            case 1: point points<MAXPOINTS>;
+        };
+    }
+}
+
+mod literal {
+    xdr_derive::xdr!(
+        r#"
+        % // Passed through unchanged
+        struct name {
+            string first<>;
+            opaque tag[4];
+        };
+        "#
+    );
+}
+
+#[test]
+fn tokens() {
+    use tokens::*;
+
+    let s = shape::Const1(vec![
+        point { x: 1, y: -2, c: colour::BLACK },
+        point { x: 3, y: 4, c: colour::GREEN },
+    ]);
+    let mut buf = Vec::new();
+    pack(&s, &mut buf).unwrap();
+    assert_eq!(buf.len(), 4 + 4 + 2 * 12);
+    assert_eq!(&buf[16..20], &[0xff; 4]);
+    assert_eq!(unpack::<_, shape>(&mut &buf[..]).unwrap(), s);
+
+    let mut buf = Vec::new();
+    pack(&shape::Const1(vec![point { x: 0, y: 0, c: colour::RED }; 5]), &mut buf).unwrap_err();
+    assert_eq!(MAXPOINTS, 4);
+}
+
+#[test]
+fn literal() {
+    use literal::*;
+
+    let n = name { first: "ab".into(), tag: *b"wxyz" };
+    let mut buf = Vec::new();
+    pack(&n, &mut buf).unwrap();
+    assert_eq!(buf, b"\0\0\0\x02ab\0\0wxyz");
+    assert_eq!(unpack::<_, name>(&mut &buf[..]).unwrap(), n);
+}
@@ -0,0 +1,96 @@
+//! Expansion of `#include` directives for other `.x` files.
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use xdr::Result;
+
+// An `#include` of a `.x` file: the name, and whether it was quoted rather than in angle
+// brackets.
+fn parse_include(line: &str) -> Option<(&str, bool)> {
+    let line = line.trim().strip_prefix('#')?.trim_start();
+    let line = line.strip_prefix("include")?.trim();
+
+    let (name, quoted) = if let Some(name) = line.strip_prefix('"') {
+        (name.strip_suffix('"')?, true)
+    } else {
+        (line.strip_prefix('<')?.strip_suffix('>')?, false)
+    };
+
+    if name.ends_with(".x") {
+        Some((name, quoted))
+    } else {
+        None
+    }
+}
+
+// Find an included file. As with the C preprocessor, quoted names are looked up relative to the
+// including file first and then in the search path, and names in angle brackets only in the
+// search path.
+fn resolve(name: &str, quoted: bool, from: &Path, dirs: &[PathBuf]) -> Option<PathBuf> {
+    let local = if quoted {
+        Some(from.parent().unwrap_or_else(|| Path::new("")))
+    } else {
+        None
+    };
+
+    local
+        .into_iter()
+        .chain(dirs.iter().map(PathBuf::as_path))
+        .map(|dir| dir.join(name))
+        .find(|path| path.is_file())
+}
+
+/// Replace each `#include` of a `.x` file in `source`, which was read from `file`, with the
+/// contents of the file. Each file is only included once. Other includes are left alone.
+pub(crate) fn expand(source: &str, file: &Path, dirs: &[PathBuf]) -> Result<String> {
+    expand_listing(source, file, dirs).map(|(source, _)| source)
+}
+
+/// `expand`, also returning the paths of the files which were included.
+pub(crate) fn expand_listing(source: &str, file: &Path, dirs: &[PathBuf]) -> Result<(String, Vec<PathBuf>)> {
+    let mut seen = HashSet::new();
+    if let Ok(path) = file.canonicalize() {
+        seen.insert(path);
+    }
+    let mut files = Vec::new();
+    let source = expand_from(source, file, dirs, &mut seen, &mut files)?;
+    Ok((source, files))
+}
+
+fn expand_from(
+    source: &str,
+    file: &Path,
+    dirs: &[PathBuf],
+    seen: &mut HashSet<PathBuf>,
+    files: &mut Vec<PathBuf>,
+) -> Result<String> {
+    let mut ret = String::with_capacity(source.len());
+
+    for line in source.lines() {
+        let (name, quoted) = match parse_include(line) {
+            None => {
+                ret.push_str(line);
+                ret.push('\n');
+                continue;
+            }
+            Some(inc) => inc,
+        };
+
+        let path = match resolve(name, quoted, file, dirs) {
+            Some(path) => path,
+            None => {
+                return Err(format!("{}: can't find included file {}", file.display(), name).into())
+            }
+        };
+
+        if seen.insert(path.canonicalize()?) {
+            let included = fs::read_to_string(&path)?;
+            files.push(path.clone());
+            ret.push_str(&expand_from(&included, &path, dirs, seen, files)?);
+        }
+    }
+
+    Ok(ret)
+}
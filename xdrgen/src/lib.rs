@@ -25,9 +25,10 @@ extern crate nom;
 #[macro_use]
 extern crate bitflags;
 
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::env;
 use std::fmt::Display;
-use std::fs::File;
+use std::fs::{self, File};
 use std::io::{Read, Write};
 use std::path::{Path, PathBuf};
 use std::result;
@@ -35,7 +36,13 @@ use std::result;
 use xdr::Result;
 
 mod spec;
-use spec::{Emit, Emitpack, Symtab};
+use spec::Emit;
+pub use spec::{Change, Compat, MemSize, ParseError, Symtab};
+
+mod include;
+
+mod options;
+pub use options::{AddrForm, Checksum, GenOptions, IdentKind, LenWidth, Opt, Rename, StrEnc, UnknownEnum};
 
 fn result_option<T, E>(resopt: result::Result<Option<T>, E>) -> Option<result::Result<T, E>> {
     match resopt {
@@ -45,13 +52,30 @@ fn result_option<T, E>(resopt: result::Result<Option<T>, E>) -> Option<result::R
     }
 }
 
+// Tag generated code with the definition it was generated for
+fn owned(name: &str) -> impl FnOnce(proc_macro2::TokenStream) -> (Option<String>, proc_macro2::TokenStream) {
+    let name = Some(name.to_string());
+    move |code| (name, code)
+}
+
+// Tag generated code which isn't for any one definition
+fn unowned(code: proc_macro2::TokenStream) -> (Option<String>, proc_macro2::TokenStream) {
+    (None, code)
+}
+
+/// Whether a line of generated code is for one of `exclude_defs`
+///
+/// Names must match exactly, so excluding `foo` doesn't exclude `foobar`. A line is for a
+/// definition if it defines it, or is an `impl` for it.
 pub fn exclude_definition_line(line: &str, exclude_defs: &[&str]) -> bool {
-    exclude_defs.iter().fold(false, |acc, v| {
-        acc || line.contains(&format!("const {}", v))
-            || line.contains(&format!("struct {}", v))
-            || line.contains(&format!("enum {}", v))
-            || line.contains(&format!("for {}", v))
-    })
+    match line.parse() {
+        Ok(tokens) => spec::items(tokens).iter().any(|item| excluded(item, exclude_defs)),
+        Err(_) => false,
+    }
+}
+
+fn excluded(item: &proc_macro2::TokenStream, exclude_defs: &[&str]) -> bool {
+    spec::item_name(item).is_some_and(|name| exclude_defs.contains(&name.as_str()))
 }
 
 /// Generate Rust code from an RFC4506 XDR specification
@@ -59,12 +83,36 @@ pub fn exclude_definition_line(line: &str, exclude_defs: &[&str]) -> bool {
 /// `infile` is simply a string used in error messages; it may be empty. `input` is a read stream of
 /// the specification, and `output` is where the generated code is sent.
 /// `exclude_defs` is list of not generated type definitions.
+///
+/// A specification without any definitions, such as an empty file, generates a module with no
+/// items. Use `GenOptions::reject_empty` to make it an error instead.
 pub fn generate<In, Out>(
     infile: &str,
-    mut input: In,
-    mut output: Out,
+    input: In,
+    output: Out,
     exclude_defs: &[&str],
 ) -> Result<()>
+where
+    In: Read,
+    Out: Write,
+{
+    generate_with_options(
+        infile,
+        input,
+        output,
+        &GenOptions::new().exclude_defs(exclude_defs),
+    )
+}
+
+/// Generate Rust code from an RFC4506 XDR specification, with options
+///
+/// This is the same as `generate`, except that generation is controlled by `opts`.
+pub fn generate_with_options<In, Out>(
+    infile: &str,
+    mut input: In,
+    output: Out,
+    opts: &GenOptions,
+) -> Result<()>
 where
     In: Read,
     Out: Write,
@@ -73,14 +121,117 @@ where
 
     input.read_to_string(&mut source)?;
 
-    let xdr = match spec::specification(&source) {
-        Ok(defns) => Symtab::new(&defns),
-        Err(e) => return Err(xdr::Error::from(format!("parse error: {}", e))),
+    if let Some(dirs) = &opts.include_dirs {
+        source = expand_includes(infile, &source, dirs)?;
+    }
+
+    let xdr: Symtab = source.parse()?;
+
+    generate_from_symtab(infile, &xdr, output, opts)
+}
+
+/// Expand `#include` directives in `source`, which was read from `infile`
+///
+/// Includes of `.x` files are replaced with the contents of the file, searching `dirs` as
+/// described for `GenOptions::include_dirs`. This is done by `generate_with_options` when that
+/// option is set, but is needed before parsing a specification into a `Symtab` directly.
+pub fn expand_includes<P: AsRef<Path>>(infile: P, source: &str, dirs: &[PathBuf]) -> Result<String> {
+    include::expand(source, infile.as_ref(), dirs)
+}
+
+/// Generate Rust code from an already parsed XDR specification
+///
+/// This allows a specification to be parsed once with `str::parse` and then used to generate
+/// several outputs.
+///
+/// ```
+/// let xdr: xdrgen::Symtab = "struct foo { int a; };".parse().unwrap();
+/// let mut code = Vec::new();
+///
+/// xdrgen::generate_from_symtab("foo.x", &xdr, &mut code, &xdrgen::GenOptions::new()).unwrap();
+/// ```
+pub fn generate_from_symtab<Out>(
+    infile: &str,
+    xdr: &Symtab,
+    mut output: Out,
+    opts: &GenOptions,
+) -> Result<()>
+where
+    Out: Write,
+{
+    // Of the specification as it was given, so that it doesn't depend on the options
+    let spec_hash = Some(xdr.fingerprint()).filter(|_| opts.self_describing);
+
+    let renamed;
+    let xdr = match &opts.rename_fn {
+        Some(rename) => {
+            renamed = xdr.rename_with(&*rename.0)?;
+            &renamed
+        }
+        None => xdr,
+    };
+    let opaque;
+    let xdr = match opts.string_encoding {
+        StrEnc::Bytes => {
+            opaque = xdr.opaque_strings();
+            &opaque
+        }
+        _ => xdr,
     };
 
-    let xdr = xdr;
+    if opts.reject_empty && xdr.is_empty() {
+        return Err(format!("{}: no definitions found", infile).into());
+    }
+    for (from, to) in xdr.renamed() {
+        warn!("{}: {} is a Rust keyword, renamed to {}", infile, from, to);
+    }
+
+    for (i, (name, _)) in opts.external_types.iter().enumerate() {
+        if xdr.typespecs().all(|(n, _)| n != name) {
+            return Err(format!("external type {}: no such definition", name).into());
+        }
+        if opts.external_types[..i].iter().any(|(n, _)| n == name) {
+            return Err(format!("external type {}: given more than once", name).into());
+        }
+    }
+
+    if let Some(case) = &opts.serde_rename_all {
+        if !options::SERDE_CASES.contains(&case.as_str()) {
+            return Err(format!("serde rename_all: unknown case {:?}", case).into());
+        }
+    }
+
+    if opts.canonical {
+        let conflict = if opts.forward_compat {
+            Some("forward_compat")
+        } else if opts.unknown_enum == UnknownEnum::Clamp {
+            Some("UnknownEnum::Clamp")
+        } else if opts.zero_copy {
+            Some("zero_copy")
+        } else {
+            None
+        };
+        if let Some(conflict) = conflict {
+            return Err(format!("canonical can't be combined with {}", conflict).into());
+        }
+    }
+
+    if opts.unknown_enum == UnknownEnum::Clamp {
+        for (name, ty) in xdr.typespecs() {
+            if let spec::Type::Enum(_) = ty {
+                if opts.enum_defaults.iter().all(|(n, _)| n != name) {
+                    return Err(format!("enum {}: UnknownEnum::Clamp needs a default set with enum_default", name).into());
+                }
+            }
+        }
+    }
 
     let res: Vec<_> = {
+        let rust_types = Some(spec::rust_type_trait())
+            .filter(|_| xdr.has_pragma("rust_type"))
+            .map(|t| Ok(unowned(t)))
+            .into_iter();
+
         let consts = xdr
             .constants()
             .filter_map(|(c, &(v, ref scope))| {
@@ -90,33 +241,220 @@ where
                     None
                 }
             })
-            .map(|c| c.define(&xdr));
+            .map(|c| c.define(xdr).map(owned(&c.0)));
+        let str_consts = xdr
+            .str_constants()
+            .map(|(c, v)| spec::StrConst(c.clone(), v.clone()).define(xdr).map(owned(c)));
+        let spec_hash_const = spec_hash.map(|hash| {
+            Ok(unowned(quote! {
+                /// Fingerprint of the specification, in the header of each top-level message.
+                pub const SPEC_HASH: u64 = #hash;
+            }))
+        });
+        // The header of top-level messages with `GenOptions::self_describing`
+        let header = |name: &str| spec_hash.filter(|_| xdr.is_toplevel(name));
 
         let typespecs = xdr
             .typespecs()
             .map(|(n, ty)| spec::Typespec(n.clone(), ty.clone()))
-            .map(|c| c.define(&xdr));
+            .map(|c| c.define_with(xdr, opts).map(owned(&c.0)));
 
         let typesyns = xdr
             .typesyns()
             .map(|(n, ty)| spec::Typesyn(n.clone(), ty.clone()))
-            .map(|c| c.define(&xdr));
+            .map(|c| c.define(xdr).map(owned(&c.0)));
+
+        let programs = xdr.programs().map(|(_, prog)| prog.define(xdr).map(unowned));
+        let proc_tables = xdr
+            .programs()
+            .filter(|_| opts.proc_tables)
+            .map(|(_, prog)| prog.proc_table(xdr).map(unowned));
 
         let packers = xdr
             .typespecs()
             .map(|(n, ty)| spec::Typespec(n.clone(), ty.clone()))
-            .filter_map(|c| result_option(c.pack(&xdr)));
+            .filter_map(|c| result_option(c.pack_toplevel(xdr, opts, header(&c.0)).map(|r| r.map(owned(&c.0)))));
 
         let unpackers = xdr
             .typespecs()
             .map(|(n, ty)| spec::Typespec(n.clone(), ty.clone()))
-            .filter_map(|c| result_option(c.unpack(&xdr)));
+            .filter_map(|c| {
+                let unpack = if xdr.is_toplevel(&c.0) {
+                    c.unpack_toplevel(xdr, opts, header(&c.0))
+                } else {
+                    c.unpack_with(xdr, opts)
+                };
+                result_option(unpack).map(|r| r.map(owned(&c.0)))
+            });
+
+        let policy_packers = xdr
+            .typespecs()
+            .filter(|&(n, _)| opts.flush_policy && xdr.is_toplevel(n))
+            .map(|(n, ty)| spec::Typespec(n.clone(), ty.clone()))
+            .filter_map(|c| c.policy_packer().map(owned(&c.0)).map(Ok));
+
+        let scratch_packers = xdr
+            .typespecs()
+            .filter(|&(n, _)| opts.scratch_packers && xdr.is_toplevel(n))
+            .map(|(n, ty)| spec::Typespec(n.clone(), ty.clone()))
+            .filter_map(|c| c.scratch_packer().map(owned(&c.0)).map(Ok));
+
+        let codecs = xdr
+            .typespecs()
+            .filter(|&(n, _)| opts.tokio_codecs && xdr.is_toplevel(n))
+            .map(|(n, ty)| spec::Typespec(n.clone(), ty.clone()))
+            .filter_map(|c| c.record_codec(opts.max_message_size).map(owned(&c.0)).map(Ok));
+
+        let content_hashes = xdr
+            .typespecs()
+            .filter(|_| opts.content_hash)
+            .map(|(n, ty)| spec::Typespec(n.clone(), ty.clone()))
+            .filter_map(|c| c.content_hasher().map(owned(&c.0)).map(Ok));
+
+        let collections = xdr
+            .typespecs()
+            .filter(|_| opts.flex_collections)
+            .map(|(n, ty)| spec::Typespec(n.clone(), ty.clone()))
+            .filter_map(|c| result_option(c.collection_impls(xdr).map(|r| r.map(owned(&c.0)))));
+
+        let array_helpers = xdr
+            .typespecs()
+            .filter(|_| opts.array_helpers)
+            .map(|(n, ty)| spec::Typespec(n.clone(), ty.clone()))
+            .filter_map(|c| c.array_helpers(opts).map(owned(&c.0)).map(Ok));
+
+        let union_helpers = xdr
+            .typespecs()
+            .filter(|_| opts.union_helpers)
+            .map(|(n, ty)| spec::Typespec(n.clone(), ty.clone()))
+            .filter_map(|c| result_option(c.union_helpers(xdr, opts).map(|r| r.map(owned(&c.0)))));
+
+        let enum_names = xdr
+            .typespecs()
+            .filter(|_| opts.enum_names)
+            .map(|(n, ty)| spec::Typespec(n.clone(), ty.clone()))
+            .filter_map(|c| result_option(c.enum_name_fn(xdr, opts).map(|r| r.map(owned(&c.0)))));
 
-        consts
+        let enum_defaults = opts
+            .enum_defaults
+            .iter()
+            .map(|(name, value)| spec::EnumDefault(name.clone(), value.clone()))
+            .map(|c| c.define(xdr).map(owned(&c.0)));
+
+        let validators = xdr
+            .typespecs()
+            .filter(|_| opts.emit_validate)
+            .map(|(n, ty)| spec::Typespec(n.clone(), ty.clone()))
+            .filter_map(|c| result_option(c.validator(xdr, opts).map(|r| r.map(owned(&c.0)))));
+
+        let tracers = xdr
+            .typespecs()
+            .filter(|_| opts.trace)
+            .map(|(n, ty)| spec::Typespec(n.clone(), ty.clone()))
+            .filter_map(|c| result_option(c.trace_unpacker(xdr, opts).map(|r| r.map(owned(&c.0)))));
+
+        let decoders = xdr
+            .typespecs()
+            .filter(|_| opts.resumable)
+            .map(|(n, ty)| spec::Typespec(n.clone(), ty.clone()))
+            .filter_map(|c| result_option(c.resumable_decoder(xdr, opts, header(&c.0)).map(|r| r.map(owned(&c.0)))));
+
+        let ffi_mirrors = xdr
+            .typespecs()
+            .filter(|_| opts.ffi_mirror)
+            .map(|(n, ty)| spec::Typespec(n.clone(), ty.clone()))
+            .filter_map(|c| result_option(c.ffi_mirror(xdr, opts).map(|r| r.map(owned(&c.0)))));
+
+        let dyn_codecs = xdr
+            .typespecs()
+            .filter(|_| opts.dyn_codec)
+            .map(|(n, ty)| spec::Typespec(n.clone(), ty.clone()))
+            .filter_map(|c| c.dyn_codec().map(owned(&c.0)).map(Ok));
+
+        let io_methods = xdr
+            .typespecs()
+            .filter(|_| opts.io_methods)
+            .map(|(n, ty)| spec::Typespec(n.clone(), ty.clone()))
+            .filter_map(|c| c.io_methods().map(owned(&c.0)).map(Ok));
+
+        let checksums = xdr
+            .typespecs()
+            .filter_map(|(n, ty)| opts.checksum.map(|algo| (spec::Typespec(n.clone(), ty.clone()), algo)))
+            .filter_map(|(c, algo)| c.checksum_packer(algo).map(owned(&c.0)).map(Ok));
+
+        let skips = xdr
+            .typespecs()
+            .filter(|_| opts.emit_skip)
+            .map(|(n, ty)| spec::Typespec(n.clone(), ty.clone()))
+            .filter_map(|c| result_option(c.skip_impl(xdr, opts, header(&c.0)).map(|r| r.map(owned(&c.0)))));
+
+        let packed_sizes = xdr
+            .typespecs()
+            .filter(|_| opts.packed_size)
+            .map(|(n, ty)| spec::Typespec(n.clone(), ty.clone()))
+            .filter_map(|c| c.packed_size(xdr, opts, header(&c.0).is_some()).map(owned(&c.0)))
+            .map(Ok);
+
+        let borrowed_views = xdr
+            .typespecs()
+            .filter(|_| opts.zero_copy)
+            .map(|(n, ty)| spec::Typespec(n.clone(), ty.clone()))
+            .filter_map(|c| result_option(c.borrowed_view(xdr, opts, header(&c.0)).map(|r| r.map(owned(&c.0)))));
+
+        let json = xdr
+            .typespecs()
+            .map(|(n, ty)| spec::Typespec(n.clone(), ty.clone()))
+            .filter_map(|c| result_option(c.json_impls(xdr, opts).map(|r| r.map(owned(&c.0)))));
+
+        let conversions = opts
+            .conversions
+            .iter()
+            .map(|(a, b)| spec::Conversion(a.clone(), b.clone()))
+            .map(|c| c.define(xdr).map(unowned));
+
+        let address_types = opts
+            .address_types
+            .iter()
+            .map(|(name, form)| spec::AddrType(name.clone(), *form))
+            .map(|c| c.define(xdr).map(unowned));
+
+        let dispatch = Some(&opts.dispatch_tags)
+            .filter(|tags| !tags.is_empty())
+            .map(|tags| spec::DispatchMap(tags.clone()).define(xdr).map(unowned));
+
+        rust_types
+            .chain(consts)
+            .chain(str_consts)
+            .chain(spec_hash_const)
             .chain(typespecs)
             .chain(typesyns)
+            .chain(programs)
+            .chain(proc_tables)
             .chain(packers)
             .chain(unpackers)
+            .chain(policy_packers)
+            .chain(scratch_packers)
+            .chain(codecs)
+            .chain(content_hashes)
+            .chain(collections)
+            .chain(array_helpers)
+            .chain(union_helpers)
+            .chain(enum_names)
+            .chain(enum_defaults)
+            .chain(validators)
+            .chain(tracers)
+            .chain(decoders)
+            .chain(ffi_mirrors)
+            .chain(packed_sizes)
+            .chain(skips)
+            .chain(dyn_codecs)
+            .chain(checksums)
+            .chain(io_methods)
+            .chain(borrowed_views)
+            .chain(json)
+            .chain(conversions)
+            .chain(address_types)
+            .chain(dispatch)
             .collect::<Result<Vec<_>>>()?
     };
 
@@ -132,13 +470,143 @@ where
         infile
     );
 
-    for it in res {
-        let line = it.to_string();
-        if !exclude_definition_line(&line, exclude_defs) {
-            let _ = writeln!(output, "{}\n", line);
+    let exclude_defs: Vec<&str> = opts.exclude_defs.iter().map(String::as_str).collect();
+
+    // Generated code refers to deprecated definitions, which shouldn't cause warnings
+    let allow_deprecated = xdr.has_pragma("deprecated");
+
+    // Definitions gated behind features, and everything referring to them, get a `#[cfg]`
+    let gates: HashMap<&str, BTreeSet<&str>> = xdr
+        .constants()
+        .map(|(n, _)| n)
+        .chain(xdr.str_constants().map(|(n, _)| n))
+        .chain(xdr.typespecs().map(|(n, _)| n))
+        .chain(xdr.typesyns().map(|(n, _)| n))
+        .map(|n| (n.as_str(), xdr.features(n)))
+        .filter(|(_, features)| !features.is_empty())
+        .collect();
+
+    // Items are excluded one at a time, so a definition is excluded the same way whichever
+    // other items it was generated with
+    let items = res
+        .into_iter()
+        .flat_map(|(owner, code)| spec::items(code).into_iter().map(move |item| (owner.clone(), item)));
+    for (owner, item) in items {
+        if excluded(&item, &exclude_defs) {
+            continue;
+        }
+        if !allow_deprecated && gates.is_empty() {
+            let _ = writeln!(output, "{}\n", item);
+            continue;
+        }
+        // An item generated for a definition, such as `fooDecoder`, is gated by what the
+        // definition and the item's header refer to, as its body can have members with the same
+        // names as gated types. Other items only refer to types.
+        let mut idents = HashSet::new();
+        match owner {
+            Some(def) => {
+                spec::token_idents(spec::item_header(&item), &mut idents);
+                idents.insert(def);
+            }
+            None => spec::token_idents(item.clone(), &mut idents),
         }
+        let features: BTreeSet<&str> = idents
+            .iter()
+            .filter_map(|id| gates.get(id.as_str()))
+            .flatten()
+            .cloned()
+            .collect();
+        let features = features.iter().map(|f| quote!(#[cfg(feature = #f)]));
+
+        let allow = if allow_deprecated { quote!(#[allow(deprecated)]) } else { quote!() };
+        let _ = writeln!(output, "{}\n", quote!(#(#features)* #allow #item));
+    }
+
+    Ok(())
+}
+
+/// Generated code for a specification, as tokens rather than text
+///
+/// This is for procedural macros which take a specification inline, such as `xdr!` in
+/// `xdr-derive`. The tokens are the code `generate_from_symtab` writes with the same options. To
+/// report parse errors against the macro's input, parse the specification with `Symtab::parse`,
+/// which gives their position.
+///
+/// ```
+/// let xdr = xdrgen::Symtab::parse("struct foo { int a; };").unwrap();
+/// let tokens = xdrgen::expand(&xdr, &xdrgen::GenOptions::new()).unwrap();
+/// # let _ = tokens;
+/// ```
+pub fn expand(xdr: &Symtab, opts: &GenOptions) -> Result<proc_macro2::TokenStream> {
+    let mut code = Vec::new();
+    generate_from_symtab("<inline>", xdr, &mut code, opts)?;
+    String::from_utf8(code)?
+        .parse()
+        .map_err(|e| format!("generated code doesn't parse: {}", e).into())
+}
+
+// The definition of `name` in `xdr`, which has to be a struct, union, enum or array typedef.
+fn typespec(xdr: &Symtab, name: &str) -> Result<spec::Typespec> {
+    match xdr.typespecs().find(|(n, _)| *n == name) {
+        Some((n, ty)) => Ok(spec::Typespec(n.clone(), ty.clone())),
+        None => Err(format!("{}: no such definition", name).into()),
+    }
+}
+
+/// `Pack` implementation for the type `name` defined in `xdr`.
+///
+/// This is the same code `generate_from_symtab` emits for the type, without its definition.
+/// It's for derive macros such as `xdr-derive`, which describe a hand-written Rust type as an
+/// XDR definition so that it gets exactly the same codecs as generated types.
+pub fn pack_impl(xdr: &Symtab, name: &str, opts: &GenOptions) -> Result<proc_macro2::TokenStream> {
+    match typespec(xdr, name)?.pack_with(xdr, opts)? {
+        Some(tok) => Ok(tok),
+        None => Err(format!("{}: not a struct, union, enum or array typedef", name).into()),
     }
+}
+
+/// `Unpack` implementation for the type `name` defined in `xdr`, as for `pack_impl`.
+pub fn unpack_impl(xdr: &Symtab, name: &str, opts: &GenOptions) -> Result<proc_macro2::TokenStream> {
+    match typespec(xdr, name)?.unpack_with(xdr, opts)? {
+        Some(tok) => Ok(tok),
+        None => Err(format!("{}: not a struct, union, enum or array typedef", name).into()),
+    }
+}
+
+/// Export a Kaitai Struct description of an XDR specification
+///
+/// This produces a `.ksy` file describing all the types in the specification, for use with
+/// Kaitai Struct's tools to dissect captured traffic. `id` becomes the Kaitai `meta/id`, and
+/// typically is the name of the specification.
+#[cfg(feature = "export-kaitai")]
+pub fn export_kaitai<Out: Write>(id: &str, xdr: &Symtab, mut output: Out) -> Result<()> {
+    let ksy = spec::kaitai(id, xdr)?;
+    output.write_all(ksy.as_bytes())?;
+    Ok(())
+}
+
+/// Export a Protocol Buffers schema for an XDR specification
+///
+/// This produces a best-effort proto3 `.proto` file with a message or enum for each type, as a
+/// starting point for moving a protocol to protobuf. `package` becomes the protobuf package, and
+/// typically is the name of the specification.
+#[cfg(feature = "export-proto")]
+pub fn export_proto<Out: Write>(package: &str, xdr: &Symtab, mut output: Out) -> Result<()> {
+    let proto = spec::proto(package, xdr)?;
+    output.write_all(proto.as_bytes())?;
+    Ok(())
+}
 
+/// Export Apache Avro schemas for an XDR specification
+///
+/// This produces a JSON array of Avro schemas, with a record, enum or fixed for each named type,
+/// for registering XDR-defined messages with a schema registry. Like `export_proto`, it only
+/// carries over the shape of the types, noting anything Avro can't express in `doc`. `namespace`
+/// becomes the Avro namespace, and typically is the name of the specification.
+#[cfg(feature = "export-avro")]
+pub fn export_avro<Out: Write>(namespace: &str, xdr: &Symtab, mut output: Out) -> Result<()> {
+    let avro = spec::avro(namespace, xdr)?;
+    output.write_all(avro.as_bytes())?;
     Ok(())
 }
 
@@ -170,25 +638,72 @@ pub fn compile<P>(infile: P, exclude_defs: &[&str]) -> Result<()>
 where
     P: AsRef<Path> + Display,
 {
-    let input = File::open(&infile)?;
+    let outdir = PathBuf::from(env::var("OUT_DIR").unwrap_or(String::from(".")));
+
+    compile_into(infile.as_ref(), &outdir, &GenOptions::new().exclude_defs(exclude_defs)).map(|_| ())
+}
+
+/// Compile every `.x` file in `dir` into OUT_DIR, as with `compile`, for a build.rs script.
+///
+/// Each `foo.x` generates `foo_xdr.rs`, whose paths are returned in the order of the
+/// specifications' names. It also prints `cargo:rerun-if-changed` for `dir`, so that new
+/// specifications are noticed, each specification, and every file they include with
+/// `GenOptions::include_dirs`.
+///
+/// ```ignore
+/// fn main() {
+///    xdrgen::compile_dir(std::path::Path::new("src/xdr"), &xdrgen::GenOptions::new()).unwrap();
+/// }
+/// ```
+pub fn compile_dir(dir: &Path, opts: &GenOptions) -> Result<Vec<PathBuf>> {
+    let outdir = PathBuf::from(env::var("OUT_DIR").unwrap_or(String::from(".")));
 
-    let mut outdir = PathBuf::from(env::var("OUT_DIR").unwrap_or(String::from(".")));
-    let outfile = PathBuf::from(infile.as_ref())
+    let mut specs = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_file() && path.extension().is_some_and(|ext| ext == "x") {
+            specs.push(path);
+        }
+    }
+    specs.sort();
+
+    println!("cargo:rerun-if-changed={}", dir.display());
+    let mut outputs = Vec::new();
+    for spec in specs {
+        let (output, includes) = compile_into(&spec, &outdir, opts)?;
+        for file in Some(&spec).into_iter().chain(&includes) {
+            println!("cargo:rerun-if-changed={}", file.display());
+        }
+        outputs.push(output);
+    }
+
+    Ok(outputs)
+}
+
+// Generate `infile`'s code into `outdir`, returning the generated file and the files `infile`
+// includes.
+fn compile_into(infile: &Path, outdir: &Path, opts: &GenOptions) -> Result<(PathBuf, Vec<PathBuf>)> {
+    let mut source = fs::read_to_string(infile)?;
+    let mut includes = Vec::new();
+    if let Some(dirs) = &opts.include_dirs {
+        let (expanded, files) = include::expand_listing(&source, infile, dirs)?;
+        source = expanded;
+        includes = files;
+    }
+    let xdr: Symtab = source.parse()?;
+
+    let outfile = infile
         .file_stem()
         .unwrap()
         .to_owned()
         .into_string()
         .unwrap()
         .replace("-", "_");
+    let outfile = outdir.join(format!("{}_xdr.rs", outfile));
 
-    outdir.push(&format!("{}_xdr.rs", outfile));
+    let output = File::create(&outfile)?;
 
-    let output = File::create(outdir)?;
+    generate_from_symtab(infile.as_os_str().to_str().unwrap_or("<unknown>"), &xdr, output, opts)?;
 
-    generate(
-        infile.as_ref().as_os_str().to_str().unwrap_or("<unknown>"),
-        input,
-        output,
-        exclude_defs,
-    )
+    Ok((outfile, includes))
 }
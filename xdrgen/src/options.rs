@@ -0,0 +1,689 @@
+//! Options controlling code generation.
+
+use std::fmt::{self, Debug, Formatter};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// Standard library address types, for `GenOptions::address_types`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddrForm {
+    /// `std::net::Ipv4Addr`, from `unsigned int` or `opaque[4]`
+    Ipv4,
+    /// `std::net::Ipv6Addr`, from `opaque[16]`
+    Ipv6,
+}
+
+/// Kinds of identifier, for `GenOptions::rename_fn`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum IdentKind {
+    /// Struct, union, enum or typedef
+    Type,
+    /// Struct or union member
+    Field,
+    /// Enum value
+    Variant,
+    /// Constant
+    Const,
+}
+
+/// Identifier renaming function, for `GenOptions::rename_fn`.
+pub type Rename = dyn Fn(&str, IdentKind) -> String + Send + Sync;
+
+// Wrapper for a renaming function, which can't derive `Debug` or `Clone` itself.
+#[derive(Clone)]
+pub(crate) struct RenameFn(pub(crate) Arc<Rename>);
+
+impl Debug for RenameFn {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "RenameFn")
+    }
+}
+
+/// What generated codecs are optimized for, for `GenOptions::optimize`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Opt {
+    /// Inline code for each field, which is fastest
+    #[default]
+    Speed,
+    /// Calls to shared helpers instead of inline code, which keeps the binary small
+    Size,
+}
+
+/// What generated enum codecs do with values not in the specification, for
+/// `GenOptions::unknown_enum`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UnknownEnum {
+    /// Fail with `ErrorKind::InvalidEnum`
+    #[default]
+    Error,
+    /// Decode as an extra `Unknown` variant holding the value, which packs as the same value
+    Preserve,
+    /// Decode as the enum's default value, given with `GenOptions::enum_default`
+    Clamp,
+}
+
+/// The width of the length prefixes of variable-length data, for `GenOptions::length_width`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LenWidth {
+    /// `unsigned int` lengths, as RFC4506 specifies
+    #[default]
+    U32,
+    /// `unsigned hyper` lengths, for protocols extended to carry more than 4GiB
+    U64,
+}
+
+/// The checksum of packed messages, for `GenOptions::checksum`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Checksum {
+    /// CRC-32 of IEEE 802.3, as used by zlib
+    Crc32,
+    /// CRC-32C (Castagnoli)
+    Crc32c,
+}
+
+/// How the bytes of XDR strings become Rust values, for `GenOptions::string_encoding`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StrEnc {
+    /// `String`, failing to unpack bytes which aren't UTF-8
+    #[default]
+    Utf8Strict,
+    /// `String`, decoding each byte as the Latin-1 character with that code point, which can't
+    /// fail. Packing fails for characters beyond U+00FF.
+    Latin1,
+    /// `Vec<u8>` of the raw bytes, as if the strings were opaque data
+    Bytes,
+}
+
+// The values serde accepts for `rename_all`.
+pub(crate) const SERDE_CASES: &[&str] = &[
+    "lowercase",
+    "UPPERCASE",
+    "PascalCase",
+    "camelCase",
+    "snake_case",
+    "SCREAMING_SNAKE_CASE",
+    "kebab-case",
+    "SCREAMING-KEBAB-CASE",
+];
+
+/// Options for `generate_with_options` and friends.
+///
+/// The defaults produce the same output as `generate`. Options are set with builder-style
+/// methods:
+///
+/// ```
+/// let opts = xdrgen::GenOptions::new()
+///     .exclude_defs(&["Internal"])
+///     .conversions(&[("FooV1", "FooV2")]);
+/// # let _ = opts;
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct GenOptions {
+    pub(crate) exclude_defs: Vec<String>,
+    pub(crate) conversions: Vec<(String, String)>,
+    pub(crate) max_message_size: Option<usize>,
+    pub(crate) flex_collections: bool,
+    pub(crate) forward_compat: bool,
+    pub(crate) union_helpers: bool,
+    pub(crate) flush_policy: bool,
+    pub(crate) emit_validate: bool,
+    pub(crate) enum_names: bool,
+    pub(crate) unsigned_enums: bool,
+    pub(crate) trace: bool,
+    pub(crate) array_helpers: bool,
+    pub(crate) reject_empty: bool,
+    pub(crate) content_hash: bool,
+    pub(crate) include_dirs: Option<Vec<PathBuf>>,
+    pub(crate) address_types: Vec<(String, AddrForm)>,
+    pub(crate) enum_defaults: Vec<(String, String)>,
+    pub(crate) external_types: Vec<(String, String)>,
+    pub(crate) optimize: Opt,
+    pub(crate) resumable: bool,
+    pub(crate) rename_fn: Option<RenameFn>,
+    pub(crate) proc_tables: bool,
+    pub(crate) shared_array_codecs: bool,
+    pub(crate) box_large_variants: Option<usize>,
+    pub(crate) metrics: bool,
+    pub(crate) unknown_enum: UnknownEnum,
+    pub(crate) serde_rename_all: Option<String>,
+    pub(crate) tokio_codecs: bool,
+    pub(crate) ffi_mirror: bool,
+    pub(crate) catch_panics: bool,
+    pub(crate) scratch_packers: bool,
+    pub(crate) zero_copy: bool,
+    pub(crate) dispatch_tags: Vec<(String, u32)>,
+    pub(crate) length_width: LenWidth,
+    pub(crate) log_fields: bool,
+    pub(crate) packed_size: bool,
+    pub(crate) string_encoding: StrEnc,
+    pub(crate) emit_skip: bool,
+    pub(crate) dyn_codec: bool,
+    pub(crate) serde_default: bool,
+    pub(crate) checksum: Option<Checksum>,
+    pub(crate) io_methods: bool,
+    pub(crate) self_describing: bool,
+    pub(crate) canonical: bool,
+}
+
+impl GenOptions {
+    pub fn new() -> GenOptions {
+        GenOptions::default()
+    }
+
+    /// Definitions which should not be emitted.
+    pub fn exclude_defs(mut self, defs: &[&str]) -> GenOptions {
+        self.exclude_defs = defs.iter().map(|d| d.to_string()).collect();
+        self
+    }
+
+    /// Pairs of structs to generate conversions between.
+    ///
+    /// For each `(Old, New)` pair, conversions are generated in both directions. Fields are
+    /// matched by name; fields which only exist in the destination are filled in with
+    /// `Default::default()`, and fields which only exist in the source are dropped. Matching
+    /// fields must have the same Rust type, otherwise generation fails.
+    ///
+    /// Conversions are `From` unless a flex field has a smaller bound in the destination than
+    /// in the source, in which case it's a `TryFrom` that fails with `InvalidLen`.
+    pub fn conversions(mut self, pairs: &[(&str, &str)]) -> GenOptions {
+        self.conversions = pairs
+            .iter()
+            .map(|&(a, b)| (a.to_string(), b.to_string()))
+            .collect();
+        self
+    }
+
+    /// Limit on the encoded size of top-level messages.
+    ///
+    /// Top-level types are those which aren't used within any other type in the specification.
+    /// Their `Unpack` implementations fail with `MessageTooLarge` once more than `max` bytes have
+    /// been consumed, regardless of the bounds on individual fields.
+    pub fn max_message_size(mut self, max: usize) -> GenOptions {
+        self.max_message_size = Some(max);
+        self
+    }
+
+    /// Implement `FromIterator` and `Extend` for variable-length array typedefs.
+    ///
+    /// For bounded arrays these panic if the bound would be exceeded; a `try_extend` method is
+    /// also generated which fails with `InvalidLen` instead.
+    pub fn flex_collections(mut self, enable: bool) -> GenOptions {
+        self.flex_collections = enable;
+        self
+    }
+
+    /// Skip unknown trailing fields on top-level structs.
+    ///
+    /// This allows older code to read messages from newer senders which have appended fields to
+    /// the end of a struct. After unpacking the known fields, everything remaining in the input is
+    /// consumed and discarded.
+    ///
+    /// This relies on the input containing exactly one message, so it must be framed by some other
+    /// means - for example each record from `xdr_codec::record::XdrRecordReader`, or a buffer
+    /// delimited by a length prefix. Unpacking from an unframed stream will consume the rest of
+    /// the stream.
+    pub fn forward_compat(mut self, enable: bool) -> GenOptions {
+        self.forward_compat = enable;
+        self
+    }
+
+    /// Generate helper methods on unions.
+    ///
+    /// Each arm gets an `is_<arm>` predicate, with the arm's label converted to snake case, so
+    /// `case NFS3_OK` becomes `is_nfs3_ok()`. The default arm is `is_default()`.
+    ///
+    /// Each arm also gets a constructor with the snake case name, taking the arm's value, so
+    /// `case NFS3_OK: ok_res resok;` is built with `nfs3_ok(resok)` and a void arm with
+    /// `nfs3_ok()`. Constructors box values for arms which are boxed. Arms whose label is
+    /// already snake case, including the default arm, are built with the variant itself.
+    pub fn union_helpers(mut self, enable: bool) -> GenOptions {
+        self.union_helpers = enable;
+        self
+    }
+
+    /// Generate a `pack_with_policy` method on top-level types.
+    ///
+    /// This takes an `xdr_codec::FlushPolicy` which decides when the output is flushed while
+    /// packing, for streaming out very large messages. Plain `Pack` is unaffected and never
+    /// flushes.
+    pub fn flush_policy(mut self, enable: bool) -> GenOptions {
+        self.flush_policy = enable;
+        self
+    }
+
+    /// Implement `xdr_codec::Validate` for all types.
+    ///
+    /// `validate` recursively checks that all bounded strings, opaque data and arrays are within
+    /// their maximum sizes, failing with `InvalidLen` otherwise. With `StrEnc::Latin1`, strings
+    /// are checked in characters, and fail with `NotLatin1` if they can't be encoded. Types
+    /// referenced by the specification but defined elsewhere must also implement `Validate`.
+    pub fn emit_validate(mut self, enable: bool) -> GenOptions {
+        self.emit_validate = enable;
+        self
+    }
+
+    /// Generate a `const fn name(self) -> &'static str` on enums, returning the name of the
+    /// value as written in the specification. Being `const`, it can be used to build static
+    /// tables.
+    pub fn enum_names(mut self, enable: bool) -> GenOptions {
+        self.enum_names = enable;
+        self
+    }
+
+    /// Use `u32` discriminants for enums without negative values.
+    ///
+    /// XDR enums are signed, so by default they're `i32`. Some protocols treat them as unsigned
+    /// and use values above `i32::MAX`; with this set such enums are `#[repr(u32)]` and are packed
+    /// and unpacked as `u32`. The encoding is the same either way.
+    pub fn unsigned_enums(mut self, enable: bool) -> GenOptions {
+        self.unsigned_enums = enable;
+        self
+    }
+
+    /// Implement `xdr_codec::UnpackTraced` for structs and unions.
+    ///
+    /// `unpack_traced` unpacks like `Unpack`, but also reports each field's name, byte offset and
+    /// value to an `xdr_codec::Trace` hook, for comparing a decode against another implementation.
+    /// It doesn't apply `max_message_size` or `forward_compat`. This adds a lot of code, so it's
+    /// best only enabled while debugging.
+    pub fn trace(mut self, enable: bool) -> GenOptions {
+        self.trace = enable;
+        self
+    }
+
+    /// Generate `pack_array` and `unpack_array` associated functions on each type.
+    ///
+    /// These pack a slice of values as an XDR variable-length array - a length prefix followed by
+    /// the elements - so that a `Vec<Foo>` can be serialized directly, without defining a typedef
+    /// to wrap it.
+    pub fn array_helpers(mut self, enable: bool) -> GenOptions {
+        self.array_helpers = enable;
+        self
+    }
+
+    /// Fail with "no definitions found" if the specification doesn't define anything.
+    ///
+    /// By default an empty or comment-only specification generates a module with no items, which
+    /// can hide a build script pointing at the wrong file.
+    pub fn reject_empty(mut self, enable: bool) -> GenOptions {
+        self.reject_empty = enable;
+        self
+    }
+
+    /// Generate a `content_hash` method on each type.
+    ///
+    /// This hashes the packed encoding of the value with `xdr_codec::content_hash`, so two values
+    /// with the same wire form always have the same hash, on any platform. It's suitable for
+    /// deduplication and content-addressed storage, but isn't a cryptographic hash.
+    pub fn content_hash(mut self, enable: bool) -> GenOptions {
+        self.content_hash = enable;
+        self
+    }
+
+    /// Expand `#include` directives for `.x` files, searching `dirs` for them.
+    ///
+    /// Without this all `#include` lines are ignored. With it, includes of files ending in `.x`
+    /// are replaced by the file's contents, and other includes - such as C headers - are still
+    /// ignored. As with the C preprocessor, `#include "foo.x"` is looked for relative to the
+    /// directory of the including file before `dirs`, and `#include <foo.x>` only in `dirs`.
+    /// The including file is the `infile` passed to `generate_with_options`. Each file is only
+    /// included once.
+    pub fn include_dirs<P: Into<PathBuf> + Clone>(mut self, dirs: &[P]) -> GenOptions {
+        self.include_dirs = Some(dirs.iter().cloned().map(Into::into).collect());
+        self
+    }
+
+    /// Types which hold IP addresses, to generate conversions to and from `std::net` types for.
+    ///
+    /// Each type must either be a typedef of a suitable type, or a struct with a single field of
+    /// one - see `AddrForm` for which types those are. `From` is implemented in both directions,
+    /// so a decoded value can be turned into an `Ipv4Addr` with `.into()`. The encoding is
+    /// unaffected. Typedefs which are plain aliases of `unsigned int` already have conversions,
+    /// so nothing is generated for them.
+    pub fn address_types(mut self, types: &[(&str, AddrForm)]) -> GenOptions {
+        self.address_types = types
+            .iter()
+            .map(|&(name, form)| (name.to_string(), form))
+            .collect();
+        self
+    }
+
+    /// Implement `Default` for an enum, returning `value`.
+    ///
+    /// XDR has no notion of a default enum value, so otherwise enums don't implement `Default`.
+    /// This can be given several times for different enums. Generation fails if `value` isn't
+    /// one of the enum's values.
+    pub fn enum_default(mut self, name: &str, value: &str) -> GenOptions {
+        self.enum_defaults.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Use existing Rust types for XDR structs, unions and enums, and only generate their codecs.
+    ///
+    /// Each `(name, path)` pair replaces the definition of `name` with `pub type name = path;`,
+    /// so the generated `Pack` and `Unpack` implementations, and any other generated types using
+    /// `name`, apply to the type at `path`. Generation fails with an error if `name` isn't a
+    /// struct, union or enum in the specification, is given more than once, or `path` isn't a
+    /// Rust path.
+    ///
+    /// The type at `path` must have the same shape as the XDR definition: fields, variants and
+    /// enum values with the same names and types. xdrgen can't see it, so this is checked when
+    /// the generated codecs are compiled. The type also needs the traits derived by any generated
+    /// types which contain it.
+    pub fn external_types(mut self, types: &[(&str, &str)]) -> GenOptions {
+        self.external_types = types
+            .iter()
+            .map(|&(name, path)| (name.to_string(), path.to_string()))
+            .collect();
+        self
+    }
+
+    /// Whether generated codecs are optimized for speed, the default, or binary size.
+    ///
+    /// With `Opt::Size`, fixed-size arrays are unpacked by a generic helper in `xdr_codec`
+    /// rather than by code inlined at each use, and enum codecs aren't marked `#[inline]`. The
+    /// encoding is the same either way.
+    pub fn optimize(mut self, opt: Opt) -> GenOptions {
+        self.optimize = opt;
+        self
+    }
+
+    /// Generate a resumable decoder for each struct and union.
+    ///
+    /// For a type `Foo` this is `FooDecoder`, an implementation of `xdr_codec::Decode<Foo>`
+    /// which can be fed input as it arrives, returning `Progress::Pending` until the value is
+    /// complete. Decoded members are kept between calls, and nested structs and unions are
+    /// decoded the same way, so only the member which is split between pieces of input is
+    /// buffered. Other members, including variable-length arrays, are buffered whole.
+    pub fn resumable(mut self, enable: bool) -> GenOptions {
+        self.resumable = enable;
+        self
+    }
+
+    /// Rename identifiers in the generated code with `rename`.
+    ///
+    /// `rename` is called with each type, member, enum value and constant name in the
+    /// specification, and returns the Rust identifier to use for it, which still has `_` appended
+    /// if it's a Rust keyword. References to the renamed definitions follow. Generation fails if
+    /// the result isn't a valid identifier, or if two names in the same scope are renamed to the
+    /// same thing. Names given to other options are the renamed ones.
+    ///
+    /// The original names are kept where they matter for interoperability: `name()` from
+    /// `GenOptions::enum_names` returns them, and with the `derive_serde` feature they're used as
+    /// the serialized names.
+    pub fn rename_fn(mut self, rename: Box<Rename>) -> GenOptions {
+        self.rename_fn = Some(RenameFn(Arc::from(rename)));
+        self
+    }
+
+    /// Generate a table of procedure names for each RPC program, for logging and tracing.
+    ///
+    /// For a program `FOO_PROG` this is a module `foo_prog` with a submodule for each version,
+    /// holding `VERSION`, `PROCS: &[(u32, &str)]` mapping procedure numbers to their names, and
+    /// `proc_name(u32) -> Option<&str>`. `PROCS` and `proc_name` for the highest version are also
+    /// available from the program's module.
+    pub fn proc_tables(mut self, enable: bool) -> GenOptions {
+        self.proc_tables = enable;
+        self
+    }
+
+    /// Unpack fixed-size arrays of non-opaque types with a generic helper.
+    ///
+    /// Normally each array is unpacked by code inlined at its use, which adds up for
+    /// specifications with many fixed arrays. With this, each array is a call to
+    /// `xdr_codec::unpack_fixed`, so arrays with the same element type and size share their code.
+    /// Packing is always a call to `xdr_codec::pack_array`. The encoding is the same.
+    pub fn shared_array_codecs(mut self, enable: bool) -> GenOptions {
+        self.shared_array_codecs = enable;
+        self
+    }
+
+    /// Box union arms whose payload is estimated to be larger than `threshold` bytes.
+    ///
+    /// A union is as large as its largest arm, so one large arm makes every value big. With this,
+    /// such arms hold a `Box` of their type instead, which shrinks the union to the size of the
+    /// remaining arms plus a pointer. `Pack` and `Unpack` box and unbox them, and the encoding is
+    /// unchanged. Sizes are estimated as for `Symtab::mem_report`.
+    pub fn box_large_variants(mut self, threshold: usize) -> GenOptions {
+        self.box_large_variants = Some(threshold);
+        self
+    }
+
+    /// Report each pack and unpack to the `xdr_codec::Metrics` hook.
+    ///
+    /// Every generated `Pack` and `Unpack` implementation passes its result, with the type's name
+    /// from the specification, to the hook installed with `xdr_codec::set_metrics`, so bytes
+    /// processed, messages decoded and decode errors can be counted by type. Nested structs,
+    /// unions and enums report as well as the top-level message, and each report costs at least
+    /// an atomic load, so it's off by default.
+    pub fn metrics(mut self, enable: bool) -> GenOptions {
+        self.metrics = enable;
+        self
+    }
+
+    /// What every generated enum's `Unpack` does with a value not in the specification.
+    ///
+    /// With `UnknownEnum::Error`, the default, decoding fails. With `UnknownEnum::Preserve`,
+    /// each enum gets an extra `Unknown(i32)` variant - `Unknown(u32)` with `unsigned_enums` -
+    /// which holds the value and packs it back unchanged, so messages from a peer with a newer
+    /// specification pass through intact. Such enums can't have explicit discriminants, so values
+    /// can't be converted with `as`, and `enum_names` gives `"unknown"` for them. With
+    /// `UnknownEnum::Clamp`, unknown values decode as the enum's default, and generation fails
+    /// unless every enum has one set with `enum_default`. Clamping loses the value, so a message
+    /// which is decoded and packed again won't be the same.
+    ///
+    /// Unions switching on an enum have no arm for unknown values, so they still fail to decode
+    /// them. Enums given with `external_types` need an `Unknown` variant to be used with
+    /// `UnknownEnum::Preserve`.
+    pub fn unknown_enum(mut self, policy: UnknownEnum) -> GenOptions {
+        self.unknown_enum = policy;
+        self
+    }
+
+    /// Add `#[serde(rename_all = case)]` to generated structs.
+    ///
+    /// This gives struct fields uniform casing in their serde representation, such as
+    /// `"camelCase"` keys in JSON, however the specification names them. Fields renamed to avoid
+    /// Rust keywords keep their `#[serde(rename)]`, which takes precedence, so they still
+    /// serialize with their name in the specification. Enum values and union arms keep their own
+    /// names: serde converts variant names as if they were Rust's `PascalCase`, which mangles the
+    /// `SCREAMING_CASE` specifications normally use. `case` is any of the values serde accepts for
+    /// `rename_all`; generation fails for anything else. It only has an effect with the
+    /// `derive_serde` feature.
+    pub fn serde_rename_all(mut self, case: &str) -> GenOptions {
+        self.serde_rename_all = Some(case.to_string());
+        self
+    }
+
+    /// Generate a `codec` function on top-level types, giving an `xdr_codec::codec::RecordCodec`
+    /// for sending and receiving them over a `tokio_util::codec::Framed` stream.
+    ///
+    /// Each message is one record, framed with record marking as for XDR-RPC over TCP. The codec
+    /// takes its limit from `max_message_size`, if set, or otherwise uses
+    /// `xdr_codec::codec::DEFAULT_MAX_RECORD`. The generated code needs `xdr-codec`'s
+    /// `tokio-codec` feature.
+    pub fn tokio_codecs(mut self, enable: bool) -> GenOptions {
+        self.tokio_codecs = enable;
+        self
+    }
+
+    /// Generate a `#[repr(C)]` mirror of each struct, for passing values to C.
+    ///
+    /// The mirror of `foo` is `foo_ffi`, with the same fields and `From` conversions each way.
+    /// Nested structs are mirrored too, so the layout is the one a C compiler would give the
+    /// same structure. Only fixed-size fields can be mirrored: integers, floats, `bool`, fixed
+    /// arrays and opaque data, and other structs of them. Structs with anything else, such as
+    /// variable-length arrays, strings, optional data, unions or enums, are skipped with a
+    /// warning.
+    pub fn ffi_mirror(mut self, enable: bool) -> GenOptions {
+        self.ffi_mirror = enable;
+        self
+    }
+
+    /// Run generated `Unpack` implementations through `xdr_codec::catch_panic`.
+    ///
+    /// When built with `--cfg fuzzing`, as `cargo fuzz` does, a panic while decoding becomes an
+    /// `ErrorKind::Panicked` error, so fuzz targets can tell decoder bugs from invalid input.
+    /// Other builds call the decoder directly.
+    pub fn catch_panics(mut self, enable: bool) -> GenOptions {
+        self.catch_panics = enable;
+        self
+    }
+
+    /// Generate a `pack_with_scratch` method on top-level types.
+    ///
+    /// This packs into a caller's `Vec<u8>`, clearing it first, so a loop packing many messages
+    /// reuses one allocation rather than making a new buffer for each.
+    pub fn scratch_packers(mut self, enable: bool) -> GenOptions {
+        self.scratch_packers = enable;
+        self
+    }
+
+    /// Generate borrowing views of types for zero-copy decoding from a byte slice.
+    ///
+    /// Each type holding variable-length strings or opaque data, directly or through the types it
+    /// contains, gets a view `name_ref<'a>` with `&'a str` and `&'a [u8]` fields borrowed from the
+    /// input, and an `into_owned` method making the owned type. Decode views with
+    /// `xdr_codec::unpack_borrowed`; types which don't borrow anything decode as themselves, so
+    /// they can be used the same way. Types given with `external_types` need their own
+    /// `UnpackBorrowed` implementations.
+    ///
+    /// Views only apply to decoding, and only from a contiguous slice. They're decoded without
+    /// `max_message_size`, `metrics` or `catch_panics`, which only apply to `Unpack`.
+    pub fn zero_copy(mut self, enable: bool) -> GenOptions {
+        self.zero_copy = enable;
+        self
+    }
+
+    /// Generate `dispatch_map`, a map from tags to decoders for the types given them.
+    ///
+    /// Each `(name, tag)` pair gives type `name` the tag `tag`. The map's values are functions
+    /// `fn(&mut dyn Read) -> Result<Box<dyn Any>>` which decode a message of that type, for
+    /// message routers which read a tag from the stream and decode whatever type it names.
+    /// Generation fails if a type is unknown or two types have the same tag. Nothing is generated
+    /// unless some tags are given.
+    pub fn dispatch_tags(mut self, tags: &[(&str, u32)]) -> GenOptions {
+        self.dispatch_tags = tags.iter().map(|&(name, tag)| (name.to_string(), tag)).collect();
+        self
+    }
+
+    /// The width of the length prefixes of variable-length arrays, opaque data and strings.
+    ///
+    /// The default `LenWidth::U32` is standard XDR. With `LenWidth::U64` lengths are packed as
+    /// `unsigned hyper`, by the helpers in `xdr_codec::wide`, for protocols which extend XDR to
+    /// carry larger data. Bounds in the specification are checked before anything is allocated
+    /// either way. This applies to everything generated which packs or unpacks, but not to types
+    /// given with `external_types`, which have their own codecs.
+    pub fn length_width(mut self, width: LenWidth) -> GenOptions {
+        self.length_width = width;
+        self
+    }
+
+    /// Log each struct field at trace level with the `log` crate as it's packed or unpacked.
+    ///
+    /// The messages name the type and field, as `point.x`, with the number of bytes it took, so
+    /// running with `RUST_LOG=trace` shows how far a decode got without implementing a `Trace`
+    /// hook. The crate using the generated code needs a `log` 0.4 dependency. Off by default, as
+    /// the calls cost something even when the level is disabled.
+    pub fn log_fields(mut self, enable: bool) -> GenOptions {
+        self.log_fields = enable;
+        self
+    }
+
+    /// Give each struct, union, enum and fixed-length array typedef whose packed size is always
+    /// the same a `PACKED_SIZE: usize` constant with that size.
+    ///
+    /// Being a constant, it can size buffers, as `[u8; point::PACKED_SIZE]`. A union only has one
+    /// if all its arms are the same size. Types with variable-length arrays, strings, opaque data
+    /// or optional data, or which contain a type given with `external_types`, don't get one.
+    pub fn packed_size(mut self, enable: bool) -> GenOptions {
+        self.packed_size = enable;
+        self
+    }
+
+    /// How the bytes of strings are decoded, as `StrEnc`.
+    ///
+    /// RFC4506 strings are ASCII, but nothing stops a peer sending other bytes, and by default
+    /// unpacking a string which isn't UTF-8 fails with `InvalidUtf8`. With `StrEnc::Latin1` the
+    /// bytes are decoded as Latin-1, which always succeeds, and with `StrEnc::Bytes` strings are
+    /// generated as `Vec<u8>`, the same as `opaque`. The encoding on the wire is the same either
+    /// way. Borrowing views from `zero_copy` can't borrow Latin-1 strings, so they hold `String`.
+    pub fn string_encoding(mut self, enc: StrEnc) -> GenOptions {
+        self.string_encoding = enc;
+        self
+    }
+
+    /// Generate `skip(input) -> Result<usize>` for each struct, union, enum and array typedef,
+    /// which reads past a packed value of the type and returns its size, without unpacking it.
+    ///
+    /// This is for skipping parts of a stream which aren't wanted, such as fields a reader doesn't
+    /// know about. Opaque data and strings are discarded as they're read, so skipping them
+    /// doesn't allocate, but otherwise a value is checked as `Unpack` would check it: bounds,
+    /// enum values and union discriminants, though not that strings are UTF-8.
+    pub fn emit_skip(mut self, enable: bool) -> GenOptions {
+        self.emit_skip = enable;
+        self
+    }
+
+    /// Implement `xdr_codec::Codec` for each struct, union, enum and array typedef, so that values
+    /// of different types can be packed and unpacked as `Box<dyn Codec>`, for registries of
+    /// message types which aren't known statically.
+    pub fn dyn_codec(mut self, enable: bool) -> GenOptions {
+        self.dyn_codec = enable;
+        self
+    }
+
+    /// With the `derive_serde` feature, mark optional struct fields and variable-length arrays of
+    /// anything but opaque data and strings with `#[serde(default)]`, so that they can be missing
+    /// when deserializing, as `None` or an empty array. Fields whose types are named typedefs
+    /// other than aliases aren't marked, as they don't implement `Default`.
+    pub fn serde_default(mut self, enable: bool) -> GenOptions {
+        self.serde_default = enable;
+        self
+    }
+
+    /// Generate `pack_with_checksum(out) -> Result<(usize, u32)>` for each struct, union, enum and
+    /// array typedef, which packs the value and also returns the checksum of the bytes written,
+    /// computed as they're written, for protocols which append one to each message.
+    pub fn checksum(mut self, algo: Checksum) -> GenOptions {
+        self.checksum = Some(algo);
+        self
+    }
+
+    /// Generate `from_reader(reader)` and `to_writer(&self, writer)` for each struct, union, enum
+    /// and array typedef, named as in `serde_json`, which unpack and pack the type without
+    /// needing `xdr_codec::unpack` and `xdr_codec::pack` in scope.
+    pub fn io_methods(mut self, enable: bool) -> GenOptions {
+        self.io_methods = enable;
+        self
+    }
+
+    /// Prefix top-level messages with a header identifying the specification, which unpacking
+    /// checks before decoding the message.
+    ///
+    /// Top-level types are those which aren't used within any other type in the specification.
+    /// The header is `xdr_codec::header`'s magic number and version, and `SPEC_HASH`, the
+    /// specification's `Symtab::fingerprint`, so a message from a peer built from a different
+    /// specification fails with `SpecMismatch` rather than being misread. This changes the
+    /// encoding, so both peers have to enable it. `skip`, `PACKED_SIZE`, zero-copy views and
+    /// resumable decoders take the header into account, but traced decoders only decode what
+    /// follows it, which can be read first with `xdr_codec::unpack_header`.
+    pub fn self_describing(mut self, enable: bool) -> GenOptions {
+        self.self_describing = enable;
+        self
+    }
+
+    /// Only unpack canonical encodings, so that each value has exactly one encoding, for
+    /// protocols which sign or hash messages.
+    ///
+    /// The padding after opaque data and strings has to be zero, and unions don't decode their
+    /// `default` arms, which don't keep the discriminant they were decoded from. Everything else
+    /// `Unpack` already checks: booleans, enum values and union discriminants have to be ones
+    /// which are defined, and strings valid UTF-8. This can't be combined with the options which
+    /// accept more than one encoding of a value, `forward_compat` and `UnknownEnum::Clamp`, or
+    /// with `zero_copy`, whose views don't check padding.
+    pub fn canonical(mut self, enable: bool) -> GenOptions {
+        self.canonical = enable;
+        self
+    }
+}
@@ -0,0 +1,72 @@
+// Conversions between types carrying IP addresses and `std::net` address types. The encoding is
+// unchanged; these only convert the decoded value.
+use proc_macro2::TokenStream;
+
+use super::{Decl, Error, Result, Symtab, Type, quote_ident};
+use super::super::AddrForm;
+
+#[derive(Debug, Clone)]
+pub struct AddrType(pub String, pub AddrForm);
+
+// If a value of type `ty` can hold the address form, the conversion from an address `a` to it.
+fn from_addr(symtab: &Symtab, ty: &Type, form: AddrForm) -> Option<TokenStream> {
+    let len = match form {
+        AddrForm::Ipv4 => 4,
+        AddrForm::Ipv6 => 16,
+    };
+
+    match symtab.resolve(ty) {
+        Type::UInt if form == AddrForm::Ipv4 => Some(quote!(u32::from(a))),
+        Type::Array(elem, sz) if **elem == Type::Opaque && sz.as_i64(symtab) == Some(len) => {
+            Some(quote!(a.octets()))
+        }
+        _ => None,
+    }
+}
+
+impl AddrType {
+    pub fn define(&self, symtab: &Symtab) -> Result<TokenStream> {
+        let AddrType(name, form) = self;
+        let addr = match form {
+            AddrForm::Ipv4 => quote!(::std::net::Ipv4Addr),
+            AddrForm::Ipv6 => quote!(::std::net::Ipv6Addr),
+        };
+        let err = || Error::from(format!("address type {}: can't be converted to {:?}", name, form));
+
+        // The field holding the address, or None for a newtype
+        let (field, conv) = match symtab.typespec(name) {
+            None => return Err(format!("address type {}: unknown type", name).into()),
+            Some(Type::Struct(decls)) => match &decls[..] {
+                [Decl::Named(field, ty)] => (Some(quote_ident(field)), from_addr(symtab, ty, *form)),
+                _ => (None, None),
+            },
+            Some(ty) => (None, from_addr(symtab, ty, *form)),
+        };
+        let conv = conv.ok_or_else(err)?;
+
+        // Aliases already have the standard conversions
+        if symtab.typesyns().any(|(n, _)| n == name) {
+            return Ok(quote!());
+        }
+
+        let name = quote_ident(name);
+        let (get, make) = match field {
+            None => (quote!(v.0), quote!(#name(#conv))),
+            Some(field) => (quote!(v.#field), quote!(#name { #field: #conv })),
+        };
+
+        Ok(quote! {
+            impl From<#name> for #addr {
+                fn from(v: #name) -> #addr {
+                    #addr::from(#get)
+                }
+            }
+
+            impl From<#addr> for #name {
+                fn from(a: #addr) -> #name {
+                    #make
+                }
+            }
+        })
+    }
+}
@@ -0,0 +1,339 @@
+// Export a specification as Apache Avro schemas, so that XDR-defined messages can be registered
+// with a schema registry and used in an Avro-based data platform.
+//
+// As with the proto export, this carries over the shape of the types, not their encoding.
+// Structs become records, enums enums, and fixed-length opaque data `fixed`. Avro unions are
+// untagged, so an XDR union becomes a record holding the discriminant and a nullable field for
+// each arm. Anything Avro can't express, such as unsigned types, bounds and enum values, is
+// kept as a note in the `doc` of the field or type.
+use std::collections::HashSet;
+use std::fmt::Write;
+
+use super::{Decl, EnumDefn, Result, Symtab, Type, UnionCase, Value};
+
+enum Json {
+    Null,
+    Num(i64),
+    Str(String),
+    Arr(Vec<Json>),
+    Obj(Vec<(&'static str, Json)>),
+}
+
+impl Json {
+    fn str<S: Into<String>>(s: S) -> Json {
+        Json::Str(s.into())
+    }
+
+    fn write(&self, out: &mut String, indent: usize) {
+        match self {
+            Json::Null => out.push_str("null"),
+            Json::Num(n) => {
+                let _ = write!(out, "{}", n);
+            }
+            Json::Str(s) => {
+                out.push('"');
+                for c in s.chars() {
+                    match c {
+                        '"' => out.push_str("\\\""),
+                        '\\' => out.push_str("\\\\"),
+                        '\n' => out.push_str("\\n"),
+                        c if (c as u32) < 0x20 => {
+                            let _ = write!(out, "\\u{:04x}", c as u32);
+                        }
+                        c => out.push(c),
+                    }
+                }
+                out.push('"');
+            }
+            // Lists of names, such as the members of a union, stay on one line
+            Json::Arr(items) if items.iter().all(|i| matches!(i, Json::Str(_))) => {
+                out.push('[');
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        out.push_str(", ");
+                    }
+                    item.write(out, indent);
+                }
+                out.push(']');
+            }
+            Json::Arr(items) => {
+                out.push('[');
+                for (i, item) in items.iter().enumerate() {
+                    out.push_str(if i > 0 { ",\n" } else { "\n" });
+                    let _ = write!(out, "{:1$}", "", indent + 2);
+                    item.write(out, indent + 2);
+                }
+                let _ = write!(out, "\n{:1$}]", "", indent);
+            }
+            Json::Obj(fields) => {
+                out.push('{');
+                for (i, (key, val)) in fields.iter().enumerate() {
+                    out.push_str(if i > 0 { ",\n" } else { "\n" });
+                    let _ = write!(out, "{:1$}\"{2}\": ", "", indent + 2, key);
+                    val.write(out, indent + 2);
+                }
+                let _ = write!(out, "\n{:1$}}}", "", indent);
+            }
+        }
+    }
+}
+
+// A schema for a value, with notes on what the mapping lost
+struct Schema {
+    json: Json,
+    notes: Vec<String>,
+}
+
+impl Schema {
+    fn new(json: Json) -> Schema {
+        Schema { json, notes: Vec::new() }
+    }
+
+    fn note<S: Into<String>>(mut self, note: S) -> Schema {
+        self.notes.push(note.into());
+        self
+    }
+}
+
+// Avro needs a named type to be defined before it's referenced by name, so each is defined
+// where it's first used and referred to by name after that
+struct Avro<'a> {
+    symtab: &'a Symtab,
+    defined: HashSet<String>,
+}
+
+impl<'a> Avro<'a> {
+    fn value(&self, v: &Value) -> Result<i64> {
+        self.symtab
+            .value(v)
+            .ok_or_else(|| format!("avro: unknown value {:?}", v).into())
+    }
+
+    // The schema for a value of type `ty`, using `name` for a `fixed` if it needs one
+    fn schema(&mut self, ty: &Type, name: &str) -> Result<Schema> {
+        let symtab = self.symtab;
+        let ret = match symtab.resolve(ty) {
+            Type::Int => Schema::new(Json::str("int")),
+            Type::UInt => Schema::new(Json::str("long")).note("unsigned int"),
+            Type::Hyper => Schema::new(Json::str("long")),
+            Type::UHyper => Schema::new(Json::str("long")).note("unsigned hyper"),
+            Type::Float => Schema::new(Json::str("float")),
+            Type::Double => Schema::new(Json::str("double")),
+            Type::Quadruple => Schema::new(Json::str("bytes")).note("quadruple precision"),
+            Type::Bool => Schema::new(Json::str("boolean")),
+            Type::Opaque => Schema::new(Json::str("bytes")),
+            Type::String => Schema::new(Json::str("string")),
+
+            Type::Ident(id, _) => match symtab.typespec(id) {
+                Some(def @ Type::Enum(_)) | Some(def @ Type::Struct(_)) | Some(def @ Type::Union(..)) => {
+                    self.named(id, def)?
+                }
+                Some(def @ Type::Array(elem, _)) if matches!(symtab.resolve(elem), Type::Opaque) => {
+                    self.named(id, def)?
+                }
+                Some(def) => {
+                    let mut s = self.schema(def, id)?;
+                    s.notes.insert(0, id.clone());
+                    s
+                }
+                None => Schema::new(Json::str(id.as_str())),
+            },
+
+            Type::Option(ty) => {
+                let s = self.schema(ty, name)?;
+                let members = match s.json {
+                    Json::Arr(mut members) => {
+                        if !matches!(members.first(), Some(Json::Str(s)) if s == "null") {
+                            members.insert(0, Json::str("null"));
+                        }
+                        members
+                    }
+                    json => vec![Json::str("null"), json],
+                };
+                Schema { json: Json::Arr(members), notes: s.notes }
+            }
+
+            Type::Array(elem, sz) => {
+                let sz = self.value(sz)?;
+                match symtab.resolve(elem) {
+                    Type::Opaque => Schema::new(Json::Obj(vec![
+                        ("type", Json::str("fixed")),
+                        ("name", Json::str(name)),
+                        ("size", Json::Num(sz)),
+                    ])),
+                    Type::String => Schema::new(Json::str("string")).note(format!("fixed length {}", sz)),
+                    elem => self.array(elem, name)?.note(format!("fixed length {}", sz)),
+                }
+            }
+
+            Type::Flex(elem, sz) => {
+                let ret = match symtab.resolve(elem) {
+                    Type::Opaque => Schema::new(Json::str("bytes")),
+                    Type::String => Schema::new(Json::str("string")),
+                    elem => self.array(elem, name)?,
+                };
+                match sz {
+                    Some(sz) => ret.note(format!("at most {}", self.value(sz)?)),
+                    None => ret,
+                }
+            }
+
+            ty => return Err(format!("avro: anonymous type {:?} not supported", ty).into()),
+        };
+        Ok(ret)
+    }
+
+    fn array(&mut self, elem: &Type, name: &str) -> Result<Schema> {
+        let items = self.schema(elem, name)?;
+        Ok(Schema {
+            json: Json::Obj(vec![("type", Json::str("array")), ("items", items.json)]),
+            notes: items.notes,
+        })
+    }
+
+    // The named type `name`, defined by `ty`: its definition the first time, and then its name
+    fn named(&mut self, name: &str, ty: &Type) -> Result<Schema> {
+        if !self.defined.insert(name.to_string()) {
+            return Ok(Schema::new(Json::str(name)));
+        }
+        let mut def = match ty {
+            Type::Enum(edefs) => self.enumeration(edefs),
+            Type::Struct(decls) => self.record(name, decls)?,
+            Type::Union(sel, cases, defl) => self.union(name, sel, cases, defl)?,
+            ty => self.schema(ty, name)?.json,
+        };
+        // A `fixed` already has its name
+        if let Json::Obj(fields) = &mut def {
+            if !fields.iter().any(|(key, _)| *key == "name") {
+                fields.insert(1, ("name", Json::str(name)));
+            }
+        }
+        Ok(Schema::new(def))
+    }
+
+    fn enumeration(&self, edefs: &[EnumDefn]) -> Json {
+        let mut symbols = Vec::new();
+        let mut values = Vec::new();
+        for EnumDefn(field, _) in edefs {
+            if let Some((v, Some(_))) = self.symtab.getconst(field) {
+                symbols.push(Json::str(field.as_str()));
+                values.push(format!("{} = {}", field, v));
+            }
+        }
+        Json::Obj(vec![
+            ("type", Json::str("enum")),
+            ("doc", Json::Str(values.join(", "))),
+            ("symbols", Json::Arr(symbols)),
+        ])
+    }
+
+    fn field(name: &str, s: Schema, default: Option<Json>) -> Json {
+        let mut ret = vec![("name", Json::str(name)), ("type", s.json)];
+        if !s.notes.is_empty() {
+            ret.push(("doc", Json::Str(s.notes.join(", "))));
+        }
+        if let Some(default) = default {
+            ret.push(("default", default));
+        }
+        Json::Obj(ret)
+    }
+
+    fn record(&mut self, name: &str, decls: &[Decl]) -> Result<Json> {
+        let mut fields = Vec::new();
+        for decl in decls {
+            if let Decl::Named(id, ty) = decl {
+                let s = self.schema(ty, &format!("{}_{}", name, id))?;
+                fields.push(Avro::field(id, s, None));
+            }
+        }
+        Ok(Json::Obj(vec![("type", Json::str("record")), ("fields", Json::Arr(fields))]))
+    }
+
+    fn union(&mut self, name: &str, sel: &Decl, cases: &[UnionCase], defl: &Option<Box<Decl>>) -> Result<Json> {
+        let label = |val: &Value| match val {
+            Value::Ident(id) => id.clone(),
+            Value::Const(c) => c.to_string(),
+        };
+
+        // Arms in declaration order, with the cases selecting them
+        let mut arms: Vec<(&Decl, Vec<String>)> = Vec::new();
+        let cases = cases
+            .iter()
+            .map(|UnionCase(val, decl)| (decl, format!("case {}", label(val))))
+            .chain(defl.iter().map(|decl| (decl.as_ref(), "default".to_string())));
+        for (decl, case) in cases {
+            let same = arms.iter_mut().find(|(d, _)| match (d, decl) {
+                (Decl::Named(a, _), Decl::Named(b, _)) => a == b,
+                _ => false,
+            });
+            match same {
+                Some((_, labels)) => labels.push(case),
+                None => arms.push((decl, vec![case])),
+            }
+        }
+
+        let mut fields = Vec::new();
+        match sel {
+            Decl::Named(id, ty) => {
+                let s = self.schema(ty, &format!("{}_{}", name, id))?;
+                fields.push(Avro::field(id, s, None));
+            }
+            Decl::Void => return Err("avro: void union selector".into()),
+        }
+
+        let mut voids = Vec::new();
+        for (decl, labels) in arms {
+            match decl {
+                Decl::Void => voids.extend(labels),
+                Decl::Named(id, ty) => {
+                    let mut s = self.schema(&Type::Option(Box::new(ty.clone())), &format!("{}_{}", name, id))?;
+                    s.notes.splice(0..0, labels);
+                    fields.push(Avro::field(id, s, Some(Json::Null)));
+                }
+            }
+        }
+
+        let mut ret = vec![("type", Json::str("record"))];
+        if !voids.is_empty() {
+            ret.push(("doc", Json::Str(format!("void: {}", voids.join(", ")))));
+        }
+        ret.push(("fields", Json::Arr(fields)));
+        Ok(Json::Obj(ret))
+    }
+}
+
+/// Avro schemas for all the types in `symtab`, in namespace `namespace`.
+///
+/// The result is a JSON array of the named types, each defined where it's first used, so it can
+/// be parsed as a whole or split into the top-level schemas.
+pub fn avro(namespace: &str, symtab: &Symtab) -> Result<String> {
+    let ns: String = namespace
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '.' { c } else { '_' })
+        .collect();
+
+    let mut avro = Avro {
+        symtab,
+        defined: HashSet::new(),
+    };
+    let mut schemas = Vec::new();
+    for (name, ty) in symtab.typespecs() {
+        let named = match ty {
+            Type::Enum(_) | Type::Struct(_) | Type::Union(..) => true,
+            Type::Array(elem, _) => matches!(symtab.resolve(elem), Type::Opaque),
+            _ => false,
+        };
+        if !named || avro.defined.contains(name) {
+            continue;
+        }
+        if let Json::Obj(mut fields) = avro.named(name, ty)?.json {
+            fields.insert(2, ("namespace", Json::Str(ns.clone())));
+            schemas.push(Json::Obj(fields));
+        }
+    }
+
+    let mut out = String::new();
+    Json::Arr(schemas).write(&mut out, 0);
+    out.push('\n');
+    Ok(out)
+}
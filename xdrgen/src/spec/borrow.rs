@@ -0,0 +1,314 @@
+// Borrowing views of types, for zero-copy decoding with `xdr_codec::UnpackBorrowed`. A type which
+// holds variable-length strings or opaque data, directly or through the types it contains, gets a
+// view `name_ref<'a>` of the same shape with `&'a str` and `&'a [u8]` borrowed from the input in
+// their place, and an `into_owned` conversion back to the type itself. Every field borrows or
+// names a type which does, so the lifetime is always used and no `PhantomData` is needed. A type
+// which doesn't borrow anything is its own view.
+use proc_macro2::{Ident, TokenStream};
+
+use super::{Codegen, Decl, Derives, GenOptions, Result, StrEnc, Symtab, Type, Typespec, UnionCase, boxed_variant,
+            derives_boxed, quote_ident, rust_type};
+
+pub(super) fn view_name(name: &str) -> Ident {
+    quote_ident(format!("{}_ref", name))
+}
+
+// Whether the view of `ty` borrows from the input. External types are decoded as themselves.
+fn borrows(ty: &Type, symtab: &Symtab, opts: &GenOptions, seen: &mut Vec<String>) -> bool {
+    use super::Type::*;
+
+    match symtab.resolve(ty) {
+        Flex(elem, _) => match elem.as_ref() {
+            // Latin-1 has to be decoded into an owned `String`
+            String => opts.string_encoding != StrEnc::Latin1,
+            Opaque => true,
+            elem => borrows(elem, symtab, opts, seen),
+        },
+        Array(elem, _) => match elem.as_ref() {
+            String | Opaque => false,
+            elem => borrows(elem, symtab, opts, seen),
+        },
+        Option(elem) => borrows(elem, symtab, opts, seen),
+        Ident(name, _) => {
+            if seen.contains(name) || opts.external_types.iter().any(|(n, _)| n == name) {
+                return false;
+            }
+            let ty = match symtab.typespec(name) {
+                Some(ty) => ty,
+                None => return false,
+            };
+            seen.push(name.clone());
+            let ret = match ty {
+                Struct(decls) => decls.iter().any(|decl| match decl {
+                    Decl::Named(field, ty) => {
+                        matches!(rust_type(symtab, name, field), Ok(None)) && borrows(ty, symtab, opts, seen)
+                    }
+                    Decl::Void => false,
+                }),
+                Union(_, cases, defl) => cases
+                    .iter()
+                    .map(|UnionCase(_, decl)| decl)
+                    .chain(defl.as_deref())
+                    .any(|decl| match decl {
+                        Decl::Named(_, ty) => borrows(ty, symtab, opts, seen),
+                        Decl::Void => false,
+                    }),
+                ty => borrows(ty, symtab, opts, seen),
+            };
+            seen.pop();
+            ret
+        }
+        _ => false,
+    }
+}
+
+// Borrowed types and conversions to owned ones, for the fields of views
+struct Views<'a> {
+    symtab: &'a Symtab,
+    opts: &'a GenOptions,
+}
+
+impl<'a> Views<'a> {
+    fn borrows(&self, ty: &Type) -> bool {
+        borrows(ty, self.symtab, self.opts, &mut Vec::new())
+    }
+
+    // The type of a view of `ty`
+    fn view_type(&self, ty: &Type) -> Result<TokenStream> {
+        use super::Type::*;
+
+        if !self.borrows(ty) {
+            return ty.as_token(self.symtab);
+        }
+        let ret = match self.symtab.resolve(ty) {
+            Flex(elem, _) => match elem.as_ref() {
+                String => quote!(&'a str),
+                Opaque => quote!(&'a [u8]),
+                elem => {
+                    let tok = self.view_type(elem)?;
+                    quote!(Vec<#tok>)
+                }
+            },
+            Array(elem, sz) => {
+                let tok = self.view_type(elem)?;
+                let sz = sz.as_token(self.symtab);
+                quote!([#tok; #sz as usize])
+            }
+            Option(elem) => {
+                let tok = self.view_type(elem)?;
+                if elem.is_boxed(self.symtab) {
+                    quote!(Option<Box<#tok>>)
+                } else {
+                    quote!(Option<#tok>)
+                }
+            }
+            Ident(name, _) => {
+                let id = view_name(name);
+                quote!(#id<'a>)
+            }
+            ty => return Err(format!("can't have unnamed type {:?}", ty).into()),
+        };
+        Ok(ret)
+    }
+
+    // Convert `v`, a view of `ty`, to `ty`
+    fn owned(&self, ty: &Type, v: TokenStream) -> TokenStream {
+        use super::Type::*;
+
+        if !self.borrows(ty) {
+            return v;
+        }
+        match self.symtab.resolve(ty) {
+            Flex(elem, _) => match elem.as_ref() {
+                String => quote!(#v.to_string()),
+                Opaque => quote!(#v.to_vec()),
+                elem => {
+                    let conv = self.owned(elem, quote!(v));
+                    quote!(#v.into_iter().map(|v| #conv).collect())
+                }
+            },
+            Array(elem, _) => {
+                let conv = self.owned(elem, quote!(v));
+                quote!(#v.map(|v| #conv))
+            }
+            Option(elem) if elem.is_boxed(self.symtab) => {
+                let conv = self.owned(elem, quote!((*v)));
+                quote!(#v.map(|v| Box::new(#conv)))
+            }
+            Option(elem) => {
+                let conv = self.owned(elem, quote!(v));
+                quote!(#v.map(|v| #conv))
+            }
+            _ => quote!(#v.into_owned()),
+        }
+    }
+}
+
+impl Type {
+    // Unpacking of a field of a view. Anything which could borrow goes through
+    // `UnpackBorrowed`, so that the view's field type decides whether it does.
+    pub(super) fn borrowed_unpacker(&self, symtab: &Symtab, opt: Codegen) -> Option<TokenStream> {
+        use super::Type::*;
+
+        let ret = match self {
+            Flex(elem, maxsz) => {
+                let maxsz = match maxsz {
+                    None => quote!(None),
+                    Some(mx) => {
+                        let mx = mx.as_token(symtab);
+                        quote!(Some(#mx as usize))
+                    }
+                };
+                let helpers = opt.flex_helpers();
+                match elem.as_ref() {
+                    String if opt.str_enc == StrEnc::Latin1 => return None,
+                    String => quote!(#helpers::unpack_str_borrowed(input, #maxsz)?),
+                    Opaque => quote!(#helpers::unpack_opaque_borrowed(input, #maxsz)?),
+                    _ => quote!(#helpers::unpack_flex_borrowed(input, #maxsz)?),
+                }
+            }
+            Array(elem, _) if matches!(elem.as_ref(), String | Opaque) => return None,
+            Array(_, sz) => {
+                let sz = sz.as_token(symtab);
+                quote!(xdr_codec::unpack_array_borrowed::<_, { #sz as usize }>(input)?)
+            }
+            Option(_) | Ident(..) => quote!(xdr_codec::UnpackBorrowed::unpack_borrowed(input)?),
+            _ => return None,
+        };
+        Some(ret)
+    }
+}
+
+impl Typespec {
+    /// Generate the borrowing view of a type with its `UnpackBorrowed` implementation, or for a
+    /// type which doesn't borrow, an `UnpackBorrowed` implementation which unpacks it. `header` is
+    /// the specification's fingerprint for a top-level type with `GenOptions::self_describing`.
+    pub fn borrowed_view(&self, symtab: &Symtab, opts: &GenOptions, header: Option<u64>) -> Result<Option<TokenStream>> {
+        let name = quote_ident(&self.0);
+        let views = Views { symtab, opts };
+
+        if !views.borrows(&Type::ident(&self.0)) {
+            return Ok(Some(quote! {
+                impl<'a> xdr_codec::UnpackBorrowed<'a> for #name {
+                    fn unpack_borrowed(input: &mut &'a [u8]) -> xdr_codec::Result<(#name, usize)> {
+                        xdr_codec::Unpack::unpack(input)
+                    }
+                }
+            }));
+        }
+        let view = format!("{}_ref", self.0);
+        if symtab.typespec(&view).is_some() {
+            return Err(format!("{}: borrowing view {} is already defined", self.0, view).into());
+        }
+        let view = view_name(&self.0);
+
+        let (def, into_owned) = match &self.1 {
+            Type::Struct(decls) => {
+                let mut defs = Vec::new();
+                let mut convs = Vec::new();
+                for decl in decls {
+                    if let Decl::Named(field, ty) = decl {
+                        let id = quote_ident(field);
+                        let (tok, conv) = match rust_type(symtab, &self.0, field)? {
+                            Some(rust) => (rust, quote!(self.#id)),
+                            None => (views.view_type(ty)?, views.owned(ty, quote!(self.#id))),
+                        };
+                        defs.push(quote!(pub #id: #tok,));
+                        convs.push(quote!(#id: #conv,));
+                    }
+                }
+                (quote!(struct #view<'a> { #(#defs)* }), quote!(#name { #(#convs)* }))
+            }
+
+            Type::Union(_, cases, defl) => {
+                // Only the default arm is boxed, as it is for recursive types in the owned union
+                let box_over = opts.box_large_variants;
+                let mut defs = Vec::new();
+                let mut convs = Vec::new();
+                let arms = cases
+                    .iter()
+                    .map(|UnionCase(val, decl)| (val.as_ident(), decl, false))
+                    .chain(defl.iter().map(|decl| (quote_ident("default"), decl.as_ref(), true)));
+                for (label, decl, default) in arms {
+                    match decl {
+                        Decl::Void => {
+                            defs.push(quote!(#label,));
+                            convs.push(quote!(#view::#label => #name::#label,));
+                        }
+                        Decl::Named(_, ty) => {
+                            let tok = views.view_type(ty)?;
+                            let viewbox = default && ty.is_boxed(symtab);
+                            let ownbox = viewbox || boxed_variant(ty, symtab, box_over);
+                            let (tok, v) = if viewbox {
+                                (quote!(Box<#tok>), quote!((*v)))
+                            } else {
+                                (tok, quote!(v))
+                            };
+                            let conv = match (views.borrows(ty), viewbox, ownbox) {
+                                (false, true, true) => quote!(v),
+                                (_, _, true) => {
+                                    let conv = views.owned(ty, v);
+                                    quote!(Box::new(#conv))
+                                }
+                                _ => views.owned(ty, v),
+                            };
+                            defs.push(quote!(#label(#tok),));
+                            convs.push(quote!(#view::#label(v) => #name::#label(#conv),));
+                        }
+                    }
+                }
+                (quote!(enum #view<'a> { #(#defs)* }), quote!(match self { #(#convs)* }))
+            }
+
+            ty @ Type::Flex(..) | ty @ Type::Array(..) => {
+                let tok = views.view_type(ty)?;
+                let conv = views.owned(ty, quote!(self.0));
+                (quote!(struct #view<'a>(pub #tok);), quote!(#name(#conv)))
+            }
+
+            _ => return Ok(None),
+        };
+
+        // The owned type's derives which a view can have too
+        let derives = derives_boxed(&self.1, symtab, opts.box_large_variants);
+        let derive: Vec<_> = [
+            (Derives::CLONE, quote!(Clone)),
+            (Derives::DEBUG, quote!(Debug)),
+            (Derives::EQ, quote!(Eq)),
+            (Derives::PARTIALEQ, quote!(PartialEq)),
+        ]
+        .iter()
+        .filter(|(d, _)| derives.contains(*d))
+        .map(|(_, tok)| tok.clone())
+        .collect();
+        let derive = if derive.is_empty() { quote!() } else { quote!(#[derive(#(#derive),*)]) };
+
+        // Decoded as the owned type would be, but without the limits and handlers which only
+        // apply to `Unpack`
+        let opt = Codegen {
+            box_over: None,
+            metrics: false,
+            catch_panics: false,
+            borrow: true,
+            header,
+            ..Codegen::new(opts)
+        };
+        let unsigned = self.unsigned_repr(symtab, opts);
+        let unpack = self.unpack_impl(symtab, None, false, unsigned, opt)?;
+
+        Ok(Some(quote! {
+            /// Borrowing view of the type of the same name, decoded with `xdr_codec::UnpackBorrowed`.
+            #derive
+            pub #def
+
+            impl<'a> #view<'a> {
+                /// Copy everything borrowed from the input, making the owned value.
+                pub fn into_owned(self) -> #name {
+                    #into_owned
+                }
+            }
+
+            #unpack
+        }))
+    }
+}
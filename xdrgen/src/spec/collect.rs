@@ -0,0 +1,106 @@
+// `FromIterator` and `Extend` for the newtype wrappers generated for variable-length array
+// typedefs, so they can be built up like any other collection, and helpers for packing slices
+// of any type without a wrapper.
+use proc_macro2::TokenStream;
+
+use super::{Codegen, GenOptions, Result, Symtab, Type, Typespec, quote_ident};
+
+impl Typespec {
+    pub fn collection_impls(&self, symtab: &Symtab) -> Result<Option<TokenStream>> {
+        let (elem, max) = match &self.1 {
+            Type::Flex(elem, max) => (elem.as_ref(), max),
+            _ => return Ok(None),
+        };
+
+        let name = quote_ident(&self.0);
+        let item = match elem {
+            Type::String => quote!(char),
+            Type::Opaque => quote!(u8),
+            ty => ty.as_token(symtab)?,
+        };
+
+        let ret = match max {
+            None => quote! {
+                impl ::std::iter::FromIterator<#item> for #name {
+                    fn from_iter<I: IntoIterator<Item = #item>>(iter: I) -> #name {
+                        #name(iter.into_iter().collect())
+                    }
+                }
+
+                impl ::std::iter::Extend<#item> for #name {
+                    fn extend<I: IntoIterator<Item = #item>>(&mut self, iter: I) {
+                        self.0.extend(iter)
+                    }
+                }
+            },
+
+            Some(max) => {
+                let max = max.as_token(symtab);
+                quote! {
+                    impl #name {
+                        /// Extend with the contents of `iter`, failing with `InvalidLen` if
+                        /// that would exceed the bound. Nothing is added on failure.
+                        pub fn try_extend<I: IntoIterator<Item = #item>>(&mut self, iter: I) -> xdr_codec::Result<()> {
+                            let len = self.0.len();
+                            for v in iter {
+                                self.0.push(v);
+                                if self.0.len() > #max as usize {
+                                    self.0.truncate(len);
+                                    return Err(xdr_codec::Error::invalidlen(#max as usize));
+                                }
+                            }
+                            Ok(())
+                        }
+                    }
+
+                    impl ::std::iter::FromIterator<#item> for #name {
+                        /// Panics if `iter` has more elements than the bound.
+                        #[track_caller]
+                        fn from_iter<I: IntoIterator<Item = #item>>(iter: I) -> #name {
+                            let mut ret = #name(Default::default());
+                            ret.extend(iter);
+                            ret
+                        }
+                    }
+
+                    impl ::std::iter::Extend<#item> for #name {
+                        /// Panics if the result would exceed the bound.
+                        #[track_caller]
+                        fn extend<I: IntoIterator<Item = #item>>(&mut self, iter: I) {
+                            if let Err(e) = self.try_extend(iter) {
+                                panic!("{}: {}", stringify!(#name), e);
+                            }
+                        }
+                    }
+                }
+            }
+        };
+
+        Ok(Some(ret))
+    }
+}
+
+impl Typespec {
+    pub fn array_helpers(&self, opts: &GenOptions) -> Option<TokenStream> {
+        match self.1 {
+            Type::Enum(_) | Type::Struct(_) | Type::Union(..) | Type::Flex(..) | Type::Array(..) => (),
+            _ => return None,
+        }
+        let name = quote_ident(&self.0);
+        let helpers = Codegen::wire(opts).flex_helpers();
+
+        Some(quote! {
+            impl #name {
+                /// Pack `items` as a variable-length array, with a length prefix.
+                pub fn pack_array<Out: xdr_codec::Write>(items: &[#name], out: &mut Out) -> xdr_codec::Result<usize> {
+                    #helpers::pack_flex(items, None, out)
+                }
+
+                /// Unpack a variable-length array packed by `pack_array`.
+                pub fn unpack_array<In: xdr_codec::Read>(input: &mut In) -> xdr_codec::Result<(Vec<#name>, usize)> {
+                    #helpers::unpack_flex(input, None)
+                }
+            }
+        })
+    }
+}
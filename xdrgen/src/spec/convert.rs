@@ -0,0 +1,123 @@
+// Conversions between pairs of structs which share field names, such as two versions of the same
+// protocol message.
+use proc_macro2::TokenStream;
+
+use super::{Decl, Result, Symtab, Type, Value, quote_ident};
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct Conversion(pub String, pub String);
+
+impl Conversion {
+    pub fn define(&self, symtab: &Symtab) -> Result<TokenStream> {
+        let a = struct_fields(symtab, &self.0)?;
+        let b = struct_fields(symtab, &self.1)?;
+
+        let fwd = convert(symtab, &self.0, a, &self.1, b)?;
+        let back = convert(symtab, &self.1, b, &self.0, a)?;
+
+        Ok(quote!(#fwd #back))
+    }
+}
+
+fn struct_fields<'a>(symtab: &'a Symtab, name: &str) -> Result<&'a Vec<Decl>> {
+    match symtab.typespec(&name.to_string()) {
+        Some(Type::Struct(decls)) => Ok(decls),
+        Some(_) => Err(format!("conversion: {} is not a struct", name).into()),
+        None => Err(format!("conversion: unknown type {}", name).into()),
+    }
+}
+
+fn field<'a>(decls: &'a [Decl], name: &str) -> Option<&'a Type> {
+    decls.iter().find_map(|d| match *d {
+        Decl::Named(ref n, ref ty) if n == name => Some(ty),
+        _ => None,
+    })
+}
+
+// Return the destination bound if converting `from` into `to` could exceed it.
+fn narrowed<'a>(symtab: &Symtab, from: &Type, to: &'a Type) -> Option<&'a Value> {
+    match (from, to) {
+        (Type::Flex(_, fmax), Type::Flex(_, Some(tmax))) => {
+            let fmax = fmax.as_ref().and_then(|v| v.as_i64(symtab));
+            match (fmax, tmax.as_i64(symtab)) {
+                (Some(f), Some(t)) if f <= t => None,
+                _ => Some(tmax),
+            }
+        }
+        _ => None,
+    }
+}
+
+fn convert(
+    symtab: &Symtab,
+    fromname: &str,
+    from: &[Decl],
+    toname: &str,
+    to: &[Decl],
+) -> Result<TokenStream> {
+    let mut checks = Vec::new();
+    let mut inits = Vec::new();
+
+    for decl in to {
+        let (name, ty) = match *decl {
+            Decl::Named(ref name, ref ty) => (name, ty),
+            Decl::Void => continue,
+        };
+        let fieldname = quote_ident(name);
+
+        match field(from, name) {
+            None => inits.push(quote!(#fieldname: Default::default(),)),
+            Some(fromty) => {
+                let fromtok = fromty.as_token(symtab)?;
+                let totok = ty.as_token(symtab)?;
+                if fromtok.to_string() != totok.to_string() {
+                    return Err(
+                        format!(
+                            "conversion {} -> {}: field {} has mismatched types {} and {}",
+                            fromname,
+                            toname,
+                            name,
+                            fromtok,
+                            totok
+                        ).into(),
+                    );
+                }
+                if let Some(max) = narrowed(symtab, fromty, ty) {
+                    let max = max.as_token(symtab);
+                    checks.push(quote! {
+                        if v.#fieldname.len() > #max as usize {
+                            return Err(xdr_codec::Error::invalidlen(#max as usize));
+                        }
+                    });
+                }
+                inits.push(quote!(#fieldname: v.#fieldname,));
+            }
+        }
+    }
+
+    let fromname = quote_ident(fromname);
+    let toname = quote_ident(toname);
+
+    let ret = if checks.is_empty() {
+        quote! {
+            impl From<#fromname> for #toname {
+                #[allow(unused_variables)]
+                fn from(v: #fromname) -> #toname {
+                    #toname { #(#inits)* }
+                }
+            }
+        }
+    } else {
+        quote! {
+            impl ::std::convert::TryFrom<#fromname> for #toname {
+                type Error = xdr_codec::Error;
+
+                fn try_from(v: #fromname) -> xdr_codec::Result<#toname> {
+                    #(#checks)*
+                    Ok(#toname { #(#inits)* })
+                }
+            }
+        }
+    };
+    Ok(ret)
+}
@@ -0,0 +1,386 @@
+// Type-level differences between two versions of a specification, classified by whether peers
+// using the two versions can still interoperate.
+use std::cmp::max;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{self, Display, Formatter};
+
+use super::{Decl, EnumDefn, Symtab, Type, UnionCase, Value};
+
+/// Whether a change affects interoperability, for `Change`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Compat {
+    /// Existing encodings are unchanged. For an optional field appended to a top-level struct,
+    /// this is one-way: old peers can read new messages with `GenOptions::forward_compat`, but
+    /// new peers can't read old ones.
+    Compatible,
+    /// Peers using the old and new specifications can misinterpret each other
+    Breaking,
+}
+
+impl Display for Compat {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Compat::Compatible => write!(f, "compatible"),
+            Compat::Breaking => write!(f, "breaking"),
+        }
+    }
+}
+
+/// A difference between two versions of a specification, from `Symtab::diff`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+    /// Definition, or `definition.member`, which changed
+    pub item: String,
+    pub compat: Compat,
+    /// Description of the change
+    pub what: String,
+}
+
+impl Display for Change {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}: {}: {}", self.compat, self.item, self.what)
+    }
+}
+
+// Short XDR-style description of a type.
+fn describe(ty: &Type) -> String {
+    match ty {
+        Type::UInt => "unsigned int".into(),
+        Type::Int => "int".into(),
+        Type::UHyper => "unsigned hyper".into(),
+        Type::Hyper => "hyper".into(),
+        Type::Float => "float".into(),
+        Type::Double => "double".into(),
+        Type::Quadruple => "quadruple".into(),
+        Type::Bool => "bool".into(),
+        Type::Opaque => "opaque".into(),
+        Type::String => "string".into(),
+        Type::Enum(_) => "enum".into(),
+        Type::Struct(_) => "struct".into(),
+        Type::Union(..) => "union".into(),
+        Type::Option(ty) => format!("{} *", describe(ty)),
+        Type::Array(ty, sz) => format!("{}[{}]", describe(ty), describe_value(sz)),
+        Type::Flex(ty, None) => format!("{}<>", describe(ty)),
+        Type::Flex(ty, Some(sz)) => format!("{}<{}>", describe(ty), describe_value(sz)),
+        Type::Ident(name, _) => name.clone(),
+    }
+}
+
+fn describe_value(val: &Value) -> String {
+    match val {
+        Value::Ident(name) => name.clone(),
+        Value::Const(v) => v.to_string(),
+    }
+}
+
+// How changing type `a` in `old` to `b` in `new` affects the encoding: None if it's the same,
+// otherwise whether it's compatible. Raising the bound on a variable-length array is
+// compatible, since everything valid before remains valid.
+fn compare(old: &Symtab, a: &Type, new: &Symtab, b: &Type) -> Option<Compat> {
+    match (old.resolve(a), new.resolve(b)) {
+        (Type::Option(a), Type::Option(b)) => compare(old, a, new, b),
+        (Type::Array(a, n), Type::Array(b, m)) => {
+            if n.as_i64(old) != m.as_i64(new) {
+                Some(Compat::Breaking)
+            } else {
+                compare(old, a, new, b)
+            }
+        }
+        (Type::Flex(a, n), Type::Flex(b, m)) => {
+            let n = n.as_ref().and_then(|n| n.as_i64(old));
+            let m = m.as_ref().and_then(|m| m.as_i64(new));
+            let bound = match (n, m) {
+                (n, m) if n == m => None,
+                (_, None) => Some(Compat::Compatible),
+                (Some(n), Some(m)) if n < m => Some(Compat::Compatible),
+                _ => Some(Compat::Breaking),
+            };
+            max(bound, compare(old, a, new, b))
+        }
+        (Type::Ident(a, _), Type::Ident(b, _)) if a == b => None,
+        (a, b) if a == b => None,
+        _ => Some(Compat::Breaking),
+    }
+}
+
+fn compare_decl(old: &Symtab, a: &Decl, new: &Symtab, b: &Decl) -> Option<Compat> {
+    match (a, b) {
+        (Decl::Void, Decl::Void) => None,
+        (Decl::Named(_, a), Decl::Named(_, b)) => compare(old, a, new, b),
+        _ => Some(Compat::Breaking),
+    }
+}
+
+fn describe_decl(decl: &Decl) -> String {
+    match decl {
+        Decl::Void => "void".into(),
+        Decl::Named(_, ty) => describe(ty),
+    }
+}
+
+fn kind(ty: &Type) -> &'static str {
+    match ty {
+        Type::Enum(_) => "enum",
+        Type::Struct(_) => "struct",
+        Type::Union(..) => "union",
+        _ => "typedef",
+    }
+}
+
+// Accumulates the changes between two specifications.
+struct Differ<'a> {
+    old: &'a Symtab,
+    new: &'a Symtab,
+    changes: Vec<Change>,
+}
+
+impl<'a> Differ<'a> {
+    fn push<S: Into<String>>(&mut self, item: &str, compat: Compat, what: S) {
+        self.changes.push(Change {
+            item: item.to_string(),
+            compat,
+            what: what.into(),
+        });
+    }
+
+    fn definition(&mut self, name: &str, a: &Type, b: &Type) {
+        match (self.old.resolve(a), self.new.resolve(b)) {
+            (Type::Enum(a), Type::Enum(b)) => self.enumeration(name, a, b),
+            (Type::Struct(a), Type::Struct(b)) => self.structure(name, a, b),
+            (Type::Union(asel, acases, adefl), Type::Union(bsel, bcases, bdefl)) => {
+                if let Some(compat) = compare_decl(self.old, asel, self.new, bsel) {
+                    let what = format!(
+                        "discriminant changed from {} to {}",
+                        describe_decl(asel),
+                        describe_decl(bsel)
+                    );
+                    self.push(name, compat, what);
+                }
+                self.union(name, acases, bcases);
+                self.union_default(name, adefl.as_deref(), bdefl.as_deref());
+            }
+            (a, b) if kind(a) != kind(b) => {
+                self.push(name, Compat::Breaking, format!("changed from {} to {}", kind(a), kind(b)))
+            }
+            (a, b) => {
+                if let Some(compat) = compare(self.old, a, self.new, b) {
+                    self.push(name, compat, format!("changed from {} to {}", describe(a), describe(b)))
+                }
+            }
+        }
+    }
+
+    fn enumeration(&mut self, name: &str, a: &[EnumDefn], b: &[EnumDefn]) {
+        fn values(symtab: &Symtab, defs: &[EnumDefn]) -> BTreeMap<String, i64> {
+            defs.iter()
+                .filter_map(|EnumDefn(n, _)| symtab.getconst(n).map(|(v, _)| (n.clone(), v)))
+                .collect()
+        }
+        let a = values(self.old, a);
+        let b = values(self.new, b);
+
+        // Names which only appear in one version but have the same value are renames
+        let renamed: BTreeMap<&String, &String> = a
+            .iter()
+            .filter(|(n, _)| !b.contains_key(*n))
+            .filter_map(|(n, v)| {
+                b.iter()
+                    .find(|(m, w)| *w == v && !a.contains_key(*m))
+                    .map(|(m, _)| (n, m))
+            })
+            .collect();
+
+        for (n, v) in &a {
+            let item = format!("{}.{}", name, n);
+            match (b.get(n), renamed.get(n)) {
+                (Some(w), _) if w == v => (),
+                (Some(w), _) => self.push(&item, Compat::Breaking, format!("value changed from {} to {}", v, w)),
+                (None, Some(m)) => self.push(&item, Compat::Compatible, format!("renamed to {}", m)),
+                (None, None) => self.push(&item, Compat::Breaking, "removed"),
+            }
+        }
+
+        let renames: BTreeSet<&String> = renamed.values().cloned().collect();
+        for (m, w) in &b {
+            if !a.contains_key(m) && !renames.contains(m) {
+                let item = format!("{}.{}", name, m);
+                self.push(&item, Compat::Compatible, format!("added with value {}", w));
+            }
+        }
+    }
+
+    fn structure(&mut self, name: &str, a: &[Decl], b: &[Decl]) {
+        fn fields(decls: &[Decl]) -> Vec<(&String, &Type)> {
+            decls
+                .iter()
+                .filter_map(|decl| match decl {
+                    Decl::Named(field, ty) => Some((field, ty)),
+                    Decl::Void => None,
+                })
+                .collect()
+        }
+        let a = fields(a);
+        let b = fields(b);
+        let in_old = |field: &String| a.iter().any(|(n, _)| *n == field);
+        let in_new = |field: &String| b.iter().any(|(n, _)| *n == field);
+
+        // Fields in both versions must stay in the same order
+        let common_old: Vec<_> = a.iter().map(|f| f.0).filter(|n| in_new(n)).collect();
+        let common_new: Vec<_> = b.iter().map(|f| f.0).filter(|n| in_old(n)).collect();
+        if common_old != common_new {
+            self.push(name, Compat::Breaking, "fields reordered");
+            return;
+        }
+
+        let (mut i, mut j) = (0, 0);
+        while i < a.len() || j < b.len() {
+            match (a.get(i), b.get(j)) {
+                (Some((an, at)), Some((bn, bt))) if an == bn => {
+                    if let Some(compat) = compare(self.old, at, self.new, bt) {
+                        let what = format!("type changed from {} to {}", describe(at), describe(bt));
+                        self.push(&format!("{}.{}", name, an), compat, what);
+                    }
+                    i += 1;
+                    j += 1;
+                }
+                (Some((an, at)), Some((bn, bt))) if !in_new(an) && !in_old(bn) => {
+                    let item = format!("{}.{}", name, an);
+                    match compare(self.old, at, self.new, bt) {
+                        None => self.push(&item, Compat::Compatible, format!("renamed to {}", bn)),
+                        Some(_) => self.push(&item, Compat::Breaking, format!("replaced by {} {}", describe(bt), bn)),
+                    }
+                    i += 1;
+                    j += 1;
+                }
+                (Some((an, _)), _) if !in_new(an) => {
+                    self.push(&format!("{}.{}", name, an), Compat::Breaking, "removed");
+                    i += 1;
+                }
+                (_, Some((bn, bt))) => {
+                    let item = format!("{}.{}", name, bn);
+                    // Decoders of top-level types can skip trailing data, so old peers can
+                    // still read the struct with `GenOptions::forward_compat`. Extensions are
+                    // optional fields, which new senders can leave out.
+                    let optional = matches!(self.new.resolve(bt), Type::Option(_));
+                    if i == a.len() && optional && self.new.is_toplevel(name) {
+                        self.push(&item, Compat::Compatible, format!("added trailing {} field", describe(bt)));
+                    } else {
+                        self.push(&item, Compat::Breaking, format!("added {} field", describe(bt)));
+                    }
+                    j += 1;
+                }
+                (Some((an, _)), None) => {
+                    self.push(&format!("{}.{}", name, an), Compat::Breaking, "removed");
+                    i += 1;
+                }
+                (None, None) => unreachable!(),
+            }
+        }
+    }
+
+    fn union(&mut self, name: &str, a: &[UnionCase], b: &[UnionCase]) {
+        fn cases<'c>(symtab: &Symtab, cases: &'c [UnionCase]) -> BTreeMap<i64, &'c Decl> {
+            cases
+                .iter()
+                .filter_map(|UnionCase(val, decl)| val.as_i64(symtab).map(|v| (v, decl)))
+                .collect()
+        }
+        let a = cases(self.old, a);
+        let b = cases(self.new, b);
+
+        for (v, adecl) in &a {
+            match b.get(v) {
+                None => self.push(name, Compat::Breaking, format!("removed case {}", v)),
+                Some(bdecl) => {
+                    if let Some(compat) = compare_decl(self.old, adecl, self.new, bdecl) {
+                        let what = format!(
+                            "case {} changed from {} to {}",
+                            v,
+                            describe_decl(adecl),
+                            describe_decl(bdecl)
+                        );
+                        self.push(name, compat, what);
+                    }
+                }
+            }
+        }
+        for (v, bdecl) in &b {
+            if !a.contains_key(v) {
+                self.push(name, Compat::Compatible, format!("added case {} {}", v, describe_decl(bdecl)));
+            }
+        }
+    }
+
+    fn union_default(&mut self, name: &str, a: Option<&Decl>, b: Option<&Decl>) {
+        match (a, b) {
+            (None, None) => (),
+            (None, Some(_)) => self.push(name, Compat::Compatible, "added default case"),
+            (Some(_), None) => self.push(name, Compat::Breaking, "removed default case"),
+            (Some(a), Some(b)) => {
+                if let Some(compat) = compare_decl(self.old, a, self.new, b) {
+                    let what = format!("default case changed from {} to {}", describe_decl(a), describe_decl(b));
+                    self.push(name, compat, what);
+                }
+            }
+        }
+    }
+}
+
+impl Symtab {
+    fn definition(&self, name: &str) -> Option<&Type> {
+        self.typespecs.get(name).or_else(|| self.typesyns.get(name))
+    }
+
+    /// Differences between the types and constants of this specification and a newer version.
+    ///
+    /// Each change is classified as compatible if existing encodings are unaffected, such as a
+    /// new enum value or union case, or a raised bound. Anything which changes the encoding of
+    /// existing values, like a changed field type or reordered fields, is breaking. So is
+    /// removing anything, or adding a field to a struct, except at the end of a top-level struct
+    /// when decoders skip trailing data. Changes are ordered by name.
+    pub fn diff(&self, new: &Symtab) -> Vec<Change> {
+        let mut differ = Differ {
+            old: self,
+            new,
+            changes: Vec::new(),
+        };
+
+        // Enum values are checked with their enums
+        fn consts(symtab: &Symtab) -> BTreeMap<&String, i64> {
+            symtab
+                .consts
+                .iter()
+                .filter(|(_, (_, scope))| scope.is_none())
+                .map(|(name, (val, _))| (name, *val))
+                .collect()
+        }
+        let (a, b) = (consts(self), consts(new));
+        for name in a.keys().chain(b.keys()).collect::<BTreeSet<_>>() {
+            match (a.get(name), b.get(name)) {
+                (Some(v), Some(w)) if v == w => (),
+                (Some(v), Some(w)) => differ.push(name, Compat::Breaking, format!("value changed from {} to {}", v, w)),
+                (Some(_), None) => differ.push(name, Compat::Breaking, "removed"),
+                (None, Some(w)) => differ.push(name, Compat::Compatible, format!("added with value {}", w)),
+                (None, None) => unreachable!(),
+            }
+        }
+
+        let names: BTreeSet<&String> = self
+            .typespecs
+            .keys()
+            .chain(self.typesyns.keys())
+            .chain(new.typespecs.keys())
+            .chain(new.typesyns.keys())
+            .collect();
+        for name in names {
+            match (self.definition(name), new.definition(name)) {
+                (Some(a), Some(b)) => differ.definition(name, a, b),
+                (Some(a), None) => differ.push(name, Compat::Breaking, format!("removed {}", kind(a))),
+                (None, Some(b)) => differ.push(name, Compat::Compatible, format!("added {}", kind(b))),
+                (None, None) => unreachable!(),
+            }
+        }
+
+        differ.changes
+    }
+}
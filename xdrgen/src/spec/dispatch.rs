@@ -0,0 +1,41 @@
+// A map from message tags to type-erased decoders, for routers which decode messages of several
+// types, choosing the type by a tag they've read from the stream.
+use proc_macro2::TokenStream;
+
+use super::{Result, Symtab, quote_ident};
+
+/// `dispatch_map`, decoding the types given tags with `GenOptions::dispatch_tags`.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct DispatchMap(pub Vec<(String, u32)>);
+
+impl DispatchMap {
+    pub fn define(&self, symtab: &Symtab) -> Result<TokenStream> {
+        let mut entries = Vec::new();
+        for (i, (name, tag)) in self.0.iter().enumerate() {
+            if symtab.typespec(name).is_none() {
+                return Err(format!("dispatch: unknown type {}", name).into());
+            }
+            if let Some((other, _)) = self.0[..i].iter().find(|(_, t)| t == tag) {
+                return Err(format!("dispatch: {} and {} both have tag {}", other, name, tag).into());
+            }
+
+            let name = quote_ident(name);
+            entries.push(quote! {
+                map.insert(#tag, (|mut input: &mut dyn xdr_codec::Read| {
+                    let (v, _): (#name, usize) = xdr_codec::Unpack::unpack(&mut input)?;
+                    Ok(Box::new(v) as Box<_>)
+                }) as xdr_codec::DispatchFn);
+            });
+        }
+
+        Ok(quote! {
+            /// Decoders of the messages with dispatch tags, by tag. Each decodes one message and
+            /// returns it boxed, to be identified with `Any::type_id` or `downcast`.
+            pub fn dispatch_map() -> ::std::collections::HashMap<u32, xdr_codec::DispatchFn> {
+                let mut map = ::std::collections::HashMap::new();
+                #(#entries)*
+                map
+            }
+        })
+    }
+}
@@ -0,0 +1,80 @@
+// Helper methods on generated enums.
+use proc_macro2::TokenStream;
+
+use super::{EnumDefn, GenOptions, IdentKind, Result, Symtab, Type, Typespec, UnknownEnum, quote_ident};
+
+/// `Default` for an enum, selecting a designated value.
+#[derive(Debug, Clone)]
+pub struct EnumDefault(pub String, pub String);
+
+impl EnumDefault {
+    pub fn define(&self, symtab: &Symtab) -> Result<TokenStream> {
+        let EnumDefault(name, value) = self;
+
+        match symtab.typespec(name) {
+            Some(Type::Enum(edefs)) => {
+                if !edefs.iter().any(|EnumDefn(field, _)| field == value) {
+                    return Err(format!("enum default: {} has no value {}", name, value).into());
+                }
+            }
+            Some(_) => return Err(format!("enum default: {} is not an enum", name).into()),
+            None => return Err(format!("enum default: unknown type {}", name).into()),
+        }
+
+        let name = quote_ident(name);
+        let value = quote_ident(value);
+
+        Ok(quote! {
+            impl Default for #name {
+                fn default() -> #name {
+                    #name::#value
+                }
+            }
+        })
+    }
+}
+
+impl Typespec {
+    // Whether this is an enum which gets `u32` discriminants: `GenOptions::unsigned_enums` is
+    // set and none of the values are negative.
+    pub(super) fn unsigned_repr(&self, symtab: &Symtab, opts: &GenOptions) -> bool {
+        match &self.1 {
+            Type::Enum(edefs) if opts.unsigned_enums => edefs.iter().all(|EnumDefn(field, _)| {
+                matches!(symtab.getconst(field), Some((val, _)) if val >= 0 && val <= u32::MAX as i64)
+            }),
+            _ => false,
+        }
+    }
+
+    /// `const fn name`, returning the XDR name of each enum value, and `"unknown"` for values
+    /// preserved by `UnknownEnum::Preserve`.
+    pub fn enum_name_fn(&self, symtab: &Symtab, opts: &GenOptions) -> Result<Option<TokenStream>> {
+        let edefs = match &self.1 {
+            Type::Enum(edefs) => edefs,
+            _ => return Ok(None),
+        };
+        let name = quote_ident(&self.0);
+
+        let mut arms: Vec<_> = edefs
+            .iter()
+            .filter(|EnumDefn(field, _)| matches!(symtab.getconst(field), Some((_, Some(_)))))
+            .map(|EnumDefn(field, _)| {
+                let tok = quote_ident(field);
+                let xdrname = symtab.original(IdentKind::Variant, None, field).unwrap_or(field);
+                quote!(#name::#tok => #xdrname,)
+            })
+            .collect();
+        if opts.unknown_enum == UnknownEnum::Preserve {
+            arms.push(quote!(#name::Unknown(_) => "unknown",));
+        }
+
+        Ok(Some(quote! {
+            impl #name {
+                /// Name of the value in the XDR specification.
+                pub const fn name(self) -> &'static str {
+                    match self { #(#arms)* }
+                }
+            }
+        }))
+    }
+}
@@ -0,0 +1,155 @@
+// `#[repr(C)]` mirrors of structs, for passing decoded values to C. Only structs made entirely
+// of fixed-size fields can be mirrored; anything variable-length, optional or a union has no C
+// layout to match.
+use std::result;
+
+use proc_macro2::{Ident, TokenStream};
+
+use super::{Decl, GenOptions, Result, Symtab, Type, Typespec, quote_ident, rust_type};
+
+// How a field is converted between the struct and its mirror
+enum Conv {
+    Copy,
+    From,
+    MapFrom,
+}
+
+// A mirrored field: its name, its type in the mirror, and how it's converted
+type Field = (Ident, TokenStream, Conv);
+
+fn mirror_name(name: &str) -> Ident {
+    quote_ident(format!("{}_ffi", name))
+}
+
+// The mirror of a field type, or why it can't have one
+fn mirror_type(
+    ty: &Type,
+    symtab: &Symtab,
+    opts: &GenOptions,
+    seen: &mut Vec<String>,
+) -> result::Result<(TokenStream, Conv), String> {
+    use super::Type::*;
+
+    match ty {
+        Quadruple => Err("quadruple has no Rust type".to_string()),
+        _ if ty.is_prim(symtab) => Ok((ty.as_token(symtab).map_err(|e| e.to_string())?, Conv::Copy)),
+        Array(elem, sz) => match elem.as_ref() {
+            Opaque | String => Ok((ty.as_token(symtab).map_err(|e| e.to_string())?, Conv::Copy)),
+            Array(..) => Err("nested arrays can't be mirrored".to_string()),
+            elem => {
+                let sz = sz.as_token(symtab);
+                let (tok, conv) = mirror_type(elem, symtab, opts, seen)?;
+                let conv = match conv {
+                    Conv::Copy => Conv::Copy,
+                    _ => Conv::MapFrom,
+                };
+                Ok((quote!([#tok; #sz as usize]), conv))
+            }
+        },
+        Ident(..) => match symtab.resolve(ty) {
+            Ident(name, _) => {
+                if opts.external_types.iter().any(|(n, _)| n == name) {
+                    return Err(format!("{} is an external type", name));
+                }
+                match symtab.typespec(name) {
+                    Some(ty) if ty.is_marker() => Err(format!("{} has no fields, so no C layout", name)),
+                    Some(Struct(decls)) => {
+                        if seen.contains(name) {
+                            return Err(format!("{} contains itself", name));
+                        }
+                        seen.push(name.clone());
+                        let res = mirror_fields(name, decls, symtab, opts, seen);
+                        seen.pop();
+                        res.map_err(|(field, why)| format!("{}.{}: {}", name, field, why))?;
+                        let id = mirror_name(name);
+                        Ok((quote!(#id), Conv::From))
+                    }
+                    Some(Enum(_)) => Err(format!("enum {} has no fixed C representation", name)),
+                    Some(_) => Err(format!("{} isn't a struct", name)),
+                    None => Err(format!("{} is undefined", name)),
+                }
+            }
+            ty => mirror_type(ty, symtab, opts, seen),
+        },
+        Option(_) => Err("optional data is variable-length".to_string()),
+        Flex(..) | Opaque | String => Err("variable-length".to_string()),
+        _ => Err("unions and anonymous types can't be mirrored".to_string()),
+    }
+}
+
+// The mirrored fields of struct `name`, or the first field which can't be mirrored and why
+fn mirror_fields(
+    name: &str,
+    decls: &[Decl],
+    symtab: &Symtab,
+    opts: &GenOptions,
+    seen: &mut Vec<String>,
+) -> result::Result<Vec<Field>, (String, String)> {
+    let mut ret = Vec::new();
+    for decl in decls {
+        if let Decl::Named(field, ty) = decl {
+            if let Ok(Some(_)) | Err(_) = rust_type(symtab, name, field) {
+                return Err((field.clone(), "has a rust_type".to_string()));
+            }
+            let (tok, conv) = mirror_type(ty, symtab, opts, seen).map_err(|why| (field.clone(), why))?;
+            ret.push((quote_ident(field), tok, conv));
+        }
+    }
+    Ok(ret)
+}
+
+impl Typespec {
+    pub fn ffi_mirror(&self, symtab: &Symtab, opts: &GenOptions) -> Result<Option<TokenStream>> {
+        let decls = match &self.1 {
+            Type::Struct(decls) if !self.1.is_marker() => decls,
+            _ => return Ok(None),
+        };
+        if opts.external_types.iter().any(|(n, _)| *n == self.0) {
+            return Ok(None);
+        }
+        let mirror = format!("{}_ffi", self.0);
+        if symtab.typespec(&mirror).is_some() {
+            return Err(format!("{}: FFI mirror {} is already defined", self.0, mirror).into());
+        }
+
+        let fields = match mirror_fields(&self.0, decls, symtab, opts, &mut vec![self.0.clone()]) {
+            Ok(fields) => fields,
+            Err((field, why)) => {
+                warn!("{}.{}: no FFI mirror: {}", self.0, field, why);
+                return Ok(None);
+            }
+        };
+
+        let name = quote_ident(&self.0);
+        let mirror = mirror_name(&self.0);
+        let defs = fields.iter().map(|(f, ty, _)| quote!(pub #f: #ty,));
+        // The same field conversions work in both directions
+        let convs: Vec<_> = fields
+            .iter()
+            .map(|(f, _, conv)| match conv {
+                Conv::Copy => quote!(#f: v.#f,),
+                Conv::From => quote!(#f: From::from(v.#f),),
+                Conv::MapFrom => quote!(#f: v.#f.map(From::from),),
+            })
+            .collect();
+
+        Ok(Some(quote! {
+            /// `#[repr(C)]` layout of the struct of the same name, for passing to C.
+            #[repr(C)]
+            #[derive(Clone, Copy, Debug, PartialEq)]
+            pub struct #mirror { #(#defs)* }
+
+            impl From<#name> for #mirror {
+                fn from(v: #name) -> #mirror {
+                    #mirror { #(#convs)* }
+                }
+            }
+
+            impl From<#mirror> for #name {
+                fn from(v: #mirror) -> #name {
+                    #name { #(#convs)* }
+                }
+            }
+        }))
+    }
+}
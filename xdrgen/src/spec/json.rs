@@ -0,0 +1,203 @@
+// JSON conversions using xdr-codec's built-in JSON support, for users who want JSON without serde.
+// The representation is described in `xdr_codec::json`.
+use proc_macro2::TokenStream;
+
+use super::{Decl, EnumDefn, Result, Symtab, Type, Typespec, UnionCase, UnknownEnum, Value, boxed_variant, quote_ident,
+            rust_type};
+use super::super::{GenOptions, IdentKind};
+
+// Conversion of the place `val`, of type `ty`, to a `Json`. Opaque data becomes a hex string.
+fn to_json(ty: &Type, val: TokenStream) -> TokenStream {
+    match ty {
+        Type::Array(elem, _) | Type::Flex(elem, _) if **elem == Type::Opaque => {
+            quote!(xdr_codec::json::Json::hex(&(#val)[..]))
+        }
+        Type::Array(elem, _) if **elem == Type::String => quote!(xdr_codec::json::Json::hex(&(#val)[..])),
+        _ => quote!(xdr_codec::json::ToJson::to_json_value(&#val)),
+    }
+}
+
+// Conversion of the `Json` reference `json` to a value of type `ty`.
+fn from_json(ty: &Type, json: TokenStream, symtab: &Symtab) -> TokenStream {
+    match ty {
+        Type::Array(elem, sz) if **elem == Type::Opaque || **elem == Type::String => {
+            let sz = sz.as_token(symtab);
+            quote!(#json.unhex_array::<{ #sz as usize }>()?)
+        }
+        Type::Flex(elem, _) if **elem == Type::Opaque => quote!(#json.unhex()?),
+        _ => quote!(xdr_codec::json::FromJson::from_json_value(#json)?),
+    }
+}
+
+// The name of something in the JSON representation, which is its name in the specification.
+fn json_name(symtab: &Symtab, kind: IdentKind, within: Option<&str>, name: &str) -> String {
+    symtab.original(kind, within, name).unwrap_or(name).to_string()
+}
+
+impl Typespec {
+    pub fn json_impls(&self, symtab: &Symtab, opts: &GenOptions) -> Result<Option<TokenStream>> {
+        let name = quote_ident(&self.0);
+
+        let (to, from) = match &self.1 {
+            Type::Enum(edefs) => {
+                let (labels, variants): (Vec<_>, Vec<_>) = edefs
+                    .iter()
+                    .filter(|EnumDefn(field, _)| matches!(symtab.getconst(field), Some((_, Some(_)))))
+                    .map(|EnumDefn(field, _)| (json_name(symtab, IdentKind::Variant, None, field), quote_ident(field)))
+                    .unzip();
+
+                let to = quote! {
+                    xdr_codec::json::Json::String(match self { #(#name::#variants => #labels,)* }.to_string())
+                };
+                let from = quote! {
+                    match json.as_str()? {
+                        #(#labels => Ok(#name::#variants),)*
+                        v => Err(xdr_codec::Error::from(format!("json: unknown value {:?}", v))),
+                    }
+                };
+                if opts.unknown_enum == UnknownEnum::Preserve {
+                    // Preserved values are numbers
+                    let to = quote! {
+                        match self {
+                            #(#name::#variants => xdr_codec::json::Json::String(#labels.to_string()),)*
+                            #name::Unknown(v) => xdr_codec::json::ToJson::to_json_value(v),
+                        }
+                    };
+                    let from = quote! {
+                        if let xdr_codec::json::Json::Number(_) = json {
+                            return Ok(#name::Unknown(xdr_codec::json::FromJson::from_json_value(json)?));
+                        }
+                        #from
+                    };
+                    (to, from)
+                } else {
+                    (to, from)
+                }
+            }
+
+            Type::Struct(decls) => {
+                let mut labels = Vec::new();
+                let mut tos = Vec::new();
+                let mut froms = Vec::new();
+
+                for decl in decls {
+                    if let Decl::Named(field, ty) = decl {
+                        let label = json_name(symtab, IdentKind::Field, Some(&self.0), field);
+                        let rust = rust_type(symtab, &self.0, field)?;
+                        let field = quote_ident(field);
+                        let from = from_json(ty, quote!(json.field(#label)?), symtab);
+                        let (to, from) = match rust {
+                            // Represented as the XDR type
+                            Some(rust) => {
+                                let xty = ty.as_token(symtab)?;
+                                (
+                                    to_json(ty, quote!((<#rust as RustType<#xty>>::to_xdr(&self.#field)))),
+                                    quote!(<#rust as RustType<#xty>>::from_xdr(#from)?),
+                                )
+                            }
+                            None => (to_json(ty, quote!(self.#field)), from),
+                        };
+                        tos.push(to);
+                        froms.push(quote!(#field: #from,));
+                        labels.push(label);
+                    }
+                }
+
+                let to = quote! {
+                    xdr_codec::json::Json::Object(vec![#((#labels.to_string(), #tos),)*])
+                };
+                let from = quote!(Ok(#name { #(#froms)* }));
+                (to, from)
+            }
+
+            Type::Union(_, cases, defl) => {
+                let arms = cases
+                    .iter()
+                    .map(|UnionCase(val, decl)| {
+                        let label = match val {
+                            Value::Ident(id) => json_name(symtab, IdentKind::Variant, None, id),
+                            Value::Const(c) => c.to_string(),
+                        };
+                        let variant = val.as_ident();
+                        let boxed = match decl {
+                            Decl::Named(_, ty) => boxed_variant(ty, symtab, opts.box_large_variants),
+                            Decl::Void => false,
+                        };
+                        (label, quote!(#variant), decl, boxed)
+                    })
+                    .chain(defl.iter().map(|decl| {
+                        let boxed = match decl.as_ref() {
+                            Decl::Named(_, ty) => ty.is_boxed(symtab) || boxed_variant(ty, symtab, opts.box_large_variants),
+                            Decl::Void => false,
+                        };
+                        ("default".to_string(), quote!(default), decl.as_ref(), boxed)
+                    }));
+
+                let mut tos = Vec::new();
+                let mut froms = Vec::new();
+                for (label, variant, decl, boxed) in arms {
+                    match decl {
+                        Decl::Void => {
+                            tos.push(quote!(#name::#variant => xdr_codec::json::Json::String(#label.to_string()),));
+                            froms.push(quote!((#label, _) => Ok(#name::#variant),));
+                        }
+                        Decl::Named(_, ty) => {
+                            let to = to_json(ty, if boxed { quote!(**v) } else { quote!(*v) });
+                            let mut from = from_json(ty, quote!(v), symtab);
+                            if boxed {
+                                from = quote!(Box::new(#from));
+                            }
+                            tos.push(quote! {
+                                #name::#variant(v) => xdr_codec::json::Json::Object(vec![(#label.to_string(), #to)]),
+                            });
+                            froms.push(quote!((#label, Some(v)) => Ok(#name::#variant(#from)),));
+                        }
+                    }
+                }
+
+                let to = quote!(match self { #(#tos)* });
+                let from = quote! {
+                    match json.tag()? {
+                        #(#froms)*
+                        (v, _) => Err(xdr_codec::Error::from(format!("json: unknown arm {:?}", v))),
+                    }
+                };
+                (to, from)
+            }
+
+            ty @ Type::Array(..) | ty @ Type::Flex(..) => {
+                let to = to_json(ty, quote!(self.0));
+                let from = from_json(ty, quote!(json), symtab);
+                (to, quote!(Ok(#name(#from))))
+            }
+
+            _ => return Ok(None),
+        };
+
+        Ok(Some(quote! {
+            impl xdr_codec::json::ToJson for #name {
+                fn to_json_value(&self) -> xdr_codec::json::Json {
+                    #to
+                }
+            }
+
+            impl xdr_codec::json::FromJson for #name {
+                fn from_json_value(json: &xdr_codec::json::Json) -> xdr_codec::Result<#name> {
+                    #from
+                }
+            }
+
+            impl #name {
+                /// JSON representation, with opaque data as hex.
+                pub fn to_json(&self) -> String {
+                    xdr_codec::json::to_string(self)
+                }
+
+                /// Parse the JSON representation produced by `to_json`.
+                pub fn from_json(text: &str) -> xdr_codec::Result<#name> {
+                    xdr_codec::json::from_str(text)
+                }
+            }
+        }))
+    }
+}
@@ -0,0 +1,346 @@
+// Export a specification as a Kaitai Struct description (https://kaitai.io), for dissecting
+// captured traffic with Kaitai's tools.
+//
+// This covers the common subset of XDR: everything which can be generated as Rust, other than
+// anonymous inline structs and unions. Kaitai identifiers must be lower case, so all names are
+// converted to snake case.
+use std::fmt::Write;
+
+use super::union::snake_case;
+use super::{Decl, EnumDefn, Result, Symtab, Type, UnionCase, Value};
+
+// Minimal YAML document
+enum Yaml {
+    Scalar(String),
+    Map(Vec<(String, Yaml)>),
+    List(Vec<Yaml>),
+}
+
+impl Yaml {
+    fn map() -> Yaml {
+        Yaml::Map(Vec::new())
+    }
+
+    fn with<V: Into<Yaml>>(mut self, key: &str, val: V) -> Yaml {
+        if let Yaml::Map(ref mut m) = self {
+            m.push((key.to_string(), val.into()))
+        }
+        self
+    }
+
+    // Type of a field which only has an id and a type
+    fn simple_type(&self) -> Option<&str> {
+        match self {
+            Yaml::Map(m) if m.len() == 2 && m[0].0 == "id" && m[1].0 == "type" => match m[1].1 {
+                Yaml::Scalar(ref ty) => Some(ty),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    // Add an `if` condition, combined with any existing one
+    fn cond(mut self, cond: &str) -> Yaml {
+        if cond.is_empty() {
+            return self;
+        }
+        if let Yaml::Map(ref mut m) = self {
+            match m.iter_mut().find(|(k, _)| k == "if") {
+                Some((_, Yaml::Scalar(prev))) => *prev = format!("({}) and ({})", prev, cond),
+                _ => m.push(("if".to_string(), cond.into())),
+            }
+        }
+        self
+    }
+
+    fn write(&self, out: &mut String, indent: usize) {
+        match self {
+            Yaml::Scalar(s) => {
+                let _ = writeln!(out, " {}", s);
+            }
+            Yaml::Map(m) => {
+                let _ = writeln!(out);
+                for (k, v) in m {
+                    let _ = write!(out, "{:1$}{2}:", "", indent, k);
+                    v.write(out, indent + 2);
+                }
+            }
+            Yaml::List(l) => {
+                let _ = writeln!(out);
+                for v in l {
+                    let _ = write!(out, "{:1$}-", "", indent);
+                    match v {
+                        // Lay out list of maps in the usual compact style
+                        Yaml::Map(m) => {
+                            for (i, (k, v)) in m.iter().enumerate() {
+                                let pad = if i == 0 { 1 } else { indent + 2 };
+                                let _ = write!(out, "{:1$}{2}:", "", pad, k);
+                                v.write(out, indent + 4);
+                            }
+                        }
+                        v => v.write(out, indent + 2),
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<'a> From<&'a str> for Yaml {
+    fn from(s: &'a str) -> Yaml {
+        Yaml::Scalar(s.to_string())
+    }
+}
+
+impl From<String> for Yaml {
+    fn from(s: String) -> Yaml {
+        Yaml::Scalar(s)
+    }
+}
+
+impl From<i64> for Yaml {
+    fn from(v: i64) -> Yaml {
+        Yaml::Scalar(v.to_string())
+    }
+}
+
+// Length-prefixed and padded byte strings, and void union arms
+const HELPERS: &str = r#"
+  xdr_void:
+    seq: []
+  xdr_opaque:
+    seq:
+      - id: len
+        type: u4
+      - id: data
+        size: len
+      - id: pad
+        size: (4 - len % 4) % 4
+  xdr_string:
+    seq:
+      - id: len
+        type: u4
+      - id: data
+        type: str
+        size: len
+        encoding: UTF-8
+      - id: pad
+        size: (4 - len % 4) % 4
+"#;
+
+fn value(symtab: &Symtab, v: &Value) -> Result<i64> {
+    symtab
+        .value(v)
+        .ok_or_else(|| format!("kaitai: unknown value {:?}", v).into())
+}
+
+fn padding(sz: i64) -> i64 {
+    (4 - sz % 4) % 4
+}
+
+// Kaitai fields for a single XDR field (`Option` and variable arrays need a preceding count)
+fn fields(symtab: &Symtab, id: &str, ty: &Type) -> Result<Vec<Yaml>> {
+    let id = snake_case(id);
+    let field = Yaml::map().with("id", id.as_str());
+
+    let ret = match symtab.resolve(ty) {
+        Type::Option(ty) => {
+            let present = format!("{}_present", id);
+            let mut ret = vec![Yaml::map().with("id", present.as_str()).with("type", "u4")];
+            for f in fields(symtab, &id, ty)? {
+                ret.push(f.cond(&format!("{} != 0", present)));
+            }
+            ret
+        }
+
+        Type::Array(elem, sz) => {
+            let sz = value(symtab, sz)?;
+            match symtab.resolve(elem) {
+                Type::Opaque | Type::String => {
+                    let mut ret = vec![field.with("size", sz)];
+                    if padding(sz) != 0 {
+                        let pad = format!("{}_pad", id);
+                        ret.push(Yaml::map().with("id", pad.as_str()).with("size", padding(sz)));
+                    }
+                    ret
+                }
+                elem => vec![
+                    scalar(symtab, field, elem)?
+                        .with("repeat", "expr")
+                        .with("repeat-expr", sz),
+                ],
+            }
+        }
+
+        Type::Flex(elem, _) => match symtab.resolve(elem) {
+            Type::Opaque => vec![field.with("type", "xdr_opaque")],
+            Type::String => vec![field.with("type", "xdr_string")],
+            elem => {
+                let len = format!("{}_len", id);
+                vec![
+                    Yaml::map().with("id", len.as_str()).with("type", "u4"),
+                    scalar(symtab, field, elem)?
+                        .with("repeat", "expr")
+                        .with("repeat-expr", len),
+                ]
+            }
+        },
+
+        ty => vec![scalar(symtab, field, ty)?],
+    };
+
+    Ok(ret)
+}
+
+// Add the type of a single value to `field`
+fn scalar(symtab: &Symtab, field: Yaml, ty: &Type) -> Result<Yaml> {
+    let ret = match symtab.resolve(ty) {
+        Type::Int => field.with("type", "s4"),
+        Type::UInt | Type::Bool => field.with("type", "u4"),
+        Type::Hyper => field.with("type", "s8"),
+        Type::UHyper => field.with("type", "u8"),
+        Type::Float => field.with("type", "f4"),
+        Type::Double => field.with("type", "f8"),
+        Type::Quadruple => field.with("size", 16),
+        Type::Opaque => field.with("type", "xdr_opaque"),
+        Type::String => field.with("type", "xdr_string"),
+
+        Type::Ident(name, _) => match symtab.typespecs.get(name) {
+            Some(Type::Enum(_)) => field.with("type", "s4").with("enum", snake_case(name)),
+            _ => field.with("type", snake_case(name)),
+        },
+
+        ty @ Type::Option(_) | ty @ Type::Array(..) | ty @ Type::Flex(..) => {
+            return Err(format!("kaitai: nested array/option type {:?}", ty).into())
+        }
+        ty => return Err(format!("kaitai: anonymous type {:?} not supported", ty).into()),
+    };
+    Ok(ret)
+}
+
+fn seq(symtab: &Symtab, decls: &[Decl]) -> Result<Yaml> {
+    let mut ret = Vec::new();
+    for decl in decls {
+        if let Decl::Named(id, ty) = decl {
+            ret.extend(fields(symtab, id, ty)?);
+        }
+    }
+    Ok(Yaml::List(ret))
+}
+
+// Unions are the selector followed by a `switch-on` field for the arm. Arms which don't map to a
+// single Kaitai type get a type of their own.
+fn union(
+    symtab: &Symtab,
+    name: &str,
+    sel: &Decl,
+    cases: &[UnionCase],
+    defl: &Option<Box<Decl>>,
+    types: &mut Vec<(String, Yaml)>,
+) -> Result<Yaml> {
+    let (selid, selty) = match sel {
+        Decl::Named(id, ty) => (snake_case(id), ty),
+        Decl::Void => return Err("kaitai: void union selector".into()),
+    };
+    let mut ret = fields(symtab, &selid, selty)?;
+
+    let selenum = match symtab.resolve(selty) {
+        Type::Ident(name, _) if matches!(symtab.typespecs.get(name), Some(Type::Enum(_))) => {
+            Some(snake_case(name))
+        }
+        _ => None,
+    };
+
+    let mut arm = |decl: &Decl| -> Result<String> {
+        let (id, ty) = match decl {
+            Decl::Void => return Ok("xdr_void".to_string()),
+            Decl::Named(id, ty) => (id, ty),
+        };
+        let fields = fields(symtab, id, ty)?;
+        if let [field] = &fields[..] {
+            if let Some(ty) = field.simple_type() {
+                return Ok(ty.to_string());
+            }
+        }
+        let armty = format!("{}_{}", name, snake_case(id));
+        if !types.iter().any(|(n, _)| *n == armty) {
+            types.push((armty.clone(), Yaml::map().with("seq", Yaml::List(fields))));
+        }
+        Ok(armty)
+    };
+
+    let mut arms = Vec::new();
+    for UnionCase(val, decl) in cases {
+        let key = match (&selenum, val) {
+            (Some(e), Value::Ident(id)) => format!("'{}::{}'", e, snake_case(id)),
+            _ => value(symtab, val)?.to_string(),
+        };
+        arms.push((key, Yaml::from(arm(decl)?)));
+    }
+    if let Some(decl) = defl {
+        arms.push(("_".to_string(), Yaml::from(arm(decl)?)));
+    }
+
+    let switch = Yaml::map()
+        .with("switch-on", selid)
+        .with("cases", Yaml::Map(arms));
+    ret.push(Yaml::map().with("id", "value").with("type", switch));
+
+    Ok(Yaml::List(ret))
+}
+
+/// Kaitai Struct description of all the types in `symtab`.
+pub fn kaitai(id: &str, symtab: &Symtab) -> Result<String> {
+    let mut types = Vec::new();
+    let mut enums = Vec::new();
+
+    for (name, ty) in symtab.typespecs() {
+        let name = snake_case(name);
+        match ty {
+            Type::Enum(edefs) => {
+                let mut vals = Vec::new();
+                for EnumDefn(field, _) in edefs {
+                    if let Some((v, Some(_))) = symtab.getconst(field) {
+                        vals.push((v.to_string(), Yaml::from(snake_case(field))));
+                    }
+                }
+                enums.push((name, Yaml::Map(vals)));
+            }
+            Type::Struct(decls) => types.push((name, Yaml::map().with("seq", seq(symtab, decls)?))),
+            Type::Union(sel, cases, defl) => {
+                let mut armtypes = Vec::new();
+                let seq = union(symtab, &name, sel, cases, defl, &mut armtypes)?;
+                types.push((name, Yaml::map().with("seq", seq)));
+                types.extend(armtypes);
+            }
+            ty @ Type::Flex(..) | ty @ Type::Array(..) => {
+                let seq = Yaml::List(fields(symtab, "value", ty)?);
+                types.push((name, Yaml::map().with("seq", seq)))
+            }
+            _ => (),
+        }
+    }
+
+    let meta = Yaml::map()
+        .with("id", snake_case(id))
+        .with("endian", "be");
+    let doc = Yaml::map().with("meta", meta);
+
+    let mut out = String::new();
+    let _ = writeln!(out, "# Generated by xdrgen from {}", id);
+    if let Yaml::Map(m) = doc {
+        for (k, v) in m {
+            let _ = write!(out, "{}:", k);
+            v.write(&mut out, 2);
+        }
+    }
+    let _ = write!(out, "types:");
+    Yaml::Map(types).write(&mut out, 2);
+    out.push_str(HELPERS.trim_start_matches('\n'));
+    if !enums.is_empty() {
+        let _ = write!(out, "enums:");
+        Yaml::Map(enums).write(&mut out, 2);
+    }
+
+    Ok(out)
+}
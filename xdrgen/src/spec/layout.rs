@@ -0,0 +1,271 @@
+// Estimated in-memory sizes of the generated types, for tuning how large collections of decoded
+// values are represented.
+//
+// This follows the structure of the types rather than asking the compiler, so it's only an
+// estimate: struct fields are assumed to be reordered to avoid padding, unions have a one byte
+// tag, and the only niches used are those of pointers, `Vec`, `String`, `bool` and enums.
+use std::collections::BTreeMap;
+use std::fmt::{self, Display, Formatter};
+use std::mem::size_of;
+
+use super::{Decl, EnumDefn, Result, Symtab, Type, UnionCase};
+
+/// Estimated in-memory size of a generated type, from `Symtab::mem_report`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemSize {
+    pub name: String,
+    /// Size in bytes, not counting anything on the heap
+    pub size: usize,
+    pub align: usize,
+    /// For a union, its largest arm and the size of that arm's payload
+    pub largest: Option<(String, usize)>,
+}
+
+impl Display for MemSize {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}: {} bytes, align {}", self.name, self.size, self.align)?;
+        if let Some((arm, size)) = &self.largest {
+            write!(f, ", largest arm {} ({} bytes)", arm, size)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Layout {
+    size: usize,
+    align: usize,
+    // Whether there are invalid bit patterns, so an `Option` needs no tag
+    niche: bool,
+}
+
+const PTR: Layout = Layout {
+    size: size_of::<usize>(),
+    align: size_of::<usize>(),
+    niche: true,
+};
+
+const VEC: Layout = Layout {
+    size: 3 * size_of::<usize>(),
+    align: size_of::<usize>(),
+    niche: true,
+};
+
+fn scalar(size: usize) -> Layout {
+    Layout {
+        size,
+        align: size,
+        niche: false,
+    }
+}
+
+fn round_up(size: usize, align: usize) -> usize {
+    size.div_ceil(align) * align
+}
+
+struct Sizer<'a> {
+    symtab: &'a Symtab,
+    memo: BTreeMap<&'a str, Layout>,
+    // Types being sized, to catch types which contain themselves
+    active: Vec<&'a str>,
+}
+
+impl<'a> Sizer<'a> {
+    fn named(&mut self, name: &'a str, ty: &'a Type) -> Result<Layout> {
+        if let Some(layout) = self.memo.get(name) {
+            return Ok(*layout);
+        }
+        if self.active.contains(&name) {
+            return Err(format!("mem report: {} contains itself", name).into());
+        }
+        self.active.push(name);
+        let layout = self.defn(ty)?;
+        self.active.pop();
+        self.memo.insert(name, layout);
+        Ok(layout)
+    }
+
+    // Layout of a definition, which is a newtype for arrays and options
+    fn defn(&mut self, ty: &'a Type) -> Result<Layout> {
+        let ret = match ty {
+            Type::Enum(edefs) => {
+                let vals: Vec<_> = edefs
+                    .iter()
+                    .filter_map(|EnumDefn(field, _)| self.symtab.getconst(field))
+                    .map(|(v, _)| v)
+                    .collect();
+                let (min, max) = match (vals.iter().min(), vals.iter().max()) {
+                    (Some(min), Some(max)) => (*min, *max),
+                    _ => return Ok(scalar(0)),
+                };
+                let fits = |lo: i64, hi: i64| (min >= -lo - 1 && max <= lo) || (min >= 0 && max <= hi);
+                let size = if fits(0x7f, 0xff) {
+                    1
+                } else if fits(0x7fff, 0xffff) {
+                    2
+                } else {
+                    4
+                };
+                Layout { niche: true, ..scalar(size) }
+            }
+
+            Type::Struct(decls) => {
+                let mut size = 0;
+                let mut align = 1;
+                let mut niche = false;
+                for decl in decls {
+                    if let Decl::Named(_, ty) = decl {
+                        let l = self.ty(ty)?;
+                        size += l.size;
+                        align = align.max(l.align);
+                        niche |= l.niche;
+                    }
+                }
+                Layout {
+                    size: round_up(size, align),
+                    align,
+                    niche,
+                }
+            }
+
+            Type::Union(..) => self.union(ty)?.0,
+
+            ty => self.ty(ty)?,
+        };
+        Ok(ret)
+    }
+
+    // Layout of a union, and its largest arm
+    fn union(&mut self, ty: &'a Type) -> Result<(Layout, Option<(String, usize)>)> {
+        let (cases, defl) = match ty {
+            Type::Union(_, cases, defl) => (cases, defl),
+            _ => unreachable!(),
+        };
+
+        let mut arms = Vec::new();
+        for UnionCase(val, decl) in cases {
+            if let Decl::Named(_, ty) = decl {
+                arms.push((val.as_ident().to_string(), self.ty(ty)?));
+            }
+        }
+        if let Some(Decl::Named(_, ty)) = defl.as_deref() {
+            // Default arms which aren't primitive are boxed
+            let l = if ty.is_boxed(self.symtab) { PTR } else { self.ty(ty)? };
+            arms.push(("default".to_string(), l));
+        }
+
+        let align = arms.iter().map(|(_, l)| l.align).max().unwrap_or(1);
+        let largest = arms
+            .iter()
+            .rev()
+            .max_by_key(|(_, l)| l.size)
+            .map(|(arm, l)| (arm.clone(), l.size));
+        let payload = largest.as_ref().map_or(0, |(_, size)| *size);
+        let layout = Layout {
+            size: round_up(round_up(1, align) + payload, align),
+            align,
+            niche: true,
+        };
+        Ok((layout, largest))
+    }
+
+    // Layout of a member of type `ty`
+    fn ty(&mut self, ty: &'a Type) -> Result<Layout> {
+        let ret = match self.symtab.resolve(ty) {
+            Type::Int | Type::UInt | Type::Float => scalar(4),
+            Type::Hyper | Type::UHyper | Type::Double => scalar(8),
+            Type::Quadruple => scalar(16),
+            Type::Bool => Layout { niche: true, ..scalar(1) },
+            Type::String | Type::Opaque | Type::Flex(..) => VEC,
+
+            Type::Array(elem, sz) => {
+                let n = match self.symtab.value(sz) {
+                    Some(n) => n as usize,
+                    None => return Err(format!("mem report: unknown array size {:?}", sz).into()),
+                };
+                match elem.as_ref() {
+                    Type::String | Type::Opaque => scalar(1).times(n),
+                    elem => self.ty(elem)?.times(n),
+                }
+            }
+
+            Type::Option(inner) => {
+                if inner.is_boxed(self.symtab) {
+                    PTR
+                } else {
+                    let l = self.ty(inner)?;
+                    if l.niche {
+                        l
+                    } else {
+                        Layout {
+                            size: l.size + l.align,
+                            niche: true,
+                            ..l
+                        }
+                    }
+                }
+            }
+
+            Type::Ident(name, _) => match self.symtab.typespecs.get_key_value(name) {
+                Some((name, ty)) => self.named(name, ty)?,
+                None => return Err(format!("mem report: unknown type {}", name).into()),
+            },
+
+            ty => return Err(format!("mem report: anonymous type {:?} not supported", ty).into()),
+        };
+        Ok(ret)
+    }
+}
+
+impl Layout {
+    fn times(self, n: usize) -> Layout {
+        Layout {
+            size: self.size * n,
+            niche: self.niche && n > 0,
+            ..self
+        }
+    }
+}
+
+impl Symtab {
+    /// Estimated in-memory size of each generated type, in name order.
+    ///
+    /// The size is worked out from the structure of the types, counting `Vec` and `String` as
+    /// three pointers and anything boxed as one, so heap allocations aren't included. It's an
+    /// estimate of what the compiler will do, meant for spotting large types, such as unions with
+    /// one large arm, rather than an exact figure.
+    pub fn mem_report(&self) -> Result<Vec<MemSize>> {
+        let mut sizer = Sizer {
+            symtab: self,
+            memo: BTreeMap::new(),
+            active: Vec::new(),
+        };
+
+        let mut ret = Vec::new();
+        for (name, ty) in &self.typespecs {
+            let (layout, largest) = match ty {
+                Type::Union(..) => sizer.union(ty)?,
+                _ => (sizer.named(name, ty)?, None),
+            };
+            ret.push(MemSize {
+                name: name.clone(),
+                size: layout.size,
+                align: layout.align,
+                largest,
+            });
+        }
+        Ok(ret)
+    }
+}
+
+impl Type {
+    // Estimated in-memory size of a value of this type, if it can be worked out.
+    pub(super) fn mem_size(&self, symtab: &Symtab) -> Option<usize> {
+        let mut sizer = Sizer {
+            symtab,
+            memo: BTreeMap::new(),
+            active: Vec::new(),
+        };
+        sizer.ty(self).ok().map(|l| l.size)
+    }
+}
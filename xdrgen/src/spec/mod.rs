@@ -1,6 +1,8 @@
 use std::collections::btree_map::{BTreeMap, Iter};
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt::{self, Display, Formatter};
 use std::io::{Write, stderr};
+use std::str::FromStr;
 
 use std::result;
 
@@ -8,14 +10,58 @@ use proc_macro2::{Ident, Span, TokenStream};
 use quote::{self, ToTokens};
 
 mod xdr_nom;
+mod addr;
+#[cfg(feature = "export-avro")]
+mod avro;
+mod borrow;
+mod collect;
+mod convert;
+mod diff;
+mod dispatch;
+mod enums;
+mod ffi;
+#[cfg(feature = "json")]
+mod json;
+#[cfg(feature = "export-kaitai")]
+mod kaitai;
+mod layout;
+mod packed;
+#[cfg(feature = "export-proto")]
+mod proto;
+mod pragma;
+mod rename;
+mod resume;
+mod rpc;
+mod skip;
+mod trace;
+mod union;
+mod validate;
 
 use xdr::Error;
 
 pub type Result<T> = result::Result<T, Error>;
 
+#[cfg(test)]
 pub use self::xdr_nom::specification;
+use self::xdr_nom::located_specification;
+pub use self::addr::AddrType;
+pub use self::convert::Conversion;
+pub use self::diff::{Change, Compat};
+pub use self::dispatch::DispatchMap;
+pub use self::enums::EnumDefault;
+pub use self::layout::MemSize;
+pub use self::pragma::Pragma;
+#[cfg(feature = "export-kaitai")]
+pub use self::kaitai::kaitai;
+#[cfg(feature = "export-proto")]
+pub use self::proto::proto;
+#[cfg(feature = "export-avro")]
+pub use self::avro::avro;
+pub use self::rpc::{Procedure, Program, Version};
 
 use super::result_option;
+use super::{Checksum, GenOptions, IdentKind, LenWidth, Opt, StrEnc, UnknownEnum};
+use super::options::SERDE_CASES;
 
 #[cfg(not(feature="derive_strum_enum_string"))]
 bitflags! {
@@ -180,6 +226,7 @@ pub enum Type {
     // Compound types
     Enum(Vec<EnumDefn>),
     Struct(Vec<Decl>),
+    // Cases are in the order they're declared, which generated variants follow
     Union(Box<Decl>, Vec<UnionCase>, Option<Box<Decl>>),
 
     Option(Box<Type>),
@@ -191,6 +238,65 @@ pub enum Type {
 }
 
 impl Type {
+    // Collect the names of all types referenced by this one.
+    fn idents(&self, out: &mut HashSet<String>) {
+        fn decl(d: &Decl, out: &mut HashSet<String>) {
+            if let Decl::Named(_, ty) = d {
+                ty.idents(out);
+            }
+        }
+
+        match self {
+            Type::Ident(name, _) => {
+                out.insert(name.clone());
+            }
+            Type::Option(ty) | Type::Array(ty, _) | Type::Flex(ty, _) => ty.idents(out),
+            Type::Struct(decls) => decls.iter().for_each(|d| decl(d, out)),
+            Type::Union(sel, cases, defl) => {
+                decl(sel, out);
+                cases.iter().for_each(|UnionCase(_, d)| decl(d, out));
+                if let Some(d) = defl {
+                    decl(d, out);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    // Collect the names of constants used in the type, as bounds or union cases.
+    fn const_idents(&self, out: &mut HashSet<String>) {
+        fn value(v: &Value, out: &mut HashSet<String>) {
+            if let Value::Ident(id) = v {
+                out.insert(id.clone());
+            }
+        }
+        fn decl(d: &Decl, out: &mut HashSet<String>) {
+            if let Decl::Named(_, ty) = d {
+                ty.const_idents(out);
+            }
+        }
+
+        match self {
+            Type::Option(ty) | Type::Flex(ty, None) => ty.const_idents(out),
+            Type::Array(ty, sz) | Type::Flex(ty, Some(sz)) => {
+                value(sz, out);
+                ty.const_idents(out);
+            }
+            Type::Struct(decls) => decls.iter().for_each(|d| decl(d, out)),
+            Type::Union(sel, cases, defl) => {
+                decl(sel, out);
+                for UnionCase(val, d) in cases {
+                    value(val, out);
+                    decl(d, out);
+                }
+                if let Some(d) = defl {
+                    decl(d, out);
+                }
+            }
+            _ => (),
+        }
+    }
+
     fn array(ty: Type, sz: Value) -> Type {
         Type::Array(Box::new(ty), sz)
     }
@@ -232,6 +338,15 @@ impl Type {
         }
     }
 
+    // A struct with no members, from `struct foo {};` or `typedef void foo;`, which is generated
+    // as a unit struct with an empty encoding.
+    fn is_marker(&self) -> bool {
+        match self {
+            Type::Struct(decls) => decls.iter().all(|d| *d == Decl::Void),
+            _ => false,
+        }
+    }
+
     fn is_prim(&self, symtab: &Symtab) -> bool {
         use self::Type::*;
 
@@ -330,7 +445,7 @@ impl Type {
     }
 
 
-    fn packer(&self, val: TokenStream, symtab: &Symtab) -> Result<TokenStream> {
+    fn packer_opt(&self, val: TokenStream, symtab: &Symtab, opt: Codegen) -> Result<TokenStream> {
         use self::Type::*;
 
         let res = match self {
@@ -345,10 +460,14 @@ impl Type {
                         quote!(Some(#mx as usize))
                     }
                 };
+                let helpers = opt.flex_helpers();
                 match ty {
-                    &Opaque => quote!(xdr_codec::pack_opaque_flex(&#val, #maxsz, out)?),
-                    &String => quote!(xdr_codec::pack_string(&#val, #maxsz, out)?),
-                    _ => quote!(xdr_codec::pack_flex(&#val, #maxsz, out)?),
+                    &Opaque => quote!(#helpers::pack_opaque_flex(&#val, #maxsz, out)?),
+                    &String if opt.str_enc == StrEnc::Latin1 => {
+                        quote!(#helpers::pack_opaque_flex(&xdr_codec::latin1::encode(&#val)?, #maxsz, out)?)
+                    }
+                    &String => quote!(#helpers::pack_string(&#val, #maxsz, out)?),
+                    _ => quote!(#helpers::pack_flex(&#val, #maxsz, out)?),
                 }
             }
 
@@ -379,15 +498,27 @@ impl Type {
         }
     }
 
-    fn unpacker(&self, symtab: &Symtab) -> TokenStream {
+    fn unpacker_opt(&self, symtab: &Symtab, opt: Codegen) -> TokenStream {
         use self::Type::*;
 
+        if opt.borrow {
+            if let Some(unpack) = self.borrowed_unpacker(symtab, opt) {
+                return unpack;
+            }
+        }
+
         match self {
             &Array(ref ty, ref value) => {
                 let ty = ty.as_ref();
                 let value = value.as_token(symtab);
 
                 match ty {
+                    &Opaque | &String if opt.canonical => {
+                        quote!(xdr_codec::unpack_opaque_fixed_canonical::<_, { #value as usize }>(input)?)
+                    }
+                    &Opaque | &String if opt.optimize == Opt::Size => {
+                        quote!(xdr_codec::unpack_opaque_fixed::<_, { #value as usize }>(input)?)
+                    }
                     &Opaque | &String => {
                         quote!({
                             let mut buf: [u8; #value as usize] = [0; #value as usize];
@@ -395,6 +526,9 @@ impl Type {
                             (buf, sz)
                         })
                     }
+                    _ if opt.shared_arrays || opt.optimize == Opt::Size => {
+                        quote!(xdr_codec::unpack_fixed::<_, _, { #value as usize }>(input)?)
+                    }
                     ty => {
                         let ty = ty.as_token(symtab).unwrap();
                         // Create the return array as uninitialized, since we don't know what to initialize it until
@@ -450,10 +584,20 @@ impl Type {
                     }
                 };
 
+                let helpers = opt.flex_helpers();
+                let (opaque, string) = if opt.canonical {
+                    (quote!(unpack_opaque_flex_canonical), quote!(unpack_string_canonical))
+                } else {
+                    (quote!(unpack_opaque_flex), quote!(unpack_string))
+                };
                 match ty {
-                    &String => quote!(xdr_codec::unpack_string(input, #maxsz)?),
-                    &Opaque => quote!(xdr_codec::unpack_opaque_flex(input, #maxsz)?),
-                    _ => quote!(xdr_codec::unpack_flex(input, #maxsz)?),
+                    &String if opt.str_enc == StrEnc::Latin1 => quote!({
+                        let (v, sz) = #helpers::#opaque(input, #maxsz)?;
+                        (xdr_codec::latin1::decode(&v), sz)
+                    }),
+                    &String => quote!(#helpers::#string(input, #maxsz)?),
+                    &Opaque => quote!(#helpers::#opaque(input, #maxsz)?),
+                    _ => quote!(#helpers::unpack_flex(input, #maxsz)?),
                 }
             }
 
@@ -548,14 +692,6 @@ impl Decl {
         Decl::Named(id.as_ref().to_string(), ty)
     }
 
-    fn name_as_ident(&self) -> Option<(Ident, &Type)> {
-        use self::Decl::*;
-        match self {
-            &Void => None,
-            &Named(ref name, ref ty) => Some((quote_ident(name), ty)),
-        }
-    }
-
     fn as_token(&self, symtab: &Symtab) -> Result<Option<(Ident, TokenStream)>> {
         use self::Decl::*;
         match self {
@@ -591,11 +727,19 @@ pub struct Typesyn(pub String, pub Type);
 #[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Clone)]
 pub struct Const(pub String, pub i64);
 
+// String constant, which XDR proper doesn't have but some dialects do
+#[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Clone)]
+pub struct StrConst(pub String, pub String);
+
 #[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Clone)]
 pub enum Defn {
     Typespec(String, Type),
     Typesyn(String, Type),
     Const(String, i64),
+    StrConst(String, String),
+    Program(Program),
+    /// Pragma attached to a definition, or to a field of a struct definition.
+    Pragma(String, Option<String>, Pragma),
 }
 
 impl Defn {
@@ -610,6 +754,27 @@ impl Defn {
     fn constant<S: AsRef<str>>(id: S, v: i64) -> Defn {
         Defn::Const(id.as_ref().to_string(), v)
     }
+
+    fn str_constant<S: AsRef<str>, V: AsRef<str>>(id: S, v: V) -> Defn {
+        Defn::StrConst(id.as_ref().to_string(), v.as_ref().to_string())
+    }
+
+    fn program(prog: Program) -> Defn {
+        Defn::Program(prog)
+    }
+
+    fn pragma<S: AsRef<str>>(id: S, field: Option<&str>, pragma: Pragma) -> Defn {
+        Defn::Pragma(id.as_ref().to_string(), field.map(String::from), pragma)
+    }
+
+    /// Name of the thing being defined.
+    fn name(&self) -> &str {
+        match self {
+            Defn::Typespec(name, _) | Defn::Typesyn(name, _) | Defn::Const(name, _) | Defn::StrConst(name, _) => name,
+            Defn::Program(prog) => &prog.0,
+            Defn::Pragma(name, ..) => name,
+        }
+    }
 }
 
 pub trait Emit {
@@ -622,11 +787,22 @@ pub trait Emitpack: Emit {
 }
 
 impl Emit for Const {
-    fn define(&self, _: &Symtab) -> Result<TokenStream> {
+    fn define(&self, symtab: &Symtab) -> Result<TokenStream> {
         let name = quote_ident(&self.0);
         let val = &self.1;
+        let deprecated = deprecated(symtab, &self.0, None);
 
-        Ok(quote!(pub const #name: i64 = #val;))
+        Ok(quote!(#deprecated pub const #name: i64 = #val;))
+    }
+}
+
+impl Emit for StrConst {
+    fn define(&self, symtab: &Symtab) -> Result<TokenStream> {
+        let name = quote_ident(&self.0);
+        let val = &self.1;
+        let deprecated = deprecated(symtab, &self.0, None);
+
+        Ok(quote!(#deprecated pub const #name: &str = #val;))
     }
 }
 
@@ -635,16 +811,26 @@ impl Emit for Typesyn {
         let ty = &self.1;
         let name = quote_ident(&self.0);
         let tok = ty.as_token(symtab)?;
-        Ok(quote!(pub type #name = #tok;))
+        let deprecated = deprecated(symtab, &self.0, None);
+        Ok(quote!(#deprecated pub type #name = #tok;))
     }
 }
 
 impl Emit for Typespec {
     fn define(&self, symtab: &Symtab) -> Result<TokenStream> {
+        self.define_impl(symtab, false, Codegen::default())
+    }
+}
+
+impl Typespec {
+    // `unsigned` gives enums `u32` discriminants, and `opt` boxes large union arms and adds
+    // `Unknown` variants to enums.
+    fn define_impl(&self, symtab: &Symtab, unsigned: bool, opt: Codegen) -> Result<TokenStream> {
         use self::Type::*;
 
         let name = quote_ident(&self.0);
         let ty = &self.1;
+        let box_over = opt.box_over;
 
         let ret = match ty {
             &Enum(ref edefs) => {
@@ -653,27 +839,89 @@ impl Emit for Typespec {
                     .filter_map(|&EnumDefn(ref field, _)| if let Some((val, Some(_))) =
                         symtab.getconst(field)
                     {
-                        Some((quote_ident(field), val as isize))
+                        Some((field, val))
                     } else {
                         None
                     })
-                    .map(|(field, val)| quote!(#field = #val,))
+                    .map(|(field, val)| {
+                        let rename = serde_rename(symtab, IdentKind::Variant, None, field);
+                        let field = quote_ident(field);
+                        if opt.unknown == UnknownEnum::Preserve {
+                            // Enums with fields can't have discriminants without a `repr`, and
+                            // the values are in the codecs anyway
+                            quote!(#rename #field,)
+                        } else if unsigned {
+                            let val = val as u32;
+                            quote!(#rename #field = #val,)
+                        } else {
+                            let val = val as isize;
+                            quote!(#rename #field = #val,)
+                        }
+                    })
                     .collect();
 
                 let derive = ty.derivable(symtab, None);
-                quote!(#derive pub enum #name { #(#defs)* })
+                let rename = serde_rename(symtab, IdentKind::Type, None, &self.0);
+                if opt.unknown == UnknownEnum::Preserve {
+                    if edefs.iter().any(|EnumDefn(field, _)| field == "Unknown") {
+                        return Err(format!("enum {}: value Unknown clashes with UnknownEnum::Preserve", self.0).into());
+                    }
+                    let repr = if unsigned { quote!(u32) } else { quote!(i32) };
+                    quote! {
+                        #derive #rename pub enum #name {
+                            #(#defs)*
+                            /// A value which isn't in the specification
+                            Unknown(#repr),
+                        }
+                    }
+                } else {
+                    let repr = if unsigned { quote!(#[repr(u32)]) } else { quote!() };
+                    quote!(#derive #rename #repr pub enum #name { #(#defs)* })
+                }
             }
 
-            &Struct(ref decls) => {
-                let decls: Vec<_> = decls
+            &Struct(_) if ty.is_marker() => {
+                let derive = ty.derivable(symtab, None);
+                let rename = serde_rename(symtab, IdentKind::Type, None, &self.0);
+                quote!(#derive #rename pub struct #name;)
+            }
+
+            &Struct(ref fields) => {
+                let decls: Vec<_> = fields
                     .iter()
-                    .filter_map(|decl| result_option(decl.as_token(symtab)))
-                    .map(|res| res.map(|(field, ty)| quote!(pub #field: #ty,)))
+                    .filter_map(|decl| result_option(decl.as_token(symtab)).map(|res| (decl, res)))
+                    .map(|(decl, res)| {
+                        let (field, mut ty) = res?;
+                        let attrs = match decl {
+                            Decl::Named(name, fty) => {
+                                // A Rust type from a pragma might not have a default
+                                let default = match rust_type(symtab, &self.0, name)? {
+                                    Some(rust) => {
+                                        ty = rust;
+                                        quote!()
+                                    }
+                                    None => serde_default(symtab, fty, opt),
+                                };
+                                let deprecated = deprecated(symtab, &self.0, Some(name));
+                                let rename = serde_rename(symtab, IdentKind::Field, Some(&self.0), name);
+                                let educe = educe_field(symtab, &self.0, decl)?;
+                                quote!(#deprecated #rename #default #educe)
+                            }
+                            Decl::Void => quote!(),
+                        };
+                        Ok(quote!(#attrs pub #field: #ty,))
+                    })
                     .collect::<Result<Vec<_>>>()?;
 
-                let derive = ty.derivable(symtab, None);
+                let mut derive = derives_boxed(ty, symtab, box_over);
+                let educe = educe_derive(symtab, &self.0, fields, &mut derive);
+                let rename = serde_rename(symtab, IdentKind::Type, None, &self.0);
+                let rename_all = serde_rename_all(opt);
                 quote! {
                     #derive
+                    #educe
+                    #rename
+                    #rename_all
                     pub struct #name { #(#decls)* }
                 }
             }
@@ -734,12 +982,19 @@ impl Emit for Typespec {
                         }
 
                         let label = val.as_ident();
+                        let label = match val {
+                            Ident(id) => {
+                                let rename = serde_rename(symtab, IdentKind::Variant, None, id);
+                                quote!(#rename #label)
+                            }
+                            Const(_) => quote!(#label),
+                        };
 
                         match decl {
                             &Void => Ok(quote!(#label,)),
                             &Named(ref name, ref ty) => {
                                 let mut tok = ty.as_token(symtab)?;
-                                if false && ty.is_boxed(symtab) {
+                                if boxed_variant(ty, symtab, box_over) {
                                     tok = quote!(Box<#tok>)
                                 };
                                 if labelfields {
@@ -758,7 +1013,7 @@ impl Emit for Typespec {
                     match def_val {
                         &Named(ref name, ref ty) => {
                             let mut tok = ty.as_token(symtab)?;
-                            if ty.is_boxed(symtab) {
+                            if ty.is_boxed(symtab) || boxed_variant(ty, symtab, box_over) {
                                 tok = quote!(Box<#tok>)
                             };
                             if labelfields {
@@ -772,19 +1027,23 @@ impl Emit for Typespec {
                     }
                 }
 
-                let derive = ty.derivable(symtab, None);
+                let derive = derives_boxed(ty, symtab, box_over);
+                let rename = serde_rename(symtab, IdentKind::Type, None, &self.0);
                 quote! {
                     #derive
+                    #rename
                     pub enum #name { #(#cases)* }
                 }
             }
 
             &Flex(..) | &Array(..) => {
                 let tok = ty.as_token(symtab)?;
-                let derive = ty.derivable(symtab, None);
+                let derive = derives_boxed(ty, symtab, box_over);
+                let helpers = raw_bytes_helpers(&name, ty, symtab);
                 quote! {
                     #derive
                     pub struct #name(pub #tok);
+                    #helpers
                 }
             }
 
@@ -793,36 +1052,455 @@ impl Emit for Typespec {
                 quote!(pub type #name = #tok;)
             }
         };
-        Ok(ret)
+        let deprecated = deprecated(symtab, &self.0, None);
+        Ok(quote!(#deprecated #ret))
+    }
+}
+
+// `#[deprecated]` attribute for a definition or field with a `deprecated` pragma.
+fn deprecated(symtab: &Symtab, name: &str, field: Option<&str>) -> TokenStream {
+    match symtab.pragma(name, field, "deprecated") {
+        None => quote!(),
+        Some("") => quote!(#[deprecated]),
+        Some(note) => quote!(#[deprecated(note = #note)]),
+    }
+}
+
+// `#[serde(rename)]` attribute to serialize a renamed identifier with its name in the
+// specification.
+#[cfg(feature = "derive_serde")]
+fn serde_rename(symtab: &Symtab, kind: IdentKind, within: Option<&str>, name: &str) -> TokenStream {
+    match symtab.original(kind, within, name) {
+        None => quote!(),
+        Some(orig) => quote!(#[serde(rename = #orig)]),
+    }
+}
+
+#[cfg(not(feature = "derive_serde"))]
+fn serde_rename(_symtab: &Symtab, _kind: IdentKind, _within: Option<&str>, _name: &str) -> TokenStream {
+    quote!()
+}
+
+// `#[serde(rename_all)]` attribute for a struct, from `GenOptions::serde_rename_all`.
+#[cfg(feature = "derive_serde")]
+fn serde_rename_all(opt: Codegen) -> TokenStream {
+    match opt.rename_all {
+        None => quote!(),
+        Some(case) => quote!(#[serde(rename_all = #case)]),
+    }
+}
+
+#[cfg(not(feature = "derive_serde"))]
+fn serde_rename_all(_opt: Codegen) -> TokenStream {
+    quote!()
+}
+
+// `#[serde(default)]` attribute for a field of type `ty`, from `GenOptions::serde_default`, if
+// it's optional or a variable-length array, so that it can be left out.
+#[cfg(feature = "derive_serde")]
+fn serde_default(symtab: &Symtab, ty: &Type, opt: Codegen) -> TokenStream {
+    if !opt.serde_default {
+        return quote!();
+    }
+    match symtab.resolve(ty) {
+        Type::Option(_) => quote!(#[serde(default)]),
+        Type::Flex(elem, _) if !matches!(elem.as_ref(), Type::Opaque | Type::String) => quote!(#[serde(default)]),
+        _ => quote!(),
+    }
+}
+
+#[cfg(not(feature = "derive_serde"))]
+fn serde_default(_symtab: &Symtab, _ty: &Type, _opt: Codegen) -> TokenStream {
+    quote!()
+}
+
+// Field pragmas which customize derived traits with `educe`, and their attributes.
+#[cfg(feature = "derive_educe")]
+const EDUCE_PRAGMAS: &[&str] = &["eq_ignore", "debug_hex"];
+
+// `#[derive(Educe)]` in place of the standard derives of `Debug`, `PartialEq` and `Eq` for a
+// struct with fields which customize them, so the field attributes from `educe_field` apply.
+#[cfg(feature = "derive_educe")]
+fn educe_derive(symtab: &Symtab, name: &str, decls: &[Decl], derive: &mut Derives) -> TokenStream {
+    let custom = decls.iter().any(|decl| match decl {
+        Decl::Named(field, _) => EDUCE_PRAGMAS.iter().any(|p| symtab.pragma(name, Some(field), p).is_some()),
+        Decl::Void => false,
+    });
+    if !custom {
+        return quote!();
+    }
+
+    let mut traits = Vec::new();
+    for (d, tok) in &[(Derives::DEBUG, quote!(Debug)), (Derives::PARTIALEQ, quote!(PartialEq)), (Derives::EQ, quote!(Eq))] {
+        if derive.contains(*d) {
+            derive.remove(*d);
+            traits.push(tok.clone());
+        }
+    }
+    if traits.is_empty() {
+        return quote!();
+    }
+    quote!(#[derive(educe::Educe)] #[educe(#(#traits),*)])
+}
+
+#[cfg(not(feature = "derive_educe"))]
+fn educe_derive(_symtab: &Symtab, _name: &str, _decls: &[Decl], _derive: &mut Derives) -> TokenStream {
+    quote!()
+}
+
+// `#[educe]` attributes for a struct field with an `eq_ignore` pragma, which leaves it out of
+// `PartialEq`, or a `debug_hex` pragma, which formats its opaque data as hex with `Debug`.
+#[cfg(feature = "derive_educe")]
+fn educe_field(symtab: &Symtab, name: &str, decl: &Decl) -> Result<TokenStream> {
+    let (field, ty) = match decl {
+        Decl::Named(field, ty) => (field, ty),
+        Decl::Void => return Ok(quote!()),
+    };
+
+    let eq = match symtab.pragma(name, Some(field), "eq_ignore") {
+        None => quote!(),
+        Some(_) => quote!(#[educe(PartialEq(ignore))]),
+    };
+    let debug = match symtab.pragma(name, Some(field), "debug_hex") {
+        None => quote!(),
+        Some(_) => match ty {
+            Type::Flex(elem, _) | Type::Array(elem, _) if **elem == Type::Opaque => {
+                quote!(#[educe(Debug(method(xdr_codec::debug_hex)))])
+            }
+            _ => return Err(format!("{}.{}: debug_hex needs opaque data", name, field).into()),
+        },
+    };
+    Ok(quote!(#eq #debug))
+}
+
+#[cfg(not(feature = "derive_educe"))]
+fn educe_field(_symtab: &Symtab, _name: &str, _decl: &Decl) -> Result<TokenStream> {
+    Ok(quote!())
+}
+
+// Fixed opaque data whose size is a multiple of 4 is encoded as just its raw bytes, so it can be
+// converted directly without going through the codec.
+#[cfg(feature = "zerocopy")]
+fn raw_bytes_helpers(name: &Ident, ty: &Type, symtab: &Symtab) -> TokenStream {
+    match ty {
+        Type::Array(elem, sz) if **elem == Type::Opaque => {
+            match sz.as_i64(symtab) {
+                Some(v) if v % 4 == 0 => {
+                    let sz = sz.as_token(symtab);
+                    quote! {
+                        impl #name {
+                            /// Raw bytes, identical to the packed form.
+                            #[inline]
+                            pub fn as_bytes(&self) -> &[u8] {
+                                &self.0[..]
+                            }
+
+                            /// Construct from raw bytes, which must be exactly the right size.
+                            #[inline]
+                            pub fn from_bytes(bytes: &[u8]) -> xdr_codec::Result<#name> {
+                                let mut buf = [0u8; #sz as usize];
+                                if bytes.len() != buf.len() {
+                                    return Err(xdr_codec::Error::invalidlen(bytes.len()));
+                                }
+                                buf.copy_from_slice(bytes);
+                                Ok(#name(buf))
+                            }
+                        }
+                    }
+                }
+                _ => quote!(),
+            }
+        }
+        _ => quote!(),
+    }
+}
+
+#[cfg(not(feature = "zerocopy"))]
+fn raw_bytes_helpers(_name: &Ident, _ty: &Type, _symtab: &Symtab) -> TokenStream {
+    quote!()
+}
+
+// The match arms for a union discriminant `v` which selects none of the cases, going to `defl`
+// if there's a default arm. When the selector is an enum, a value which isn't in the enum at all
+// is an invalid enum rather than a missing case, whether or not there's a default.
+fn union_fallback(sel: &Decl, symtab: &Symtab, defl: Option<TokenStream>) -> TokenStream {
+    let vals: Vec<_> = match sel {
+        Decl::Named(_, ty) => match symtab.resolve(ty) {
+            Type::Ident(id, _) => match symtab.typespec(id) {
+                Some(Type::Enum(edefs)) => edefs
+                    .iter()
+                    .filter_map(|EnumDefn(field, _)| symtab.getconst(field))
+                    .map(|(v, _)| v as i32)
+                    .collect(),
+                _ => Vec::new(),
+            },
+            _ => Vec::new(),
+        },
+        Decl::Void => Vec::new(),
+    };
+
+    let invalid = if vals.is_empty() {
+        quote!()
+    } else {
+        quote!(v if !matches!(v, #(#vals)|*) => return Err(xdr_codec::Error::invalidenum(v)),)
+    };
+    match defl {
+        Some(defl) => quote!(#invalid _ => #defl,),
+        None => quote!(#invalid v => return Err(xdr_codec::Error::invalidcase(v)),),
+    }
+}
+
+// `Ok(body)`, or for a body which is a single `expr?`, just `expr`.
+fn ok_result(body: TokenStream) -> TokenStream {
+    use proc_macro2::TokenTree;
+
+    let mut tokens: Vec<_> = body.clone().into_iter().collect();
+    let single = !tokens.iter().any(|tt| matches!(tt, TokenTree::Punct(p) if "+-*/".contains(p.as_char())));
+    match tokens.last() {
+        Some(TokenTree::Punct(p)) if single && p.as_char() == '?' => {
+            tokens.pop();
+            tokens.into_iter().collect()
+        }
+        _ => quote!(Ok(#body)),
+    }
+}
+
+// Whether a union arm of type `ty` is boxed by `GenOptions::box_large_variants`, with the
+// threshold `box_over`.
+fn boxed_variant(ty: &Type, symtab: &Symtab, box_over: Option<usize>) -> bool {
+    box_over.is_some_and(|max| ty.mem_size(symtab).is_some_and(|size| size > max))
+}
+
+// The derives for a definition of type `ty`, which can't be `Copy` if it holds any boxed arms,
+// either a non-primitive default arm or one boxed by `GenOptions::box_large_variants`.
+fn derives_boxed(ty: &Type, symtab: &Symtab, box_over: Option<usize>) -> Derives {
+    fn holds_box<'a>(ty: &'a Type, symtab: &'a Symtab, box_over: Option<usize>, seen: &mut Vec<&'a str>) -> bool {
+        match ty {
+            Type::Struct(decls) => decls.iter().any(|decl| match decl {
+                Decl::Named(_, ty) => holds_box(ty, symtab, box_over, seen),
+                Decl::Void => false,
+            }),
+            Type::Union(_, cases, defl) => {
+                matches!(defl.as_deref(), Some(Decl::Named(_, ty)) if ty.is_boxed(symtab))
+                    || cases
+                        .iter()
+                        .map(|UnionCase(_, decl)| decl)
+                        .chain(defl.as_deref())
+                        .any(|decl| match decl {
+                            Decl::Named(_, ty) => {
+                                boxed_variant(ty, symtab, box_over) || holds_box(ty, symtab, box_over, seen)
+                            }
+                            Decl::Void => false,
+                        })
+            }
+            Type::Array(ty, _) | Type::Option(ty) => holds_box(ty, symtab, box_over, seen),
+            Type::Ident(id, _) if !seen.contains(&id.as_str()) => {
+                seen.push(id);
+                symtab
+                    .typespec(id)
+                    .is_some_and(|ty| holds_box(ty, symtab, box_over, seen))
+            }
+            _ => false,
+        }
+    }
+
+    let mut derive = ty.derivable(symtab, None);
+    if holds_box(ty, symtab, box_over, &mut Vec::new()) {
+        derive.remove(Derives::COPY);
+    }
+    derive
+}
+
+// The Rust type of field `field` of struct `name`, if it's set with a `@rust_type` pragma.
+fn rust_type(symtab: &Symtab, name: &str, field: &str) -> Result<Option<TokenStream>> {
+    match symtab.pragma(name, Some(field), "rust_type") {
+        None => Ok(None),
+        Some(path) => match path.parse() {
+            Ok(tok) => Ok(Some(tok)),
+            Err(_) => Err(format!("{}.{}: invalid rust_type {:?}", name, field, path).into()),
+        },
+    }
+}
+
+// The options which affect generated definitions and the bodies of generated codecs.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+struct Codegen {
+    optimize: Opt,
+    shared_arrays: bool,
+    box_over: Option<usize>,
+    metrics: bool,
+    catch_panics: bool,
+    unknown: UnknownEnum,
+    rename_all: Option<&'static str>,
+    // Unpacking the borrowing view of a type, for `GenOptions::zero_copy`
+    borrow: bool,
+    len_width: LenWidth,
+    str_enc: StrEnc,
+    log_fields: bool,
+    serde_default: bool,
+    // The specification's fingerprint, for the header of a top-level message with
+    // `GenOptions::self_describing`
+    header: Option<u64>,
+    canonical: bool,
+}
+
+impl Codegen {
+    fn new(opts: &GenOptions) -> Codegen {
+        Codegen {
+            optimize: opts.optimize,
+            shared_arrays: opts.shared_array_codecs,
+            box_over: opts.box_large_variants,
+            metrics: opts.metrics,
+            catch_panics: opts.catch_panics,
+            unknown: opts.unknown_enum,
+            rename_all: opts
+                .serde_rename_all
+                .as_ref()
+                .and_then(|case| SERDE_CASES.iter().find(|c| *c == case))
+                .copied(),
+            borrow: false,
+            len_width: opts.length_width,
+            str_enc: opts.string_encoding,
+            log_fields: opts.log_fields,
+            serde_default: opts.serde_default,
+            header: None,
+            canonical: opts.canonical,
+        }
+    }
+
+    // Only the options which change the encoding, for code which doesn't apply the others
+    fn wire(opts: &GenOptions) -> Codegen {
+        Codegen {
+            len_width: opts.length_width,
+            str_enc: opts.string_encoding,
+            canonical: opts.canonical,
+            ..Codegen::default()
+        }
+    }
+
+    // Where the helpers for variable-length data with the chosen length prefixes are
+    fn flex_helpers(&self) -> TokenStream {
+        match self.len_width {
+            LenWidth::U32 => quote!(xdr_codec),
+            LenWidth::U64 => quote!(xdr_codec::wide),
+        }
+    }
+}
+
+// The variants of an enum with their values, as `u32` literals if `unsigned` and otherwise `i32`.
+fn enum_values<'a>(
+    edefs: &'a [EnumDefn],
+    symtab: &'a Symtab,
+    unsigned: bool,
+) -> impl Iterator<Item = (Ident, TokenStream)> + 'a {
+    edefs.iter().filter_map(move |EnumDefn(field, _)| match symtab.getconst(field) {
+        Some((val, Some(_))) => {
+            let val = if unsigned {
+                let val = val as u32;
+                quote!(#val)
+            } else {
+                let val = val as i32;
+                quote!(#val)
+            };
+            Some((quote_ident(field), val))
+        }
+        _ => None,
+    })
+}
+
+// The name a type is reported to `xdr_codec::Metrics` as, which is its name in the specification.
+fn metrics_label(symtab: &Symtab, name: &str) -> String {
+    symtab.original(IdentKind::Type, None, name).unwrap_or(name).to_string()
+}
+
+/// The conversion trait for struct fields whose type is set with a `@rust_type` pragma.
+pub fn rust_type_trait() -> TokenStream {
+    quote! {
+        /// Conversion between the Rust type of a field given by a `@rust_type` pragma and the
+        /// field's XDR type `X`, which it's packed and unpacked as.
+        pub trait RustType<X>: Sized {
+            fn from_xdr(x: X) -> xdr_codec::Result<Self>;
+            fn to_xdr(&self) -> X;
+        }
+    }
+}
+
+#[cfg(not(feature = "json"))]
+impl Typespec {
+    pub fn json_impls(&self, _symtab: &Symtab, _opts: &GenOptions) -> Result<Option<TokenStream>> {
+        Ok(None)
     }
 }
 
 impl Emitpack for Typespec {
     fn pack(&self, symtab: &Symtab) -> Result<Option<TokenStream>> {
+        self.pack_impl(symtab, false, Codegen::default())
+    }
+
+    fn unpack(&self, symtab: &Symtab) -> Result<Option<TokenStream>> {
+        self.unpack_impl(symtab, None, false, false, Codegen::default())
+    }
+}
+
+impl Typespec {
+    fn pack_impl(&self, symtab: &Symtab, unsigned: bool, opt: Codegen) -> Result<Option<TokenStream>> {
         use self::Type::*;
         use self::Decl::*;
 
         let name = quote_ident(&self.0);
         let ty = &self.1;
         let mut directive = quote!();
+        let inline = match opt.optimize {
+            Opt::Speed => quote!(#[inline]),
+            Opt::Size => quote!(),
+        };
 
         let body: TokenStream = match ty {
+            Enum(edefs) if opt.unknown == UnknownEnum::Preserve => {
+                directive = inline;
+                let arms = enum_values(edefs, symtab, unsigned)
+                    .map(|(field, val)| quote!(#name::#field => #val,));
+                quote!(match *self { #(#arms)* #name::Unknown(v) => v }.pack(out)?)
+            }
+
+            &Enum(_) if unsigned => {
+                directive = inline;
+                quote!((*self as u32).pack(out)?)
+            }
+
             &Enum(_) => {
-                directive = quote!(#[inline]);
-                ty.packer(quote!(self), symtab)?
+                directive = inline;
+                ty.packer_opt(quote!(self), symtab, opt)?
+            }
+
+            &Struct(_) if ty.is_marker() => {
+                directive = quote!(#inline #[allow(unused_variables)]);
+                quote!(0)
             }
 
             &Struct(ref decl) => {
                 let decls: Vec<_> = decl.iter()
                     .filter_map(|d| match d {
                         &Void => None,
-                        &Named(ref name, ref ty) => Some((quote_ident(name), ty)),
+                        &Named(ref name, ref ty) => Some((name, ty)),
                     })
-                    .map(|(field, ty)| {
-                        let p = ty.packer(quote!(self.#field), symtab).unwrap();
-                        quote!(#p + )
+                    .map(|(name, ty)| {
+                        let field = quote_ident(name);
+                        let val = match rust_type(symtab, &self.0, name)? {
+                            Some(rust) => {
+                                let xty = ty.as_token(symtab)?;
+                                quote!((<#rust as RustType<#xty>>::to_xdr(&self.#field)))
+                            }
+                            None => quote!(self.#field),
+                        };
+                        let p = ty.packer_opt(val, symtab, opt).unwrap();
+                        if opt.log_fields {
+                            let msg = format!("{}.{}: packed {{}} bytes", self.0, name);
+                            return Ok(quote!({ let fsz = #p; log::trace!(#msg, fsz); fsz } + ));
+                        }
+                        Ok(quote!(#p + ))
                     })
-                    .collect();
+                    .collect::<Result<_>>()?;
                 quote!(#(#decls)* 0)
             }
 
@@ -831,12 +1509,20 @@ impl Emitpack for Typespec {
                     .iter()
                     .filter_map(|&UnionCase(ref val, ref decl)| {
                         let label = val.as_ident();
-                        let disc = val.as_token(symtab);
+                        let disc = match val.as_i64(symtab) {
+                            // Enums with an `Unknown` variant can't be cast
+                            Some(v) if opt.unknown == UnknownEnum::Preserve => {
+                                let v = v as i32;
+                                quote!(#v)
+                            }
+                            _ => val.as_token(symtab),
+                        };
 
                         let ret = match decl {
                             &Void => quote!(&#name::#label => (#disc as i32).pack(out)?,),
                             &Named(_, ref ty) => {
-                                let pack = match ty.packer(quote!(val), symtab) {
+                                let val = if boxed_variant(ty, symtab, opt.box_over) { quote!((**val)) } else { quote!(val) };
+                                let pack = match ty.packer_opt(val, symtab, opt) {
                                     Err(_) => return None,
                                     Ok(p) => p,
                                 };
@@ -870,7 +1556,7 @@ impl Emitpack for Typespec {
             }
 
             // Array and Flex types are wrapped in tuple structs
-            &Flex(..) | &Array(..) => ty.packer(quote!(self.0), symtab)?,
+            &Flex(..) | &Array(..) => ty.packer_opt(quote!(self.0), symtab, opt)?,
 
             &Ident(_, _) => return Ok(None),
 
@@ -878,73 +1564,390 @@ impl Emitpack for Typespec {
                 if ty.is_prim(symtab) {
                     return Ok(None);
                 } else {
-                    ty.packer(quote!(self), symtab)?
+                    ty.packer_opt(quote!(self), symtab, opt)?
                 }
             }
         };
 
         trace!("body {:?}", body);
 
+        let body = match opt.header {
+            Some(hash) => quote!(xdr_codec::pack_header(#hash, out)? + #body),
+            None => body,
+        };
+
+        let body = if opt.metrics {
+            let label = metrics_label(symtab, &self.0);
+            let body = ok_result(body);
+            quote!(xdr_codec::metrics::packed(#label, (|| -> xdr_codec::Result<usize> { #body })()))
+        } else {
+            ok_result(body)
+        };
+
         Ok(Some(quote! {
             impl<Out: xdr_codec::Write> xdr_codec::Pack<Out> for #name {
                 #directive
                     fn pack(&self, out: &mut Out) -> xdr_codec::Result<usize> {
-                        Ok(#body)
+                        #body
                     }
             }
         }))
     }
 
-    fn unpack(&self, symtab: &Symtab) -> Result<Option<TokenStream>> {
+    /// Generate the definition, applying the options which affect it.
+    pub fn define_with(&self, symtab: &Symtab, opts: &GenOptions) -> Result<TokenStream> {
+        if let Some((_, path)) = opts.external_types.iter().find(|(n, _)| *n == self.0) {
+            self.define_external(path)
+        } else {
+            self.define_impl(symtab, self.unsigned_repr(symtab, opts), Codegen::new(opts))
+        }
+    }
+
+    // An alias for a type defined outside the generated code, which the codec implementations
+    // then apply to.
+    fn define_external(&self, path: &str) -> Result<TokenStream> {
+        match self.1 {
+            Type::Struct(_) | Type::Union(..) | Type::Enum(_) => (),
+            _ => {
+                return Err(format!("external type {}: only structs, unions and enums can be external", self.0).into())
+            }
+        }
+        let ident = |seg: &str| {
+            seg.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+                && seg.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        };
+        if !path.trim_start_matches("::").split("::").all(ident) {
+            return Err(format!("external type {}: invalid path {}", self.0, path).into());
+        }
+        let path: TokenStream = path.parse().expect("valid path");
+        let name = quote_ident(&self.0);
+
+        Ok(quote!(pub type #name = #path;))
+    }
+
+    /// Generate a `Pack` implementation, applying the options which affect it.
+    pub fn pack_with(&self, symtab: &Symtab, opts: &GenOptions) -> Result<Option<TokenStream>> {
+        let unsigned = self.unsigned_repr(symtab, opts);
+        let opt = Codegen::new(opts);
+        if unsigned || opt != Codegen::default() {
+            self.pack_impl(symtab, unsigned, opt)
+        } else {
+            self.pack(symtab)
+        }
+    }
+
+    /// Generate a `Pack` implementation for a top-level type, with the header of `GenOptions::self_describing`
+    /// if `header` is the specification's fingerprint.
+    pub fn pack_toplevel(&self, symtab: &Symtab, opts: &GenOptions, header: Option<u64>) -> Result<Option<TokenStream>> {
+        match header {
+            None => self.pack_with(symtab, opts),
+            Some(_) => self.pack_impl(symtab, self.unsigned_repr(symtab, opts), Codegen { header, ..Codegen::new(opts) }),
+        }
+    }
+
+    /// Generate an `Unpack` implementation, applying the options which affect it.
+    pub fn unpack_with(&self, symtab: &Symtab, opts: &GenOptions) -> Result<Option<TokenStream>> {
+        let unsigned = self.unsigned_repr(symtab, opts);
+        let opt = Codegen::new(opts);
+        if unsigned || opt != Codegen::default() {
+            self.unpack_impl(symtab, None, false, unsigned, opt)
+        } else {
+            self.unpack(symtab)
+        }
+    }
+
+    /// Generate a `pack_with_policy` method, which packs with caller-controlled flushing.
+    pub fn policy_packer(&self) -> Option<TokenStream> {
+        if let Type::Ident(..) = self.1 {
+            return None;
+        }
+        let name = quote_ident(&self.0);
+
+        Some(quote! {
+            impl #name {
+                /// Pack into `out`, flushing it according to `policy`.
+                pub fn pack_with_policy<Out, P>(&self, out: &mut Out, policy: P) -> xdr_codec::Result<usize>
+                where
+                    Out: xdr_codec::Write,
+                    P: xdr_codec::FlushPolicy,
+                {
+                    xdr_codec::pack_with_policy(self, out, policy)
+                }
+            }
+        })
+    }
+
+    /// Generate a `pack_with_scratch` method, which reuses a caller's buffer.
+    pub fn scratch_packer(&self) -> Option<TokenStream> {
+        if let Type::Ident(..) = self.1 {
+            return None;
+        }
+        let name = quote_ident(&self.0);
+
+        Some(quote! {
+            impl #name {
+                /// Pack into `scratch`, replacing its contents but keeping its allocation. It's
+                /// left empty if packing fails.
+                pub fn pack_with_scratch(&self, scratch: &mut Vec<u8>) -> xdr_codec::Result<()> {
+                    scratch.clear();
+                    match xdr_codec::Pack::pack(self, scratch) {
+                        Ok(_) => Ok(()),
+                        Err(e) => {
+                            scratch.clear();
+                            Err(e)
+                        }
+                    }
+                }
+            }
+        })
+    }
+
+    /// Generate a `codec` function, giving a `tokio_util` codec for messages of this type.
+    pub fn record_codec(&self, max: Option<usize>) -> Option<TokenStream> {
+        if let Type::Ident(..) = self.1 {
+            return None;
+        }
+        let name = quote_ident(&self.0);
+        let limit = max.map(|max| quote!(.max_record(#max)));
+
+        Some(quote! {
+            impl #name {
+                /// Codec for sending and receiving these messages one per record.
+                pub fn codec() -> xdr_codec::codec::RecordCodec<#name> {
+                    xdr_codec::codec::RecordCodec::new() #limit
+                }
+            }
+        })
+    }
+
+    /// Generate a `content_hash` method, which fingerprints the packed encoding.
+    pub fn content_hasher(&self) -> Option<TokenStream> {
+        if let Type::Ident(..) = self.1 {
+            return None;
+        }
+        let name = quote_ident(&self.0);
+
+        Some(quote! {
+            impl #name {
+                /// Stable 64-bit hash of the packed encoding, so equal messages have equal hashes.
+                /// Fails if the value can't be packed.
+                pub fn content_hash(&self) -> xdr_codec::Result<u64> {
+                    xdr_codec::content_hash(self)
+                }
+            }
+        })
+    }
+
+    /// `from_reader` and `to_writer`, unpacking and packing the type.
+    pub fn io_methods(&self) -> Option<TokenStream> {
+        if let Type::Ident(..) = self.1 {
+            return None;
+        }
+        let name = quote_ident(&self.0);
+
+        Some(quote! {
+            impl #name {
+                /// Unpack a value from `reader`, as `xdr_codec::unpack`.
+                pub fn from_reader<R: xdr_codec::Read>(mut reader: R) -> xdr_codec::Result<#name> {
+                    xdr_codec::unpack(&mut reader)
+                }
+
+                /// Pack the value into `writer`, as `xdr_codec::pack`.
+                pub fn to_writer<W: xdr_codec::Write>(&self, mut writer: W) -> xdr_codec::Result<()> {
+                    xdr_codec::pack(self, &mut writer)
+                }
+            }
+        })
+    }
+
+    /// `pack_with_checksum`, packing the value and returning the checksum of its encoding.
+    pub fn checksum_packer(&self, algo: Checksum) -> Option<TokenStream> {
+        if let Type::Ident(..) = self.1 {
+            return None;
+        }
+        let name = quote_ident(&self.0);
+        let (algo, doc) = match algo {
+            Checksum::Crc32 => (quote!(Crc32), "CRC-32"),
+            Checksum::Crc32c => (quote!(Crc32c), "CRC-32C"),
+        };
+        let doc = format!(
+            "Pack the value, returning the number of bytes written and their {}, computed as they're written.",
+            doc
+        );
+
+        Some(quote! {
+            impl #name {
+                #[doc = #doc]
+                pub fn pack_with_checksum<Out: xdr_codec::Write>(&self, out: &mut Out) -> xdr_codec::Result<(usize, u32)> {
+                    xdr_codec::pack_with_crc(self, xdr_codec::Crc::#algo, out)
+                }
+            }
+        })
+    }
+
+    /// `xdr_codec::Codec`, packing and unpacking the type through trait objects.
+    pub fn dyn_codec(&self) -> Option<TokenStream> {
+        if let Type::Ident(..) = self.1 {
+            return None;
+        }
+        let name = quote_ident(&self.0);
+        let tyname = &self.0;
+
+        Some(quote! {
+            impl xdr_codec::Codec for #name {
+                fn pack_dyn(&self, mut out: &mut dyn xdr_codec::Write) -> xdr_codec::Result<usize> {
+                    xdr_codec::Pack::pack(self, &mut out)
+                }
+
+                fn unpack_boxed(
+                    &self,
+                    mut input: &mut dyn xdr_codec::Read,
+                ) -> xdr_codec::Result<(Box<dyn xdr_codec::Codec>, usize)> {
+                    let (v, sz): (#name, usize) = xdr_codec::Unpack::unpack(&mut input)?;
+                    Ok((Box::new(v), sz))
+                }
+
+                fn unpack_dyn(&mut self, mut input: &mut dyn xdr_codec::Read) -> xdr_codec::Result<usize> {
+                    let (v, sz) = xdr_codec::Unpack::unpack(&mut input)?;
+                    *self = v;
+                    Ok(sz)
+                }
+
+                fn type_name(&self) -> &'static str {
+                    #tyname
+                }
+
+                fn as_any(&self) -> &dyn std::any::Any {
+                    self
+                }
+
+                fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+                    self
+                }
+
+                fn into_any(self: Box<Self>) -> Box<dyn std::any::Any> {
+                    self
+                }
+            }
+        })
+    }
+
+    /// Generate an `Unpack` implementation for a top-level type, applying the options which only
+    /// make sense for complete messages, and checking the header of `GenOptions::self_describing`
+    /// if `header` is the specification's fingerprint.
+    pub fn unpack_toplevel(&self, symtab: &Symtab, opts: &GenOptions, header: Option<u64>) -> Result<Option<TokenStream>> {
+        let skip_trailing = opts.forward_compat && matches!(self.1, Type::Struct(_));
+        let unsigned = self.unsigned_repr(symtab, opts);
+        let opt = Codegen { header, ..Codegen::new(opts) };
+        self.unpack_impl(symtab, opts.max_message_size, skip_trailing, unsigned, opt)
+    }
+
+    // `limit` fails the unpack once more than that many bytes have been consumed,
+    // `skip_trailing` discards anything left in the input after the value, `unsigned` reads
+    // enums as `u32`, and `opt` chooses between inline code and helper calls.
+    fn unpack_impl(
+        &self,
+        symtab: &Symtab,
+        limit: Option<usize>,
+        skip_trailing: bool,
+        unsigned: bool,
+        opt: Codegen,
+    ) -> Result<Option<TokenStream>> {
         use self::Type::*;
         use self::Decl::*;
 
-        let self_name = quote_ident(&self.0);
+        let self_name = if opt.borrow { borrow::view_name(&self.0) } else { quote_ident(&self.0) };
         let ty = &self.1;
         let mut directive = quote!();
 
         let body = match ty {
             &Enum(ref defs) => {
-                directive = quote!(#[inline]);
-                let matchdefs: Vec<_> = defs.iter()
-                    .filter_map(|&EnumDefn(ref name, _)| {
-                        let tok = quote_ident(name);
-                        if let Some((ref _val, ref scope)) = symtab.getconst(name) {
-                            // let val = *val as i32;
-                            if let &Some(ref _scope) = scope {
-                                // let scope = quote_ident(scope);
-                                // Some(quote!(#val => #scope :: #tok,))
-                                Some(quote!(x if x == #self_name :: #tok as i32 => #self_name :: #tok,))
+                if opt.optimize == Opt::Speed {
+                    directive = quote!(#[inline]);
+                }
+                let (repr, err) = if unsigned {
+                    (quote!(u32), quote!(e as i32))
+                } else {
+                    (quote!(i32), quote!(e))
+                };
+                let matchdefs: Vec<_> = if opt.unknown == UnknownEnum::Preserve {
+                    // No casts for enums with fields
+                    enum_values(defs, symtab, unsigned)
+                        .map(|(field, val)| quote!(#val => #self_name::#field,))
+                        .collect()
+                } else {
+                    defs.iter()
+                        .filter_map(|&EnumDefn(ref name, _)| {
+                            let tok = quote_ident(name);
+                            if let Some((ref _val, ref scope)) = symtab.getconst(name) {
+                                // let val = *val as i32;
+                                if let &Some(ref _scope) = scope {
+                                    // let scope = quote_ident(scope);
+                                    // Some(quote!(#val => #scope :: #tok,))
+                                    Some(quote!(x if x == #self_name :: #tok as #repr => #self_name :: #tok,))
+                                } else {
+                                    // Some(quote!(#val => #tok,))
+                                    Some(quote!(x if x == #tok as #repr => #tok,))
+                                }
                             } else {
-                                // Some(quote!(#val => #tok,))
-                                Some(quote!(x if x == #tok as i32 => #tok,))
+                                println!("unknown ident {}", name);
+                                None
                             }
-                        } else {
-                            println!("unknown ident {}", name);
-                            None
-                        }
-                    })
-                    .collect();
+                        })
+                        .collect()
+                };
+
+                let fallback = match opt.unknown {
+                    UnknownEnum::Error => quote!(e => return Err(xdr_codec::Error::invalidenum(#err))),
+                    UnknownEnum::Preserve => quote!(e => #self_name::Unknown(e)),
+                    UnknownEnum::Clamp => quote!(_ => #self_name::default()),
+                };
 
                 quote!({
-                    let (e, esz): (i32, _) = xdr_codec::Unpack::unpack(input)?;
+                    let (e, esz): (#repr, _) = xdr_codec::Unpack::unpack(input)?;
                     sz += esz;
                     match e {
                         #(#matchdefs)*
-                        e => return Err(xdr_codec::Error::invalidenum(e))
+                        #fallback
                     }
                 })
             }
 
+            &Struct(_) if ty.is_marker() => {
+                if opt.optimize == Opt::Speed {
+                    directive = quote!(#[inline]);
+                }
+                directive = quote!(#directive #[allow(unused_variables, unused_mut)]);
+                quote!(#self_name)
+            }
+
             &Struct(ref decls) => {
                 let decls: Vec<_> = decls
                     .iter()
-                    .filter_map(|decl| decl.name_as_ident())
-                    .map(|(field, ty)| {
-                        let unpack = ty.unpacker(symtab);
-                        quote!(#field: { let (v, fsz) = #unpack; sz += fsz; v },)
+                    .filter_map(|decl| match decl {
+                        Void => None,
+                        Named(name, ty) => Some((name, ty)),
                     })
-                    .collect();
+                    .map(|(name, ty)| {
+                        let field = quote_ident(name);
+                        let (unpack, v) = match rust_type(symtab, &self.0, name)? {
+                            Some(rust) => {
+                                // Converted from the owned XDR type, even in a borrowing view
+                                let unpack = ty.unpacker_opt(symtab, Codegen { borrow: false, ..opt });
+                                let xty = ty.as_token(symtab)?;
+                                (unpack, quote!(<#rust as RustType<#xty>>::from_xdr(v)?))
+                            }
+                            None => (ty.unpacker_opt(symtab, opt), quote!(v)),
+                        };
+                        let log = if opt.log_fields {
+                            let msg = format!("{}.{}: unpacked {{}} bytes", self.0, name);
+                            quote!(log::trace!(#msg, fsz);)
+                        } else {
+                            quote!()
+                        };
+                        Ok(quote!(#field: { let (v, fsz) = #unpack; sz += fsz; #log #v },))
+                    })
+                    .collect::<Result<_>>()?;
 
                 quote!(#self_name { #(#decls)* })
             }
@@ -964,47 +1967,53 @@ impl Emitpack for Typespec {
                                 //&Void => quote!(#disc => #name::#label,),
                                 &Void => quote!(x if x == (#disc as i32) => #self_name::#label,),
                                 &Named(_, ref ty) => {
-                                    let unpack = ty.unpacker(symtab);
+                                    let unpack = ty.unpacker_opt(symtab, opt);
+                                    let v = if boxed_variant(ty, symtab, opt.box_over) { quote!(Box::new(v)) } else { quote!(v) };
                                     //quote!(#disc => #name::#label({ let (v, fsz) = #unpack; sz += fsz; v }),)
-                                    quote!(x if x == (#disc as i32) => #self_name::#label({ let (v, fsz) = #unpack; sz += fsz; v }),)
+                                    quote!(x if x == (#disc as i32) => #self_name::#label({ let (v, fsz) = #unpack; sz += fsz; #v }),)
                                 },
                             };
                             Ok(ret)
                         })
                         .collect::<Result<Vec<_>>>()?;
 
-                if let &Some(ref decl) = defl {
+                // The default arm loses the discriminant, so it isn't canonical
+                if let (&Some(ref decl), false) = (defl, opt.canonical) {
                     let decl = decl.as_ref();
                     let defl = match decl {
-                        &Void => quote!(_ => #self_name::default),
+                        &Void => quote!(#self_name::default),
                         &Named(_, ref ty) => {
-                            let unpack = ty.unpacker(symtab);
-                            quote!(_ => #self_name::default({
+                            let unpack = ty.unpacker_opt(symtab, opt);
+                            let v = if !ty.is_boxed(symtab) && boxed_variant(ty, symtab, opt.box_over) {
+                                quote!(Box::new(v))
+                            } else {
+                                quote!(v)
+                            };
+                            quote!(#self_name::default({
                                 let (v, csz) = #unpack;
                                 sz += csz;
-                                v
+                                #v
                             }))
                         }
                     };
 
-                    matches.push(defl);
+                    matches.push(union_fallback(sel, symtab, Some(defl)));
                 } else {
-                    let defl = quote!(v => return Err(xdr_codec::Error::invalidcase(v as i32)));
-                    matches.push(defl);
+                    matches.push(union_fallback(sel, symtab, None));
                 }
 
                 let selunpack = match sel {
                     &Void => panic!("void switch selector?"),
-                    &Named(_, ref ty) => ty.unpacker(symtab),
+                    &Named(_, ref ty) => ty.unpacker_opt(symtab, opt),
                 };
 
                 quote!(match { let (v, dsz): (i32, _) = #selunpack; sz += dsz; v } { #(#matches)* })
             }
 
-            &Option(_) => ty.unpacker(symtab),
+            &Option(_) => ty.unpacker_opt(symtab, opt),
 
             &Flex(_, _) | &Array(_, _) => {
-                let unpk = ty.unpacker(symtab);
+                let unpk = ty.unpacker_opt(symtab, opt);
                 quote!({ let (v, usz) = #unpk; sz = usz; #self_name(v) })
             }
 
@@ -1014,32 +2023,254 @@ impl Emitpack for Typespec {
             _ => return Err(Error::from(format!("unimplemented ty={:?}", ty))),
         };
 
+        let body = if skip_trailing {
+            quote!({
+                let v = #body;
+                sz += ::std::io::copy(input, &mut ::std::io::sink())? as usize;
+                v
+            })
+        } else {
+            body
+        };
+
+        // Within the limit, which applies to the whole message
+        let body = match opt.header {
+            Some(hash) => quote!({
+                sz += xdr_codec::unpack_header(#hash, input)?;
+                #body
+            }),
+            None => body,
+        };
+
+        let body = match limit {
+            None => quote! {
+                #[allow(unused_assignments)]
+                let mut sz = 0;
+                Ok((#body, sz))
+            },
+            Some(limit) => quote! {
+                let input = &mut xdr_codec::LimitRead::new(input, #limit);
+                let res = (|| -> xdr_codec::Result<(#self_name, usize)> {
+                    #[allow(unused_assignments)]
+                    let mut sz = 0;
+                    Ok((#body, sz))
+                })();
+                match res {
+                    Err(_) if input.exceeded() => Err(xdr_codec::Error::messagetoolarge(#limit)),
+                    res => res,
+                }
+            },
+        };
+
+        let body = if opt.catch_panics {
+            quote!(xdr_codec::catch_panic(|| -> xdr_codec::Result<(#self_name, usize)> { #body }))
+        } else {
+            body
+        };
+
+        let body = if opt.metrics {
+            let label = metrics_label(symtab, &self.0);
+            quote!(xdr_codec::metrics::unpacked(#label, (|| -> xdr_codec::Result<(#self_name, usize)> { #body })()))
+        } else {
+            body
+        };
+
+        if opt.borrow {
+            return Ok(Some(quote! {
+                impl<'a> xdr_codec::UnpackBorrowed<'a> for #self_name<'a> {
+                    fn unpack_borrowed(input: &mut &'a [u8]) -> xdr_codec::Result<(#self_name<'a>, usize)> {
+                        #body
+                    }
+                }
+            }));
+        }
+
         Ok(Some(quote! {
             impl<In: xdr_codec::Read> xdr_codec::Unpack<In> for #self_name {
                 #directive
                     fn unpack(input: &mut In) -> xdr_codec::Result<(#self_name, usize)> {
-                        #[allow(unused_assignments)]
-                        let mut sz = 0;
-                        Ok((#body, sz))
+                        #body
                     }
             }
         }))
     }
 }
 
+/// The name an item of generated code is for: the name it defines, or for an `impl` the type
+/// it's implemented on. `None` for anything else, such as macros or modules.
+pub fn item_name(item: &TokenStream) -> Option<String> {
+    use proc_macro2::TokenTree;
+
+    let mut tokens = item.clone().into_iter().peekable();
+    while let Some(tt) = tokens.next() {
+        match tt {
+            // Attributes
+            TokenTree::Punct(ref p) if p.as_char() == '#' => {
+                tokens.next();
+            }
+            TokenTree::Ident(ref id) if id == "pub" => {
+                if let Some(TokenTree::Group(_)) = tokens.peek() {
+                    tokens.next();
+                }
+            }
+            TokenTree::Ident(ref id) if ["const", "struct", "enum", "type"].contains(&&*id.to_string()) => {
+                return match tokens.next() {
+                    Some(TokenTree::Ident(name)) => Some(name.to_string()),
+                    _ => None,
+                };
+            }
+            TokenTree::Ident(ref id) if id == "impl" => {
+                // The self type follows the generics, or `for` in a trait impl
+                let mut depth = 0;
+                let mut name = None;
+                for tt in tokens {
+                    match tt {
+                        TokenTree::Punct(ref p) if p.as_char() == '<' => depth += 1,
+                        TokenTree::Punct(ref p) if p.as_char() == '>' => depth -= 1,
+                        TokenTree::Ident(ref id) if depth == 0 && id == "for" => name = None,
+                        TokenTree::Ident(ref id) if depth == 0 && id == "where" => break,
+                        TokenTree::Ident(id) if depth == 0 => name = Some(id.to_string()),
+                        TokenTree::Group(_) => break,
+                        _ => (),
+                    }
+                }
+                return name;
+            }
+            _ => return None,
+        }
+    }
+    None
+}
+
+/// The tokens of an item of generated code before its body, such as the trait and type of an
+/// `impl`, or all of an item without a body.
+pub fn item_header(item: &TokenStream) -> TokenStream {
+    use proc_macro2::{Delimiter, TokenTree};
+
+    item.clone()
+        .into_iter()
+        .take_while(|tt| !matches!(tt, TokenTree::Group(g) if g.delimiter() == Delimiter::Brace))
+        .collect()
+}
+
+/// Split a stream of generated code into its individual top-level items.
+pub fn items(tokens: TokenStream) -> Vec<TokenStream> {
+    use proc_macro2::{Delimiter, TokenTree};
+
+    let mut ret = Vec::new();
+    let mut cur = TokenStream::new();
+
+    for tt in tokens {
+        // Items end with either a `{ ... }` body or a `;`. Attributes are `#[...]`, so never
+        // end an item.
+        let end = match tt {
+            TokenTree::Group(ref g) => g.delimiter() == Delimiter::Brace,
+            TokenTree::Punct(ref p) => p.as_char() == ';',
+            _ => false,
+        };
+        cur.extend(Some(tt));
+        if end {
+            ret.push(cur);
+            cur = TokenStream::new();
+        }
+    }
+    if !cur.is_empty() {
+        ret.push(cur);
+    }
+    ret
+}
+
+/// Collect all the identifiers in `tokens`, including within groups.
+pub fn token_idents(tokens: TokenStream, out: &mut HashSet<String>) {
+    use proc_macro2::TokenTree;
+
+    for tt in tokens {
+        match tt {
+            TokenTree::Ident(id) => {
+                out.insert(id.to_string());
+            }
+            TokenTree::Group(g) => token_idents(g.stream(), out),
+            _ => (),
+        }
+    }
+}
+
+/// Symbol table for a parsed XDR specification.
+///
+/// This is constructed by parsing the text of a specification with `str::parse`.
 #[derive(Debug, Clone)]
 pub struct Symtab {
     consts: BTreeMap<String, (i64, Option<String>)>,
+    strconsts: BTreeMap<String, String>,
     typespecs: BTreeMap<String, Type>,
     typesyns: BTreeMap<String, Type>,
+    programs: BTreeMap<String, Program>,
+    pragmas: BTreeMap<(String, Option<String>), Vec<Pragma>>,
+    // Names before `rename_with`, by kind, the type of members, and new name
+    originals: BTreeMap<(IdentKind, Option<String>, String), String>,
+}
+
+/// Failure to parse a specification, from `Symtab::parse`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /// Byte offset in the specification of the first thing which couldn't be parsed, if known
+    pub offset: Option<usize>,
+    /// Line and column of `offset`, both starting at 1
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self.offset {
+            Some(_) => write!(f, "line {}, column {}: {}", self.line, self.column, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl FromStr for Symtab {
+    type Err = Error;
+
+    fn from_str(source: &str) -> result::Result<Symtab, Error> {
+        Symtab::parse(source).map_err(|e| Error::from(format!("parse error: {}", e)))
+    }
 }
 
 impl Symtab {
+    /// Parse the text of a specification, reporting where parsing failed.
+    ///
+    /// This is the same as `str::parse`, but the error has the position of the definition which
+    /// couldn't be parsed, for tools such as procedural macros which point at the bad input.
+    pub fn parse(source: &str) -> result::Result<Symtab, ParseError> {
+        match located_specification(source) {
+            Ok(defns) => Ok(Symtab::new(&defns)),
+            Err((offset, message)) => {
+                let before = &source[..offset.unwrap_or(0)];
+                let line = before.matches('\n').count() + 1;
+                let column = before[before.rfind('\n').map_or(0, |nl| nl + 1)..].chars().count() + 1;
+                Err(ParseError {
+                    offset,
+                    line,
+                    column,
+                    message,
+                })
+            }
+        }
+    }
+
     pub fn new(defns: &Vec<Defn>) -> Symtab {
         let mut ret = Symtab {
             consts: BTreeMap::new(),
+            strconsts: BTreeMap::new(),
             typespecs: BTreeMap::new(),
             typesyns: BTreeMap::new(),
+            programs: BTreeMap::new(),
+            pragmas: BTreeMap::new(),
+            originals: BTreeMap::new(),
         };
 
         ret.update_consts(&defns);
@@ -1057,9 +2288,17 @@ impl Symtab {
 
                 &Defn::Const(ref name, val) => self.defconst(name, val, None),
 
+                Defn::StrConst(name, val) => {
+                    self.strconsts.insert(name.clone(), val.clone());
+                }
+
                 &Defn::Typesyn(ref name, ref ty) => {
                     self.deftypesyn(name, ty);
                 }
+
+                Defn::Program(prog) => self.defprogram(prog),
+
+                Defn::Pragma(name, field, pragma) => self.defpragma(name, field, pragma),
             }
         }
     }
@@ -1103,6 +2342,17 @@ impl Symtab {
         self.typesyns.insert(From::from(name.as_ref()), ty.clone());
     }
 
+    fn defprogram(&mut self, prog: &Program) {
+        self.programs.insert(prog.0.clone(), prog.clone());
+    }
+
+    fn defpragma(&mut self, name: &str, field: &Option<String>, pragma: &Pragma) {
+        self.pragmas
+            .entry((name.to_string(), field.clone()))
+            .or_default()
+            .push(pragma.clone());
+    }
+
     pub fn getconst(&self, name: &String) -> Option<(i64, Option<String>)> {
         match self.consts.get(name) {
             None => None,
@@ -1129,10 +2379,59 @@ impl Symtab {
         }
     }
 
+    // Follow `ty` through typedef aliases to the type it names.
+    fn resolve<'a>(&'a self, ty: &'a Type) -> &'a Type {
+        match ty {
+            Type::Ident(name, _) => match self.typesyns.get(name) {
+                Some(syn) => self.resolve(syn),
+                None => ty,
+            },
+            _ => ty,
+        }
+    }
+
+    // A copy with every string made opaque data, which is encoded the same way, for
+    // `StrEnc::Bytes`.
+    pub(crate) fn opaque_strings(&self) -> Symtab {
+        fn decl(d: &Decl) -> Decl {
+            match d {
+                Decl::Named(name, ty) => Decl::Named(name.clone(), opaque(ty)),
+                Decl::Void => Decl::Void,
+            }
+        }
+        fn opaque(ty: &Type) -> Type {
+            use self::Type::*;
+
+            match ty {
+                String => Opaque,
+                Struct(decls) => Struct(decls.iter().map(decl).collect()),
+                Union(sel, cases, defl) => Union(
+                    sel.clone(),
+                    cases.iter().map(|UnionCase(val, d)| UnionCase(val.clone(), decl(d))).collect(),
+                    defl.as_ref().map(|d| Box::new(decl(d))),
+                ),
+                Option(ty) => Option(Box::new(opaque(ty))),
+                Array(ty, sz) => Array(Box::new(opaque(ty)), sz.clone()),
+                Flex(ty, max) => Flex(Box::new(opaque(ty)), max.clone()),
+                ty => ty.clone(),
+            }
+        }
+
+        let mut ret = self.clone();
+        for ty in ret.typespecs.values_mut().chain(ret.typesyns.values_mut()) {
+            *ty = opaque(ty);
+        }
+        ret
+    }
+
     pub fn constants(&self) -> Iter<String, (i64, Option<String>)> {
         self.consts.iter()
     }
 
+    pub fn str_constants(&self) -> Iter<'_, String, String> {
+        self.strconsts.iter()
+    }
+
     pub fn typespecs(&self) -> Iter<String, Type> {
         self.typespecs.iter()
     }
@@ -1140,6 +2439,128 @@ impl Symtab {
     pub fn typesyns(&self) -> Iter<String, Type> {
         self.typesyns.iter()
     }
+
+    pub fn programs(&self) -> Iter<'_, String, Program> {
+        self.programs.iter()
+    }
+
+    /// Return true if the specification has no definitions, as when it's empty or only contains
+    /// comments.
+    pub fn is_empty(&self) -> bool {
+        self.consts.is_empty() && self.strconsts.is_empty() && self.typespecs.is_empty() && self.typesyns.is_empty()
+            && self.programs.is_empty()
+    }
+
+    /// Arguments of the first pragma called `pragma` on a definition, or on one of its fields.
+    pub fn pragma(&self, name: &str, field: Option<&str>, pragma: &str) -> Option<&str> {
+        self.pragmas
+            .get(&(name.to_string(), field.map(String::from)))
+            .and_then(|ps| ps.iter().find(|p| p.0 == pragma))
+            .map(|p| p.1.as_str())
+    }
+
+    /// Identifiers which are Rust keywords, and so are renamed in the generated code, along with
+    /// what they're renamed to.
+    pub fn renamed(&self) -> Vec<(String, String)> {
+        fn fields<'a>(ty: &'a Type, out: &mut Vec<&'a str>) {
+            let decls: Vec<&Decl> = match ty {
+                Type::Struct(decls) => decls.iter().collect(),
+                Type::Union(sel, cases, defl) => Some(sel.as_ref())
+                    .into_iter()
+                    .chain(cases.iter().map(|UnionCase(_, d)| d))
+                    .chain(defl.as_deref())
+                    .collect(),
+                _ => return,
+            };
+            out.extend(decls.into_iter().filter_map(|d| match d {
+                Decl::Named(name, _) => Some(name.as_str()),
+                Decl::Void => None,
+            }));
+        }
+
+        let mut names: Vec<&str> = self
+            .consts
+            .keys()
+            .chain(self.strconsts.keys())
+            .chain(self.typespecs.keys())
+            .chain(self.typesyns.keys())
+            .map(String::as_str)
+            .collect();
+        for ty in self.typespecs.values() {
+            fields(ty, &mut names);
+        }
+
+        let mut ret: Vec<_> = names
+            .into_iter()
+            .filter(|name| KEYWORDS.contains(name))
+            .map(|name| (name.to_string(), quote_ident(name).to_string()))
+            .collect();
+        ret.sort();
+        ret.dedup();
+        ret
+    }
+
+    /// Features which `name` is gated behind by `@feature` pragmas, either on its own definition
+    /// or on any type or constant it refers to.
+    pub fn features(&self, name: &str) -> BTreeSet<&str> {
+        let mut ret = BTreeSet::new();
+        self.collect_features(name, &mut HashSet::new(), &mut ret);
+        ret
+    }
+
+    fn collect_features<'a>(&'a self, name: &str, seen: &mut HashSet<String>, out: &mut BTreeSet<&'a str>) {
+        if !seen.insert(name.to_string()) {
+            return;
+        }
+        if let Some(ps) = self.pragmas.get(&(name.to_string(), None)) {
+            out.extend(ps.iter().filter(|p| p.0 == "feature").map(|p| p.1.as_str()));
+        }
+
+        let mut idents = HashSet::new();
+        if let Some(ty) = self.typespec(&name.to_string()) {
+            ty.idents(&mut idents);
+            ty.const_idents(&mut idents);
+        }
+        for id in idents {
+            self.collect_features(&id, seen, out);
+        }
+    }
+
+    /// Return true if any definition has a pragma called `pragma`.
+    pub fn has_pragma(&self, pragma: &str) -> bool {
+        self.pragmas.values().flatten().any(|p| p.0 == pragma)
+    }
+
+    /// Fingerprint of the definitions in the specification, which changes when any constant or
+    /// type is changed, added or removed.
+    ///
+    /// This is the 64-bit FNV-1a hash of a description of the definitions, which is the same
+    /// whatever order they're in and however they're formatted. It isn't guaranteed to stay the
+    /// same between versions of xdrgen.
+    pub fn fingerprint(&self) -> u64 {
+        let mut hasher = xdr_codec::Fnv64::new();
+        let _ = write!(hasher, "{:?}{:?}{:?}{:?}", self.consts, self.strconsts, self.typespecs, self.typesyns);
+        hasher.finish()
+    }
+
+    /// Return true if `name` is a type which isn't used within any other type.
+    ///
+    /// Plain aliases (`typedef Foo Bar;`) don't count as uses, as they're the same type.
+    pub fn is_toplevel(&self, name: &str) -> bool {
+        let mut used = HashSet::new();
+
+        for ty in self.typespecs.values() {
+            ty.idents(&mut used);
+        }
+        for ty in self.typesyns.values() {
+            if let Type::Ident(..) = ty {
+                continue;
+            }
+            ty.idents(&mut used);
+        }
+
+        !used.contains(name)
+    }
 }
 
 
@@ -0,0 +1,80 @@
+// Packed sizes of types whose encoding always has the same length, as a constant usable in array
+// lengths and other const contexts. Types with variable-length data, optional data or union arms
+// of different sizes have no such constant.
+use std::convert::TryFrom;
+
+use proc_macro2::TokenStream;
+
+use super::{Decl, GenOptions, Symtab, Type, Typespec, UnionCase, quote_ident};
+
+// The packed size of `ty` if it's fixed. `seen` are the types being sized, so recursive types,
+// which always go through an optional or variable-length array, are caught rather than looping.
+fn fixed_size(ty: &Type, symtab: &Symtab, opts: &GenOptions, seen: &mut Vec<String>) -> Option<usize> {
+    use super::Type::*;
+
+    let decl_size = |decl: &Decl, seen: &mut Vec<_>| match decl {
+        Decl::Named(_, ty) => fixed_size(ty, symtab, opts, seen),
+        Decl::Void => Some(0),
+    };
+
+    match ty {
+        Int | UInt | Float | Bool | Enum(_) => Some(4),
+        Hyper | UHyper | Double => Some(8),
+        Array(elem, sz) => {
+            let sz = usize::try_from(sz.as_i64(symtab)?).ok()?;
+            match elem.as_ref() {
+                Opaque => Some(sz.div_ceil(4) * 4),
+                String => None,
+                elem => fixed_size(elem, symtab, opts, seen)?.checked_mul(sz),
+            }
+        }
+        Struct(decls) => decls.iter().try_fold(0usize, |sum, decl| sum.checked_add(decl_size(decl, seen)?)),
+        Union(_, cases, defl) => {
+            let mut arms = cases.iter().map(|UnionCase(_, decl)| decl).chain(defl.as_deref());
+            let size = decl_size(arms.next()?, seen)?;
+            for decl in arms {
+                if decl_size(decl, seen)? != size {
+                    return None;
+                }
+            }
+            size.checked_add(4)
+        }
+        Ident(name, _) => {
+            // External types have their own codecs
+            if seen.contains(name) || opts.external_types.iter().any(|(n, _)| n == name) {
+                return None;
+            }
+            let ty = match symtab.resolve(ty) {
+                Ident(name, _) => symtab.typespec(name)?,
+                ty => ty,
+            };
+            seen.push(name.clone());
+            let ret = fixed_size(ty, symtab, opts, seen);
+            seen.pop();
+            ret
+        }
+        Quadruple | Opaque | String | Option(_) | Flex(..) => None,
+    }
+}
+
+impl Typespec {
+    /// `PACKED_SIZE`, the size of every packed value of the type, if it's fixed, including the
+    /// header of a top-level message with `GenOptions::self_describing`.
+    pub fn packed_size(&self, symtab: &Symtab, opts: &GenOptions, header: bool) -> Option<TokenStream> {
+        // Other typespecs are type aliases, which can't have associated constants
+        match self.1 {
+            Type::Enum(_) | Type::Struct(_) | Type::Union(..) | Type::Array(..) => (),
+            _ => return None,
+        }
+        let size = fixed_size(&self.1, symtab, opts, &mut vec![self.0.clone()])?;
+        let size = if header { size + xdr_codec::header::HEADER_SIZE } else { size };
+        let name = quote_ident(&self.0);
+
+        Some(quote! {
+            impl #name {
+                /// The packed size of every value of this type, which is fixed.
+                pub const PACKED_SIZE: usize = #size;
+            }
+        })
+    }
+}
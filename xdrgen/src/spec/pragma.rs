@@ -0,0 +1,18 @@
+// Annotations written in the specification as specially formatted comments.
+//
+// A block comment starting with `@` right before a definition or struct field, such as
+//
+//     /* @deprecated use NewFoo */
+//     struct Foo { ... };
+//
+// is a pragma named `deprecated` with the arguments `use NewFoo`. Anywhere else they're just
+// comments.
+
+#[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Clone)]
+pub struct Pragma(pub String, pub String);
+
+impl Pragma {
+    pub fn new<S: AsRef<str>, A: AsRef<str>>(name: S, args: A) -> Pragma {
+        Pragma(name.as_ref().to_string(), args.as_ref().to_string())
+    }
+}
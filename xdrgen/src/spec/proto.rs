@@ -0,0 +1,270 @@
+// Export a specification as a Protocol Buffers (proto3) schema, as a starting point for moving a
+// protocol from XDR to protobuf/gRPC.
+//
+// The mapping is best-effort: messages don't have the same encoding, so this only carries over
+// the shape of the types. Structs become messages, enums enums, and unions a message holding the
+// discriminant and a `oneof` of the arms. Fields are numbered in declaration order. Anything
+// protobuf can't express, such as fixed lengths and size bounds, is noted in a comment.
+use std::fmt::Write;
+
+use super::{Decl, EnumDefn, Result, Symtab, Type, UnionCase, Value};
+
+// A protobuf field: its label (repeated or optional), type and any notes on what was lost
+struct Field {
+    label: &'static str,
+    ty: String,
+    notes: Vec<String>,
+}
+
+impl Field {
+    fn new<S: Into<String>>(ty: S) -> Field {
+        Field {
+            label: "",
+            ty: ty.into(),
+            notes: Vec::new(),
+        }
+    }
+
+    fn labeled(mut self, label: &'static str) -> Field {
+        self.label = label;
+        self
+    }
+
+    fn note(mut self, note: String) -> Field {
+        self.notes.push(note);
+        self
+    }
+
+    fn write(&self, out: &mut String, indent: usize, name: &str, num: usize) {
+        let _ = write!(out, "{:1$}", "", indent);
+        if !self.label.is_empty() {
+            let _ = write!(out, "{} ", self.label);
+        }
+        let _ = write!(out, "{} {} = {};", self.ty, name, num);
+        if !self.notes.is_empty() {
+            let _ = write!(out, " // {}", self.notes.join(", "));
+        }
+        let _ = writeln!(out);
+    }
+}
+
+fn value(symtab: &Symtab, v: &Value) -> Result<i64> {
+    symtab
+        .value(v)
+        .ok_or_else(|| format!("proto: unknown value {:?}", v).into())
+}
+
+// The field for a value of type `ty`
+fn field(symtab: &Symtab, ty: &Type) -> Result<Field> {
+    let ret = match symtab.resolve(ty) {
+        Type::Int => Field::new("int32"),
+        Type::UInt => Field::new("uint32"),
+        Type::Hyper => Field::new("int64"),
+        Type::UHyper => Field::new("uint64"),
+        Type::Float => Field::new("float"),
+        Type::Double => Field::new("double"),
+        Type::Quadruple => Field::new("bytes").note("quadruple precision".to_string()),
+        Type::Bool => Field::new("bool"),
+        Type::Opaque => Field::new("bytes"),
+        Type::String => Field::new("string"),
+
+        Type::Ident(name, _) => match symtab.typespec(name) {
+            // Typedefs which map to a plain field are used directly, others have a message
+            Some(ty @ Type::Array(..)) | Some(ty @ Type::Flex(..)) | Some(ty @ Type::Option(_)) => {
+                match field(symtab, ty)? {
+                    Field { label: "", ty, mut notes } => {
+                        notes.insert(0, name.clone());
+                        Field { label: "", ty, notes }
+                    }
+                    _ => Field::new(name.as_str()),
+                }
+            }
+            _ => Field::new(name.as_str()),
+        },
+
+        Type::Option(ty) => field(symtab, ty)?.labeled("optional"),
+
+        Type::Array(elem, sz) => {
+            let sz = value(symtab, sz)?;
+            let note = format!("fixed length {}", sz);
+            match symtab.resolve(elem) {
+                Type::Opaque => Field::new("bytes").note(note),
+                Type::String => Field::new("string").note(note),
+                elem => field(symtab, elem)?.labeled("repeated").note(note),
+            }
+        }
+
+        Type::Flex(elem, sz) => {
+            let ret = match symtab.resolve(elem) {
+                Type::Opaque => Field::new("bytes"),
+                Type::String => Field::new("string"),
+                elem => field(symtab, elem)?.labeled("repeated"),
+            };
+            match sz {
+                Some(sz) => ret.note(format!("at most {}", value(symtab, sz)?)),
+                None => ret,
+            }
+        }
+
+        ty => return Err(format!("proto: anonymous type {:?} not supported", ty).into()),
+    };
+    Ok(ret)
+}
+
+// A message wrapping a single field, for types which can't be used directly as a field
+fn wrapper(out: &mut String, indent: usize, name: &str, field: &Field) {
+    let _ = writeln!(out, "{:1$}message {2} {{", "", indent, name);
+    field.write(out, indent + 2, "value", 1);
+    let _ = writeln!(out, "{:1$}}}", "", indent);
+}
+
+fn message(out: &mut String, symtab: &Symtab, name: &str, decls: &[Decl]) -> Result<()> {
+    let _ = writeln!(out, "message {} {{", name);
+    let mut num = 0;
+    for decl in decls {
+        if let Decl::Named(id, ty) = decl {
+            num += 1;
+            field(symtab, ty)?.write(out, 2, id, num);
+        }
+    }
+    let _ = writeln!(out, "}}");
+    Ok(())
+}
+
+fn union(
+    out: &mut String,
+    symtab: &Symtab,
+    name: &str,
+    sel: &Decl,
+    cases: &[UnionCase],
+    defl: &Option<Box<Decl>>,
+) -> Result<()> {
+    let label = |val: &Value| match val {
+        Value::Ident(id) => id.clone(),
+        Value::Const(c) => c.to_string(),
+    };
+
+    // Arms in declaration order, with the cases selecting them
+    fn add<'a>(arms: &mut Vec<(&'a Decl, Vec<String>)>, decl: &'a Decl, case: String) {
+        if let Decl::Named(id, _) = decl {
+            let same = |(d, _): &&mut (&Decl, _)| matches!(d, Decl::Named(other, _) if other == id);
+            if let Some((_, cases)) = arms.iter_mut().find(same) {
+                cases.push(case);
+                return;
+            }
+        }
+        arms.push((decl, vec![case]));
+    }
+    let mut arms = Vec::new();
+    for UnionCase(val, decl) in cases {
+        add(&mut arms, decl, format!("case {}", label(val)));
+    }
+    if let Some(decl) = defl {
+        add(&mut arms, decl, "default".to_string());
+    }
+
+    let _ = writeln!(out, "message {} {{", name);
+    match sel {
+        Decl::Named(id, ty) => field(symtab, ty)?.write(out, 2, id, 1),
+        Decl::Void => return Err("proto: void union selector".into()),
+    }
+
+    let mut wrappers = String::new();
+    let mut voids = Vec::new();
+    let _ = writeln!(out, "  oneof arm {{");
+    let mut num = 1;
+    for (decl, labels) in arms {
+        match decl {
+            Decl::Void => voids.extend(labels),
+            Decl::Named(id, ty) => {
+                num += 1;
+                let mut f = field(symtab, ty)?;
+                // Fields in a oneof can't be repeated or optional
+                if !f.label.is_empty() {
+                    let wrapped = format!("{}_value", id);
+                    wrapper(&mut wrappers, 2, &wrapped, &f);
+                    f = Field::new(wrapped);
+                }
+                f.notes.splice(0..0, labels);
+                f.write(out, 4, id, num);
+            }
+        }
+    }
+    let _ = writeln!(out, "  }}");
+    if !voids.is_empty() {
+        let _ = writeln!(out, "  // void: {}", voids.join(", "));
+    }
+    out.push_str(&wrappers);
+    let _ = writeln!(out, "}}");
+    Ok(())
+}
+
+// proto3 enums must start with a zero value, so move it first or add one
+fn enumeration(out: &mut String, symtab: &Symtab, name: &str, edefs: &[EnumDefn]) -> Result<()> {
+    let mut vals = Vec::new();
+    for EnumDefn(field, _) in edefs {
+        if let Some((v, Some(_))) = symtab.getconst(field) {
+            vals.push((field.clone(), v));
+        }
+    }
+    match vals.iter().position(|(_, v)| *v == 0) {
+        Some(zero) => {
+            let v = vals.remove(zero);
+            vals.insert(0, v);
+        }
+        None => vals.insert(0, (format!("{}_UNSPECIFIED", name.to_uppercase()), 0)),
+    }
+
+    let _ = writeln!(out, "enum {} {{", name);
+    if vals.iter().enumerate().any(|(i, (_, v))| vals[..i].iter().any(|(_, o)| o == v)) {
+        let _ = writeln!(out, "  option allow_alias = true;");
+    }
+    for (field, v) in vals {
+        let _ = writeln!(out, "  {} = {};", field, v);
+    }
+    let _ = writeln!(out, "}}");
+    Ok(())
+}
+
+/// Protocol Buffers schema for all the types in `symtab`, in package `package`.
+pub fn proto(package: &str, symtab: &Symtab) -> Result<String> {
+    let pkg: String = package
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+
+    let mut out = String::new();
+    let _ = writeln!(out, "// Generated by xdrgen from {}", package);
+    let _ = writeln!(out, "syntax = \"proto3\";");
+    let _ = writeln!(out);
+    let _ = writeln!(out, "package {};", pkg);
+
+    let consts: Vec<_> = symtab.constants().filter(|(_, (_, scope))| scope.is_none()).collect();
+    if !consts.is_empty() {
+        let _ = writeln!(out);
+        for (name, (val, _)) in consts {
+            let _ = writeln!(out, "// const {} = {};", name, val);
+        }
+    }
+
+    for (name, ty) in symtab.typespecs() {
+        let mut def = String::new();
+        match ty {
+            Type::Enum(edefs) => enumeration(&mut def, symtab, name, edefs)?,
+            Type::Struct(decls) => message(&mut def, symtab, name, decls)?,
+            Type::Union(sel, cases, defl) => union(&mut def, symtab, name, sel, cases, defl)?,
+            ty => {
+                let f = field(symtab, ty)?;
+                if !f.label.is_empty() {
+                    wrapper(&mut def, 0, name, &f);
+                }
+            }
+        }
+        if !def.is_empty() {
+            let _ = writeln!(out);
+            out.push_str(&def);
+        }
+    }
+
+    Ok(out)
+}
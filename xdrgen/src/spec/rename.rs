@@ -0,0 +1,252 @@
+// Renaming identifiers throughout a specification, for `GenOptions::rename_fn`.
+use std::collections::BTreeMap;
+
+use super::{Decl, EnumDefn, Procedure, Program, Result, Symtab, Type, UnionCase, Value, Version};
+use super::super::{IdentKind, Rename};
+
+fn valid_ident(id: &str) -> bool {
+    let mut chars = id.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => (),
+        _ => return false,
+    }
+    id != "_" && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+struct Renamer<'a> {
+    old: &'a Symtab,
+    rename: &'a Rename,
+    // The renamed type whose members are being renamed
+    within: Option<String>,
+    originals: BTreeMap<(IdentKind, Option<String>, String), String>,
+}
+
+impl<'a> Renamer<'a> {
+    fn name(&mut self, kind: IdentKind, name: &str) -> Result<String> {
+        let new = (self.rename)(name, kind);
+        if !valid_ident(&new) {
+            return Err(format!("rename: {} renamed to invalid identifier {:?}", name, new).into());
+        }
+        if new != name {
+            let within = match kind {
+                IdentKind::Field => self.within.clone(),
+                _ => None,
+            };
+            self.originals.insert((kind, within, new.clone()), name.to_string());
+        }
+        Ok(new)
+    }
+
+    // Rename the names in one scope, failing if two of them end up the same.
+    fn scope<'n, I>(&mut self, kind: IdentKind, names: I) -> Result<BTreeMap<&'n str, String>>
+    where
+        I: IntoIterator<Item = &'n String>,
+    {
+        let mut ret = BTreeMap::new();
+        let mut seen: BTreeMap<String, &str> = BTreeMap::new();
+
+        for name in names {
+            let new = self.name(kind, name)?;
+            match seen.get(&new) {
+                Some(other) if *other != name.as_str() => {
+                    return Err(format!("rename: {} and {} are both renamed to {}", other, name, new).into())
+                }
+                _ => (),
+            }
+            seen.insert(new.clone(), name);
+            ret.insert(name.as_str(), new);
+        }
+        Ok(ret)
+    }
+
+    fn value(&mut self, val: &Value) -> Result<Value> {
+        Ok(match val {
+            Value::Ident(id) => match self.old.consts.get(id) {
+                Some((_, None)) => Value::Ident(self.name(IdentKind::Const, id)?),
+                Some((_, Some(_))) => Value::Ident(self.name(IdentKind::Variant, id)?),
+                None => val.clone(),
+            },
+            Value::Const(_) => val.clone(),
+        })
+    }
+
+    fn decls<'d, I>(&mut self, decls: I) -> Result<Vec<Decl>>
+    where
+        I: IntoIterator<Item = &'d Decl> + Clone,
+    {
+        let fields = decls.clone().into_iter().filter_map(|decl| match decl {
+            Decl::Named(field, _) => Some(field),
+            Decl::Void => None,
+        });
+        let fields = self.scope(IdentKind::Field, fields)?;
+
+        decls
+            .into_iter()
+            .map(|decl| match decl {
+                Decl::Named(field, ty) => Ok(Decl::Named(fields[field.as_str()].clone(), self.ty(ty)?)),
+                Decl::Void => Ok(Decl::Void),
+            })
+            .collect()
+    }
+
+    fn ty(&mut self, ty: &Type) -> Result<Type> {
+        Ok(match ty {
+            Type::Ident(id, derives) => {
+                if self.old.typespecs.contains_key(id) || self.old.typesyns.contains_key(id) {
+                    Type::Ident(self.name(IdentKind::Type, id)?, *derives)
+                } else {
+                    ty.clone()
+                }
+            }
+            Type::Enum(defs) => {
+                let variants = self.scope(IdentKind::Variant, defs.iter().map(|EnumDefn(name, _)| name))?;
+                let defs = defs
+                    .iter()
+                    .map(|EnumDefn(name, val)| {
+                        let val = match val {
+                            Some(val) => Some(self.value(val)?),
+                            None => None,
+                        };
+                        Ok(EnumDefn(variants[name.as_str()].clone(), val))
+                    })
+                    .collect::<Result<_>>()?;
+                Type::Enum(defs)
+            }
+            Type::Struct(decls) => Type::Struct(self.decls(decls)?),
+            Type::Union(sel, cases, defl) => {
+                let mut decls = self.decls(
+                    Some(sel.as_ref())
+                        .into_iter()
+                        .chain(cases.iter().map(|UnionCase(_, decl)| decl))
+                        .chain(defl.as_deref()),
+                )?
+                .into_iter();
+
+                let sel = decls.next().unwrap();
+                let cases = cases
+                    .iter()
+                    .map(|UnionCase(val, _)| Ok(UnionCase(self.value(val)?, decls.next().unwrap())))
+                    .collect::<Result<_>>()?;
+                let defl = decls.next().map(Box::new);
+                Type::Union(Box::new(sel), cases, defl)
+            }
+            Type::Option(ty) => Type::Option(Box::new(self.ty(ty)?)),
+            Type::Array(ty, sz) => Type::Array(Box::new(self.ty(ty)?), self.value(sz)?),
+            Type::Flex(ty, sz) => {
+                let sz = match sz {
+                    Some(sz) => Some(self.value(sz)?),
+                    None => None,
+                };
+                Type::Flex(Box::new(self.ty(ty)?), sz)
+            }
+            _ => ty.clone(),
+        })
+    }
+
+    fn program(&mut self, prog: &Program) -> Result<Program> {
+        let Program(name, versions, val) = prog;
+        let versions = versions
+            .iter()
+            .map(|Version(name, procs, val)| {
+                let procs = procs
+                    .iter()
+                    .map(|Procedure(ret, name, args, val)| {
+                        let ret = match ret {
+                            Some(ret) => Some(self.ty(ret)?),
+                            None => None,
+                        };
+                        let args = args.iter().map(|arg| self.ty(arg)).collect::<Result<_>>()?;
+                        Ok(Procedure(ret, name.clone(), args, self.value(val)?))
+                    })
+                    .collect::<Result<_>>()?;
+                Ok(Version(name.clone(), procs, self.value(val)?))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Program(name.clone(), versions, self.value(val)?))
+    }
+}
+
+impl Symtab {
+    /// Copy of the specification with every type, member, enum value and constant renamed by
+    /// `rename`, as for `GenOptions::rename_fn`.
+    pub fn rename_with(&self, rename: &Rename) -> Result<Symtab> {
+        let mut r = Renamer {
+            old: self,
+            rename,
+            within: None,
+            originals: BTreeMap::new(),
+        };
+
+        let consts = self.consts.iter().filter(|(_, (_, scope))| scope.is_none()).map(|(name, _)| name);
+        let consts = consts.chain(self.strconsts.keys());
+        r.scope(IdentKind::Const, consts)?;
+        let types = r.scope(IdentKind::Type, self.typespecs.keys().chain(self.typesyns.keys()))?;
+
+        let mut ret = Symtab {
+            consts: BTreeMap::new(),
+            strconsts: BTreeMap::new(),
+            typespecs: BTreeMap::new(),
+            typesyns: BTreeMap::new(),
+            programs: BTreeMap::new(),
+            pragmas: BTreeMap::new(),
+            originals: BTreeMap::new(),
+        };
+
+        for (name, (val, scope)) in &self.consts {
+            let (name, scope) = match scope {
+                None => (r.name(IdentKind::Const, name)?, None),
+                Some(scope) => (r.name(IdentKind::Variant, name)?, Some(r.name(IdentKind::Type, scope)?)),
+            };
+            if ret.consts.contains_key(&name) {
+                return Err(format!("rename: two constants are renamed to {}", name).into());
+            }
+            ret.consts.insert(name, (*val, scope));
+        }
+        for (name, val) in &self.strconsts {
+            let name = r.name(IdentKind::Const, name)?;
+            if ret.consts.contains_key(&name) || ret.strconsts.contains_key(&name) {
+                return Err(format!("rename: two constants are renamed to {}", name).into());
+            }
+            ret.strconsts.insert(name, val.clone());
+        }
+        for (name, ty) in &self.typespecs {
+            r.within = Some(types[name.as_str()].clone());
+            ret.typespecs.insert(types[name.as_str()].clone(), r.ty(ty)?);
+        }
+        for (name, ty) in &self.typesyns {
+            r.within = Some(types[name.as_str()].clone());
+            ret.typesyns.insert(types[name.as_str()].clone(), r.ty(ty)?);
+        }
+        r.within = None;
+        for (name, prog) in &self.programs {
+            ret.programs.insert(name.clone(), r.program(prog)?);
+        }
+        for ((name, field), pragmas) in &self.pragmas {
+            let name = match types.get(name.as_str()) {
+                Some(name) => name.clone(),
+                None => name.clone(),
+            };
+            r.within = Some(name.clone());
+            let field = match field {
+                Some(field) => Some(r.name(IdentKind::Field, field)?),
+                None => None,
+            };
+            ret.pragmas.insert((name, field), pragmas.clone());
+        }
+
+        ret.originals = r.originals;
+        Ok(ret)
+    }
+
+    /// The name in the specification of something which was renamed to `name` by `rename_with`.
+    /// Members are looked up in the type `within`, which is ignored for anything else.
+    pub fn original(&self, kind: IdentKind, within: Option<&str>, name: &str) -> Option<&str> {
+        let within = match kind {
+            IdentKind::Field => within.map(str::to_string),
+            _ => None,
+        };
+        self.originals
+            .get(&(kind, within, name.to_string()))
+            .map(String::as_str)
+    }
+}
@@ -0,0 +1,194 @@
+// Resumable decoders for structs and unions, which keep their progress between pieces of input.
+use proc_macro2::TokenStream;
+
+use super::{Codegen, Decl, Error, Result, Symtab, Type, Typespec, UnionCase, boxed_variant, quote_ident, rust_type,
+            union_fallback};
+use super::super::GenOptions;
+
+fn decoder_name(name: &str) -> TokenStream {
+    let name = quote_ident(format!("{}Decoder", name));
+    quote!(#name)
+}
+
+// The decoder type for a member of type `ty`, and an expression constructing it. Structs and
+// unions have their own decoders; anything else is buffered until it's complete.
+fn decoder(ty: &Type, symtab: &Symtab, opts: &GenOptions) -> Result<(TokenStream, TokenStream)> {
+    if let Type::Ident(id, _) = symtab.resolve(ty) {
+        if let Some(Type::Struct(_)) | Some(Type::Union(..)) = symtab.typespec(id) {
+            let dec = decoder_name(id);
+            return Ok((dec.clone(), quote!(#dec::default())));
+        }
+    }
+
+    let tok = ty.as_token(symtab)?;
+    let unpack = ty.unpacker_opt(symtab, Codegen::wire(opts));
+    Ok((
+        quote!(xdr_codec::resume::Leaf<#tok>),
+        quote!(xdr_codec::resume::Leaf::new(|input| Ok(#unpack))),
+    ))
+}
+
+impl Typespec {
+    /// The resumable decoder for the type, which first checks the header of
+    /// `GenOptions::self_describing` if `header` is the specification's fingerprint.
+    pub fn resumable_decoder(&self, symtab: &Symtab, opts: &GenOptions, header: Option<u64>) -> Result<Option<TokenStream>> {
+        let name = quote_ident(&self.0);
+        let dec = decoder_name(&self.0);
+
+        let (fields, init, feed) = match &self.1 {
+            Type::Struct(decls) => {
+                let mut fields = Vec::new();
+                let mut inits = Vec::new();
+                let mut names = Vec::new();
+                let mut values = Vec::new();
+
+                for decl in decls {
+                    if let Decl::Named(field, ty) = decl {
+                        let rust = rust_type(symtab, &self.0, field)?;
+                        let field = quote_ident(field);
+                        let tok = ty.as_token(symtab)?;
+                        let (dty, init) = decoder(ty, symtab, opts)?;
+
+                        // Decoded as the XDR type, and converted once complete
+                        values.push(match rust {
+                            Some(rust) => quote!(#field: <#rust as RustType<#tok>>::from_xdr(#field)?),
+                            None => quote!(#field),
+                        });
+                        fields.push(quote!(#field: xdr_codec::resume::Field<#tok, #dty>,));
+                        inits.push(quote!(#field: xdr_codec::resume::Field::new(#init),));
+                        names.push(field);
+                    }
+                }
+
+                let feed = quote! {
+                    #(
+                        if !self.#names.feed(input)? {
+                            return Ok(xdr_codec::Progress::Pending);
+                        }
+                    )*
+                    #(let #names = self.#names.take();)*
+                    *self = #dec::default();
+                    Ok(xdr_codec::Progress::Complete(#name { #(#values,)* }))
+                };
+
+                (quote!(#(#fields)*), quote!(#(#inits)*), feed)
+            }
+
+            Type::Union(sel, cases, defl) => {
+                // An arm's decoder, or for a void arm the completed value
+                let arm = |label: TokenStream, decl: &Decl, boxed: bool| -> Result<TokenStream> {
+                    Ok(match decl {
+                        Decl::Void => quote!({
+                            *self = #dec::default();
+                            return Ok(xdr_codec::Progress::Complete(#name::#label));
+                        }),
+                        Decl::Named(_, ty) => {
+                            let (_, init) = decoder(ty, symtab, opts)?;
+                            let map = if boxed { quote!(|v| #name::#label(Box::new(v))) } else { quote!(#name::#label) };
+                            quote!(Box::new(xdr_codec::resume::Map::new(#init, #map)) as Box<dyn xdr_codec::Decode<#name>>)
+                        }
+                    })
+                };
+
+                let mut arms = cases
+                    .iter()
+                    .map(|UnionCase(val, decl)| {
+                        let label = val.as_ident();
+                        let disc = match val.as_i64(symtab) {
+                            Some(v) => v as i32,
+                            None => return Err(Error::from(format!("discriminant value {:?} unknown", val))),
+                        };
+                        let boxed = match decl {
+                            Decl::Named(_, ty) => boxed_variant(ty, symtab, opts.box_large_variants),
+                            Decl::Void => false,
+                        };
+                        let arm = arm(quote!(#label), decl, boxed)?;
+                        Ok(quote!(x if x == (#disc as i32) => #arm,))
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+
+                let defl = match defl.as_deref() {
+                    None => None,
+                    Some(decl) => {
+                        let boxed = match decl {
+                            Decl::Named(_, ty) => ty.is_boxed(symtab) || boxed_variant(ty, symtab, opts.box_large_variants),
+                            Decl::Void => false,
+                        };
+                        Some(arm(quote!(default), decl, boxed)?)
+                    }
+                };
+                arms.push(union_fallback(sel, symtab, defl.filter(|_| !opts.canonical)));
+
+                let fields = quote! {
+                    disc: xdr_codec::resume::Field<i32, xdr_codec::resume::Leaf<i32>>,
+                    arm: Option<Box<dyn xdr_codec::Decode<#name>>>,
+                };
+                let init = quote! {
+                    disc: xdr_codec::resume::Field::new(xdr_codec::resume::Leaf::new(|input| xdr_codec::Unpack::unpack(input))),
+                    arm: None,
+                };
+                let feed = quote! {
+                    if !self.disc.feed(input)? {
+                        return Ok(xdr_codec::Progress::Pending);
+                    }
+                    if self.arm.is_none() {
+                        let disc = *self.disc.get().unwrap();
+                        self.arm = Some(match disc { #(#arms)* });
+                    }
+                    let res = self.arm.as_mut().unwrap().feed(input)?;
+                    if let xdr_codec::Progress::Complete(_) = res {
+                        *self = #dec::default();
+                    }
+                    Ok(res)
+                };
+
+                (fields, init, feed)
+            }
+
+            _ => return Ok(None),
+        };
+
+        let (fields, init, feed) = match header {
+            Some(hash) => (
+                quote!(_header: xdr_codec::resume::Field<(), xdr_codec::resume::Leaf<()>>, #fields),
+                quote! {
+                    _header: xdr_codec::resume::Field::new(xdr_codec::resume::Leaf::new(|input| {
+                        Ok(((), xdr_codec::unpack_header(#hash, input)?))
+                    })),
+                    #init
+                },
+                quote! {
+                    if !self._header.feed(input)? {
+                        return Ok(xdr_codec::Progress::Pending);
+                    }
+                    #feed
+                },
+            ),
+            None => (fields, init, feed),
+        };
+
+        Ok(Some(quote! {
+            /// Resumable decoder, which can be fed input as it arrives.
+            pub struct #dec {
+                #fields
+            }
+
+            impl Default for #dec {
+                fn default() -> #dec {
+                    #dec { #init }
+                }
+            }
+
+            impl xdr_codec::Decode<#name> for #dec {
+                #[allow(unused_variables, unreachable_code)]
+                fn feed(&mut self, input: &mut &[u8]) -> xdr_codec::Result<xdr_codec::Progress<#name>> {
+                    #feed
+                }
+            }
+
+            impl xdr_codec::Resumable for #name {
+                type Decoder = #dec;
+            }
+        }))
+    }
+}
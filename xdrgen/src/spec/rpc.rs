@@ -0,0 +1,161 @@
+// ONC RPC program definitions (RFC5531 section 12).
+//
+// These don't generate any codecs themselves, but the procedure signatures give names to the
+// argument and result types.
+use std::collections::BTreeSet;
+
+use proc_macro2::TokenStream;
+
+use super::union::snake_case;
+use super::{Emit, Error, Result, Symtab, Type, Value, quote_ident};
+
+#[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Clone)]
+pub struct Program(pub String, pub Vec<Version>, pub Value);
+
+#[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Clone)]
+pub struct Version(pub String, pub Vec<Procedure>, pub Value);
+
+/// Procedure with its result type, name, argument types and number. `void` results and
+/// arguments are `None` and an empty list respectively.
+#[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Clone)]
+pub struct Procedure(pub Option<Type>, pub String, pub Vec<Type>, pub Value);
+
+fn number(symtab: &Symtab, what: &str, v: &Value) -> Result<u32> {
+    symtab
+        .value(v)
+        .map(|v| v as u32)
+        .ok_or_else(|| Error::from(format!("{}: unknown value {:?}", what, v)))
+}
+
+impl Program {
+    pub fn new<S: AsRef<str>>(id: S, vers: Vec<Version>, num: Value) -> Program {
+        Program(id.as_ref().to_string(), vers, num)
+    }
+
+    /// Module with the names of the program's procedures, for `GenOptions::proc_tables`.
+    pub fn proc_table(&self, symtab: &Symtab) -> Result<TokenStream> {
+        let Program(name, versions, num) = self;
+        let prognum = number(symtab, name, num)?;
+        let progmod = quote_ident(snake_case(name));
+        let progdoc = format!("Procedures of program {}.", name);
+
+        let mut latest = None;
+        let mut vers = Vec::new();
+        for Version(vname, procs, vnum) in versions {
+            let vnum = number(symtab, vname, vnum)?;
+            let vmod = quote_ident(snake_case(vname));
+            let vdoc = format!("Procedures of version {} of {}.", vnum, name);
+
+            let mut procs = procs
+                .iter()
+                .map(|Procedure(_, pname, _, pnum)| Ok((number(symtab, pname, pnum)?, pname.as_str())))
+                .collect::<Result<Vec<_>>>()?;
+            procs.sort();
+            let (nums, names): (Vec<_>, Vec<_>) = procs.into_iter().unzip();
+
+            vers.push(quote! {
+                #[doc = #vdoc]
+                pub mod #vmod {
+                    pub const VERSION: u32 = #vnum;
+
+                    /// Procedure numbers and names, in numerical order.
+                    pub const PROCS: &[(u32, &str)] = &[#((#nums, #names)),*];
+
+                    /// The name of procedure `proc_`, if it's defined.
+                    pub fn proc_name(proc_: u32) -> Option<&'static str> {
+                        match proc_ {
+                            #(#nums => Some(#names),)*
+                            _ => None,
+                        }
+                    }
+                }
+            });
+            if latest.as_ref().is_none_or(|(v, _)| *v < vnum) {
+                latest = Some((vnum, vmod));
+            }
+        }
+        let latest = latest.map(|(_, vmod)| quote!(pub use self::#vmod::{PROCS, proc_name};));
+
+        Ok(quote! {
+            #[doc = #progdoc]
+            pub mod #progmod {
+                pub const PROGRAM: u32 = #prognum;
+
+                #latest
+
+                #(#vers)*
+            }
+        })
+    }
+}
+
+impl Version {
+    pub fn new<S: AsRef<str>>(id: S, procs: Vec<Procedure>, num: Value) -> Version {
+        Version(id.as_ref().to_string(), procs, num)
+    }
+}
+
+impl Procedure {
+    pub fn new<S: AsRef<str>>(ret: Option<Type>, id: S, args: Vec<Type>, num: Value) -> Procedure {
+        Procedure(ret, id.as_ref().to_string(), args, num)
+    }
+
+    fn args_token(&self, symtab: &Symtab) -> Result<TokenStream> {
+        let args = self
+            .2
+            .iter()
+            .map(|ty| ty.as_token(symtab))
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(match args.len() {
+            1 => args.into_iter().next().unwrap(),
+            _ => quote!((#(#args),*)),
+        })
+    }
+
+    fn result_token(&self, symtab: &Symtab) -> Result<TokenStream> {
+        match self.0 {
+            None => Ok(quote!(())),
+            Some(ref ty) => ty.as_token(symtab),
+        }
+    }
+}
+
+impl Emit for Program {
+    // Versions often share procedure names, such as `MOUNTPROC_NULL` in versions 1 and 3 of
+    // mount.x. Aliases which are the same in every version are defined once, and a procedure
+    // whose types differ between versions gets aliases suffixed with each version's name,
+    // `{PROC}_{VERSION}Args` and `{PROC}_{VERSION}Result`.
+    fn define(&self, symtab: &Symtab) -> Result<TokenStream> {
+        let mut sigs: Vec<(&str, &str, String, String, TokenStream, TokenStream)> = Vec::new();
+        for Version(vname, procs, _) in &self.1 {
+            for proc_ in procs {
+                let argty = proc_.args_token(symtab)?;
+                let resty = proc_.result_token(symtab)?;
+                sigs.push((&proc_.1, vname, argty.to_string(), resty.to_string(), argty, resty));
+            }
+        }
+
+        let mut ret = Vec::new();
+        let mut done = BTreeSet::new();
+        for (pname, vname, args, res, argty, resty) in &sigs {
+            let shared = sigs
+                .iter()
+                .filter(|(p, ..)| p == pname)
+                .all(|(_, _, a, r, ..)| a == args && r == res);
+            let prefix = if shared { pname.to_string() } else { format!("{}_{}", pname, vname) };
+            if !done.insert(prefix.clone()) {
+                continue;
+            }
+            let argname = quote_ident(format!("{}Args", prefix));
+            let resname = quote_ident(format!("{}Result", prefix));
+
+            ret.push(quote! {
+                pub type #argname = #argty;
+                pub type #resname = #resty;
+            });
+        }
+
+        Ok(quote!(#(#ret)*))
+    }
+}
@@ -0,0 +1,146 @@
+// `skip` functions, which read past a value of a type without building it, for `GenOptions::emit_skip`.
+// They follow the structure of the unpacking code, but only count bytes: opaque data and strings
+// are discarded as they're read, and only scalars and enums, which don't allocate, are actually
+// unpacked, so that they're checked the same way.
+use proc_macro2::TokenStream;
+
+use super::{Codegen, Decl, GenOptions, Result, Symtab, Type, Typespec, UnionCase, quote_ident, union_fallback};
+
+// Whether `name` has a generated `skip` function
+fn has_skip(name: &str, symtab: &Symtab, opts: &GenOptions) -> bool {
+    !opts.external_types.iter().any(|(n, _)| n == name)
+        && !opts.exclude_defs.iter().any(|n| n == name)
+        && symtab
+            .typespec(&name.to_string())
+            .is_some_and(|ty| matches!(ty, Type::Enum(_) | Type::Struct(_) | Type::Union(..) | Type::Flex(..) | Type::Array(..)))
+}
+
+// An expression skipping a `ty` from `input`, evaluating to the number of bytes skipped
+fn skipper(ty: &Type, symtab: &Symtab, opts: &GenOptions) -> Result<TokenStream> {
+    use super::Type::*;
+
+    let helpers = Codegen::wire(opts).flex_helpers();
+    let maxsz = |maxsz: &std::option::Option<super::Value>| match maxsz {
+        None => quote!(None),
+        Some(mx) => {
+            let mx = mx.as_token(symtab);
+            quote!(Some(#mx as usize))
+        }
+    };
+
+    let ret = match ty {
+        Flex(elem, max) => {
+            let max = maxsz(max);
+            match elem.as_ref() {
+                Opaque | String => quote!(#helpers::skip_opaque_flex(input, #max)?),
+                elem => {
+                    let elem = skipper(elem, symtab, opts)?;
+                    quote!(#helpers::skip_flex(input, #max, |input: &mut In| -> xdr_codec::Result<usize> { Ok(#elem) })?)
+                }
+            }
+        }
+        Array(elem, sz) => {
+            let sz = sz.as_token(symtab);
+            match elem.as_ref() {
+                Opaque | String => quote!(xdr_codec::skip_opaque_array(input, #sz as usize)?),
+                elem => {
+                    let elem = skipper(elem, symtab, opts)?;
+                    quote!({
+                        let mut sz = 0;
+                        for _ in 0..#sz as usize {
+                            sz += #elem;
+                        }
+                        sz
+                    })
+                }
+            }
+        }
+        Option(elem) => {
+            let elem = skipper(elem, symtab, opts)?;
+            quote!({
+                let (have, sz): (bool, _) = xdr_codec::Unpack::unpack(input)?;
+                if have { sz + #elem } else { sz }
+            })
+        }
+        Struct(decls) => {
+            let fields = decls
+                .iter()
+                .filter_map(|decl| match decl {
+                    Decl::Named(_, ty) => Some(skipper(ty, symtab, opts)),
+                    Decl::Void => None,
+                })
+                .collect::<Result<Vec<_>>>()?;
+            quote!(0 #(+ #fields)*)
+        }
+        Union(sel, cases, defl) => {
+            let arm = |decl: &Decl| match decl {
+                Decl::Named(_, ty) => skipper(ty, symtab, opts),
+                Decl::Void => Ok(quote!(0)),
+            };
+            let mut arms = Vec::new();
+            for UnionCase(val, decl) in cases {
+                let disc = match val.as_i64(symtab) {
+                    Some(v) => v as i32,
+                    None => return Err(format!("discriminant value {:?} unknown", val).into()),
+                };
+                let skip = arm(decl)?;
+                arms.push(quote!(x if x == (#disc as i32) => #skip,));
+            }
+            let defl = defl.as_deref().map(arm).transpose()?;
+            arms.push(union_fallback(sel, symtab, defl.filter(|_| !opts.canonical)));
+
+            quote!({
+                let (v, dsz): (i32, _) = xdr_codec::Unpack::unpack(input)?;
+                dsz + match v { #(#arms)* }
+            })
+        }
+        Ident(name, _) if has_skip(name, symtab, opts) => {
+            let name = quote_ident(name);
+            quote!(#name::skip(input)?)
+        }
+        Ident(..) if symtab.resolve(ty) != ty => skipper(symtab.resolve(ty), symtab, opts)?,
+        // Scalars, and types which are defined elsewhere, are unpacked
+        ty => {
+            let tok = ty.as_token(symtab)?;
+            quote!({
+                let (_, sz): (#tok, usize) = xdr_codec::Unpack::unpack(input)?;
+                sz
+            })
+        }
+    };
+    Ok(ret)
+}
+
+impl Typespec {
+    /// `skip`, reading past a value of the type without building it, after checking the header
+    /// of `GenOptions::self_describing` if `header` is the specification's fingerprint.
+    pub fn skip_impl(&self, symtab: &Symtab, opts: &GenOptions, header: Option<u64>) -> Result<Option<TokenStream>> {
+        if !has_skip(&self.0, symtab, opts) {
+            return Ok(None);
+        }
+        let name = quote_ident(&self.0);
+        let body = match &self.1 {
+            // Enums are checked, as they are by `Unpack`
+            Type::Enum(_) => quote!({
+                let (_, sz): (#name, usize) = xdr_codec::Unpack::unpack(input)?;
+                sz
+            }),
+            ty => skipper(ty, symtab, opts)?,
+        };
+        let body = match header {
+            Some(hash) => quote!(xdr_codec::unpack_header(#hash, input)? + #body),
+            None => body,
+        };
+
+        Ok(Some(quote! {
+            impl #name {
+                /// Read past a packed value of this type without unpacking it, returning the
+                /// number of bytes it took. Strings aren't checked for being UTF-8.
+                #[allow(unused_variables)]
+                pub fn skip<In: xdr_codec::Read>(input: &mut In) -> xdr_codec::Result<usize> {
+                    Ok(#body)
+                }
+            }
+        }))
+    }
+}
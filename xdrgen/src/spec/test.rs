@@ -1,5 +1,5 @@
 use super::specification;
-use super::super::generate;
+use super::super::{GenOptions, Symtab, compile_dir, exclude_definition_line, expand, generate, generate_from_symtab, generate_with_options};
 use std::io::Cursor;
 
 #[test]
@@ -251,3 +251,695 @@ union foo switch (int x) {
     println!("spec {:?}", s);
     assert!(s.is_ok())
 }
+
+#[test]
+fn conversion_mismatch() {
+    let spec = r#"
+        struct A { int x; };
+        struct B { hyper x; };
+        struct C { int x; int y; };
+        union D switch (int x) { case 0: void; };
+"#;
+
+    let gen = |pairs: &[(&str, &str)]| {
+        let opts = GenOptions::new().conversions(pairs);
+        generate_with_options("", Cursor::new(spec.as_bytes()), Vec::new(), &opts)
+    };
+
+    assert!(gen(&[("A", "C")]).is_ok());
+    assert!(gen(&[("A", "B")]).is_err());
+    assert!(gen(&[("A", "D")]).is_err());
+    assert!(gen(&[("A", "Nope")]).is_err());
+}
+
+#[test]
+fn deprecated_pragma() {
+    let spec = r#"
+        /* @deprecated use bar */
+        struct foo { int a; /* @deprecated */ int b; };
+        struct bar { int a; };
+"#;
+
+    let mut out = Vec::new();
+    generate("", Cursor::new(spec.as_bytes()), &mut out, &[]).unwrap();
+    let out = String::from_utf8(out).unwrap();
+
+    assert!(out.contains(r#"# [deprecated (note = "use bar")] # [derive"#));
+    assert!(out.contains("# [deprecated] pub b : i32"));
+    assert!(!out.contains("# [deprecated] pub a"));
+    assert!(out.contains("# [allow (deprecated)] impl < Out : xdr_codec :: Write > xdr_codec :: Pack < Out > for foo"));
+}
+
+#[cfg(feature = "derive_educe")]
+#[test]
+fn educe_pragmas() {
+    let spec = r#"
+        struct frame {
+            unsigned int seq;
+            /* @eq_ignore */ unsigned hyper received_at;
+            /* @debug_hex */ opaque mac[8];
+        };
+        struct plain { int a; };
+"#;
+
+    let mut out = Vec::new();
+    generate("", Cursor::new(spec.as_bytes()), &mut out, &[]).unwrap();
+    let out = String::from_utf8(out).unwrap();
+
+    assert!(out.contains("# [derive (educe :: Educe)] # [educe (Debug , PartialEq , Eq)] pub struct frame"), "{}", out);
+    assert!(out.contains("# [educe (PartialEq (ignore))] pub received_at : u64"));
+    assert!(out.contains("# [educe (Debug (method (xdr_codec :: debug_hex)))] pub mac : [u8 ; 8i64 as usize]"));
+    assert!(!out.contains("# [educe (PartialEq (ignore))] pub seq"));
+    assert_eq!(out.matches("educe :: Educe").count(), 1);
+
+    let spec = "struct bad { /* @debug_hex */ int a; };";
+    let err = generate("", Cursor::new(spec.as_bytes()), Vec::new(), &[]).unwrap_err();
+    assert_eq!(err.to_string(), "bad.a: debug_hex needs opaque data");
+}
+
+#[test]
+fn string_constants() {
+    let spec = r#"
+        const MAGIC = "XDR\x01";
+        const GREETING = "say \"hi\"\n";
+        const COUNT = 2;
+        /* @deprecated */ const OLD = "";
+"#;
+
+    let mut out = Vec::new();
+    generate("", Cursor::new(spec.as_bytes()), &mut out, &[]).unwrap();
+    let out = String::from_utf8(out).unwrap();
+
+    assert!(out.contains(r#"pub const MAGIC : & str = "XDR\u{1}" ;"#), "{}", out);
+    assert!(out.contains(r#"pub const GREETING : & str = "say \"hi\"\n" ;"#));
+    assert!(out.contains("pub const COUNT : i64 = 2i64 ;"));
+    assert!(out.contains(r#"# [deprecated] pub const OLD : & str = "" ;"#));
+}
+
+#[test]
+fn track_caller_collections() {
+    let spec = "typedef int small<3>; typedef int many<>;";
+    let opts = GenOptions::new().flex_collections(true);
+    let mut out = Vec::new();
+    generate_with_options("", Cursor::new(spec.as_bytes()), &mut out, &opts).unwrap();
+    let out = String::from_utf8(out).unwrap();
+
+    // Only the bounded array's impls can panic
+    assert!(out.contains("# [track_caller] fn from_iter < I : IntoIterator < Item = i32 >> (iter : I) -> small"), "{}", out);
+    assert!(out.contains("# [track_caller] fn extend < I : IntoIterator < Item = i32 >> (& mut self , iter : I) { if let Err"));
+    assert_eq!(out.matches("# [track_caller]").count(), 2);
+}
+
+#[test]
+fn parse_once() {
+    let spec = "struct foo { int a; }; typedef foo bar;";
+    let xdr: Symtab = spec.parse().unwrap();
+
+    let mut a = Vec::new();
+    let mut b = Vec::new();
+    generate_from_symtab("x", &xdr, &mut a, &GenOptions::new()).unwrap();
+    generate_from_symtab("x", &xdr, &mut b, &GenOptions::new().emit_validate(true)).unwrap();
+
+    let mut direct = Vec::new();
+    generate("x", Cursor::new(spec.as_bytes()), &mut direct, &[]).unwrap();
+    assert_eq!(a, direct);
+    assert!(b.len() > a.len());
+
+    assert!("struct { int a; };".parse::<Symtab>().is_err());
+}
+
+#[cfg(feature = "export-kaitai")]
+#[test]
+fn kaitai() {
+    let spec = r#"
+        enum kind { FILE = 0, DIR = 1 };
+        struct entry { string name<>; unsigned hyper size; };
+        union lookup switch (kind k) {
+            case FILE: entry file;
+            case DIR: entry children<>;
+            default: void;
+        };
+"#;
+    let xdr: Symtab = spec.parse().unwrap();
+    let out = super::kaitai("lookup", &xdr).unwrap();
+
+    assert!(out.contains("meta:\n  id: lookup\n  endian: be\n"));
+    assert!(out.contains("    1: dir\n"));
+    assert!(out.contains("        type: xdr_string\n"));
+    assert!(out.contains("        type: u8\n"));
+    assert!(out.contains("        enum: kind\n"));
+    assert!(out.contains("          switch-on: k\n"));
+    assert!(out.contains("            'kind::file': entry\n"));
+    assert!(out.contains("            'kind::dir': lookup_children\n"));
+    assert!(out.contains("            _: xdr_void\n"));
+    assert!(out.contains("  lookup_children:\n"));
+}
+
+#[cfg(feature = "export-proto")]
+#[test]
+fn proto() {
+    let spec = r#"
+        const MAXNAME = 255;
+        enum kind { FILE = 1, DIR = 2 };
+        typedef opaque handle[32];
+        struct entry { string name<MAXNAME>; unsigned hyper size; handle fh; entry *next; };
+        union lookup switch (kind k) {
+            case FILE: entry file;
+            case DIR: entry children<>;
+            default: void;
+        };
+"#;
+    let xdr: Symtab = spec.parse().unwrap();
+    let out = super::proto("nfs-lite", &xdr).unwrap();
+
+    assert!(out.contains("syntax = \"proto3\";\n\npackage nfs_lite;\n"), "{}", out);
+    assert!(out.contains("// const MAXNAME = 255;\n"));
+    assert!(out.contains("enum kind {\n  KIND_UNSPECIFIED = 0;\n  FILE = 1;\n  DIR = 2;\n}\n"));
+    assert!(out.contains(
+        "message entry {\n  string name = 1; // at most 255\n  uint64 size = 2;\n  \
+         bytes fh = 3; // handle, fixed length 32\n  optional entry next = 4;\n}\n"
+    ));
+    assert!(out.contains("  kind k = 1;\n  oneof arm {\n    entry file = 2; // case FILE\n"));
+    assert!(out.contains("    children_value children = 3; // case DIR\n  }\n  // void: default\n"));
+    assert!(out.contains("  message children_value {\n    repeated entry value = 1;\n  }\n"));
+    assert!(!out.contains("message handle"));
+}
+
+#[cfg(feature = "export-avro")]
+#[test]
+fn avro() {
+    let spec = r#"
+        enum kind { FILE = 1, DIR = 2 };
+        typedef opaque handle[32];
+        struct entry { string name<255>; unsigned hyper size; handle fh; entry *next; opaque tag[3]; };
+        union lookup switch (kind k) {
+            case FILE: entry file;
+            case DIR: entry children<>;
+            default: void;
+        };
+        union any switch (int x) { case 1: int a; case 2: int b; };
+"#;
+    let xdr: Symtab = spec.parse().unwrap();
+    let out = super::avro("nfs-lite", &xdr).unwrap();
+
+    // Named types are defined at their first use, and referred to by name after that
+    assert!(out.starts_with("[\n  {\n    \"type\": \"record\",\n    \"name\": \"any\",\n    \"namespace\": \"nfs_lite\",\n"), "{}", out);
+    assert!(out.contains("\"name\": \"size\",\n        \"type\": \"long\",\n        \"doc\": \"unsigned hyper\"\n"));
+    assert!(out.contains("\"type\": {\n          \"type\": \"fixed\",\n          \"name\": \"handle\",\n          \"size\": 32\n"));
+    assert!(out.contains("\"name\": \"entry_tag\",\n          \"size\": 3\n"));
+    assert!(out.contains("\"name\": \"next\",\n        \"type\": [\"null\", \"entry\"]\n"));
+    assert_eq!(out.matches("\"name\": \"entry\"").count(), 1);
+    assert_eq!(out.matches("\"name\": \"handle\"").count(), 1);
+    assert!(out.contains("\"doc\": \"FILE = 1, DIR = 2\",\n    \"symbols\": [\"FILE\", \"DIR\"]\n"));
+
+    // Unions are records with a nullable field for each arm
+    assert!(out.contains("\"name\": \"lookup\",\n    \"namespace\": \"nfs_lite\",\n    \"doc\": \"void: default\",\n"));
+    assert!(out.contains("\"name\": \"k\",\n        \"type\": \"kind\"\n"));
+    assert!(out.contains(
+        "\"name\": \"file\",\n        \"type\": [\"null\", \"entry\"],\n        \"doc\": \"case FILE\",\n        \"default\": null\n"
+    ));
+    assert!(out.contains("\"name\": \"b\",\n        \"type\": [\"null\", \"int\"],\n        \"doc\": \"case 2\",\n"));
+}
+
+#[test]
+fn empty_spec() {
+    for spec in &["", "\n", "/* nothing to see */\n", "%#include <foo.h>\n"] {
+        let mut out = Vec::new();
+        generate("empty.x", Cursor::new(spec.as_bytes()), &mut out, &[]).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("// Generated from empty.x by xdrgen."));
+        assert!(out.trim_end().ends_with("// DO NOT EDIT"), "{:?}: {}", spec, out);
+
+        let opts = GenOptions::new().reject_empty(true);
+        let err = generate_with_options("empty.x", Cursor::new(spec.as_bytes()), Vec::new(), &opts)
+            .unwrap_err();
+        assert_eq!(err.to_string(), "empty.x: no definitions found");
+    }
+
+    let opts = GenOptions::new().reject_empty(true);
+    generate_with_options("x", Cursor::new(&b"const a = 1;"[..]), Vec::new(), &opts).unwrap();
+}
+
+#[test]
+fn parse_error_position() {
+    let spec = "struct a { int x; };\n\n  struct b { int y; bogus };\n";
+    let err = Symtab::parse(spec).unwrap_err();
+    assert_eq!((err.offset, err.line, err.column), (Some(24), 3, 3));
+    assert_eq!(err.to_string(), "line 3, column 3: Eof: struct b { int y; bogus };");
+
+    let err = spec.parse::<Symtab>().unwrap_err();
+    assert_eq!(err.to_string(), "parse error: line 3, column 3: Eof: struct b { int y; bogus };");
+
+    assert!(Symtab::parse("struct a { int x; };").is_ok());
+}
+
+#[test]
+fn expand_tokens() {
+    let xdr = Symtab::parse("struct a { int x; };").unwrap();
+    let tokens = expand(&xdr, &GenOptions::new()).unwrap().to_string();
+    assert!(tokens.contains("pub struct a"), "{}", tokens);
+    assert!(tokens.contains("xdr_codec :: Pack < Out > for a"), "{}", tokens);
+}
+
+#[test]
+fn includes() {
+    use std::fs::{create_dir_all, write};
+
+    let tmp = tempdir::TempDir::new("include").unwrap();
+    let proj = tmp.path().join("proj");
+    let sys = tmp.path().join("sys");
+    create_dir_all(proj.join("sub")).unwrap();
+    create_dir_all(sys.join("sub")).unwrap();
+
+    write(proj.join("sub/types.x"), "struct proj_types { int a; };").unwrap();
+    write(sys.join("sub/types.x"), "struct sys_types { int a; };").unwrap();
+    write(sys.join("only_sys.x"), "#include \"sub/types.x\"\nstruct only_sys { int a; };").unwrap();
+    write(proj.join("only_proj.x"), "struct only_proj { int a; };").unwrap();
+
+    let gen = |spec: &str| {
+        let main = proj.join("main.x");
+        write(&main, spec).unwrap();
+        let opts = GenOptions::new().include_dirs(&[&sys]);
+        let mut out = Vec::new();
+        generate_with_options(main.to_str().unwrap(), Cursor::new(spec.as_bytes()), &mut out, &opts)
+            .map(|_| String::from_utf8(out).unwrap())
+    };
+
+    // Quoted includes are relative to the including file first
+    let out = gen("#include \"sub/types.x\"\n").unwrap();
+    assert!(out.contains("pub struct proj_types"));
+    assert!(!out.contains("sys_types"));
+
+    // Angle brackets only use the search path
+    let out = gen("#include <sub/types.x>\n").unwrap();
+    assert!(out.contains("pub struct sys_types"));
+    assert!(!out.contains("proj_types"));
+    assert!(gen("#include <only_proj.x>\n").is_err());
+
+    // Quoted falls back to the search path, and nested includes are relative to their own file
+    let out = gen("#include \"only_sys.x\"\n#include <sub/types.x>\n").unwrap();
+    assert!(out.contains("pub struct only_sys"));
+    assert_eq!(out.matches("pub struct sys_types").count(), 1);
+
+    // Other includes are still ignored
+    assert!(gen("#include <rpc/rpc.h>\n%#include \"x.h\"\nconst a = 1;\n").is_ok());
+    assert!(gen("#include \"missing.x\"\n").is_err());
+}
+
+#[test]
+fn compile_all_in_dir() {
+    use std::fs::{create_dir_all, read_to_string, write};
+
+    let tmp = tempdir::TempDir::new("compile_dir").unwrap();
+    let specs = tmp.path().join("specs");
+    let common = tmp.path().join("common");
+    let out = tmp.path().join("out");
+    for dir in &[&specs, &common, &out] {
+        create_dir_all(dir).unwrap();
+    }
+
+    write(common.join("shared.x"), "struct shared { int a; };").unwrap();
+    write(specs.join("b-types.x"), "#include <shared.x>\nstruct b { shared s; };").unwrap();
+    write(specs.join("a.x"), "struct a { int x; };").unwrap();
+    write(specs.join("notes.txt"), "not a specification").unwrap();
+
+    ::std::env::set_var("OUT_DIR", &out);
+    let opts = GenOptions::new().include_dirs(&[&common]);
+    let outputs = compile_dir(&specs, &opts).unwrap();
+
+    assert_eq!(outputs, vec![out.join("a_xdr.rs"), out.join("b_types_xdr.rs")]);
+    assert!(read_to_string(&outputs[0]).unwrap().contains("pub struct a"));
+    let b = read_to_string(&outputs[1]).unwrap();
+    assert!(b.contains("pub struct b") && b.contains("pub struct shared"), "{}", b);
+
+    write(specs.join("bad.x"), "struct bad {").unwrap();
+    assert!(compile_dir(&specs, &opts).is_err());
+}
+
+#[test]
+fn feature_pragma() {
+    let spec = r#"
+        /* @feature vendor_ext */
+        struct ext { int a; };
+        struct wrapper { ext e<>; };
+        struct plain { int b; };
+"#;
+
+    let mut out = Vec::new();
+    generate("", Cursor::new(spec.as_bytes()), &mut out, &[]).unwrap();
+    let out = String::from_utf8(out).unwrap();
+
+    let gated = |item: &str| {
+        let line = out.lines().find(|l| l.contains(item)).unwrap();
+        line.starts_with(r#"# [cfg (feature = "vendor_ext")] "#)
+    };
+
+    assert!(gated("pub struct ext"));
+    assert!(gated("xdr_codec :: Pack < Out > for ext"));
+    assert!(gated("xdr_codec :: Unpack < In > for ext"));
+    // Uses of gated types are gated too
+    assert!(gated("pub struct wrapper"));
+    assert!(gated("xdr_codec :: Unpack < In > for wrapper"));
+
+    assert!(!gated("pub struct plain"));
+    assert!(!gated("xdr_codec :: Pack < Out > for plain"));
+
+    // Members named the same as gated types don't make anything gated
+    let spec = r#"
+        /* @feature vendor_ext */
+        struct ext { int a; };
+        /* @feature big */
+        const MAXLEN = 4;
+        struct holder { int ext; };
+        union choice switch (int k) { case 0: int ext; default: void; };
+        struct bounded { int b<MAXLEN>; };
+"#;
+    let mut out = Vec::new();
+    let opts = GenOptions::new().resumable(true);
+    generate_with_options("", Cursor::new(spec.as_bytes()), &mut out, &opts).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    let gated = |item: &str, feature: &str| {
+        let line = out.lines().find(|l| l.contains(item)).unwrap();
+        line.starts_with(&format!(r#"# [cfg (feature = "{}")] "#, feature))
+    };
+
+    for item in &["pub struct holder", "xdr_codec :: Unpack < In > for holder", "pub struct holderDecoder", "pub enum choice"] {
+        assert!(!gated(item, "vendor_ext"), "{}", item);
+    }
+    assert!(gated("xdr_codec :: Decode < ext > for extDecoder", "vendor_ext"));
+    assert!(gated("Resumable decoder, which can be fed input as it arrives.\"] pub struct extDecoder", "vendor_ext"));
+    // Constants used as bounds gate their users
+    assert!(gated("pub struct bounded", "big"));
+    assert!(gated("xdr_codec :: Unpack < In > for bounded", "big"));
+
+    // Items are gated by the definition they're generated for, not one their name starts with
+    let spec = r#"
+        /* @feature vendor_ext */
+        struct foo { int a; };
+        struct fooBar { int b; };
+        program foo_prog { version foo_v1 { fooBar GET(fooBar) = 1; } = 1; } = 0x20000001;
+"#;
+    let mut out = Vec::new();
+    let opts = GenOptions::new().resumable(true).proc_tables(true);
+    generate_with_options("", Cursor::new(spec.as_bytes()), &mut out, &opts).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    let gated = |item: &str| {
+        let line = out.lines().find(|l| l.contains(item)).unwrap();
+        line.starts_with(r#"# [cfg (feature = "vendor_ext")] "#)
+    };
+
+    assert!(gated("pub struct fooDecoder"));
+    for item in &["pub struct fooBar ", "pub struct fooBarDecoder", "pub mod foo_prog", "pub type GETArgs"] {
+        assert!(!gated(item), "{}", item);
+    }
+}
+
+#[test]
+fn renamed() {
+    let spec = r#"
+        const match = 1;
+        enum kind { fn = 0, plain = 1 };
+        struct type { int impl; int ok; };
+        union u switch (kind loop) { case fn: int ref; case plain: void; };
+        typedef int move<>;
+"#;
+    let xdr: Symtab = spec.parse().unwrap();
+
+    let renamed: Vec<_> = xdr.renamed().into_iter().map(|(from, to)| format!("{}->{}", from, to)).collect();
+    assert_eq!(
+        renamed,
+        ["fn->fn_", "impl->impl_", "loop->loop_", "match->match_", "move->move_", "ref->ref_", "type->type_"]
+    );
+
+    assert!("struct foo { int a; };".parse::<Symtab>().unwrap().renamed().is_empty());
+}
+
+#[test]
+fn rename_originals() {
+    use super::super::IdentKind;
+
+    let xdr: Symtab = "struct a { int foo_bar; }; struct b { int fooBar; };".parse().unwrap();
+    let renamed = xdr.rename_with(&|name: &str, _| name.replace("_b", "B")).unwrap();
+
+    // Only the member which was renamed has its original name
+    assert_eq!(renamed.original(IdentKind::Field, Some("a"), "fooBar"), Some("foo_bar"));
+    assert_eq!(renamed.original(IdentKind::Field, Some("b"), "fooBar"), None);
+    assert_eq!(renamed.original(IdentKind::Type, None, "a"), None);
+}
+
+#[test]
+fn renamed_warning() {
+    use log::{Log, LogLevelFilter, LogMetadata, LogRecord};
+    use std::sync::Mutex;
+
+    lazy_static! {
+        static ref LOGGED: Mutex<Vec<String>> = Mutex::new(Vec::new());
+    }
+
+    struct Capture;
+    impl Log for Capture {
+        fn enabled(&self, _: &LogMetadata) -> bool {
+            true
+        }
+        fn log(&self, record: &LogRecord) {
+            LOGGED.lock().unwrap().push(record.args().to_string());
+        }
+    }
+
+    log::set_logger(|max| {
+        max.set(LogLevelFilter::Warn);
+        Box::new(Capture)
+    }).unwrap();
+
+    let spec = "struct foo { int type; };";
+    generate("foo.x", Cursor::new(spec.as_bytes()), Vec::new(), &[]).unwrap();
+
+    let logged = LOGGED.lock().unwrap();
+    assert!(logged.iter().any(|l| l == "foo.x: type is a Rust keyword, renamed to type_"), "{:?}", logged);
+}
+
+#[test]
+fn diff() {
+    let old: Symtab = r#"
+        const MAX = 10;
+        const GONE = 1;
+        enum colour { RED = 0, GREEN = 1, BLUE = 2 };
+        struct point { int x; int y; };
+        struct msg { point p; string name<MAX>; };
+        struct pair { int a; int b; };
+        union shape switch (int kind) {
+            case 0: point centre;
+            case 1: void;
+            default: void;
+        };
+        typedef int ids<5>;
+        typedef int count;
+        struct removed { int a; };
+        struct tail { int a; };
+    "#.parse().unwrap();
+    let new: Symtab = r#"
+        const MAX = 20;
+        enum colour { RED = 0, LIME = 1, BLUE = 3, CYAN = 4 };
+        typedef int coord;
+        struct point { coord x; int y; int z; };
+        struct msg { point p; string name<>; unsigned int *flags; };
+        struct pair { int b; int a; };
+        union shape switch (int kind) {
+            case 0: hyper centre;
+            case 2: void;
+        };
+        typedef int ids<3>;
+        typedef hyper count;
+        struct added { int a; };
+        struct tail { int a; int b; };
+    "#.parse().unwrap();
+
+    let changes: Vec<_> = old.diff(&new).iter().map(|c| c.to_string()).collect();
+    assert_eq!(
+        changes,
+        [
+            "breaking: GONE: removed",
+            "breaking: MAX: value changed from 10 to 20",
+            "compatible: added: added struct",
+            "breaking: colour.BLUE: value changed from 2 to 3",
+            "compatible: colour.GREEN: renamed to LIME",
+            "compatible: colour.CYAN: added with value 4",
+            "compatible: coord: added typedef",
+            "breaking: count: changed from int to hyper",
+            "breaking: ids: changed from int<5> to int<3>",
+            "compatible: msg.name: type changed from string<MAX> to string<>",
+            "compatible: msg.flags: added trailing unsigned int * field",
+            "breaking: pair: fields reordered",
+            "breaking: point.z: added int field",
+            "breaking: removed: removed struct",
+            "breaking: shape: case 0 changed from point to hyper",
+            "breaking: shape: removed case 1",
+            "compatible: shape: added case 2 void",
+            "breaking: shape: removed default case",
+            "breaking: tail.b: added int field",
+        ]
+    );
+
+    assert!(old.diff(&old).is_empty());
+}
+
+#[test]
+fn mem_report() {
+    use std::mem::size_of;
+
+    let xdr: Symtab = r#"
+        enum colour { RED = 0, GREEN = 1, BLUE = 2 };
+        struct point { int x; int y; colour c; };
+        struct image { opaque pixels[100]; point corners[4]; };
+        union msg switch (colour c) {
+            case RED: int small;
+            case GREEN: image large;
+            case BLUE: void;
+        };
+        struct list { int v; list *next; };
+        struct named { string name<>; hyper id; bool b; point *p; };
+        typedef int count;
+        struct counts { count a; count *b; };
+    "#.parse().unwrap();
+
+    let ptr = size_of::<usize>();
+    let sizes: Vec<_> = xdr.mem_report().unwrap().iter().map(|s| s.to_string()).collect();
+    assert_eq!(
+        sizes,
+        [
+            "colour: 1 bytes, align 1".to_string(),
+            "counts: 12 bytes, align 4".to_string(),
+            "image: 148 bytes, align 4".to_string(),
+            format!("list: {} bytes, align {}", 2 * ptr, ptr),
+            "msg: 152 bytes, align 4, largest arm GREEN (148 bytes)".to_string(),
+            format!("named: {} bytes, align {}", (4 * ptr + 9).div_ceil(ptr) * ptr, ptr),
+            "point: 12 bytes, align 4".to_string(),
+        ]
+    );
+
+    let xdr: Symtab = "struct loop { int a; loop b; };".parse().unwrap();
+    assert!(xdr.mem_report().is_err());
+}
+
+#[test]
+fn tokio_codecs() {
+    let spec = r#"
+        struct item { int x; };
+        struct request { item items<>; };
+        typedef request alias;
+    "#;
+    let gen = |opts: &GenOptions| {
+        let mut out = Vec::new();
+        generate_with_options("", Cursor::new(spec.as_bytes()), &mut out, opts).unwrap();
+        String::from_utf8(out).unwrap()
+    };
+
+    let code = gen(&GenOptions::new().tokio_codecs(true).max_message_size(1024));
+    assert_eq!(code.matches("fn codec ()").count(), 1);
+    assert!(code.contains(
+        "pub fn codec () -> xdr_codec :: codec :: RecordCodec < request > { \
+         xdr_codec :: codec :: RecordCodec :: new () . max_record (1024usize) } }"
+    ));
+
+    let code = gen(&GenOptions::new().tokio_codecs(true));
+    assert!(code.contains("xdr_codec :: codec :: RecordCodec :: new () } }"));
+    assert!(!gen(&GenOptions::new()).contains("RecordCodec"));
+}
+
+#[test]
+fn exclude_exact() {
+    use super::{item_name, items};
+
+    let spec = r#"
+        const FOO = 1;
+        const FOO_MAX = 2;
+        struct foo { int a; };
+        struct foobar { foo f<FOO_MAX>; };
+        union for_foo switch (int x) { case 0: foobar b; default: void; };
+    "#;
+    let names = |spec: &str, exclude: &[&str]| {
+        let opts = GenOptions::new().exclude_defs(exclude).union_helpers(true);
+        let mut out = Vec::new();
+        generate_with_options("", Cursor::new(spec.as_bytes()), &mut out, &opts).unwrap();
+        let tokens = String::from_utf8(out).unwrap().parse().unwrap();
+        items(tokens).iter().map(|item| item_name(item).unwrap()).collect::<Vec<_>>()
+    };
+
+    // Only the items named exactly `FOO` and `foo` go, not those whose names start with them
+    let all = names(spec, &[]);
+    let plain = names(spec, &["FOO", "foo"]);
+    let kept: Vec<_> = all.iter().filter(|n| *n != "foo" && *n != "FOO").cloned().collect();
+    assert_eq!(plain, kept);
+    assert!(plain.len() < all.len());
+    assert!(["FOO_MAX", "foobar", "for_foo"].iter().all(|name| plain.iter().any(|n| n == name)));
+
+    // Deprecated definitions add attributes to each item, but the same items are generated
+    let deprecated = names(&spec.replace("struct foobar", "/* @deprecated */ struct foobar"), &["FOO", "foo"]);
+    assert_eq!(plain, deprecated);
+
+    assert!(exclude_definition_line("impl Foo for foo { }", &["foo"]));
+    assert!(!exclude_definition_line("impl Foo for foobar { }", &["foo"]));
+}
+
+#[test]
+fn union_arm_order() {
+    let spec = r#"
+        enum kind { C = 3, A = 1, B = 2 };
+        union u switch (kind k) { case C: int c; case A: case B: hyper ab; default: void; };
+        union v switch (int x) { case 5: int five; case 1: void; case 3: bool three; };
+    "#;
+    let mut out = Vec::new();
+    generate("", Cursor::new(spec.as_bytes()), &mut out, &[]).unwrap();
+    let out = String::from_utf8(out).unwrap();
+
+    assert!(out.contains("pub enum u { C (i32) , A (i64) , B (i64) , default , }"));
+    assert!(out.contains("pub enum v { Const5 (i32) , Const1 , Const3 (bool) , }"));
+}
+
+#[test]
+fn dispatch_tags() {
+    let spec = r#"
+        struct ping { int seq; };
+        struct pong { int seq; };
+    "#;
+    let gen = |opts: &GenOptions| {
+        let mut out = Vec::new();
+        generate_with_options("", Cursor::new(spec.as_bytes()), &mut out, opts).map(|_| String::from_utf8(out).unwrap())
+    };
+
+    let code = gen(&GenOptions::new().dispatch_tags(&[("ping", 1), ("pong", 2)])).unwrap();
+    assert_eq!(code.matches("map . insert").count(), 2);
+    assert!(code.contains("map . insert (2u32"));
+    assert!(!gen(&GenOptions::new()).unwrap().contains("dispatch_map"));
+
+    assert!(gen(&GenOptions::new().dispatch_tags(&[("ping", 1), ("pong", 1)])).is_err());
+    assert!(gen(&GenOptions::new().dispatch_tags(&[("pang", 1)])).is_err());
+}
+
+#[test]
+fn length_width() {
+    use super::super::LenWidth;
+
+    let spec = r#"
+        struct item { string name<>; int vals<4>; };
+        typedef opaque blob<>;
+    "#;
+    let gen = |opts: GenOptions| {
+        let mut out = Vec::new();
+        let opts = opts.trace(true).resumable(true).array_helpers(true).zero_copy(true);
+        generate_with_options("", Cursor::new(spec.as_bytes()), &mut out, &opts).unwrap();
+        String::from_utf8(out).unwrap()
+    };
+
+    // Standard lengths are the default, and never use the wide helpers
+    let code = gen(GenOptions::new());
+    assert_eq!(code, gen(GenOptions::new().length_width(LenWidth::U32)));
+    assert!(!code.contains("wide"));
+
+    let code = gen(GenOptions::new().length_width(LenWidth::U64));
+    for helper in &["pack_string", "pack_flex", "pack_opaque_flex", "unpack_string", "unpack_flex",
+                    "unpack_opaque_flex", "unpack_str_borrowed", "unpack_flex_borrowed", "unpack_opaque_borrowed"] {
+        assert!(code.contains(&format!("xdr_codec :: wide :: {} (", helper)), "{}", helper);
+        assert!(!code.contains(&format!("xdr_codec :: {} (", helper)), "{}", helper);
+    }
+}
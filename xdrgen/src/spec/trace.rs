@@ -0,0 +1,133 @@
+// `xdr_codec::UnpackTraced` implementations, which report each field to a trace hook as it's
+// decoded.
+use proc_macro2::TokenStream;
+
+use super::{Codegen, Decl, Derives, Error, Result, Symtab, Type, Typespec, UnionCase, boxed_variant, quote_ident, rust_type,
+            union_fallback};
+use super::super::GenOptions;
+
+// Unpack a field `name` of type `ty`, evaluating to its value. Structs and unions are traced
+// recursively, everything else is reported as a single value.
+fn field(name: &str, ty: &Type, symtab: &Symtab, opts: &GenOptions) -> Result<TokenStream> {
+    let nested = match ty {
+        Type::Ident(id, _) => matches!(symtab.typespec(id), Some(Type::Struct(_)) | Some(Type::Union(..))),
+        _ => false,
+    };
+
+    let tok = ty.as_token(symtab)?;
+    let ret = if nested {
+        quote!({
+            trace.enter(#name, offset + sz);
+            let (v, fsz) = <#tok as xdr_codec::UnpackTraced<In>>::unpack_traced(input, offset + sz, trace)?;
+            trace.leave();
+            sz += fsz;
+            v
+        })
+    } else {
+        let unpack = ty.unpacker_opt(symtab, Codegen::wire(opts));
+        let value = if ty.derivable(symtab, None).contains(Derives::DEBUG) {
+            quote!(&v)
+        } else {
+            quote!(&"..")
+        };
+        quote!({
+            let (v, fsz): (#tok, _) = #unpack;
+            trace.field(#name, offset + sz, #value);
+            sz += fsz;
+            v
+        })
+    };
+    Ok(ret)
+}
+
+impl Typespec {
+    pub fn trace_unpacker(&self, symtab: &Symtab, opts: &GenOptions) -> Result<Option<TokenStream>> {
+        let name = quote_ident(&self.0);
+
+        let body = match &self.1 {
+            Type::Struct(decls) => {
+                let fields = decls
+                    .iter()
+                    .filter_map(|decl| match decl {
+                        Decl::Named(field, ty) => Some((field, ty)),
+                        Decl::Void => None,
+                    })
+                    .map(|(field, ty)| {
+                        let mut unpack = self::field(field, ty, symtab, opts)?;
+                        // Traced as the XDR type
+                        if let Some(rust) = rust_type(symtab, &self.0, field)? {
+                            let xty = ty.as_token(symtab)?;
+                            unpack = quote!(<#rust as RustType<#xty>>::from_xdr(#unpack)?);
+                        }
+                        let field = quote_ident(field);
+                        Ok(quote!(#field: #unpack,))
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+
+                quote!(#name { #(#fields)* })
+            }
+
+            Type::Union(sel, cases, defl) => {
+                let selname = match sel.as_ref() {
+                    Decl::Named(selname, _) => selname,
+                    Decl::Void => return Err("void switch selector".into()),
+                };
+                let selunpack = field(selname, &Type::Int, symtab, opts)?;
+
+                let mut arms = cases
+                    .iter()
+                    .map(|UnionCase(val, decl)| {
+                        let label = val.as_ident();
+                        let disc = match val.as_i64(symtab) {
+                            Some(v) => v as i32,
+                            None => return Err(Error::from(format!("discriminant value {:?} unknown", val))),
+                        };
+                        let ret = match decl {
+                            Decl::Void => quote!(x if x == (#disc as i32) => #name::#label,),
+                            Decl::Named(field, ty) => {
+                                let mut unpack = self::field(field, ty, symtab, opts)?;
+                                if boxed_variant(ty, symtab, opts.box_large_variants) {
+                                    unpack = quote!(Box::new(#unpack));
+                                }
+                                quote!(x if x == (#disc as i32) => #name::#label(#unpack),)
+                            }
+                        };
+                        Ok(ret)
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+
+                let defl = match defl.as_deref() {
+                    None => None,
+                    Some(Decl::Void) => Some(quote!(#name::default)),
+                    Some(Decl::Named(field, ty)) => {
+                        let mut unpack = self::field(field, ty, symtab, opts)?;
+                        if ty.is_boxed(symtab) || boxed_variant(ty, symtab, opts.box_large_variants) {
+                            unpack = quote!(Box::new(#unpack));
+                        }
+                        Some(quote!(#name::default(#unpack)))
+                    }
+                };
+                arms.push(union_fallback(sel, symtab, defl.filter(|_| !opts.canonical)));
+
+                quote!(match #selunpack { #(#arms)* })
+            }
+
+            _ => return Ok(None),
+        };
+
+        Ok(Some(quote! {
+            impl<In: xdr_codec::Read> xdr_codec::UnpackTraced<In> for #name {
+                #[allow(unused_variables)]
+                fn unpack_traced(
+                    input: &mut In,
+                    offset: usize,
+                    trace: &mut dyn xdr_codec::Trace,
+                ) -> xdr_codec::Result<(#name, usize)> {
+                    #[allow(unused_assignments)]
+                    let mut sz = 0;
+                    Ok((#body, sz))
+                }
+            }
+        }))
+    }
+}
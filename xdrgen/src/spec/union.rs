@@ -0,0 +1,105 @@
+// Helper methods on generated union enums.
+use proc_macro2::TokenStream;
+
+use super::{Decl, GenOptions, Result, Symtab, Type, Typespec, UnionCase, boxed_variant, quote_ident};
+
+// Convert an arm label such as `NFS3_OK` or `FooBar` into `nfs3_ok` or `foo_bar`.
+pub(super) fn snake_case(label: &str) -> String {
+    let mut ret = String::new();
+    let mut prev_lower = false;
+
+    for c in label.trim_end_matches('_').chars() {
+        if c.is_uppercase() && prev_lower {
+            ret.push('_');
+        }
+        prev_lower = c.is_lowercase() || c.is_ascii_digit();
+        ret.extend(c.to_lowercase());
+    }
+    ret
+}
+
+impl Typespec {
+    pub fn union_helpers(&self, symtab: &Symtab, opts: &GenOptions) -> Result<Option<TokenStream>> {
+        let (cases, defl) = match &self.1 {
+            Type::Union(_, cases, defl) => (cases, defl),
+            _ => return Ok(None),
+        };
+
+        let name = quote_ident(&self.0);
+        let box_over = opts.box_large_variants;
+        // The default arm boxes more types than the others, matching the enum's definition
+        let arms = cases
+            .iter()
+            .map(|UnionCase(val, decl)| {
+                let boxed = match decl {
+                    Decl::Named(_, ty) => boxed_variant(ty, symtab, box_over),
+                    Decl::Void => false,
+                };
+                (val.as_ident(), decl, boxed)
+            })
+            .chain(defl.iter().map(|d| {
+                let boxed = match d.as_ref() {
+                    Decl::Named(_, ty) => ty.is_boxed(symtab) || boxed_variant(ty, symtab, box_over),
+                    Decl::Void => false,
+                };
+                (quote_ident("default"), d.as_ref(), boxed)
+            }));
+
+        let mut helpers = Vec::new();
+        for (label, decl, boxed) in arms {
+            let arm = snake_case(&label.to_string());
+            let pred = quote_ident(format!("is_{}", arm));
+            let pat = match decl {
+                Decl::Void => quote!(#name::#label),
+                Decl::Named(..) => quote!(#name::#label(..)),
+            };
+
+            helpers.push(quote! {
+                #[inline]
+                pub fn #pred(&self) -> bool {
+                    matches!(self, #pat)
+                }
+            });
+
+            // An arm whose label is already snake case is constructed by its variant
+            let ctor = quote_ident(&arm);
+            if ctor == label {
+                continue;
+            }
+            helpers.push(match decl {
+                Decl::Void => quote! {
+                    #[inline]
+                    pub fn #ctor() -> #name {
+                        #name::#label
+                    }
+                },
+                Decl::Named(field, ty) => {
+                    let field = quote_ident(field);
+                    let ty = ty.as_token(symtab)?;
+                    let val = if boxed { quote!(Box::new(#field)) } else { quote!(#field) };
+                    quote! {
+                        #[inline]
+                        pub fn #ctor(#field: #ty) -> #name {
+                            #name::#label(#val)
+                        }
+                    }
+                }
+            });
+        }
+
+        Ok(Some(quote! {
+            impl #name {
+                #(#helpers)*
+            }
+        }))
+    }
+}
+
+#[test]
+fn test_snake_case() {
+    assert_eq!(snake_case("NFS3_OK"), "nfs3_ok");
+    assert_eq!(snake_case("FooBar"), "foo_bar");
+    assert_eq!(snake_case("Const1"), "const1");
+    assert_eq!(snake_case("type_"), "type");
+    assert_eq!(snake_case("default"), "default");
+}
@@ -0,0 +1,103 @@
+// `xdr_codec::Validate` implementations, which check bounds which aren't enforced by the Rust
+// types.
+use proc_macro2::TokenStream;
+
+use super::{Decl, Result, StrEnc, Symtab, Type, Typespec, UnionCase, quote_ident, rust_type};
+use super::super::GenOptions;
+
+// Check a value `val` of type `ty`.
+fn check(val: TokenStream, ty: &Type, symtab: &Symtab, opts: &GenOptions) -> TokenStream {
+    let bound = match ty {
+        // Packed a byte per character, if they can be
+        Type::Flex(elem, Some(max)) if **elem == Type::String && opts.string_encoding == StrEnc::Latin1 => {
+            let max = max.as_token(symtab);
+            quote! {
+                if let Some(c) = #val.chars().find(|&c| c > '\u{ff}') {
+                    return Err(xdr_codec::ErrorKind::NotLatin1(c).into());
+                }
+                if #val.chars().count() > #max as usize {
+                    return Err(xdr_codec::Error::invalidlen(#max as usize));
+                }
+            }
+        }
+        Type::Flex(_, Some(max)) => {
+            let max = max.as_token(symtab);
+            quote! {
+                if #val.len() > #max as usize {
+                    return Err(xdr_codec::Error::invalidlen(#max as usize));
+                }
+            }
+        }
+        _ => quote!(),
+    };
+
+    quote! {
+        #bound
+        xdr_codec::Validate::validate(&#val)?;
+    }
+}
+
+impl Typespec {
+    pub fn validator(&self, symtab: &Symtab, opts: &GenOptions) -> Result<Option<TokenStream>> {
+        let name = quote_ident(&self.0);
+
+        let body = match &self.1 {
+            // Enums can only hold their defined values
+            Type::Enum(_) => quote!(),
+
+            Type::Struct(decls) => {
+                let mut checks = Vec::new();
+                for decl in decls {
+                    if let Decl::Named(field, ty) = decl {
+                        let rust = rust_type(symtab, &self.0, field)?;
+                        let field = quote_ident(field);
+                        checks.push(match rust {
+                            // Checked as the XDR type
+                            Some(rust) => {
+                                let xty = ty.as_token(symtab)?;
+                                let check = check(quote!(x), ty, symtab, opts);
+                                quote!({
+                                    let x = <#rust as RustType<#xty>>::to_xdr(&self.#field);
+                                    #check
+                                })
+                            }
+                            None => check(quote!(self.#field), ty, symtab, opts),
+                        });
+                    }
+                }
+                quote!(#(#checks)*)
+            }
+
+            Type::Union(_, cases, defl) => {
+                let arms = cases
+                    .iter()
+                    .map(|UnionCase(val, decl)| (val.as_ident(), decl))
+                    .chain(defl.iter().map(|d| (quote_ident("default"), d.as_ref())));
+
+                let arms: Vec<_> = arms
+                    .map(|(label, decl)| match decl {
+                        Decl::Void => quote!(#name::#label => (),),
+                        Decl::Named(_, ty) => {
+                            let check = check(quote!((*v)), ty, symtab, opts);
+                            quote!(#name::#label(ref v) => { #check })
+                        }
+                    })
+                    .collect();
+                quote!(match *self { #(#arms)* })
+            }
+
+            ty @ Type::Flex(..) | ty @ Type::Array(..) => check(quote!(self.0), ty, symtab, opts),
+
+            _ => return Ok(None),
+        };
+
+        Ok(Some(quote! {
+            impl xdr_codec::Validate for #name {
+                fn validate(&self) -> xdr_codec::Result<()> {
+                    #body
+                    Ok(())
+                }
+            }
+        }))
+    }
+}
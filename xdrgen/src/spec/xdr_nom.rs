@@ -1,10 +1,11 @@
-// Grammar for a .x file specifying XDR type codecs. Does not include any RPC syntax. Should match RFC4506.
+// Grammar for a .x file specifying XDR type codecs. Should match RFC4506, plus the program
+// definitions from RFC5531.
 use nom::{Err, ErrorKind, IResult, Needed, is_digit, is_space, not_line_ending};
 use nom::IResult::*;
 
 use std::str;
 
-use super::{Decl, Defn, EnumDefn, Type, UnionCase, Value};
+use super::{Decl, Defn, EnumDefn, Pragma, Procedure, Program, Type, UnionCase, Value, Version};
 use super::{Derives};
 
 #[inline]
@@ -25,18 +26,27 @@ fn eof(input: &[u8]) -> IResult<&[u8], ()> {
     }
 }
 
+#[cfg(test)]
 pub fn specification(input: &str) -> Result<Vec<Defn>, String> {
+    located_specification(input).map_err(|(_, msg)| msg)
+}
+
+// Parse a specification, failing with the byte offset of the error in `input` if it's known.
+pub fn located_specification(input: &str) -> Result<Vec<Defn>, (Option<usize>, String)> {
+    // The parser needs at least one byte to look at
+    if input.is_empty() {
+        return Ok(Vec::new());
+    }
     match spec(input.as_bytes()) {
         Done(_, spec) => Ok(spec),
-        Error(Err::Position(kind, input)) => {
-            Err(format!(
-                "{:?}: {}",
-                kind,
-                String::from(str::from_utf8(input).unwrap())
-            ))
+        Error(Err::Position(kind, rest)) | Error(Err::NodePosition(kind, rest, _)) => {
+            // Only the start of what couldn't be parsed is useful
+            let text = String::from(str::from_utf8(rest).unwrap());
+            let text = text.lines().next().unwrap_or("");
+            Err((Some(input.len() - rest.len()), format!("{:?}: {}", kind, text)))
         }
-        Error(err) => Err(format!("Error: {:?}", err)),
-        Incomplete(need) => Err(format!("Incomplete {:?}", need)),
+        Error(err) => Err((None, format!("Error: {:?}", err))),
+        Incomplete(need) => Err((Some(input.len()), format!("Incomplete {:?}", need))),
     }
 }
 
@@ -45,9 +55,16 @@ named!(spec< Vec<Defn> >,
         opt!(directive) >>
         defns: many0!(definition) >>
         spaces >> eof >>
-        (defns))
+        (defns.into_iter().flatten().collect()))
 );
 
+#[test]
+fn test_empty_spec() {
+    assert_eq!(specification(""), Ok(vec!()));
+    assert_eq!(specification(" \n"), Ok(vec!()));
+    assert_eq!(specification("/* nothing */\n// here\n"), Ok(vec!()));
+}
+
 #[test]
 fn test_spec() {
     assert_eq!(spec(&b"#include <foo>"[..]),
@@ -81,9 +98,50 @@ enum bop { a = 2, b = 1 };
                                                                              EnumDefn::new("b", Some(Value::Const(1)))))))));
 }
 
-named!(definition<Defn>,
-       alt!(type_def => { |t| t } |
-            const_def => { |c| c }));
+// A definition along with the pragmas attached to it
+named!(definition< Vec<Defn> >,
+    do_parse!(
+        pragmas: many0!(pragma) >>
+        defns: alt!(struct_def |
+                    type_def => { |t| vec!(t) } |
+                    const_def => { |c| vec!(c) } |
+                    program_def => { |p| vec!(p) }) >>
+        ({
+            let name = defns[0].name().to_string();
+            let mut defns = defns;
+            defns.extend(pragmas.into_iter().map(|p| Defn::pragma(&name, None, p)));
+            defns
+        }))
+);
+
+#[test]
+fn test_pragma() {
+    assert_eq!(pragma(&b"/* @deprecated use bar */"[..]),
+               Done(&b""[..], Pragma::new("deprecated", "use bar")));
+    assert_eq!(pragma(&b"\n  /*@deprecated*/"[..]),
+               Done(&b""[..], Pragma::new("deprecated", "")));
+    assert!(pragma(&b"/* deprecated */"[..]).is_err());
+
+    assert_eq!(spec(&br#"
+/* plain comment */
+/* @deprecated use bar */
+/* @other */
+struct foo {
+    int a;
+    /* @deprecated gone */ int b; /* @ignored */
+};
+/* @deprecated */
+typedef int baz;
+"#[..]),
+               Done(&b""[..],
+                    vec!(Defn::typespec("foo", Type::Struct(vec!(Decl::named("a", Type::Int),
+                                                                 Decl::named("b", Type::Int)))),
+                         Defn::pragma("foo", Some("b"), Pragma::new("deprecated", "gone")),
+                         Defn::pragma("foo", None, Pragma::new("deprecated", "use bar")),
+                         Defn::pragma("foo", None, Pragma::new("other", "")),
+                         Defn::typesyn("baz", Type::Int),
+                         Defn::pragma("baz", None, Pragma::new("deprecated", "")))));
+}
 
 fn is_hexdigit(ch: u8) -> bool {
     match ch as char {
@@ -238,6 +296,7 @@ kw!(kw_hyper, b"hyper");
 kw!(kw_int, b"int");
 kw!(kw_long, b"long"); // special case - part time keyword
 kw!(kw_opaque, b"opaque");
+kw!(kw_program, b"program"); // RPC only - not reserved
 kw!(kw_quadruple, b"quadruple");
 kw!(kw_short, b"short"); // special case - part time keyword
 kw!(kw_string, b"string");
@@ -246,6 +305,7 @@ kw!(kw_switch, b"switch");
 kw!(kw_typedef, b"typedef");
 kw!(kw_union, b"union");
 kw!(kw_unsigned, b"unsigned");
+kw!(kw_version, b"version"); // RPC only - not reserved
 kw!(kw_void, b"void");
 
 named!(keyword<()>,
@@ -340,6 +400,18 @@ fn test_ident() {
 named!(blockcomment<()>,
     do_parse!(apply!(ctag, "/*") >> take_until_and_consume!(&b"*/"[..]) >> (())));
 
+// Pragmas are block comments starting with `@`
+named!(pragma_start<()>,
+    do_parse!(apply!(ctag, "/*") >> opt!(whitespace) >> apply!(ctag, "@") >> (())));
+
+named!(pragma<Pragma>,
+    do_parse!(
+        plain_spaces >> pragma_start >>
+        name: token >>
+        args: take_until_and_consume!(&b"*/"[..]) >>
+        (Pragma::new(str::from_utf8(name).unwrap(), str::from_utf8(args).unwrap().trim())))
+);
+
 // `linecomment`, and `directive` end at eol, but do not consume it
 named!(linecomment<()>,
     do_parse!(
@@ -402,6 +474,20 @@ named!(spaces<()>,
     )
 );
 
+// Like `spaces`, but stops at a pragma.
+named!(plain_spaces<()>,
+    map!(
+        many0!(
+            alt!( do_parse!(eol >> opt!(complete!(directive)) >> (()))
+                | whitespace
+                | do_parse!(not!(pragma_start) >> blockcomment >> (()))
+                | linecomment
+                )
+        ),
+        ignore
+    )
+);
+
 fn ws(input: &[u8]) -> &[u8] {
     match spaces(input) {
         Done(rest, _) => rest,
@@ -464,14 +550,83 @@ named!(struct_type_spec< Vec<Decl> >,
        preceded!(kw_struct, struct_body));
 
 named!(struct_body< Vec<Decl> >,
+    map!(struct_body_pragmas, |decls| decls.into_iter().map(|(_, d)| d).collect()));
+
+named!(struct_body_pragmas< Vec<(Vec<Pragma>, Decl)> >,
     do_parse!(
         lbrace >>
-        decls: many1!(terminated!(declaration, semi)) >>
+        decls: many0!(do_parse!(
+            pragmas: many0!(pragma) >>
+            decls: terminated!(member_declarations, semi) >>
+            (decls.into_iter().map(|d| (pragmas.clone(), d)).collect::<Vec<_>>()))) >>
         rbrace >>
-        (decls)
+        (decls.into_iter().flatten().collect())
+    )
+);
+
+// One of several declarators sharing a type, as in C's `int a, b[4], *c;`
+#[derive(Debug)]
+enum Declarator {
+    Plain(String),
+    Array(String, Value),
+    Flex(String, Option<Value>),
+    Option(String),
+}
+
+named!(declarator<Declarator>,
+    alt!(
+        do_parse!(id: ident >> lbrack >> sz:value >> rbrack >> (Declarator::Array(id.to_string(), sz)))
+    |   do_parse!(id: ident >> lt >> sz:opt!(value) >> gt >> (Declarator::Flex(id.to_string(), sz)))
+    |   do_parse!(star >> id: ident >> (Declarator::Option(id.to_string())))
+    |   map!(ident, |id| Declarator::Plain(id.to_string()))
     )
 );
 
+// Struct members, which may have several comma-separated declarators
+named!(member_declarations< Vec<Decl> >,
+    alt!(
+        map_res!(
+            do_parse!(
+                ty: array_type_spec >>
+                first: declarator >>
+                rest: many1!(preceded!(comma, declarator)) >>
+                (ty, first, rest)),
+            |(ty, first, rest): (Type, Declarator, Vec<Declarator>)| {
+                Some(first).into_iter().chain(rest).map(|d| {
+                    // `opaque` and `string` are only valid as arrays
+                    let plain = ty != Type::Opaque && ty != Type::String;
+                    match d {
+                        Declarator::Array(id, sz) => Ok(Decl::named(id, Type::array(ty.clone(), sz))),
+                        Declarator::Flex(id, sz) => Ok(Decl::named(id, Type::flex(ty.clone(), sz))),
+                        Declarator::Option(id) if plain => Ok(Decl::named(id, Type::option(ty.clone()))),
+                        Declarator::Plain(id) if plain => Ok(Decl::named(id, ty.clone())),
+                        _ => Err(()),
+                    }
+                }).collect::<Result<Vec<_>, ()>>()
+            })
+    |   map!(declaration, |d| vec!(d))
+    )
+);
+
+// Struct definition along with pragmas for its fields
+named!(struct_def< Vec<Defn> >,
+    do_parse!(
+        kw_struct >> id:ident >> body:struct_body_pragmas >> semi >>
+        ({
+            let mut pragmas = Vec::new();
+            let mut decls = Vec::new();
+            for (ps, decl) in body {
+                if let Decl::Named(ref field, _) = decl {
+                    pragmas.extend(ps.into_iter().map(|p| Defn::pragma(id, Some(field), p)));
+                }
+                decls.push(decl);
+            }
+            let mut defns = vec!(Defn::typespec(id, Type::Struct(decls)));
+            defns.extend(pragmas);
+            defns
+        }))
+);
+
 named!(union_type_spec<(Decl, Vec<UnionCase>, Option<Decl>)>,
     do_parse!(kw_union >> body:union_body >> (body)));
 
@@ -526,6 +681,28 @@ named!(array_type_spec<Type>,
             )
        );
 
+#[test]
+fn test_multi_decls() {
+    assert_eq!(struct_body(&b"{ int a, b[4], *c, d<>; hyper e; }"[..]),
+               Done(&b""[..], vec!(
+                   Decl::named("a", Type::Int),
+                   Decl::named("b", Type::Array(Box::new(Type::Int), Value::Const(4))),
+                   Decl::named("c", Type::Option(Box::new(Type::Int))),
+                   Decl::named("d", Type::Flex(Box::new(Type::Int), None)),
+                   Decl::named("e", Type::Hyper))));
+
+    assert_eq!(struct_body(&b"{ opaque a[4], b<>; string c<10> , d<>; }"[..]),
+               Done(&b""[..], vec!(
+                   Decl::named("a", Type::Array(Box::new(Type::Opaque), Value::Const(4))),
+                   Decl::named("b", Type::Flex(Box::new(Type::Opaque), None)),
+                   Decl::named("c", Type::Flex(Box::new(Type::String), Some(Value::Const(10)))),
+                   Decl::named("d", Type::Flex(Box::new(Type::String), None)))));
+
+    assert!(struct_body(&b"{ opaque a[4], b; }"[..]).is_err());
+    assert!(struct_body(&b"{ string *a, b<>; }"[..]).is_err());
+    assert!(struct_body(&b"{ int a, ; }"[..]).is_err());
+}
+
 #[test]
 fn test_decls() {
     assert_eq!(declaration(&b"void "[..]), Done(&b" "[..], Decl::Void));
@@ -655,15 +832,69 @@ fn test_enum() {
                Error(Err::Position(ErrorKind::Alt, &b"enum { } "[..])));
 }
 
+// A double-quoted string literal, with its C-style escapes decoded
+fn string_lit(input: &[u8]) -> IResult<&[u8], String> {
+    let start = ws(input);
+    if start.first() != Some(&b'"') {
+        return Error(Err::Position(ErrorKind::Tag, start));
+    }
+
+    let mut out = Vec::new();
+    let mut i = 1;
+    loop {
+        let c = match start.get(i) {
+            None => return Error(Err::Position(ErrorKind::Custom(2), start)),
+            Some(b'"') => break,
+            Some(b'\\') => {
+                let esc = match start.get(i + 1) {
+                    Some(b'n') => b'\n',
+                    Some(b't') => b'\t',
+                    Some(b'r') => b'\r',
+                    Some(b'0') => 0,
+                    Some(b'x') => match start.get(i + 2..i + 4).map(str::from_utf8) {
+                        Some(Ok(hex)) if hex.bytes().all(is_hexdigit) => {
+                            i += 2;
+                            u8::from_str_radix(hex, 16).unwrap()
+                        }
+                        _ => return Error(Err::Position(ErrorKind::Custom(2), &start[i..])),
+                    },
+                    Some(&c @ b'\\') | Some(&c @ b'"') | Some(&c @ b'\'') => c,
+                    _ => return Error(Err::Position(ErrorKind::Custom(2), &start[i..])),
+                };
+                i += 1;
+                esc
+            }
+            Some(&c) => c,
+        };
+        out.push(c);
+        i += 1;
+    }
+
+    match String::from_utf8(out) {
+        Ok(s) => Done(&start[i + 1..], s),
+        Err(_) => Error(Err::Position(ErrorKind::Custom(2), start)),
+    }
+}
+
 named!(const_def<Defn>,
     do_parse!(
-        kw_const >> id:ident >> eq >> v:number >> semi >>
-            (Defn::constant(id, v)))
+        kw_const >> id:ident >> eq >>
+        defn: alt!(number => { |v| Defn::constant(id, v) } |
+                   string_lit => { |v| Defn::str_constant(id, v) }) >>
+        semi >>
+            (defn))
 );
 
 #[test]
 fn test_const() {
     assert_eq!(const_def(&b"const foo = 123;"[..]), Done(&b""[..], Defn::constant("foo", 123)));
+    assert_eq!(const_def(&b"const foo = \"a\\\"b\";"[..]), Done(&b""[..], Defn::str_constant("foo", "a\"b")));
+    assert_eq!(string_lit(&br#" "\t\x41\\\n\0" "#[..]), Done(&b" "[..], String::from("\tA\\\n\0")));
+    assert_eq!(string_lit(&br#""caf\xc3\xa9""#[..]), Done(&b""[..], String::from("caf\u{e9}")));
+    assert!(string_lit(&br#""\q""#[..]).is_err());
+    assert!(string_lit(&br#""\x4""#[..]).is_err());
+    assert!(string_lit(&br#""\xff""#[..]).is_err());
+    assert!(const_def(&b"const foo = \"open;"[..]).is_err());
 }
 
 named!(type_def<Defn>,
@@ -682,6 +913,7 @@ named!(type_def<Defn>,
                 }
             })
         )
+    |   do_parse!(kw_typedef >> kw_void >> id:ident >> semi >> (Defn::typespec(id, Type::Struct(Vec::new()))))
     |   do_parse!(kw_enum >> id:ident >> e:enum_body >> semi >> (Defn::typespec(id, Type::Enum(e))))
     |   do_parse!(kw_struct >> id:ident >> s:struct_body >> semi >> (Defn::typespec(id, Type::Struct(s))))
     |   do_parse!(kw_union >> id:ident >> u:union_body >> semi >> (Defn::typespec(id, Type::union(u))))
@@ -702,6 +934,10 @@ fn test_typedef() {
 
     assert_eq!(type_def(&b"struct foo { int a; };"[..]),
                Done(&b""[..], Defn::typespec("foo", Type::Struct(vec!(Decl::named("a", Type::Int))))));
+    assert_eq!(type_def(&b"struct foo {};"[..]),
+               Done(&b""[..], Defn::typespec("foo", Type::Struct(vec!()))));
+    assert_eq!(type_def(&b"typedef void foo;"[..]),
+               Done(&b""[..], Defn::typespec("foo", Type::Struct(vec!()))));
 
     assert_eq!(type_def(&b"union foo switch(int a) { case 1: int a; };"[..]),
                Done(&b""[..], Defn::typespec("foo",
@@ -709,3 +945,56 @@ fn test_typedef() {
                                                          vec!(UnionCase(Value::Const(1), Decl::named("a", Type::Int))),
                                                          None))));
 }
+
+named!(program_def<Defn>,
+    do_parse!(
+        kw_program >> id:ident >> lbrace >>
+        vers: many1!(version_def) >>
+        rbrace >> eq >> v:value >> semi >>
+        (Defn::program(Program::new(id, vers, v))))
+);
+
+named!(version_def<Version>,
+    do_parse!(
+        kw_version >> id:ident >> lbrace >>
+        procs: many1!(procedure_def) >>
+        rbrace >> eq >> v:value >> semi >>
+        (Version::new(id, procs, v)))
+);
+
+named!(procedure_def<Procedure>,
+    do_parse!(
+        ret: proc_type >> id:ident >> lparen >>
+        args: separated_nonempty_list!(comma, proc_type) >>
+        rparen >> eq >> v:value >> semi >>
+        (Procedure::new(ret, id, args.into_iter().flatten().collect(), v)))
+);
+
+named!(proc_type<Option<Type>>,
+    alt!(kw_void => { |_| None } |
+         type_spec => { Some }));
+
+#[test]
+fn test_program() {
+    assert_eq!(program_def(&br#"
+program PROG {
+    version VERS {
+        void PROC_NULL(void) = 0;
+        res PROC_GET(struct args) = 1;
+        int PROC_ADD(int, hyper) = 2;
+    } = 1;
+} = 0x20000001;"#[..]),
+               Done(&b""[..],
+                    Defn::program(Program::new("PROG",
+                        vec!(Version::new("VERS",
+                            vec!(Procedure::new(None, "PROC_NULL", vec!(), Value::Const(0)),
+                                 Procedure::new(Some(Type::ident("res")), "PROC_GET", vec!(Type::ident("args")), Value::Const(1)),
+                                 Procedure::new(Some(Type::Int), "PROC_ADD", vec!(Type::Int, Type::Hyper), Value::Const(2))),
+                            Value::Const(1))),
+                        Value::Const(0x20000001)))));
+
+    // Still usable as identifiers
+    assert_eq!(type_def(&b"struct foo { int version; int program; };"[..]),
+               Done(&b""[..], Defn::typespec("foo", Type::Struct(vec!(Decl::named("version", Type::Int),
+                                                                      Decl::named("program", Type::Int))))));
+}
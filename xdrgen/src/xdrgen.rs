@@ -5,12 +5,43 @@ extern crate env_logger;
 extern crate clap;
 
 use std::fs::File;
-use std::io::{BufReader, Write};
+#[cfg(any(feature = "export-kaitai", feature = "export-proto", feature = "export-avro"))]
+use std::path::Path;
+use std::path::PathBuf;
+use std::io::{BufReader, Read, Write};
 use std::io::{stderr, stdin, stdout};
 
-use clap::App;
+use clap::{App, Arg};
 
-use xdrgen::generate;
+use xdrgen::{Compat, GenOptions, Symtab, expand_includes, generate_from_symtab};
+
+// Read and parse a specification, expanding includes if there are search directories.
+fn read_spec(name: &str, input: &mut dyn Read, dirs: Option<&[PathBuf]>) -> xdr_codec::Result<Symtab> {
+    let mut source = String::new();
+    input.read_to_string(&mut source)?;
+
+    if let Some(dirs) = dirs {
+        source = expand_includes(name, &source, dirs)?;
+    }
+    source.parse()
+}
+
+// Print the changes from `old` to `new`, breaking ones first. Exits with status 1 if there are
+// any breaking changes.
+fn diff<Out: Write>(old: &Symtab, new: &Symtab, mut output: Out) -> xdr_codec::Result<()> {
+    let mut changes = old.diff(new);
+    changes.sort_by_key(|c| std::cmp::Reverse(c.compat));
+
+    for change in &changes {
+        writeln!(output, "{}", change)?;
+    }
+
+    if changes.iter().any(|c| c.compat == Compat::Breaking) {
+        output.flush()?;
+        std::process::exit(1);
+    }
+    Ok(())
+}
 
 fn main() {
     let _ = env_logger::init();
@@ -18,12 +49,16 @@ fn main() {
     let matches = App::new("XDR code generator")
         .version(env!("CARGO_PKG_VERSION"))
         .arg_from_usage("[FILE] 'Set .x file'")
+        .arg_from_usage("--emit [FORMAT] 'Output format: rust (default), kaitai, proto or avro'")
+        .arg(Arg::from_usage("-I [DIR]... 'Expand #include of .x files, searching DIR'").number_of_values(1))
+        .arg_from_usage("--diff [OLD] 'Report changes from the OLD .x file instead of generating code'")
+        .arg_from_usage("--mem-report 'Report the estimated in-memory size of each type instead of generating code'")
         .get_matches();
 
     let output = stdout();
     let mut err = stderr();
 
-    let res = if let Some(fname) = matches.value_of("FILE") {
+    let (name, mut input): (_, Box<dyn Read>) = if let Some(fname) = matches.value_of("FILE") {
         let f = match File::open(fname) {
             Ok(f) => f,
             Err(e) => {
@@ -31,11 +66,49 @@ fn main() {
                 std::process::exit(1);
             }
         };
-        generate(fname, BufReader::new(f), output, &[])
+        (fname, Box::new(BufReader::new(f)))
     } else {
-        generate("stdin", BufReader::new(stdin()), output, &[])
+        ("stdin", Box::new(BufReader::new(stdin())))
     };
 
+    let dirs = matches.values_of("I").map(|dirs| dirs.map(PathBuf::from).collect::<Vec<_>>());
+
+    let res = read_spec(name, &mut input, dirs.as_deref()).and_then(|xdr| {
+        if let Some(oldname) = matches.value_of("diff") {
+            let mut old = File::open(oldname).map_err(xdr_codec::Error::from)?;
+            let old = read_spec(oldname, &mut old, dirs.as_deref())?;
+            return diff(&old, &xdr, output);
+        }
+
+        if matches.is_present("mem-report") {
+            let mut output = output;
+            for size in xdr.mem_report()? {
+                writeln!(output, "{}", size)?;
+            }
+            return Ok(());
+        }
+
+        match matches.value_of("emit").unwrap_or("rust") {
+            "rust" => generate_from_symtab(name, &xdr, output, &GenOptions::new()),
+            #[cfg(feature = "export-kaitai")]
+            "kaitai" => {
+                let id = Path::new(name).file_stem().and_then(|s| s.to_str()).unwrap_or(name);
+                xdrgen::export_kaitai(id, &xdr, output)
+            }
+            #[cfg(feature = "export-proto")]
+            "proto" => {
+                let id = Path::new(name).file_stem().and_then(|s| s.to_str()).unwrap_or(name);
+                xdrgen::export_proto(id, &xdr, output)
+            }
+            #[cfg(feature = "export-avro")]
+            "avro" => {
+                let id = Path::new(name).file_stem().and_then(|s| s.to_str()).unwrap_or(name);
+                xdrgen::export_avro(id, &xdr, output)
+            }
+            other => Err(format!("unknown output format {}", other).into()),
+        }
+    });
+
     if let Err(e) = res {
         let _ = writeln!(&mut err, "Failed: {}", e);
     }
@@ -8,10 +8,16 @@ use std::fs::{File, create_dir_all};
 use std::io::{Cursor, Write};
 use std::process::Command;
 
-use xdrgen::generate;
+use xdrgen::{AddrForm, Checksum, GenOptions, IdentKind, LenWidth, Opt, Rename, StrEnc, UnknownEnum, generate_with_options};
 use xdr_codec::Result;
 
 fn build_test(name: &str, xdr_spec: &str) -> Result<()> {
+    build_test_with(name, xdr_spec, &GenOptions::new(), "")
+}
+
+// Generate code for `xdr_spec` with `opts`, and run `tests` against it. `tests` is Rust source
+// which is put into the same module as the generated code.
+fn build_test_with(name: &str, xdr_spec: &str, opts: &GenOptions, tests: &str) -> Result<()> {
     let tempdir = tempdir::TempDir::new("build").expect("Failed to make tempdir");
     let dir = tempdir.path();
 
@@ -35,28 +41,46 @@ name = "test"
 path = "{}"
 
 [dependencies]
-xdr-codec = {{ path = "{}" }}
+xdr-codec = {{ path = "{}", features = [{}] }}
+log = "0.4"
+{}
 "#,
         mainfile.as_os_str().to_string_lossy(),
         std::env::current_dir()?
             .join("../xdr-codec")
             .as_os_str()
-            .to_string_lossy()
+            .to_string_lossy(),
+        if cfg!(feature = "json") { "\"json\"" } else { "" },
+        if cfg!(feature = "derive_serde") {
+            "serde = { version = \"1\", features = [\"derive\"] }\nserde_json = \"1\""
+        } else {
+            ""
+        }
     );
 
     let template = format!(
         r#"
 #![allow(dead_code, non_camel_case_types, unused_assignments, unused_imports)]
+#![deny(deprecated)]
 extern crate xdr_codec;
+extern crate log;
+{}
 
 mod test {{
     use xdr_codec;
+    use log;
+    {}
     include!("{}");
+
+    {}
 }}
 
 fn main() {{}}
 "#,
-        testfile.as_os_str().to_string_lossy()
+        if cfg!(feature = "derive_serde") { "extern crate serde;\nextern crate serde_json;" } else { "" },
+        if cfg!(feature = "derive_serde") { "use serde::{Deserialize, Serialize};\n    use serde_json;" } else { "" },
+        testfile.as_os_str().to_string_lossy(),
+        tests
     );
 
     {
@@ -73,7 +97,7 @@ fn main() {{}}
 
     {
         let test = File::create(&testfile)?;
-        generate(name, Cursor::new(xdr_spec.as_bytes()), test, &[])?;
+        generate_with_options(name, Cursor::new(xdr_spec.as_bytes()), test, opts)?;
     }
 
     let compile = {
@@ -81,6 +105,8 @@ fn main() {{}}
         let cmd = cmd
                .current_dir(std::env::current_dir()?)
                //.env("CARGO_HOME", cargohome)
+               // Share a target dir so xdr-codec and its deps are only built once
+               .env("CARGO_TARGET_DIR", concat!(env!("CARGO_TARGET_TMPDIR"), "/build_test"))
                .arg("test")
                .arg("--manifest-path").arg(cargotoml);
         println!("CWD: {:?} Command: {:?}", std::env::current_dir(), cmd);
@@ -100,6 +126,16 @@ fn main() {{}}
     }
 }
 
+// Derive serde's traits for types defined in `tests`, which generated types containing them need
+// with `derive_serde`.
+fn with_serde_derives(tests: &str) -> String {
+    if cfg!(feature = "derive_serde") {
+        tests.replace("#[derive(Debug,", "#[derive(serde::Serialize, serde::Deserialize, Debug,")
+    } else {
+        tests.to_string()
+    }
+}
+
 #[test]
 fn typedef_arrays() {
     let name = "typedef_arrays";
@@ -318,3 +354,2989 @@ fn derive_float() {
         panic!("test {} failed: {}", name, e);
     }
 }
+
+#[test]
+fn conversions() {
+    let name = "conversions";
+    let spec = r#"
+        struct FooV1 { int a; string name<32>; };
+        struct FooV2 { int a; string name<32>; unsigned hyper extra; };
+        struct BarV1 { string name<>; };
+        struct BarV2 { string name<4>; int b; };
+    "#;
+    let tests = r#"
+        use std::convert::TryFrom;
+
+        #[test]
+        fn widen_and_narrow() {
+            let v1 = FooV1 { a: 1, name: "x".into() };
+            let v2 = FooV2::from(v1.clone());
+            assert_eq!(v2, FooV2 { a: 1, name: "x".into(), extra: 0 });
+            assert_eq!(FooV1::from(FooV2 { extra: 3, ..v2 }), v1);
+        }
+
+        #[test]
+        fn bound_checked() {
+            assert!(BarV2::try_from(BarV1 { name: "abcd".into() }).is_ok());
+            assert!(BarV2::try_from(BarV1 { name: "abcde".into() }).is_err());
+            assert_eq!(BarV1::from(BarV2 { name: "ab".into(), b: 1 }).name, "ab");
+        }
+    "#;
+    let opts = GenOptions::new().conversions(&[("FooV1", "FooV2"), ("BarV1", "BarV2")]);
+
+    if let Err(e) = build_test_with(name, spec, &opts, tests) {
+        panic!("test {} failed: {}", name, e);
+    }
+}
+
+#[test]
+fn max_message_size() {
+    let name = "max_message_size";
+    let spec = r#"
+        struct inner {
+            opaque data<16>;
+        };
+        struct outer {
+            inner parts<8>;
+        };
+        typedef outer message;
+    "#;
+    let tests = r#"
+        use xdr_codec::{Error, ErrorKind, pack, unpack};
+
+        fn packed(parts: usize) -> Vec<u8> {
+            let msg = outer {
+                parts: (0..parts).map(|_| inner { data: vec![0; 16] }).collect(),
+            };
+            let mut out = Vec::new();
+            pack(&msg, &mut out).unwrap();
+            out
+        }
+
+        #[test]
+        fn within_limit() {
+            // 4 byte count + 2 * (4 byte len + 16 bytes)
+            let buf = packed(2);
+            assert_eq!(buf.len(), 44);
+            let msg: message = unpack(&mut &buf[..]).unwrap();
+            assert_eq!(msg.parts.len(), 2);
+        }
+
+        #[test]
+        fn exceeds_limit() {
+            let buf = packed(3);
+            match unpack::<_, message>(&mut &buf[..]) {
+                Err(Error(ErrorKind::MessageTooLarge(50), _)) => (),
+                res => panic!("bad result {:?}", res),
+            }
+            // Only the top-level type is limited
+            let _: inner = unpack(&mut &buf[4..]).unwrap();
+        }
+
+        #[test]
+        fn truncated() {
+            let buf = packed(2);
+            match unpack::<_, message>(&mut &buf[..40]) {
+                Err(Error(ErrorKind::IOError(_), _)) => (),
+                res => panic!("bad result {:?}", res),
+            }
+        }
+    "#;
+
+    let opts = GenOptions::new().max_message_size(50);
+    if let Err(e) = build_test_with(name, spec, &opts, tests) {
+        panic!("test {} failed: {}", name, e);
+    }
+}
+
+#[test]
+fn flex_collections() {
+    let name = "flex_collections";
+    let spec = r#"
+        typedef int small<3>;
+        typedef int many<>;
+        typedef string name<4>;
+    "#;
+    let tests = r#"
+        #[test]
+        fn collect() {
+            let v: small = (1..3).collect();
+            assert_eq!(v, small(vec![1, 2]));
+
+            let mut m: many = (0..10).collect();
+            m.extend(10..20);
+            assert_eq!(m.0.len(), 20);
+
+            let n: name = "abcd".chars().collect();
+            assert_eq!(n.0, "abcd");
+        }
+
+        #[test]
+        fn bounded() {
+            let mut v: small = (1..3).collect();
+            assert!(v.try_extend(vec![3, 4]).is_err());
+            assert_eq!(v, small(vec![1, 2]));
+            v.try_extend(Some(3)).unwrap();
+            assert_eq!(v, small(vec![1, 2, 3]));
+
+            let mut n = name(String::new());
+            assert!(n.try_extend("hello".chars()).is_err());
+        }
+
+        #[test]
+        #[should_panic]
+        fn collect_overflow() {
+            let _: small = (0..4).collect();
+        }
+
+        #[test]
+        #[should_panic]
+        fn extend_overflow() {
+            let mut v: small = (1..3).collect();
+            v.extend(vec![3, 4]);
+        }
+
+        #[test]
+        fn overflow_location() {
+            use std::panic;
+            use std::sync::{Arc, Mutex};
+            use std::thread;
+
+            // Only this thread's panic, as other tests panic concurrently
+            let me = thread::current().id();
+            let file = Arc::new(Mutex::new(None));
+            let hook_file = file.clone();
+            panic::set_hook(Box::new(move |info| if thread::current().id() == me {
+                *hook_file.lock().unwrap() = info.location().map(|l| l.file().to_string());
+            }));
+            let res = panic::catch_unwind(|| {
+                let mut v: small = (1..3).collect();
+                v.extend(vec![3, 4]);
+            });
+            let _ = panic::take_hook();
+
+            assert!(res.is_err());
+            let file = file.lock().unwrap().clone().unwrap();
+            assert!(file.ends_with("flex_collections.rs"), "panicked in {}", file);
+        }
+    "#;
+
+    let opts = GenOptions::new().flex_collections(true);
+    if let Err(e) = build_test_with(name, spec, &opts, tests) {
+        panic!("test {} failed: {}", name, e);
+    }
+}
+
+#[test]
+fn forward_compat() {
+    let name = "forward_compat";
+    let spec = r#"
+        struct point {
+            int x;
+            int y;
+        };
+        struct msg {
+            point pos;
+            string label<>;
+        };
+    "#;
+    let tests = r#"
+        use std::io::{BufReader, Cursor};
+        use xdr_codec::{Pack, Unpack, pack, unpack};
+        use xdr_codec::record::{XdrRecordReader, XdrRecordWriter};
+
+        #[test]
+        fn extra_fields() {
+            let mut buf = Vec::new();
+            {
+                let mut wr = XdrRecordWriter::new(&mut buf);
+                // A newer msg with an extra trailing hyper and string
+                let mut rec = Vec::new();
+                msg { pos: point { x: 1, y: 2 }, label: "hi".into() }.pack(&mut rec).unwrap();
+                0x1122334455667788i64.pack(&mut rec).unwrap();
+                xdr_codec::pack_string("new", None, &mut rec).unwrap();
+                ::std::io::Write::write_all(&mut wr, &rec).unwrap();
+            }
+
+            let rd = XdrRecordReader::new(BufReader::new(Cursor::new(buf)));
+            let recs: Vec<Vec<u8>> = rd.into_iter().map(|r| r.unwrap()).collect();
+            assert_eq!(recs.len(), 1);
+
+            let (m, sz) = msg::unpack(&mut &recs[0][..]).unwrap();
+            assert_eq!(m, msg { pos: point { x: 1, y: 2 }, label: "hi".into() });
+            assert_eq!(sz, recs[0].len());
+        }
+
+        #[test]
+        fn nested_not_skipped() {
+            // point isn't top-level, so it only consumes its own fields
+            let buf = vec![0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, 3];
+            let mut input = &buf[..];
+            let p: point = unpack(&mut input).unwrap();
+            assert_eq!(p, point { x: 1, y: 2 });
+            assert_eq!(input.len(), 4);
+        }
+    "#;
+
+    let opts = GenOptions::new().forward_compat(true);
+    if let Err(e) = build_test_with(name, spec, &opts, tests) {
+        panic!("test {} failed: {}", name, e);
+    }
+}
+
+#[test]
+fn rpc_aliases() {
+    let name = "rpc_aliases";
+    let spec = r#"
+        struct lookup_args {
+            string name<>;
+        };
+        enum lookup_status { FOUND = 0, MISSING = 1 };
+
+        program LOOKUP_PROG {
+            version LOOKUP_V1 {
+                void LOOKUPPROC_NULL(void) = 0;
+                lookup_status LOOKUPPROC_FIND(lookup_args) = 1;
+                int LOOKUPPROC_COUNT(lookup_args, int) = 2;
+            } = 1;
+        } = 0x20000123;
+    "#;
+    let tests = r#"
+        #[test]
+        fn aliases() {
+            let _: LOOKUPPROC_NULLArgs = ();
+            let _: LOOKUPPROC_NULLResult = ();
+
+            let args: LOOKUPPROC_FINDArgs = lookup_args { name: "foo".into() };
+            let res: LOOKUPPROC_FINDResult = lookup_status::MISSING;
+            assert_eq!(args.name, "foo");
+            assert_eq!(res, lookup_status::MISSING);
+
+            let _: LOOKUPPROC_COUNTArgs = (lookup_args { name: "x".into() }, 1i32);
+            let _: LOOKUPPROC_COUNTResult = 0i32;
+        }
+    "#;
+
+    if let Err(e) = build_test_with(name, spec, &GenOptions::new(), tests) {
+        panic!("test {} failed: {}", name, e);
+    }
+}
+
+#[test]
+fn rpc_aliases_versions() {
+    let name = "rpc_aliases_versions";
+    // As in mount.x, where MOUNTPROC_NULL is in both versions
+    let spec = r#"
+        typedef string dirpath<1024>;
+        struct fhstatus { int status; };
+        struct mountres3 { int status; unsigned hyper fh; };
+
+        program MOUNT_PROGRAM {
+            version MOUNTVERS {
+                void MOUNTPROC_NULL(void) = 0;
+                fhstatus MOUNTPROC_MNT(dirpath) = 1;
+                void MOUNTPROC_UMNT(dirpath) = 3;
+            } = 1;
+            version MOUNT_V3 {
+                void MOUNTPROC_NULL(void) = 0;
+                mountres3 MOUNTPROC_MNT(dirpath) = 1;
+                void MOUNTPROC_UMNT(dirpath) = 3;
+            } = 3;
+        } = 100005;
+    "#;
+    let tests = r#"
+        #[test]
+        fn aliases() {
+            let _: MOUNTPROC_NULLArgs = ();
+            let _: MOUNTPROC_NULLResult = ();
+            let _: MOUNTPROC_UMNTArgs = dirpath("/export".to_string());
+
+            let _: MOUNTPROC_MNT_MOUNTVERSArgs = dirpath("/".to_string());
+            let _: MOUNTPROC_MNT_MOUNTVERSResult = fhstatus { status: 0 };
+            let _: MOUNTPROC_MNT_MOUNT_V3Result = mountres3 { status: 0, fh: 1 };
+        }
+    "#;
+
+    if let Err(e) = build_test_with(name, spec, &GenOptions::new().proc_tables(true), tests) {
+        panic!("test {} failed: {}", name, e);
+    }
+}
+
+#[test]
+fn proc_tables() {
+    let name = "proc_tables";
+    let spec = r#"
+        const NFS_PROGRAM = 100003;
+        typedef string filename<>;
+        program NFS_PROG {
+            version NFS_V2 {
+                void NFSPROC_NULL(void) = 0;
+                int NFSPROC_ROOT(void) = 3;
+            } = 2;
+            version NFS_V3 {
+                void NFSPROC3_NULL(void) = 0;
+                int NFSPROC3_LOOKUP(filename) = 3;
+                int NFSPROC3_READ(filename) = 6;
+            } = 3;
+        } = NFS_PROGRAM;
+    "#;
+    let tests = r#"
+        #[test]
+        fn names() {
+            assert_eq!(nfs_prog::PROGRAM, 100003);
+            assert_eq!(nfs_prog::nfs_v3::VERSION, 3);
+            assert_eq!(nfs_prog::nfs_v3::PROCS, &[(0, "NFSPROC3_NULL"), (3, "NFSPROC3_LOOKUP"), (6, "NFSPROC3_READ")]);
+            assert_eq!(nfs_prog::proc_name(3), Some("NFSPROC3_LOOKUP"));
+            assert_eq!(nfs_prog::nfs_v2::proc_name(3), Some("NFSPROC_ROOT"));
+            assert_eq!(nfs_prog::proc_name(4), None);
+            assert_eq!(nfs_prog::PROCS.len(), 3);
+        }
+    "#;
+
+    if let Err(e) = build_test_with(name, spec, &GenOptions::new().proc_tables(true), tests) {
+        panic!("test {} failed: {}", name, e);
+    }
+}
+
+#[test]
+fn marker_types() {
+    let name = "marker_types";
+    let spec = r#"
+        struct ack {};
+        typedef void nothing;
+        struct msg {
+            int seq;
+            ack a;
+            nothing n;
+            int len;
+        };
+        union reply switch (int ok) {
+            case 0: ack done;
+            case 1: int err;
+        };
+    "#;
+    let tests = r#"
+        use xdr_codec::{pack, unpack};
+
+        #[test]
+        fn zero_sized() {
+            assert_eq!(::std::mem::size_of::<ack>(), 0);
+            assert_eq!(::std::mem::size_of::<nothing>(), 0);
+
+            let mut buf = Vec::new();
+            assert_eq!(xdr_codec::Pack::pack(&ack, &mut buf).unwrap(), 0);
+            assert!(buf.is_empty());
+            let (_, sz): (ack, usize) = xdr_codec::Unpack::unpack(&mut &[1u8, 2, 3][..]).unwrap();
+            assert_eq!(sz, 0);
+        }
+
+        #[test]
+        fn as_members() {
+            let m = msg { seq: 1, a: ack, n: nothing, len: 2 };
+            let mut buf = Vec::new();
+            pack(&m, &mut buf).unwrap();
+            assert_eq!(buf, vec![0, 0, 0, 1, 0, 0, 0, 2]);
+            assert_eq!(unpack::<_, msg>(&mut &buf[..]).unwrap(), m);
+
+            let mut buf = Vec::new();
+            pack(&reply::Const0(ack), &mut buf).unwrap();
+            assert_eq!(buf, vec![0, 0, 0, 0]);
+            assert_eq!(unpack::<_, reply>(&mut &buf[..]).unwrap(), reply::Const0(ack));
+        }
+    "#;
+
+    if let Err(e) = build_test_with(name, spec, &GenOptions::new(), tests) {
+        panic!("test {} failed: {}", name, e);
+    }
+}
+
+#[test]
+fn shared_array_codecs() {
+    let name = "shared_array_codecs";
+    let spec = r#"
+        struct point { int x; int y; };
+        struct shape {
+            point corners[4];
+            point shadow[4];
+            hyper weights[3];
+            opaque tag[3];
+        };
+        typedef point quad[4];
+    "#;
+    let tests = r#"
+        use xdr_codec::{pack, unpack};
+
+        fn p(x: i32) -> point {
+            point { x, y: -x }
+        }
+
+        #[test]
+        fn round_trip() {
+            let s = shape {
+                corners: [p(1), p(2), p(3), p(4)],
+                shadow: [p(5), p(6), p(7), p(8)],
+                weights: [1, 2, 3],
+                tag: [9, 8, 7],
+            };
+            let mut buf = Vec::new();
+            pack(&s, &mut buf).unwrap();
+            assert_eq!(buf.len(), 2 * 32 + 24 + 4);
+            assert_eq!(&buf[..8], &[0, 0, 0, 1, 0xff, 0xff, 0xff, 0xff]);
+            assert_eq!(unpack::<_, shape>(&mut &buf[..]).unwrap(), s);
+
+            let q = quad([p(1), p(2), p(3), p(4)]);
+            let mut qbuf = Vec::new();
+            pack(&q, &mut qbuf).unwrap();
+            assert_eq!(&qbuf[..], &buf[..32]);
+            assert_eq!(unpack::<_, quad>(&mut &qbuf[..]).unwrap(), q);
+
+            // A short array fails cleanly
+            assert!(unpack::<_, quad>(&mut &qbuf[..20]).is_err());
+        }
+    "#;
+
+    let opts = GenOptions::new().shared_array_codecs(true);
+    let mut out = Vec::new();
+    generate_with_options(name, Cursor::new(spec.as_bytes()), &mut out, &opts).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    assert!(!out.contains("fn unpack_fixed"));
+    assert_eq!(out.matches("xdr_codec :: unpack_fixed ::").count(), 4);
+    assert!(!out.contains("MaybeUninit < point >"));
+
+    if let Err(e) = build_test_with(name, spec, &opts, tests) {
+        panic!("test {} failed: {}", name, e);
+    }
+}
+
+#[test]
+fn union_predicates() {
+    let name = "union_predicates";
+    let spec = r#"
+        enum status { STATUS_OK = 0, STATUS_ERR = 1, STATUS_MOVED = 2 };
+        union result switch (status stat) {
+            case STATUS_OK: int value;
+            case STATUS_ERR: void;
+            case STATUS_MOVED: string where<>;
+        };
+        union tagged switch (int tag) {
+            case 1: void;
+            case 2: hyper big;
+            default: int other;
+        };
+    "#;
+    let tests = r#"
+        #[test]
+        fn predicates() {
+            let ok = result::STATUS_OK(1);
+            let err = result::STATUS_ERR;
+            let moved = result::STATUS_MOVED("there".into());
+
+            assert!(ok.is_status_ok() && !ok.is_status_err() && !ok.is_status_moved());
+            assert!(!err.is_status_ok() && err.is_status_err() && !err.is_status_moved());
+            assert!(!moved.is_status_ok() && !moved.is_status_err() && moved.is_status_moved());
+
+            assert!(tagged::Const1.is_const1());
+            assert!(tagged::Const2(1).is_const2());
+            assert!(tagged::default(3).is_default());
+            assert!(!tagged::default(3).is_const1());
+        }
+    "#;
+
+    let opts = GenOptions::new().union_helpers(true);
+    if let Err(e) = build_test_with(name, spec, &opts, tests) {
+        panic!("test {} failed: {}", name, e);
+    }
+}
+
+#[test]
+fn union_constructors() {
+    let name = "union_constructors";
+    let spec = r#"
+        enum status { STATUS_OK = 0, STATUS_ERR = 1, STATUS_MOVED = 2 };
+        struct location { string host<>; unsigned int port; };
+        struct big { hyper a; hyper b; hyper c; hyper d; hyper e; };
+        union result switch (status stat) {
+            case STATUS_OK: big value;
+            case STATUS_ERR: void;
+            case STATUS_MOVED: location where;
+        };
+        union tagged switch (int tag) {
+            case 1: void;
+            case 2: hyper big;
+            default: int other;
+        };
+    "#;
+    let tests = r#"
+        #[test]
+        fn constructors() {
+            let loc = location { host: "there".into(), port: 80 };
+            assert_eq!(result::status_moved(loc.clone()), result::STATUS_MOVED(loc));
+            assert_eq!(result::status_err(), result::STATUS_ERR);
+            // Boxed by box_large_variants
+            let v = big { a: 1, b: 2, c: 3, d: 4, e: 5 };
+            assert_eq!(result::status_ok(v.clone()), result::STATUS_OK(Box::new(v)));
+
+            assert_eq!(tagged::const1(), tagged::Const1);
+            assert_eq!(tagged::const2(7), tagged::Const2(7));
+            assert!(tagged::default(3).is_default());
+        }
+    "#;
+
+    let opts = GenOptions::new().union_helpers(true).box_large_variants(36);
+    if let Err(e) = build_test_with(name, spec, &opts, tests) {
+        panic!("test {} failed: {}", name, e);
+    }
+}
+
+#[test]
+fn union_enum_discriminants() {
+    let name = "union_enum_discriminants";
+    let spec = r#"
+        enum color { RED = 0, GREEN = 1, BLUE = 2 };
+        union paint switch (color c) {
+            case RED: int red;
+            case GREEN: void;
+        };
+        union shade switch (color c) {
+            case RED: void;
+            default: int other;
+        };
+    "#;
+    let tests = r#"
+        use xdr_codec::{Error, ErrorKind, Unpack};
+
+        #[test]
+        fn discriminants() {
+            // BLUE is a color, but not a case of paint
+            match paint::unpack(&mut &[0u8, 0, 0, 2][..]) {
+                Err(Error(ErrorKind::InvalidCase(2), _)) => (),
+                other => panic!("unexpected {:?}", other),
+            }
+            // 7 isn't a color at all
+            match paint::unpack(&mut &[0u8, 0, 0, 7][..]) {
+                Err(Error(ErrorKind::InvalidEnum(7), _)) => (),
+                other => panic!("unexpected {:?}", other),
+            }
+
+            // A default arm takes BLUE, but still not an invalid color
+            let (s, _) = shade::unpack(&mut &[0u8, 0, 0, 2, 0, 0, 0, 9][..]).unwrap();
+            assert_eq!(s, shade::default(9));
+            match shade::unpack(&mut &[0u8, 0, 0, 7, 0, 0, 0, 9][..]) {
+                Err(Error(ErrorKind::InvalidEnum(7), _)) => (),
+                other => panic!("unexpected {:?}", other),
+            }
+        }
+    "#;
+
+    if let Err(e) = build_test_with(name, spec, &GenOptions::new(), tests) {
+        panic!("test {} failed: {}", name, e);
+    }
+}
+
+#[test]
+fn box_large_variants() {
+    let name = "box_large_variants";
+    let spec = r#"
+        struct block { opaque data[32]; int id; };
+        union chunk switch (int kind) {
+            case 0: int small;
+            case 1: block large;
+            case 2: hyper blocks[4];
+            default: opaque rest[24];
+        };
+    "#;
+    let tests = r#"
+        use std::mem::size_of;
+        use xdr_codec::{pack, unpack};
+
+        #[test]
+        fn boxed() {
+            assert!(size_of::<chunk>() <= 2 * size_of::<usize>());
+
+            let b = block { data: [7; 32], id: 3 };
+            for c in vec![
+                chunk::Const0(1),
+                chunk::Const1(Box::new(b)),
+                chunk::Const2(Box::new([9; 4])),
+            ] {
+                let mut buf = Vec::new();
+                pack(&c, &mut buf).unwrap();
+                let c2: chunk = unpack(&mut &buf[..]).unwrap();
+                assert_eq!(c, c2);
+            }
+
+            let mut buf = vec![0, 0, 0, 5];
+            buf.extend_from_slice(&[1; 24]);
+            let c: chunk = unpack(&mut &buf[..]).unwrap();
+            assert_eq!(c, chunk::default(Box::new([1; 24])));
+        }
+    "#;
+
+    let opts = GenOptions::new().box_large_variants(16).trace(true).resumable(true);
+    if let Err(e) = build_test_with(name, spec, &opts, tests) {
+        panic!("test {} failed: {}", name, e);
+    }
+}
+
+#[test]
+fn metrics() {
+    let name = "metrics";
+    let spec = r#"
+        enum colour { RED = 1, GREEN = 2 };
+        struct point { int x; colour c; };
+        struct shape { point points<>; };
+    "#;
+    let tests = r#"
+        use std::sync::Mutex;
+        use xdr_codec::{pack, unpack, Error, Metrics};
+
+        struct Log(Mutex<Vec<String>>);
+
+        impl Metrics for Log {
+            fn on_pack(&self, ty: &str, bytes: usize) {
+                self.0.lock().unwrap().push(format!("pack {} {}", ty, bytes));
+            }
+            fn on_unpack(&self, ty: &str, bytes: usize) {
+                self.0.lock().unwrap().push(format!("unpack {} {}", ty, bytes));
+            }
+            fn on_unpack_error(&self, ty: &str, _err: &Error) {
+                self.0.lock().unwrap().push(format!("error {}", ty));
+            }
+        }
+
+        static LOG: Log = Log(Mutex::new(Vec::new()));
+
+        #[test]
+        fn counted() {
+            assert!(xdr_codec::set_metrics(&LOG));
+
+            let s = shape { points: vec![point { x: 1, c: colour::GREEN }] };
+            let mut buf = Vec::new();
+            pack(&s, &mut buf).unwrap();
+            let s2: shape = unpack(&mut &buf[..]).unwrap();
+            assert_eq!(s, s2);
+
+            buf[11] = 7;
+            assert!(unpack::<_, shape>(&mut &buf[..]).is_err());
+
+            assert_eq!(*LOG.0.lock().unwrap(), vec![
+                "pack colour 4", "pack point 8", "pack shape 12",
+                "unpack colour 4", "unpack point 8", "unpack shape 12",
+                "error colour", "error point", "error shape",
+            ]);
+        }
+    "#;
+
+    let opts = GenOptions::new().metrics(true).max_message_size(64);
+    if let Err(e) = build_test_with(name, spec, &opts, tests) {
+        panic!("test {} failed: {}", name, e);
+    }
+}
+
+#[test]
+fn flush_policy() {
+    let name = "flush_policy";
+    let spec = r#"
+        struct chunk {
+            opaque data<>;
+        };
+        struct upload {
+            chunk chunks<>;
+        };
+    "#;
+    let tests = r#"
+        use std::io::{self, Write};
+        use xdr_codec::flush::{FlushEvery, NeverFlush};
+
+        #[derive(Default)]
+        struct Recorder {
+            data: Vec<u8>,
+            flushes: Vec<usize>,
+        }
+
+        impl Write for Recorder {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.data.extend_from_slice(buf);
+                Ok(buf.len())
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                self.flushes.push(self.data.len());
+                Ok(())
+            }
+        }
+
+        fn msg() -> upload {
+            upload { chunks: (0..16).map(|_| chunk { data: vec![7; 1020] }).collect() }
+        }
+
+        #[test]
+        fn flushes() {
+            let mut out = Recorder::default();
+            let sz = msg().pack_with_policy(&mut out, FlushEvery(4096)).unwrap();
+            assert_eq!(sz, out.data.len());
+            assert_eq!(out.flushes, vec![4100, 8196, 12292, 16388]);
+            assert!(out.flushes.iter().zip(out.flushes.iter().skip(1)).all(|(a, b)| b - a >= 4096));
+
+            let mut plain = Vec::new();
+            xdr_codec::pack(&msg(), &mut plain).unwrap();
+            assert_eq!(plain, out.data);
+        }
+
+        #[test]
+        fn never() {
+            let mut out = Recorder::default();
+            msg().pack_with_policy(&mut out, NeverFlush).unwrap();
+            assert!(out.flushes.is_empty());
+        }
+    "#;
+
+    let opts = GenOptions::new().flush_policy(true);
+    if let Err(e) = build_test_with(name, spec, &opts, tests) {
+        panic!("test {} failed: {}", name, e);
+    }
+}
+
+#[test]
+fn deprecated() {
+    let name = "deprecated";
+    let spec = r#"
+        /* @deprecated use point3 */
+        struct point {
+            int x;
+            /* @deprecated always zero */
+            int y;
+        };
+        struct point3 {
+            int x;
+            int y;
+            int z;
+        };
+        /* @deprecated */
+        typedef point oldpoint;
+        struct shape {
+            point corners<>;
+        };
+    "#;
+    let tests = r#"
+        use xdr_codec::{pack, unpack};
+
+        // Generated code using the deprecated definitions doesn't warn, only uses outside it
+        #[test]
+        #[allow(deprecated)]
+        fn roundtrip() {
+            let s = shape { corners: vec![point { x: 1, y: 2 }] };
+            let mut buf = Vec::new();
+            pack(&s, &mut buf).unwrap();
+            let s2: shape = unpack(&mut &buf[..]).unwrap();
+            assert_eq!(s, s2);
+            let _: oldpoint = s2.corners[0].clone();
+        }
+    "#;
+
+    if let Err(e) = build_test_with(name, spec, &GenOptions::new(), tests) {
+        panic!("test {} failed: {}", name, e);
+    }
+}
+
+#[test]
+fn rust_type() {
+    let name = "rust_type";
+    let spec = r#"
+        struct event {
+            /* @rust_type Timestamp */
+            unsigned hyper when;
+            /* @rust_type Vec<Tag> */
+            string tags<8>;
+            int count;
+        };
+    "#;
+    let tests = r#"
+        use xdr_codec::{pack, unpack, Decode, Progress, UnpackTraced, Validate};
+
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        pub struct Timestamp { secs: u32, nanos: u32 }
+
+        impl RustType<u64> for Timestamp {
+            fn from_xdr(x: u64) -> xdr_codec::Result<Timestamp> {
+                if x as u32 >= 1_000_000_000 {
+                    return Err("bad nanos".into());
+                }
+                Ok(Timestamp { secs: (x >> 32) as u32, nanos: x as u32 })
+            }
+            fn to_xdr(&self) -> u64 {
+                (self.secs as u64) << 32 | self.nanos as u64
+            }
+        }
+
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        pub struct Tag(String);
+
+        impl RustType<String> for Vec<Tag> {
+            fn from_xdr(x: String) -> xdr_codec::Result<Vec<Tag>> {
+                Ok(x.split(',').filter(|t| !t.is_empty()).map(|t| Tag(t.to_string())).collect())
+            }
+            fn to_xdr(&self) -> String {
+                self.iter().map(|t| t.0.as_str()).collect::<Vec<_>>().join(",")
+            }
+        }
+
+        #[test]
+        fn roundtrip() {
+            let e = event {
+                when: Timestamp { secs: 5, nanos: 7 },
+                tags: vec![Tag("a".into()), Tag("bc".into())],
+                count: 3,
+            };
+            let mut buf = Vec::new();
+            pack(&e, &mut buf).unwrap();
+            // The fields are encoded as their XDR types
+            assert_eq!(&buf[..16], &[0, 0, 0, 5, 0, 0, 0, 7, 0, 0, 0, 4, b'a', b',', b'b', b'c']);
+            let e2: event = unpack(&mut &buf[..]).unwrap();
+            assert_eq!(e, e2);
+
+            buf[4] = 0xff;
+            assert!(unpack::<_, event>(&mut &buf[..]).is_err());
+        }
+
+        #[test]
+        fn other_codecs() {
+            let e = event { when: Timestamp { secs: 5, nanos: 7 }, tags: vec![Tag("a".into())], count: 3 };
+            let mut buf = Vec::new();
+            pack(&e, &mut buf).unwrap();
+
+            // The fields are traced as their XDR types
+            let mut names = Vec::new();
+            let (t, _) = event::unpack_traced(
+                &mut &buf[..],
+                0,
+                &mut |name: &str, _: usize, v: &dyn std::fmt::Debug| names.push(format!("{}={:?}", name, v)),
+            ).unwrap();
+            assert_eq!(t, e);
+            assert_eq!(names, ["when=21474836487", "tags=\"a\"", "count=3"]);
+
+            assert_eq!(eventDecoder::default().feed(&mut &buf[..]).unwrap(), Progress::Complete(e.clone()));
+            buf[4] = 0xff;
+            assert!(eventDecoder::default().feed(&mut &buf[..]).is_err());
+
+            // Bounds apply to the XDR encoding
+            assert!(e.validate().is_ok());
+            let long = event { tags: vec![Tag("abcd".into()), Tag("efgh".into())], ..e };
+            assert!(long.validate().is_err());
+        }
+    "#;
+    #[cfg(feature = "json")]
+    let tests = &format!(
+        "{}{}",
+        tests,
+        r##"
+        #[test]
+        fn json() {
+            let e = event { when: Timestamp { secs: 5, nanos: 7 }, tags: vec![Tag("a".into())], count: 3 };
+            assert_eq!(e.to_json(), r#"{"when":21474836487,"tags":"a","count":3}"#);
+            assert_eq!(event::from_json(&e.to_json()).unwrap(), e);
+            assert!(event::from_json(r#"{"when":4294967295,"tags":"","count":3}"#).is_err());
+        }
+    "##
+    );
+
+    let opts = GenOptions::new().trace(true).resumable(true).emit_validate(true);
+    if let Err(e) = build_test_with(name, spec, &opts, &with_serde_derives(tests)) {
+        panic!("test {} failed: {}", name, e);
+    }
+}
+
+#[test]
+fn validate() {
+    let name = "validate";
+    let spec = r#"
+        const MAXNAME = 8;
+        typedef string name<MAXNAME>;
+        enum kind { FILE = 1, DIR = 2 };
+        struct entry {
+            name n;
+            kind k;
+            opaque cookie<4>;
+            entry *next;
+        };
+        union lookup switch (kind k) {
+            case FILE: entry file;
+            case DIR: entry children<2>;
+        };
+    "#;
+    let tests = r#"
+        use xdr_codec::{Error, ErrorKind, Validate};
+
+        fn entry(n: &str, cookie: usize, next: Option<Box<entry>>) -> entry {
+            entry { n: name(n.into()), k: kind::FILE, cookie: vec![0; cookie], next }
+        }
+
+        fn is_invalidlen(res: xdr_codec::Result<()>, len: usize) -> bool {
+            match res {
+                Err(Error(ErrorKind::InvalidLen(l), _)) => l == len,
+                _ => false,
+            }
+        }
+
+        #[test]
+        fn valid() {
+            entry("short", 4, Some(Box::new(entry("other", 0, None)))).validate().unwrap();
+            lookup::DIR(vec![entry("a", 1, None), entry("b", 2, None)]).validate().unwrap();
+        }
+
+        #[test]
+        fn over_bound() {
+            assert!(is_invalidlen(name("much too long".into()).validate(), 8));
+            assert!(is_invalidlen(entry("ok", 5, None).validate(), 4));
+
+            // Nested values are checked
+            let nested = entry("ok", 0, Some(Box::new(entry("much too long", 0, None))));
+            assert!(is_invalidlen(nested.validate(), 8));
+            assert!(is_invalidlen(lookup::FILE(entry("ok", 5, None)).validate(), 4));
+            let dirs = lookup::DIR(vec![entry("a", 0, None), entry("b", 0, None), entry("c", 0, None)]);
+            assert!(is_invalidlen(dirs.validate(), 2));
+
+            // Agrees with pack
+            assert!(xdr_codec::pack(&nested, &mut Vec::new()).is_err());
+        }
+    "#;
+
+    let opts = GenOptions::new().emit_validate(true);
+    if let Err(e) = build_test_with(name, spec, &opts, tests) {
+        panic!("test {} failed: {}", name, e);
+    }
+}
+
+#[test]
+fn enum_names() {
+    let name = "enum_names";
+    let spec = r#"
+        enum color { RED = 1, GREEN = 2, BLUE = 4 };
+        enum keyword { type = 0, fn = 1 };
+    "#;
+    let tests = r#"
+        const NAMES: [&str; 3] = [color::RED.name(), color::GREEN.name(), color::BLUE.name()];
+
+        #[test]
+        fn names() {
+            assert_eq!(NAMES, ["RED", "GREEN", "BLUE"]);
+            assert_eq!(keyword::type_.name(), "type");
+            assert_eq!(keyword::fn_.name(), "fn");
+        }
+    "#;
+
+    let opts = GenOptions::new().enum_names(true);
+    if let Err(e) = build_test_with(name, spec, &opts, tests) {
+        panic!("test {} failed: {}", name, e);
+    }
+}
+
+#[test]
+fn unsigned_enums() {
+    let name = "unsigned_enums";
+    let spec = r#"
+        enum status { OK = 0, ACCESS_VIOLATION = 0xC0000005 };
+        enum delta { DOWN = -1, UP = 1 };
+        struct reply { status st; delta d; };
+        union result switch (status s) {
+            case OK: int v;
+            case ACCESS_VIOLATION: void;
+        };
+    "#;
+    let tests = r#"
+        use xdr_codec::{Pack, Unpack};
+
+        #[test]
+        fn large_value() {
+            assert_eq!(std::mem::size_of::<status>(), 4);
+            assert_eq!(status::ACCESS_VIOLATION as u32, 0xC0000005);
+            assert_eq!(delta::DOWN as i32, -1);
+
+            let mut buf = Vec::new();
+            let r = reply { st: status::ACCESS_VIOLATION, d: delta::DOWN };
+            assert_eq!(r.pack(&mut buf).unwrap(), 8);
+            assert_eq!(buf, [0xc0, 0x00, 0x00, 0x05, 0xff, 0xff, 0xff, 0xff]);
+
+            let (r, sz) = reply::unpack(&mut &buf[..]).unwrap();
+            assert_eq!(sz, 8);
+            assert_eq!(r.st, status::ACCESS_VIOLATION);
+            assert_eq!(r.d, delta::DOWN);
+
+            let (r, _) = result::unpack(&mut &[0xc0, 0, 0, 5][..]).unwrap();
+            assert!(matches!(r, result::ACCESS_VIOLATION));
+
+            assert!(status::unpack(&mut &[0, 0, 0, 1][..]).is_err());
+        }
+    "#;
+
+    let opts = GenOptions::new().unsigned_enums(true);
+    if let Err(e) = build_test_with(name, spec, &opts, tests) {
+        panic!("test {} failed: {}", name, e);
+    }
+}
+
+#[test]
+fn trace() {
+    let name = "trace";
+    let spec = r#"
+        struct point { int x; int y; };
+        union shape switch (int kind) {
+            case 0: void;
+            case 1: point centre;
+            default: opaque data<>;
+        };
+        struct drawing { string title<>; shape shapes<>; shape last; };
+        struct big { int a; int b; };
+        union msg2 switch (int k) {
+            case 0: int a;
+            default: big d;
+        };
+    "#;
+    let tests = r#"
+        use std::fmt::Debug;
+        use xdr_codec::{Pack, Trace, Unpack, UnpackTraced};
+
+        #[derive(Default)]
+        struct Log(Vec<String>, String);
+
+        impl Trace for Log {
+            fn field(&mut self, name: &str, offset: usize, value: &dyn Debug) {
+                self.0.push(format!("{}{}@{} = {:?}", self.1, name, offset, value));
+            }
+            fn enter(&mut self, name: &str, _offset: usize) {
+                self.1 += name;
+                self.1 += ".";
+            }
+            fn leave(&mut self) {
+                let len = self.1[..self.1.len() - 1].rfind('.').map_or(0, |i| i + 1);
+                self.1.truncate(len);
+            }
+        }
+
+        #[test]
+        fn decode_log() {
+            let d = drawing {
+                title: "x".into(),
+                shapes: vec![shape::Const0],
+                last: shape::Const1(point { x: 1, y: -2 }),
+            };
+            let mut buf = Vec::new();
+            d.pack(&mut buf).unwrap();
+
+            let mut log = Log::default();
+            let (t, sz) = drawing::unpack_traced(&mut &buf[..], 0, &mut log).unwrap();
+            assert_eq!(sz, buf.len());
+            assert_eq!(t.last, drawing::unpack(&mut &buf[..]).unwrap().0.last);
+            assert_eq!(
+                log.0,
+                [
+                    "title@0 = \"x\"",
+                    "shapes@8 = [Const0]",
+                    "last.kind@16 = 1",
+                    "last.centre.x@20 = 1",
+                    "last.centre.y@24 = -2",
+                ]
+            );
+        }
+
+        #[test]
+        fn closure() {
+            let mut names = Vec::new();
+            let (_, sz) = point::unpack_traced(
+                &mut &[0, 0, 0, 1, 0, 0, 0, 2][..],
+                100,
+                &mut |name: &str, offset: usize, _: &dyn Debug| names.push((name.to_string(), offset)),
+            ).unwrap();
+            assert_eq!(sz, 8);
+            assert_eq!(names, [("x".to_string(), 100), ("y".to_string(), 104)]);
+        }
+
+        #[test]
+        fn boxed_default() {
+            let buf = [0, 0, 0, 7, 0, 0, 0, 1, 0, 0, 0, 2];
+
+            let mut log = Log::default();
+            let (t, sz) = msg2::unpack_traced(&mut &buf[..], 0, &mut log).unwrap();
+            assert_eq!(sz, 12);
+            assert_eq!(t, msg2::default(Box::new(big { a: 1, b: 2 })));
+            assert_eq!(log.0, ["k@0 = 7", "d.a@4 = 1", "d.b@8 = 2"]);
+        }
+    "#;
+
+    let opts = GenOptions::new().trace(true);
+    if let Err(e) = build_test_with(name, spec, &opts, tests) {
+        panic!("test {} failed: {}", name, e);
+    }
+}
+
+#[test]
+fn float_arrays() {
+    let name = "float_arrays";
+    let spec = r#"
+        typedef double vec3[3];
+        typedef float samples<4>;
+        struct floats {
+            float f[4];
+            double d<>;
+            vec3 v;
+            samples s;
+            bool tail;
+        };
+    "#;
+    let tests = r#"
+        use xdr_codec::{Pack, Unpack};
+
+        #[test]
+        fn round_trip() {
+            let v = floats {
+                f: [1.0, -2.5, f32::MAX, f32::MIN_POSITIVE],
+                d: vec![0.1, -1e300],
+                v: vec3([1.5, 2.5, -3.5]),
+                s: samples(vec![0.5; 3]),
+                tail: true,
+            };
+
+            let mut buf = Vec::new();
+            let sz = v.pack(&mut buf).unwrap();
+            assert_eq!(sz, 4 * 4 + (4 + 2 * 8) + 3 * 8 + (4 + 3 * 4) + 4);
+            assert_eq!(buf.len(), sz);
+
+            // Elements are packed back to back, big-endian
+            assert_eq!(&buf[0..8], &[0x3f, 0x80, 0, 0, 0xc0, 0x20, 0, 0]);
+            assert_eq!(&buf[16..20], &[0, 0, 0, 2]);
+            assert_eq!(&buf[20..28], &0.1f64.to_bits().to_be_bytes());
+            assert_eq!(&buf[sz - 4..], &[0, 0, 0, 1]);
+
+            let (u, usz) = floats::unpack(&mut &buf[..]).unwrap();
+            assert_eq!(usz, sz);
+            assert_eq!(u, v);
+        }
+
+        #[test]
+        fn bounded() {
+            let mut buf = Vec::new();
+            samples(vec![0.0; 5]).pack(&mut buf).unwrap_err();
+        }
+    "#;
+
+    if let Err(e) = build_test_with(name, spec, &GenOptions::new(), tests) {
+        panic!("test {} failed: {}", name, e);
+    }
+}
+
+#[test]
+fn array_helpers() {
+    let name = "array_helpers";
+    let spec = r#"
+        struct foo { int a; string b<>; };
+        enum color { RED = 1, GREEN = 2 };
+    "#;
+    let tests = r#"
+        #[test]
+        fn round_trip() {
+            let v = vec![foo { a: 1, b: "x".into() }, foo { a: 2, b: "yz".into() }];
+
+            let mut buf = Vec::new();
+            let sz = foo::pack_array(&v, &mut buf).unwrap();
+            assert_eq!(sz, 4 + 2 * 12);
+            assert_eq!(&buf[..4], &[0, 0, 0, 2]);
+
+            let (u, usz) = foo::unpack_array(&mut &buf[..]).unwrap();
+            assert_eq!(usz, sz);
+            assert_eq!(u, v);
+
+            let mut buf = Vec::new();
+            color::pack_array(&[], &mut buf).unwrap();
+            assert_eq!(color::unpack_array(&mut &buf[..]).unwrap(), (vec![], 4));
+        }
+    "#;
+
+    let opts = GenOptions::new().array_helpers(true);
+    if let Err(e) = build_test_with(name, spec, &opts, tests) {
+        panic!("test {} failed: {}", name, e);
+    }
+}
+
+#[test]
+fn content_hash() {
+    let name = "content_hash";
+    let spec = r#"
+        struct entry { string name<>; opaque data<>; unsigned hyper size; };
+        union item switch (int kind) {
+            case 0: entry e;
+            case 1: void;
+        };
+        typedef string short<2>;
+    "#;
+    let tests = r#"
+        fn mk(name: &str) -> entry {
+            entry { name: name.into(), data: vec![1, 2, 3], size: 3 }
+        }
+
+        #[test]
+        fn equal() {
+            let a = mk("a");
+            let mut b = mk("b");
+            assert_eq!(a.content_hash().unwrap(), a.clone().content_hash().unwrap());
+            assert_ne!(a.content_hash().unwrap(), b.content_hash().unwrap());
+
+            // Built differently, but the same value
+            b.name = String::with_capacity(100);
+            b.name.push('a');
+            b.data.reserve(100);
+            assert_eq!(a.content_hash().unwrap(), b.content_hash().unwrap());
+
+            assert_eq!(item::Const0(a).content_hash().unwrap(), item::Const0(b).content_hash().unwrap());
+            assert_ne!(item::Const1.content_hash().unwrap(), item::Const0(mk("")).content_hash().unwrap());
+        }
+
+        #[test]
+        fn unpackable() {
+            assert!(short("abc".into()).content_hash().is_err());
+        }
+    "#;
+
+    let opts = GenOptions::new().content_hash(true);
+    if let Err(e) = build_test_with(name, spec, &opts, tests) {
+        panic!("test {} failed: {}", name, e);
+    }
+}
+
+#[test]
+fn address_types() {
+    let name = "address_types";
+    let spec = r#"
+        typedef opaque ip4[4];
+        typedef opaque ip6[16];
+        typedef unsigned int ip4_int;
+        struct host { unsigned int addr; };
+        struct peer { ip4 addr; unsigned int port; };
+    "#;
+    let tests = r#"
+        use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+        use xdr_codec::Unpack;
+
+        #[test]
+        fn convert() {
+            let (p, _) = peer::unpack(&mut &[192, 168, 0, 1, 0, 0, 0, 80][..]).unwrap();
+            let addr: Ipv4Addr = p.addr.into();
+            assert_eq!(addr, Ipv4Addr::new(192, 168, 0, 1));
+            assert_eq!(IpAddr::from(addr).to_string(), "192.168.0.1");
+            assert_eq!(ip4::from(addr), p.addr);
+
+            let (h, _) = host::unpack(&mut &[10, 0, 0, 2][..]).unwrap();
+            assert_eq!(Ipv4Addr::from(h), Ipv4Addr::new(10, 0, 0, 2));
+            assert_eq!(host::from(Ipv4Addr::new(10, 0, 0, 2)), h);
+
+            let i: ip4_int = Ipv4Addr::LOCALHOST.into();
+            assert_eq!(i, 0x7f000001);
+
+            let v6 = ip6::from(Ipv6Addr::LOCALHOST);
+            assert_eq!(v6.0[15], 1);
+            assert_eq!(Ipv6Addr::from(v6), Ipv6Addr::LOCALHOST);
+        }
+    "#;
+
+    let opts = GenOptions::new().address_types(&[
+        ("ip4", AddrForm::Ipv4),
+        ("ip6", AddrForm::Ipv6),
+        ("ip4_int", AddrForm::Ipv4),
+        ("host", AddrForm::Ipv4),
+    ]);
+    if let Err(e) = build_test_with(name, spec, &opts, tests) {
+        panic!("test {} failed: {}", name, e);
+    }
+
+    let opts = GenOptions::new().address_types(&[("peer", AddrForm::Ipv4)]);
+    let err = generate_with_options("x", Cursor::new(spec.as_bytes()), Vec::new(), &opts).unwrap_err();
+    assert_eq!(err.to_string(), "address type peer: can't be converted to Ipv4");
+}
+
+#[test]
+fn feature_gate() {
+    let name = "feature_gate";
+    let spec = r#"
+        /* @feature vendor_ext */
+        const EXT_MAX = 4;
+        /* @feature vendor_ext */
+        enum ext_kind { EXT_A = 1 };
+        struct ext { ext_kind k; int v<EXT_MAX>; };
+        typedef ext exts<>;
+        struct plain { int b; };
+    "#;
+    let tests = r#"
+        // Without the feature, only the ungated definitions exist
+        #[test]
+        fn ungated() {
+            let _ = plain { b: 1 };
+        }
+    "#;
+
+    let opts = GenOptions::new().emit_validate(true).union_helpers(true);
+    if let Err(e) = build_test_with(name, spec, &opts, tests) {
+        panic!("test {} failed: {}", name, e);
+    }
+}
+
+#[test]
+fn enum_default() {
+    let name = "enum_default";
+    let spec = r#"
+        enum color { RED = 1, GREEN = 2, BLUE = 4 };
+        enum mode { OFF = 0, ON = 1 };
+        typedef int other;
+    "#;
+    let tests = r#"
+        #[derive(Default)]
+        struct settings { c: color, m: mode }
+
+        #[test]
+        fn default() {
+            assert_eq!(color::default(), color::GREEN);
+            let s = settings::default();
+            assert_eq!((s.c, s.m), (color::GREEN, mode::OFF));
+        }
+    "#;
+
+    let opts = GenOptions::new()
+        .enum_default("color", "GREEN")
+        .enum_default("mode", "OFF");
+    if let Err(e) = build_test_with(name, spec, &opts, tests) {
+        panic!("test {} failed: {}", name, e);
+    }
+
+    for &(ty, value, msg) in &[
+        ("color", "PURPLE", "enum default: color has no value PURPLE"),
+        ("other", "X", "enum default: other is not an enum"),
+    ] {
+        let opts = GenOptions::new().enum_default(ty, value);
+        let err = generate_with_options("x", Cursor::new(spec.as_bytes()), Vec::new(), &opts).unwrap_err();
+        assert_eq!(err.to_string(), msg);
+    }
+}
+
+#[test]
+fn unknown_enum() {
+    let spec = r#"
+        enum color { RED = 1, GREEN = 2, BLUE = -4 };
+        struct pixel { color c; int x; };
+        union paint switch (color c) {
+            case RED: int red;
+            case GREEN: void;
+        };
+    "#;
+    let common = r#"
+        use xdr_codec::{pack, unpack};
+
+        fn pixel_with(c: i32) -> Vec<u8> {
+            let mut buf = Vec::new();
+            pack(&c, &mut buf).unwrap();
+            pack(&3i32, &mut buf).unwrap();
+            buf
+        }
+
+        #[test]
+        fn known() {
+            for c in [color::RED, color::GREEN, color::BLUE] {
+                let p = pixel { c, x: 3 };
+                let mut buf = Vec::new();
+                pack(&p, &mut buf).unwrap();
+                assert_eq!(unpack::<_, pixel>(&mut &buf[..]).unwrap(), p);
+            }
+            assert_eq!(pixel_with(-4), { let mut b = Vec::new(); pack(&color::BLUE, &mut b).unwrap(); pack(&3i32, &mut b).unwrap(); b });
+
+            // Unions have no arm for unknown values whatever the policy
+            let mut buf = Vec::new();
+            pack(&9i32, &mut buf).unwrap();
+            assert!(unpack::<_, paint>(&mut &buf[..]).is_err());
+        }
+    "#;
+
+    let error = r#"
+        #[test]
+        fn error() {
+            match unpack::<_, pixel>(&mut &pixel_with(9)[..]) {
+                Err(xdr_codec::Error(xdr_codec::ErrorKind::InvalidEnum(9), _)) => (),
+                other => panic!("unexpected {:?}", other),
+            }
+        }
+    "#;
+    let opts = GenOptions::new().unknown_enum(UnknownEnum::Error);
+    let tests = format!("{}{}", common, error);
+    if let Err(e) = build_test_with("unknown_enum_error", spec, &opts, &tests) {
+        panic!("test unknown_enum_error failed: {}", e);
+    }
+
+    let preserve = r#"
+        #[test]
+        fn preserve() {
+            let buf = pixel_with(9);
+            let p: pixel = unpack(&mut &buf[..]).unwrap();
+            assert_eq!(p, pixel { c: color::Unknown(9), x: 3 });
+            assert_eq!(p.c.name(), "unknown");
+            assert_eq!(color::BLUE.name(), "BLUE");
+
+            let mut buf2 = Vec::new();
+            pack(&p, &mut buf2).unwrap();
+            assert_eq!(buf, buf2);
+        }
+    "#;
+    let opts = GenOptions::new()
+        .unknown_enum(UnknownEnum::Preserve)
+        .enum_names(true)
+        .trace(true)
+        .resumable(true);
+    let tests = format!("{}{}", common, preserve);
+    if let Err(e) = build_test_with("unknown_enum_preserve", spec, &opts, &tests) {
+        panic!("test unknown_enum_preserve failed: {}", e);
+    }
+
+    let clamp = r#"
+        #[test]
+        fn clamp() {
+            let p: pixel = unpack(&mut &pixel_with(9)[..]).unwrap();
+            assert_eq!(p, pixel { c: color::GREEN, x: 3 });
+        }
+    "#;
+    let opts = GenOptions::new().unknown_enum(UnknownEnum::Clamp).enum_default("color", "GREEN");
+    let tests = format!("{}{}", common, clamp);
+    if let Err(e) = build_test_with("unknown_enum_clamp", spec, &opts, &tests) {
+        panic!("test unknown_enum_clamp failed: {}", e);
+    }
+
+    let opts = GenOptions::new().unknown_enum(UnknownEnum::Clamp);
+    let err = generate_with_options("x", Cursor::new(spec.as_bytes()), Vec::new(), &opts).unwrap_err();
+    assert_eq!(err.to_string(), "enum color: UnknownEnum::Clamp needs a default set with enum_default");
+
+    let opts = GenOptions::new().unknown_enum(UnknownEnum::Preserve);
+    let spec = "enum status { OK = 0, Unknown = 1 };";
+    let err = generate_with_options("x", Cursor::new(spec.as_bytes()), Vec::new(), &opts).unwrap_err();
+    assert_eq!(err.to_string(), "enum status: value Unknown clashes with UnknownEnum::Preserve");
+}
+
+#[cfg(feature = "derive_serde")]
+#[test]
+fn serde_rename_all() {
+    let name = "serde_rename_all";
+    let spec = r#"
+        enum link_state { LINK_UP = 1, LINK_DOWN = 2 };
+        struct link_info {
+            unsigned int link_id;
+            link_state current_state;
+            int type;
+        };
+    "#;
+    let tests = r##"
+        #[test]
+        fn keys() {
+            let info = link_info { link_id: 7, current_state: link_state::LINK_DOWN, type_: -1 };
+            let json = serde_json::to_string(&info).unwrap();
+            // `type_` keeps its explicit rename to the specification's name
+            assert_eq!(json, r#"{"linkId":7,"currentState":"LINK_DOWN","type":-1}"#);
+            assert_eq!(serde_json::from_str::<link_info>(&json).unwrap(), info);
+        }
+    "##;
+
+    let opts = GenOptions::new().serde_rename_all("camelCase");
+    let mut code = Vec::new();
+    generate_with_options(name, Cursor::new(spec.as_bytes()), &mut code, &opts).unwrap();
+    let code = String::from_utf8(code).unwrap();
+    assert_eq!(code.matches("# [serde (rename_all = \"camelCase\")]").count(), 1, "{}", code);
+
+    if let Err(e) = build_test_with(name, spec, &opts, tests) {
+        panic!("test {} failed: {}", name, e);
+    }
+
+    let opts = GenOptions::new().serde_rename_all("CamelCase");
+    let err = generate_with_options(name, Cursor::new(spec.as_bytes()), Vec::new(), &opts).unwrap_err();
+    assert_eq!(err.to_string(), "serde rename_all: unknown case \"CamelCase\"");
+}
+
+#[cfg(feature = "derive_serde")]
+#[test]
+fn serde_default() {
+    let name = "serde_default";
+    let spec = r#"
+        struct point { int x; int y; };
+        typedef point *maybe_point;
+        struct shape {
+            string name<>;
+            point *origin;
+            maybe_point centre;
+            point corners<4>;
+        };
+    "#;
+    let tests = r##"
+        #[test]
+        fn missing() {
+            let s: shape = serde_json::from_str(r#"{"name":"dot"}"#).unwrap();
+            assert_eq!(s, shape { name: "dot".to_string(), origin: None, centre: None, corners: vec![] });
+
+            let s: shape = serde_json::from_str(r#"{"name":"dot","origin":{"x":1,"y":2}}"#).unwrap();
+            assert_eq!(s.origin, Some(Box::new(point { x: 1, y: 2 })));
+
+            // Not for required fields
+            assert!(serde_json::from_str::<shape>("{}").is_err());
+        }
+    "##;
+
+    let opts = GenOptions::new().serde_default(true);
+    let mut code = Vec::new();
+    generate_with_options(name, Cursor::new(spec.as_bytes()), &mut code, &opts).unwrap();
+    let code = String::from_utf8(code).unwrap();
+    assert_eq!(code.matches("# [serde (default)]").count(), 3, "{}", code);
+
+    if let Err(e) = build_test_with(name, spec, &opts, tests) {
+        panic!("test {} failed: {}", name, e);
+    }
+}
+
+#[test]
+fn ffi_mirror() {
+    let name = "ffi_mirror";
+    let spec = r#"
+        struct point { int x; hyper y; bool b; };
+        typedef point corner;
+        struct rect { corner corners[2]; opaque tag[3]; unsigned int flags; };
+        struct named { string name<>; point p; };
+    "#;
+    let tests = r#"
+        use std::mem::{align_of, size_of};
+
+        fn rect() -> rect {
+            let p = |x| point { x: x, y: -(x as i64), b: x > 0 };
+            rect { corners: [p(1), p(-2)], tag: *b"abc", flags: 7 }
+        }
+
+        #[test]
+        fn layout() {
+            assert_eq!(size_of::<point_ffi>(), 24);
+            assert_eq!(size_of::<rect_ffi>(), 56);
+            assert_eq!(align_of::<rect_ffi>(), 8);
+        }
+
+        #[test]
+        fn conversions() {
+            let r = rect_ffi::from(rect());
+            assert_eq!(r.corners[1], point_ffi { x: -2, y: 2, b: false });
+            assert_eq!(&r.tag, b"abc");
+            assert_eq!(r.flags, 7);
+            assert_eq!(rect::from(r), rect());
+        }
+    "#;
+
+    let opts = GenOptions::new().ffi_mirror(true);
+    if let Err(e) = build_test_with(name, spec, &opts, tests) {
+        panic!("test {} failed: {}", name, e);
+    }
+}
+
+#[test]
+fn catch_panics() {
+    let name = "catch_panics";
+    let spec = r#"
+        enum kind { A = 1, B = 2 };
+        struct msg { kind k; string name<4>; };
+    "#;
+    let tests = r#"
+        use xdr_codec::{Error, ErrorKind, unpack};
+
+        #[test]
+        fn unchanged() {
+            let m: msg = unpack(&mut &[0, 0, 0, 2, 0, 0, 0, 1, b'x', 0, 0, 0][..]).unwrap();
+            assert_eq!(m, msg { k: kind::B, name: "x".into() });
+
+            match unpack::<_, msg>(&mut &[0, 0, 0, 3][..]) {
+                Err(Error(ErrorKind::InvalidEnum(3), _)) => (),
+                other => panic!("unexpected {:?}", other),
+            }
+        }
+    "#;
+
+    let opts = GenOptions::new().catch_panics(true);
+    if let Err(e) = build_test_with(name, spec, &opts, tests) {
+        panic!("test {} failed: {}", name, e);
+    }
+}
+
+#[test]
+fn scratch_packers() {
+    let name = "scratch_packers";
+    let spec = r#"
+        struct sample { unsigned int id; opaque data<>; };
+        struct batch { sample samples<>; };
+    "#;
+    let tests = r#"
+        use xdr_codec::pack;
+
+        #[test]
+        fn reuses_buffer() {
+            let msg = |n| batch { samples: (0..n).map(|i| sample { id: i, data: vec![7; 100] }).collect() };
+
+            let mut scratch = Vec::new();
+            msg(10).pack_with_scratch(&mut scratch).unwrap();
+            let (ptr, cap) = (scratch.as_ptr(), scratch.capacity());
+
+            for n in 0..1000 {
+                let m = msg(n % 10);
+                m.pack_with_scratch(&mut scratch).unwrap();
+                let mut fresh = Vec::new();
+                pack(&m, &mut fresh).unwrap();
+                assert_eq!(scratch, fresh);
+            }
+            assert_eq!((scratch.as_ptr(), scratch.capacity()), (ptr, cap));
+        }
+    "#;
+
+    let opts = GenOptions::new().scratch_packers(true);
+    if let Err(e) = build_test_with(name, spec, &opts, tests) {
+        panic!("test {} failed: {}", name, e);
+    }
+}
+
+#[test]
+fn zero_copy() {
+    let name = "zero_copy";
+    let spec = r#"
+        enum kind { FILE = 1, DIR = 2, LINK = 3 };
+        typedef string filename<255>;
+        typedef string tag<>;
+        struct stat { unsigned hyper size; kind k; };
+        struct entry {
+            filename name;
+            opaque cookie<8>;
+            stat st;
+            tag tags[2];
+            entry *next;
+        };
+        union target switch (kind k) {
+            case LINK: string path<>;
+            case DIR: entry children<>;
+            case FILE: void;
+        };
+        union info switch (int v) {
+            case 0: stat st;
+            default: entry e;
+        };
+    "#;
+    let tests = r#"
+        use xdr_codec::{pack, unpack, unpack_borrowed, Pack};
+
+        // Recursive types have no derives, so compare encodings
+        fn packed<T: Pack<Vec<u8>>>(v: &T) -> Vec<u8> {
+            let mut buf = Vec::new();
+            pack(v, &mut buf).unwrap();
+            buf
+        }
+
+        fn listing() -> entry {
+            let e = |n: &str, next| entry {
+                name: filename(n.to_string()),
+                cookie: vec![1, 2, 3],
+                st: stat { size: 42, k: kind::FILE },
+                tags: [tag(n.to_uppercase()), tag(String::new())],
+                next,
+            };
+            e("a", Some(Box::new(e("bb", None))))
+        }
+
+        #[test]
+        fn borrows() {
+            let mut buf = Vec::new();
+            pack(&listing(), &mut buf).unwrap();
+            let range = buf.as_ptr_range();
+
+            let v: entry_ref = unpack_borrowed(&buf).unwrap();
+            assert_eq!(v.name.0, "a");
+            assert!(range.contains(&v.name.0.as_ptr()));
+            assert_eq!(v.cookie, &[1, 2, 3]);
+            assert!(range.contains(&v.cookie.as_ptr()));
+            assert_eq!(v.tags.clone().map(|t| t.0), ["A", ""]);
+            assert_eq!(v.st, stat { size: 42, k: kind::FILE });
+            assert_eq!(v.next.as_ref().unwrap().name.0, "bb");
+
+            let owned: entry = unpack(&mut &buf[..]).unwrap();
+            assert_eq!(packed(&v.into_owned()), packed(&owned));
+        }
+
+        #[test]
+        fn unions() {
+            for t in vec![
+                target::LINK("/x".to_string()),
+                target::DIR(vec![listing(), listing()]),
+                target::FILE,
+            ] {
+                let mut buf = Vec::new();
+                pack(&t, &mut buf).unwrap();
+                let v: target_ref = unpack_borrowed(&buf).unwrap();
+                if let target_ref::LINK(p) = v {
+                    assert_eq!(p, "/x");
+                }
+                assert_eq!(packed(&v.into_owned()), buf);
+            }
+
+            // Any other discriminant selects the default arm
+            let mut buf = packed(&5i32);
+            buf.extend(packed(&listing()));
+            match unpack_borrowed::<info_ref>(&buf).unwrap().into_owned() {
+                info::default(e) => assert_eq!(packed(&*e), &buf[4..]),
+                _ => panic!("not the default arm"),
+            }
+        }
+
+        #[test]
+        fn non_borrowing() {
+            // Types without anything to borrow decode as themselves
+            let s = stat { size: 1, k: kind::DIR };
+            let mut buf = Vec::new();
+            pack(&s, &mut buf).unwrap();
+            assert_eq!(unpack_borrowed::<stat>(&buf).unwrap(), s);
+            assert_eq!(unpack_borrowed::<kind>(&buf[8..]).unwrap(), kind::DIR);
+        }
+
+        #[test]
+        fn limits() {
+            // Make the cookie longer than its bound of 8
+            let mut buf = packed(&listing());
+            assert!(unpack_borrowed::<entry_ref>(&buf).is_ok());
+            assert!(unpack_borrowed::<entry_ref>(&buf[..buf.len() - 1]).is_err());
+            buf[11] = 9;
+            assert!(unpack_borrowed::<entry_ref>(&buf).is_err());
+        }
+    "#;
+
+    let opts = GenOptions::new().zero_copy(true);
+    if let Err(e) = build_test_with(name, spec, &opts, tests) {
+        panic!("test {} failed: {}", name, e);
+    }
+}
+
+#[test]
+fn dispatch_map() {
+    let name = "dispatch_map";
+    let spec = r#"
+        struct ping { unsigned hyper seq; };
+        struct chat { string from<>; string text<>; };
+        typedef chat chat_alias;
+        enum level { LOW = 1, HIGH = 2 };
+    "#;
+    let tests = r#"
+        use xdr_codec::pack;
+
+        #[test]
+        fn decode_by_tag() {
+            let map = dispatch_map();
+            assert_eq!(map.len(), 3);
+
+            let mut buf = Vec::new();
+            pack(&ping { seq: 9 }, &mut buf).unwrap();
+            let v = map[&1](&mut &buf[..]).unwrap();
+            assert_eq!(v.downcast_ref::<ping>().unwrap().seq, 9);
+
+            let mut buf = Vec::new();
+            pack(&chat { from: "a".into(), text: "hi".into() }, &mut buf).unwrap();
+            let v = map[&20](&mut &buf[..]).unwrap();
+            assert!(v.downcast_ref::<ping>().is_none());
+            assert_eq!(v.downcast::<chat>().unwrap().text, "hi");
+
+            let v = map[&3](&mut &[0u8, 0, 0, 2][..]).unwrap();
+            assert_eq!(*v.downcast::<level>().unwrap(), level::HIGH);
+
+            assert!(map[&1](&mut &buf[..3]).is_err());
+        }
+    "#;
+
+    let opts = GenOptions::new().dispatch_tags(&[("ping", 1), ("chat_alias", 20), ("level", 3)]);
+    if let Err(e) = build_test_with(name, spec, &opts, tests) {
+        panic!("test {} failed: {}", name, e);
+    }
+}
+
+#[test]
+fn length_width() {
+    let spec = r#"
+        struct blob { string name<16>; opaque data<>; unsigned int parts<>; blob *next; };
+    "#;
+    let tests = |prefix: usize| {
+        format!(
+            r#"
+        use xdr_codec::{{pack, unpack, Error, ErrorKind}};
+
+        const PREFIX: usize = {};
+
+        fn sample() -> blob {{
+            blob {{
+                name: "abc".to_string(),
+                data: vec![1, 2, 3, 4, 5],
+                parts: vec![7, 8],
+                next: Some(Box::new(blob {{ name: String::new(), data: vec![], parts: vec![], next: None }})),
+            }}
+        }}
+
+        #[test]
+        fn round_trip() {{
+            let mut buf = Vec::new();
+            pack(&sample(), &mut buf).unwrap();
+            // Three lengths and the option flag at the top level, and three lengths in `next`
+            assert_eq!(buf.len(), 4 + 8 + 8 + 4 + 4 + 6 * PREFIX);
+            assert_eq!(&buf[PREFIX - 4..PREFIX + 3], &[0, 0, 0, 3, b'a', b'b', b'c']);
+
+            let v: blob = unpack(&mut &buf[..]).unwrap();
+            assert_eq!(v.name, "abc");
+            assert_eq!(v.data, vec![1, 2, 3, 4, 5]);
+            assert_eq!(v.parts, vec![7, 8]);
+            assert!(v.next.unwrap().next.is_none());
+        }}
+
+        #[test]
+        fn bounds() {{
+            let mut buf = Vec::new();
+            pack(&sample(), &mut buf).unwrap();
+            buf[PREFIX - 1] = 17;
+            match unpack::<_, blob>(&mut &buf[..]) {{
+                Err(Error(ErrorKind::InvalidLen(16), _)) => (),
+                Err(e) => panic!("unexpected {{}}", e),
+                Ok(_) => panic!("unpacked name longer than its bound"),
+            }}
+
+            // A huge length fails at the end of the input, not by allocating
+            let mut huge = vec![0, 0, 0, 0];
+            huge.extend(vec![0xff; PREFIX]);
+            huge.extend(vec![0; 64]);
+            assert!(unpack::<_, blob>(&mut &huge[..]).is_err());
+        }}
+    "#,
+            prefix
+        )
+    };
+
+    for &(name, width, prefix) in &[("length_u32", LenWidth::U32, 4), ("length_u64", LenWidth::U64, 8)] {
+        let opts = GenOptions::new().length_width(width);
+        if let Err(e) = build_test_with(name, spec, &opts, &tests(prefix)) {
+            panic!("test {} failed: {}", name, e);
+        }
+    }
+}
+
+#[test]
+fn log_fields() {
+    let name = "log_fields";
+    let spec = r#"
+        struct point { int x; string label<>; };
+    "#;
+    let tests = r#"
+        use std::sync::Mutex;
+
+        struct Capture;
+        static LINES: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+        impl log::Log for Capture {
+            fn enabled(&self, _: &log::Metadata) -> bool { true }
+            fn log(&self, record: &log::Record) {
+                if record.level() == log::Level::Trace {
+                    LINES.lock().unwrap().push(record.args().to_string());
+                }
+            }
+            fn flush(&self) {}
+        }
+        static CAPTURE: Capture = Capture;
+
+        #[test]
+        fn traced() {
+            log::set_logger(&CAPTURE).unwrap();
+            log::set_max_level(log::LevelFilter::Trace);
+
+            let p = point { x: 3, label: "ab".to_string() };
+            let mut buf = Vec::new();
+            xdr_codec::pack(&p, &mut buf).unwrap();
+            let (q, _): (point, _) = xdr_codec::Unpack::unpack(&mut &buf[..]).unwrap();
+            assert_eq!(q, p);
+
+            assert_eq!(*LINES.lock().unwrap(), vec![
+                "point.x: packed 4 bytes",
+                "point.label: packed 8 bytes",
+                "point.x: unpacked 4 bytes",
+                "point.label: unpacked 8 bytes",
+            ]);
+        }
+    "#;
+
+    let opts = GenOptions::new().log_fields(true);
+    let mut code = Vec::new();
+    generate_with_options(name, Cursor::new(spec.as_bytes()), &mut code, &opts).unwrap();
+    assert_eq!(String::from_utf8(code).unwrap().matches("log :: trace !").count(), 4);
+    if let Err(e) = build_test_with(name, spec, &opts, tests) {
+        panic!("test {} failed: {}", name, e);
+    }
+
+    // Nothing is logged by default
+    let mut code = Vec::new();
+    generate_with_options(name, Cursor::new(spec.as_bytes()), &mut code, &GenOptions::new()).unwrap();
+    assert!(!String::from_utf8(code).unwrap().contains("log ::"));
+}
+
+#[test]
+fn packed_size() {
+    let name = "packed_size";
+    let spec = r#"
+        enum kind { A = 1, B = 2 };
+        typedef opaque mac[6];
+        typedef unsigned hyper stamp;
+        struct header { kind k; mac m; stamp at; bool flags[3]; };
+        union shape switch (kind k) { case A: int a; case B: float b; };
+        struct framed { header h; shape s; };
+        union uneven switch (int d) { case 0: int a; case 1: void; };
+        struct body { header h; opaque data<>; };
+        struct maybe { header *next; };
+    "#;
+    let tests = r#"
+        #[test]
+        fn sizes() {
+            assert_eq!(kind::PACKED_SIZE, 4);
+            assert_eq!(mac::PACKED_SIZE, 8);
+            assert_eq!(header::PACKED_SIZE, 4 + 8 + 8 + 12);
+            assert_eq!(shape::PACKED_SIZE, 8);
+            assert_eq!(framed::PACKED_SIZE, header::PACKED_SIZE + shape::PACKED_SIZE);
+        }
+
+        #[test]
+        fn buffer() {
+            const SIZE: usize = framed::PACKED_SIZE;
+            let mut buf = [0u8; SIZE];
+            let v = framed {
+                h: header { k: kind::B, m: mac([1, 2, 3, 4, 5, 6]), at: 7, flags: [true, false, true] },
+                s: shape::B(1.5),
+            };
+            let sz = xdr_codec::Pack::pack(&v, &mut &mut buf[..]).unwrap();
+            assert_eq!(sz, buf.len());
+            assert_eq!(xdr_codec::unpack::<_, framed>(&mut &buf[..]).unwrap(), v);
+        }
+    "#;
+
+    let opts = GenOptions::new().packed_size(true);
+    let mut code = Vec::new();
+    generate_with_options(name, Cursor::new(spec.as_bytes()), &mut code, &opts).unwrap();
+    // Not for uneven union arms, variable-length data, optional data or aliases
+    assert_eq!(String::from_utf8(code).unwrap().matches("PACKED_SIZE").count(), 5);
+    if let Err(e) = build_test_with(name, spec, &opts, tests) {
+        panic!("test {} failed: {}", name, e);
+    }
+}
+
+#[test]
+fn string_encoding() {
+    let spec = r#"
+        typedef string label<8>;
+        struct msg { string name<>; label tag; int n; };
+        struct short { string s<4>; };
+    "#;
+    // "caf\xe9" in Latin-1, which isn't UTF-8, then "ok" and 7
+    let common = r#"
+        const BYTES: &[u8] = &[0, 0, 0, 4, 0x63, 0x61, 0x66, 0xe9, 0, 0, 0, 2, 0x6f, 0x6b, 0, 0, 0, 0, 0, 7];
+    "#;
+
+    let strict = r#"
+        #[test]
+        fn invalid() {
+            match xdr_codec::unpack::<_, msg>(&mut &BYTES[..]) {
+                Err(xdr_codec::Error(xdr_codec::ErrorKind::InvalidUtf8(_), _)) => (),
+                other => panic!("unexpected {:?}", other),
+            }
+        }
+    "#;
+    let latin1 = r#"
+        #[test]
+        fn invalid() {
+            let (m, sz): (msg, _) = xdr_codec::Unpack::unpack(&mut &BYTES[..]).unwrap();
+            assert_eq!(sz, BYTES.len());
+            assert_eq!(m, msg { name: "caf\u{e9}".to_string(), tag: label("ok".to_string()), n: 7 });
+
+            let mut buf = Vec::new();
+            xdr_codec::pack(&m, &mut buf).unwrap();
+            assert_eq!(buf, BYTES);
+
+            let v: msg = xdr_codec::unpack_borrowed(BYTES).unwrap();
+            assert_eq!(v, m);
+
+            let bad = msg { name: "\u{263a}".to_string(), ..m };
+            assert!(xdr_codec::pack(&bad, &mut Vec::new()).is_err());
+        }
+
+        #[test]
+        fn validate() {
+            use xdr_codec::Validate;
+
+            // Bounds are in characters, which are each a byte
+            let s = short { s: "\u{e9}\u{e9}\u{e9}\u{e9}".to_string() };
+            assert!(s.validate().is_ok());
+            assert_eq!(xdr_codec::Pack::pack(&s, &mut Vec::new()).unwrap(), 8);
+            assert!(short { s: "\u{e9}".repeat(5) }.validate().is_err());
+            assert!(label("\u{e9}".repeat(8)).validate().is_ok());
+            match (short { s: "\u{263a}".to_string() }).validate() {
+                Err(xdr_codec::Error(xdr_codec::ErrorKind::NotLatin1('\u{263a}'), _)) => (),
+                other => panic!("unexpected {:?}", other),
+            }
+        }
+    "#;
+    let bytes = r#"
+        #[test]
+        fn invalid() {
+            let (m, sz): (msg, _) = xdr_codec::Unpack::unpack(&mut &BYTES[..]).unwrap();
+            assert_eq!(sz, BYTES.len());
+            assert_eq!(m, msg { name: vec![0x63, 0x61, 0x66, 0xe9], tag: label(b"ok".to_vec()), n: 7 });
+
+            let mut buf = Vec::new();
+            xdr_codec::pack(&m, &mut buf).unwrap();
+            assert_eq!(buf, BYTES);
+        }
+    "#;
+
+    for (name, enc, tests) in &[
+        ("string_utf8", StrEnc::Utf8Strict, strict),
+        ("string_latin1", StrEnc::Latin1, latin1),
+        ("string_bytes", StrEnc::Bytes, bytes),
+    ] {
+        // Views hold Latin-1 strings as `String`
+        let opts = GenOptions::new().string_encoding(*enc).zero_copy(true).emit_validate(true);
+        if let Err(e) = build_test_with(name, spec, &opts, &format!("{}{}", common, tests)) {
+            panic!("test {} failed: {}", name, e);
+        }
+    }
+
+    // The default is strict
+    let mut default = Vec::new();
+    let mut strict = Vec::new();
+    generate_with_options("x", Cursor::new(spec.as_bytes()), &mut default, &GenOptions::new()).unwrap();
+    let opts = GenOptions::new().string_encoding(StrEnc::Utf8Strict);
+    generate_with_options("x", Cursor::new(spec.as_bytes()), &mut strict, &opts).unwrap();
+    assert_eq!(default, strict);
+}
+
+#[test]
+fn shared_discriminant() {
+    let name = "shared_discriminant";
+    let spec = r#"
+        enum op { READ = 1, WRITE = 2 };
+        union request switch (op o) { case READ: unsigned int len; case WRITE: opaque data<>; };
+        union reply switch (op o) { case READ: opaque data<>; case WRITE: void; };
+    "#;
+    let tests = r#"
+        #[test]
+        fn same_enum() {
+            // Both unions' discriminants are the one `op`
+            let req = request::READ(4);
+            let rep = reply::WRITE;
+            let mut buf = Vec::new();
+            xdr_codec::pack(&req, &mut buf).unwrap();
+            xdr_codec::pack(&rep, &mut buf).unwrap();
+            let (o, _): (op, _) = xdr_codec::Unpack::unpack(&mut &buf[..]).unwrap();
+            assert_eq!(o, op::READ);
+            let (o, _): (op, _) = xdr_codec::Unpack::unpack(&mut &buf[8..]).unwrap();
+            assert_eq!(o, op::WRITE);
+        }
+    "#;
+
+    let mut code = Vec::new();
+    generate_with_options(name, Cursor::new(spec.as_bytes()), &mut code, &GenOptions::new()).unwrap();
+    let code = String::from_utf8(code).unwrap();
+    assert_eq!(code.matches("pub enum op").count(), 1, "{}", code);
+    if let Err(e) = build_test_with(name, spec, &GenOptions::new(), tests) {
+        panic!("test {} failed: {}", name, e);
+    }
+}
+
+#[test]
+fn emit_skip() {
+    let name = "emit_skip";
+    let spec = r#"
+        enum kind { A = 1, B = 2 };
+        struct point { int x; hyper y; };
+        typedef point path<4>;
+        union shape switch (kind k) { case A: path p; case B: string label<>; };
+        struct msg {
+            string name<>;
+            opaque data<>;
+            opaque tag[3];
+            point corners[2];
+            point *origin;
+            shape s;
+            path route;
+        };
+    "#;
+    let tests = r#"
+        use std::io::Cursor;
+        use xdr_codec::Pack;
+
+        fn sample() -> msg {
+            msg {
+                name: "named".to_string(),
+                data: vec![1, 2, 3, 4, 5],
+                tag: [7, 8, 9],
+                corners: [point { x: 1, y: 2 }, point { x: 3, y: 4 }],
+                origin: Some(Box::new(point { x: 5, y: 6 })),
+                s: shape::A(path(vec![point { x: 7, y: 8 }])),
+                route: path(vec![]),
+            }
+        }
+
+        #[test]
+        fn same_as_unpack() {
+            let mut buf = Vec::new();
+            sample().pack(&mut buf).unwrap();
+            0x1234i32.pack(&mut buf).unwrap();
+
+            let mut input = Cursor::new(&buf[..]);
+            let (_, usz): (msg, usize) = xdr_codec::Unpack::unpack(&mut input).unwrap();
+            let after = input.position();
+
+            let mut input = Cursor::new(&buf[..]);
+            assert_eq!(msg::skip(&mut input).unwrap(), usz);
+            assert_eq!(input.position(), after);
+            assert_eq!(xdr_codec::unpack::<_, i32>(&mut input).unwrap(), 0x1234);
+
+            let mut buf = Vec::new();
+            shape::B("label".to_string()).pack(&mut buf).unwrap();
+            assert_eq!(shape::skip(&mut Cursor::new(&buf[..])).unwrap(), buf.len());
+        }
+
+        #[test]
+        fn checked() {
+            // Not a kind
+            let mut buf = Vec::new();
+            3i32.pack(&mut buf).unwrap();
+            assert!(kind::skip(&mut Cursor::new(&buf[..])).is_err());
+            assert!(shape::skip(&mut Cursor::new(&buf[..])).is_err());
+
+            // Too many points
+            let mut buf = Vec::new();
+            5u32.pack(&mut buf).unwrap();
+            assert!(path::skip(&mut Cursor::new(&buf[..])).is_err());
+
+            // Short input
+            let mut buf = Vec::new();
+            sample().pack(&mut buf).unwrap();
+            buf.truncate(buf.len() - 2);
+            assert!(msg::skip(&mut Cursor::new(&buf[..])).is_err());
+        }
+    "#;
+
+    let opts = GenOptions::new().emit_skip(true);
+    if let Err(e) = build_test_with(name, spec, &opts, tests) {
+        panic!("test {} failed: {}", name, e);
+    }
+}
+
+#[test]
+fn dyn_codec() {
+    let name = "dyn_codec";
+    let spec = r#"
+        enum kind { A = 1, B = 2 };
+        struct ping { unsigned int seq; };
+        struct note { kind k; string text<>; };
+        typedef note notes<>;
+        union reply switch (kind k) { case A: ping p; case B: void; };
+    "#;
+    let tests = r#"
+        use std::collections::HashMap;
+        use std::io::Cursor;
+        use xdr_codec::Codec;
+
+        fn registry() -> HashMap<u32, Box<dyn Codec>> {
+            let mut reg: HashMap<u32, Box<dyn Codec>> = HashMap::new();
+            reg.insert(1, Box::new(ping { seq: 0 }));
+            reg.insert(2, Box::new(note { k: kind::A, text: String::new() }));
+            reg.insert(3, Box::new(notes(vec![])));
+            reg.insert(4, Box::new(reply::B));
+            reg
+        }
+
+        #[test]
+        fn roundtrip() {
+            let reg = registry();
+            let msgs: Vec<(u32, Box<dyn Codec>)> = vec![
+                (1, Box::new(ping { seq: 7 })),
+                (2, Box::new(note { k: kind::B, text: "hi".to_string() })),
+                (3, Box::new(notes(vec![note { k: kind::A, text: "x".to_string() }]))),
+                (4, Box::new(reply::A(ping { seq: 9 }))),
+            ];
+            for (tag, msg) in &msgs {
+                let mut buf = Vec::new();
+                let psz = msg.pack_dyn(&mut buf).unwrap();
+                assert_eq!(psz, buf.len());
+
+                let proto = &reg[tag];
+                assert_eq!(proto.type_name(), msg.type_name());
+                let (v, usz) = proto.unpack_boxed(&mut Cursor::new(&buf[..])).unwrap();
+                assert_eq!(usz, psz);
+
+                let mut again = Vec::new();
+                v.pack_dyn(&mut again).unwrap();
+                assert_eq!(again, buf);
+            }
+            assert_eq!(reg[&1].type_name(), "ping");
+        }
+
+        #[test]
+        fn downcast() {
+            let mut buf = Vec::new();
+            xdr_codec::Pack::pack(&ping { seq: 3 }, &mut buf).unwrap();
+
+            let reg = registry();
+            let (v, _) = reg[&1].unpack_boxed(&mut Cursor::new(&buf[..])).unwrap();
+            assert!(v.is::<ping>());
+            assert_eq!(v.downcast_ref::<ping>(), Some(&ping { seq: 3 }));
+            assert!(v.downcast_ref::<note>().is_none());
+            let v = v.downcast::<note>().unwrap_err();
+            assert_eq!(*v.downcast::<ping>().unwrap(), ping { seq: 3 });
+
+            let mut slot: Box<dyn Codec> = Box::new(ping { seq: 0 });
+            assert_eq!(slot.unpack_dyn(&mut Cursor::new(&buf[..])).unwrap(), 4);
+            assert_eq!(slot.downcast_mut::<ping>().unwrap().seq, 3);
+            // Left alone on failure
+            assert!(slot.unpack_dyn(&mut Cursor::new(&buf[..2])).is_err());
+            assert_eq!(slot.downcast_ref::<ping>().unwrap().seq, 3);
+        }
+    "#;
+
+    let opts = GenOptions::new().dyn_codec(true);
+    if let Err(e) = build_test_with(name, spec, &opts, tests) {
+        panic!("test {} failed: {}", name, e);
+    }
+}
+
+#[test]
+fn checksum() {
+    let spec = r#"
+        enum kind { A = 1, B = 2 };
+        struct msg { kind k; string text<>; opaque data[3]; };
+    "#;
+    // Bitwise, rather than with a table, to check the generated code independently
+    let common = r#"
+        fn reference(poly: u32, bytes: &[u8]) -> u32 {
+            let mut crc = !0u32;
+            for b in bytes {
+                crc ^= *b as u32;
+                for _ in 0..8 {
+                    crc = if crc & 1 != 0 { (crc >> 1) ^ poly } else { crc >> 1 };
+                }
+            }
+            !crc
+        }
+
+        fn sample() -> msg {
+            msg { k: kind::B, text: "checked".to_string(), data: [1, 2, 3] }
+        }
+    "#;
+
+    for (name, algo, poly) in [
+        ("checksum_crc32", Checksum::Crc32, "0xedb88320"),
+        ("checksum_crc32c", Checksum::Crc32c, "0x82f63b78"),
+    ] {
+        let tests = format!(
+            r#"
+            {common}
+
+            #[test]
+            fn matches() {{
+                let mut buf = Vec::new();
+                let (sz, crc) = sample().pack_with_checksum(&mut buf).unwrap();
+                assert_eq!(sz, buf.len());
+                assert_eq!(crc, reference({poly}, &buf));
+                assert_eq!(buf, {{
+                    let mut plain = Vec::new();
+                    xdr_codec::pack(&sample(), &mut plain).unwrap();
+                    plain
+                }});
+
+                let mut buf = Vec::new();
+                let (_, crc) = kind::A.pack_with_checksum(&mut buf).unwrap();
+                assert_eq!(crc, reference({poly}, &buf));
+            }}
+        "#,
+            common = common,
+            poly = poly
+        );
+
+        let opts = GenOptions::new().checksum(algo);
+        if let Err(e) = build_test_with(name, spec, &opts, &tests) {
+            panic!("test {} failed: {}", name, e);
+        }
+    }
+}
+
+#[test]
+fn io_methods() {
+    let name = "io_methods";
+    let spec = r#"
+        enum kind { A = 1, B = 2 };
+        struct msg { kind k; string text<>; };
+        typedef msg msgs<>;
+        union reply switch (kind k) { case A: msg m; case B: void; };
+    "#;
+    let tests = r#"
+        use std::io::Cursor;
+
+        #[test]
+        fn methods() {
+            let v = msg { k: kind::B, text: "hello".to_string() };
+            let mut buf = Vec::new();
+            v.to_writer(&mut buf).unwrap();
+            assert_eq!(msg::from_reader(&buf[..]).unwrap(), v);
+
+            let mut out = Cursor::new(Vec::new());
+            msgs(vec![v.clone(), v.clone()]).to_writer(&mut out).unwrap();
+            reply::A(v.clone()).to_writer(&mut out).unwrap();
+            kind::A.to_writer(&mut out).unwrap();
+
+            let mut input = Cursor::new(out.into_inner());
+            assert_eq!(msgs::from_reader(&mut input).unwrap(), msgs(vec![v.clone(), v.clone()]));
+            assert_eq!(reply::from_reader(&mut input).unwrap(), reply::A(v));
+            assert_eq!(kind::from_reader(&mut input).unwrap(), kind::A);
+            assert!(kind::from_reader(&mut input).is_err());
+        }
+    "#;
+
+    let opts = GenOptions::new().io_methods(true);
+    if let Err(e) = build_test_with(name, spec, &opts, tests) {
+        panic!("test {} failed: {}", name, e);
+    }
+}
+
+#[test]
+fn self_describing() {
+    let spec = r#"
+        enum kind { A = 1, B = 2 };
+        struct point { int x; int y; };
+        struct msg { kind k; point p; string text<>; };
+    "#;
+    // The same message, from a specification with another field
+    let other = r#"
+        enum kind { A = 1, B = 2 };
+        struct point { int x; int y; };
+        struct msg { kind k; point p; string text<>; int extra; };
+    "#;
+    let parse = |spec: &str| xdrgen::Symtab::parse(spec).unwrap().fingerprint();
+    assert_eq!(parse(spec), parse(&format!("/* comment */\n{}", spec)));
+    assert_ne!(parse(spec), parse(other));
+
+    let tests = format!(
+        r#"
+        use std::io::Cursor;
+        use xdr_codec::{{Error, ErrorKind, Pack}};
+
+        fn sample() -> msg {{
+            msg {{ k: kind::B, p: point {{ x: 1, y: 2 }}, text: "hi".to_string() }}
+        }}
+
+        #[test]
+        fn matching() {{
+            let mut buf = Vec::new();
+            let sz = sample().pack(&mut buf).unwrap();
+            assert_eq!(sz, buf.len());
+            assert_eq!(&buf[..4], b"XDRh");
+            assert_eq!(&buf[8..16], &SPEC_HASH.to_be_bytes());
+            // Only top-level types have the header
+            let mut plain = Vec::new();
+            point {{ x: 1, y: 2 }}.pack(&mut plain).unwrap();
+            assert_eq!(plain.len(), 8);
+            assert_eq!(&buf[20..28], &plain[..]);
+
+            let (v, usz): (msg, usize) = xdr_codec::Unpack::unpack(&mut Cursor::new(&buf)).unwrap();
+            assert_eq!((v, usz), (sample(), sz));
+            assert_eq!(msg::skip(&mut Cursor::new(&buf)).unwrap(), sz);
+            assert_eq!(xdr_codec::unpack_borrowed::<msg_ref>(&buf).unwrap().into_owned(), sample());
+        }}
+
+        #[test]
+        fn mismatching() {{
+            let mut buf = Vec::new();
+            xdr_codec::pack_header({other}, &mut buf).unwrap();
+            sample().pack(&mut buf).unwrap();
+            match xdr_codec::unpack::<_, msg>(&mut Cursor::new(&buf)) {{
+                Err(Error(ErrorKind::SpecMismatch(SPEC_HASH, {other}), _)) => (),
+                other => panic!("unexpected {{:?}}", other),
+            }}
+            assert!(msg::skip(&mut Cursor::new(&buf)).is_err());
+            assert!(xdr_codec::unpack_borrowed::<msg_ref>(&buf).is_err());
+
+            // Without a header at all
+            let mut buf = Vec::new();
+            kind::B.pack(&mut buf).unwrap();
+            point {{ x: 1, y: 2 }}.pack(&mut buf).unwrap();
+            "hi".to_string().pack(&mut buf).unwrap();
+            match xdr_codec::unpack::<_, msg>(&mut Cursor::new(&buf)) {{
+                Err(Error(ErrorKind::InvalidHeader(_), _)) => (),
+                other => panic!("unexpected {{:?}}", other),
+            }}
+        }}
+    "#,
+        other = format!("{:#x}", parse(other))
+    );
+
+    let opts = GenOptions::new().self_describing(true).emit_skip(true).zero_copy(true);
+    if let Err(e) = build_test_with("self_describing", spec, &opts, &tests) {
+        panic!("test self_describing failed: {}", e);
+    }
+}
+
+#[test]
+fn canonical() {
+    let spec = r#"
+        struct msg { opaque id[3]; string name<>; opaque data<4>; };
+        union choice switch (int d) { case 1: int a; default: void; };
+    "#;
+    let common = r#"
+        // id "abc", name "hi" and data { 1 }, with the padding at `pad` set to 0xff
+        fn encoded(pad: Option<usize>) -> Vec<u8> {
+            let mut buf = vec![
+                b'a', b'b', b'c', 0,
+                0, 0, 0, 2, b'h', b'i', 0, 0,
+                0, 0, 0, 1, 1, 0, 0, 0,
+            ];
+            if let Some(pad) = pad {
+                buf[pad] = 0xff;
+            }
+            buf
+        }
+
+        fn expected() -> msg {
+            msg { id: *b"abc", name: "hi".to_string(), data: vec![1] }
+        }
+
+        fn unpack<T: xdr_codec::Unpack<std::io::Cursor<Vec<u8>>>>(buf: Vec<u8>) -> xdr_codec::Result<T> {
+            xdr_codec::unpack(&mut std::io::Cursor::new(buf))
+        }
+    "#;
+
+    let canonical = format!(
+        r#"
+        {}
+        use xdr_codec::{{Error, ErrorKind}};
+
+        #[test]
+        fn accepted() {{
+            assert_eq!(unpack::<msg>(encoded(None)).unwrap(), expected());
+            assert_eq!(unpack::<choice>(vec![0, 0, 0, 1, 0, 0, 0, 7]).unwrap(), choice::Const1(7));
+        }}
+
+        #[test]
+        fn rejected() {{
+            for pad in [3, 10, 11, 17, 19] {{
+                match unpack::<msg>(encoded(Some(pad))) {{
+                    Err(Error(ErrorKind::NonCanonical(_), _)) => (),
+                    other => panic!("padding at {{}}: unexpected {{:?}}", pad, other),
+                }}
+            }}
+            // The default arm, which would decode every other discriminant the same
+            match unpack::<choice>(vec![0, 0, 0, 2]) {{
+                Err(Error(ErrorKind::InvalidCase(2), _)) => (),
+                other => panic!("unexpected {{:?}}", other),
+            }}
+        }}
+    "#,
+        common
+    );
+    let lenient = format!(
+        r#"
+        {}
+        #[test]
+        fn lenient() {{
+            for pad in [3, 10, 11, 17, 19] {{
+                assert_eq!(unpack::<msg>(encoded(Some(pad))).unwrap(), expected());
+            }}
+            assert_eq!(unpack::<choice>(vec![0, 0, 0, 2]).unwrap(), choice::default);
+        }}
+    "#,
+        common
+    );
+
+    for (name, opts, tests) in [
+        ("canonical", GenOptions::new().canonical(true), &canonical),
+        ("canonical_size", GenOptions::new().canonical(true).optimize(Opt::Size), &canonical),
+        ("canonical_off", GenOptions::new(), &lenient),
+    ] {
+        if let Err(e) = build_test_with(name, spec, &opts, tests) {
+            panic!("test {} failed: {}", name, e);
+        }
+    }
+
+    for (opts, conflict) in [
+        (GenOptions::new().canonical(true).forward_compat(true), "forward_compat"),
+        (GenOptions::new().canonical(true).zero_copy(true), "zero_copy"),
+    ] {
+        let err = generate_with_options("x", Cursor::new(spec.as_bytes()), Vec::new(), &opts).unwrap_err();
+        assert_eq!(err.to_string(), format!("canonical can't be combined with {}", conflict));
+    }
+}
+
+#[test]
+fn external_types() {
+    let name = "external_types";
+    let spec = r#"
+        enum shape { CIRCLE = 1, SQUARE = 2 };
+        struct point { int x; int y; };
+        struct figure { shape kind; point centre; point points<>; };
+        typedef int ids<>;
+    "#;
+    let tests = r#"
+        use xdr_codec::{Pack, Unpack};
+
+        pub mod model {
+            #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+            pub enum Shape { CIRCLE = 1, SQUARE = 2 }
+
+            #[derive(Debug, Clone, PartialEq, Eq, Default)]
+            pub struct Point { pub x: i32, pub y: i32 }
+
+            impl Point {
+                pub fn origin() -> Point {
+                    Point::default()
+                }
+            }
+        }
+
+        #[test]
+        fn round_trip() {
+            let f = figure {
+                kind: model::Shape::SQUARE,
+                centre: model::Point::origin(),
+                points: vec![model::Point { x: 1, y: 2 }],
+            };
+
+            let mut buf = Vec::new();
+            let sz = f.pack(&mut buf).unwrap();
+            assert_eq!(sz, 4 + 8 + 4 + 8);
+            let (u, usz) = figure::unpack(&mut &buf[..]).unwrap();
+            assert_eq!(usz, sz);
+            assert_eq!(u, f);
+
+            let (p, _) = model::Point::unpack(&mut &buf[4..12]).unwrap();
+            assert_eq!(p, model::Point::origin());
+        }
+    "#;
+
+    let opts = GenOptions::new().external_types(&[("point", "self::model::Point"), ("shape", "model::Shape")]);
+    if let Err(e) = build_test_with(name, spec, &opts, &with_serde_derives(tests)) {
+        panic!("test {} failed: {}", name, e);
+    }
+
+    for (ty, msg) in &[
+        ("polygon", "external type polygon: no such definition"),
+        ("ids", "external type ids: only structs, unions and enums can be external"),
+    ] {
+        let opts = GenOptions::new().external_types(&[(ty, "Foo")]);
+        let err = generate_with_options("x", Cursor::new(spec.as_bytes()), Vec::new(), &opts).unwrap_err();
+        assert_eq!(err.to_string(), *msg);
+    }
+    for (types, msg) in &[
+        (&[("point", "model::Point"), ("point", "Point")][..], "external type point: given more than once"),
+        (&[("point", "model::Point<")][..], "external type point: invalid path model::Point<"),
+        (&[("point", "model::")][..], "external type point: invalid path model::"),
+        (&[("shape", "1 + 2")][..], "external type shape: invalid path 1 + 2"),
+    ] {
+        let opts = GenOptions::new().external_types(types);
+        let err = generate_with_options("x", Cursor::new(spec.as_bytes()), Vec::new(), &opts).unwrap_err();
+        assert_eq!(err.to_string(), *msg);
+    }
+}
+
+#[test]
+fn optimize() {
+    let spec = r#"
+        enum colour { RED = 0, GREEN = 1 };
+        struct point { int x; int y; };
+        typedef point triangle[3];
+        union shape switch (int kind) {
+            case 0: triangle tri;
+            case 1: opaque tag[5];
+        };
+        struct picture {
+            colour palette[2];
+            shape shapes<>;
+            string name[3];
+        };
+    "#;
+    // The same assertions for each mode, so both produce the same encoding
+    let tests = r#"
+        use xdr_codec::{Pack, Unpack};
+
+        #[test]
+        fn round_trip() {
+            let p = point { x: 1, y: 2 };
+            let pic = picture {
+                palette: [colour::GREEN, colour::RED],
+                shapes: vec![shape::Const0(triangle([p.clone(), p.clone(), p])), shape::Const1(*b"hello")],
+                name: *b"abc",
+            };
+
+            let mut buf = Vec::new();
+            let sz = pic.pack(&mut buf).unwrap();
+            assert_eq!(sz, 8 + 4 + (4 + 24) + (4 + 8) + 4);
+            assert_eq!(&buf[..12], &[0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 2]);
+            assert_eq!(&buf[44..], &b"hello\0\0\0abc\0"[..]);
+
+            let (u, usz) = picture::unpack(&mut &buf[..]).unwrap();
+            assert_eq!(usz, sz);
+            assert_eq!(u, pic);
+
+            assert!(picture::unpack(&mut &buf[..sz - 1]).is_err());
+            let mut bad = buf.clone();
+            bad[7] = 7;
+            assert!(picture::unpack(&mut &bad[..]).is_err());
+        }
+    "#;
+
+    for &(name, opt) in &[("optimize_speed", Opt::Speed), ("optimize_size", Opt::Size)] {
+        let opts = GenOptions::new().optimize(opt);
+        if let Err(e) = build_test_with(name, spec, &opts, tests) {
+            panic!("test {} failed: {}", name, e);
+        }
+    }
+
+    let mut out = Vec::new();
+    let opts = GenOptions::new().optimize(Opt::Size);
+    generate_with_options("x", Cursor::new(spec.as_bytes()), &mut out, &opts).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    assert!(out.contains("unpack_fixed"));
+    assert!(!out.contains("MaybeUninit"));
+    assert!(!out.contains("inline"));
+}
+
+#[test]
+fn resumable() {
+    let name = "resumable";
+    let spec = r#"
+        enum colour { RED = 0, GREEN = 1 };
+        struct point { int x; int y; };
+        union shape switch (colour c) {
+            case RED: point centre;
+            case GREEN: void;
+        };
+        struct msg {
+            hyper id;
+            shape s;
+            string name<10>;
+            point *next;
+        };
+        union msg2 switch (int k) {
+            case 0: int a;
+            default: point d;
+        };
+    "#;
+    let tests = r#"
+        use xdr_codec::{Decode, Pack, Progress, Resumable};
+
+        fn sample() -> (msg, Vec<u8>) {
+            let m = msg {
+                id: 7,
+                s: shape::RED(point { x: 1, y: 2 }),
+                name: "hello".into(),
+                next: Some(Box::new(point { x: 3, y: 4 })),
+            };
+            let mut buf = Vec::new();
+            m.pack(&mut buf).unwrap();
+            (m, buf)
+        }
+
+        #[test]
+        fn chunks() {
+            let (m, buf) = sample();
+
+            for chunk in 1..buf.len() + 1 {
+                let mut dec = <msg as Resumable>::Decoder::default();
+                let mut res = Progress::Pending;
+                for (i, mut piece) in buf.chunks(chunk).enumerate() {
+                    assert_eq!(res, Progress::Pending, "chunk {} piece {}", chunk, i);
+                    res = dec.feed(&mut piece).unwrap();
+                    assert!(piece.is_empty());
+                }
+                assert_eq!(res, Progress::Complete(m.clone()), "chunk {}", chunk);
+            }
+        }
+
+        #[test]
+        fn consecutive() {
+            let (m, buf) = sample();
+            let mut two = buf.clone();
+            two.extend_from_slice(&buf);
+            two.extend_from_slice(&buf[..5]);
+
+            let mut dec = msgDecoder::default();
+            let mut input = &two[..];
+            assert_eq!(dec.feed(&mut input).unwrap(), Progress::Complete(m.clone()));
+            assert_eq!(input.len(), buf.len() + 5);
+            assert_eq!(dec.feed(&mut input).unwrap(), Progress::Complete(m.clone()));
+            assert_eq!(dec.feed(&mut input).unwrap(), Progress::Pending);
+            assert!(input.is_empty());
+            assert_eq!(dec.feed(&mut &buf[5..]).unwrap(), Progress::Complete(m));
+
+            let mut dec = shapeDecoder::default();
+            assert_eq!(dec.feed(&mut &[0, 0, 0][..]).unwrap(), Progress::Pending);
+            assert_eq!(dec.feed(&mut &[1][..]).unwrap(), Progress::Complete(shape::GREEN));
+        }
+
+        #[test]
+        fn boxed_default() {
+            let mut dec = msg2Decoder::default();
+            assert_eq!(dec.feed(&mut &header()[..]).unwrap(), Progress::Pending);
+            assert_eq!(dec.feed(&mut &[0, 0, 0, 7, 0, 0][..]).unwrap(), Progress::Pending);
+            assert_eq!(
+                dec.feed(&mut &[0, 1, 0, 0, 0, 2][..]).unwrap(),
+                Progress::Complete(msg2::default(Box::new(point { x: 1, y: 2 })))
+            );
+        }
+    "#;
+
+    let errors = r#"
+        fn header() -> Vec<u8> {
+            Vec::new()
+        }
+
+        #[test]
+        fn errors() {
+            let mut dec = shapeDecoder::default();
+            assert!(dec.feed(&mut &[0, 0, 0, 9][..]).is_err());
+
+            let mut dec = msgDecoder::default();
+            let mut bad = sample().1;
+            bad[23] = 11; // name too long
+            assert!(dec.feed(&mut &bad[..]).is_err());
+        }
+    "#;
+
+    let opts = GenOptions::new().resumable(true);
+    if let Err(e) = build_test_with(name, spec, &opts, &format!("{}{}", tests, errors)) {
+        panic!("test {} failed: {}", name, e);
+    }
+
+    // The decoders follow the wire format options
+    let name = "resumable_wire";
+    let opts = GenOptions::new()
+        .resumable(true)
+        .length_width(LenWidth::U64)
+        .string_encoding(StrEnc::Latin1)
+        .self_describing(true);
+    let header = r#"
+        fn header() -> Vec<u8> {
+            let mut buf = Vec::new();
+            xdr_codec::pack_header(SPEC_HASH, &mut buf).unwrap();
+            buf
+        }
+
+        #[test]
+        fn no_header() {
+            let mut buf = Vec::new();
+            sample().0.id.pack(&mut buf).unwrap();
+            assert!(msgDecoder::default().feed(&mut &buf[..]).is_err());
+        }
+    "#;
+    if let Err(e) = build_test_with(name, spec, &opts, &format!("{}{}", tests, header)) {
+        panic!("test {} failed: {}", name, e);
+    }
+}
+
+#[test]
+fn rename_fn() {
+    let name = "rename_fn";
+    let spec = r#"
+        const MAX_NAME = 8;
+        enum colour_kind { COLOUR_RED = 1, COLOUR_GREEN = 2 };
+        struct point_xy { int x_pos; int y_pos; };
+        union shape_def switch (colour_kind kind) {
+            case COLOUR_RED: point_xy centre_point;
+            case COLOUR_GREEN: void;
+        };
+        struct labelled { string label_text<MAX_NAME>; point_xy where_at[2]; };
+    "#;
+    let tests = r#"
+        use xdr_codec::{Pack, Unpack};
+
+        #[test]
+        fn renamed() {
+            let p = PointXy { xPos: 1, yPos: 2 };
+            let l = Labelled { labelText: "hi".into(), whereAt: [p.clone(), p.clone()] };
+            let s = ShapeDef::Red(p);
+
+            let mut buf = Vec::new();
+            l.pack(&mut buf).unwrap();
+            s.pack(&mut buf).unwrap();
+            let (u, sz) = Labelled::unpack(&mut &buf[..]).unwrap();
+            assert_eq!(u, l);
+            assert_eq!(ShapeDef::unpack(&mut &buf[sz..]).unwrap().0, s);
+
+            assert_eq!(MaxName, 8);
+            assert_eq!(ColourKind::Green as i32, 2);
+            assert_eq!(ColourKind::Green.name(), "COLOUR_GREEN");
+            assert!(Labelled::unpack(&mut &[0, 0, 0, 9][..]).is_err());
+        }
+    "#;
+
+    fn camel(name: &str, upper: bool) -> String {
+        let mut ret = String::new();
+        for (i, part) in name.split('_').enumerate() {
+            let mut chars = part.chars();
+            if let Some(c) = chars.next() {
+                if i > 0 || upper {
+                    ret.extend(c.to_uppercase());
+                } else {
+                    ret.push(c);
+                }
+                ret.push_str(&chars.as_str().to_lowercase());
+            }
+        }
+        ret
+    }
+
+    let opts = GenOptions::new().enum_names(true).rename_fn(Box::new(|name, kind| match kind {
+        IdentKind::Type | IdentKind::Const => camel(&name.to_lowercase(), true),
+        IdentKind::Field => camel(name, false),
+        IdentKind::Variant => camel(name.trim_start_matches("COLOUR_").to_lowercase().as_str(), true),
+    }));
+    if let Err(e) = build_test_with(name, spec, &opts, tests) {
+        panic!("test {} failed: {}", name, e);
+    }
+
+    // Options with a renaming function can still be shared between threads
+    fn send_sync<T: Send + Sync>(_: &T) {}
+    send_sync(&opts);
+
+    let errors: [(Box<Rename>, &str); 3] = [
+        (Box::new(|_, _| "x".to_string()), "rename: colour_kind and labelled are both renamed to x"),
+        (
+            Box::new(|name, kind| if kind == IdentKind::Field { "f".into() } else { name.into() }),
+            "rename: label_text and where_at are both renamed to f",
+        ),
+        (Box::new(|name, _| format!("{}-", name)), "rename: MAX_NAME renamed to invalid identifier \"MAX_NAME-\""),
+    ];
+    for (rename, msg) in errors {
+        let opts = GenOptions::new().rename_fn(rename);
+        let err = generate_with_options("x", Cursor::new(spec.as_bytes()), Vec::new(), &opts).unwrap_err();
+        assert_eq!(err.to_string(), msg);
+    }
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn json() {
+    let name = "json";
+    let spec = r#"
+        const HASHLEN = 4;
+        typedef opaque hash[HASHLEN];
+        enum kind { FILE = 0, DIR = 1, LINK = 2 };
+        struct entry {
+            string name<>;
+            unsigned hyper size;
+            double mtime;
+            opaque cookie[2];
+            opaque data<>;
+            hash h;
+            int counts[2];
+            entry *next;
+        };
+        union lookup switch (kind k) {
+            case FILE: entry file;
+            case DIR: entry children<>;
+            case LINK: void;
+        };
+        union result switch (int v) {
+            case 0: hyper ok;
+            case -1: void;
+            default: string err<>;
+        };
+    "#;
+    let tests = r##"
+        fn entry(name: &str, next: Option<Box<entry>>) -> entry {
+            entry {
+                name: name.to_string(),
+                size: u64::max_value(),
+                mtime: 1.5,
+                cookie: [0xde, 0xad],
+                data: vec![1, 2, 255],
+                h: hash([0, 1, 2, 3]),
+                counts: [-1, 7],
+                next,
+            }
+        }
+
+        #[test]
+        fn representation() {
+            let e = entry("a\"b", None);
+            assert_eq!(
+                e.to_json(),
+                concat!(r#"{"name":"a\"b","size":18446744073709551615,"mtime":1.5,"cookie":"dead","#,
+                        r#""data":"0102ff","h":"00010203","counts":[-1,7],"next":null}"#)
+            );
+            assert_eq!(kind::DIR.to_json(), r#""DIR""#);
+            assert_eq!(lookup::LINK.to_json(), r#""LINK""#);
+            assert_eq!(result::Const0(5).to_json(), r#"{"0":5}"#);
+            assert_eq!(result::default("no".to_string()).to_json(), r#"{"default":"no"}"#);
+        }
+
+        // The generated types don't derive PartialEq, so compare the JSON after a round trip
+        #[test]
+        fn round_trip() {
+            let e = || entry("x", Some(Box::new(entry("y", None))));
+            let values = vec![
+                lookup::FILE(e()),
+                lookup::DIR(vec![e(), entry("z", None)]),
+                lookup::LINK,
+            ];
+            for v in values {
+                let json = v.to_json();
+                assert_eq!(lookup::from_json(&json).unwrap().to_json(), json);
+            }
+            for v in vec![result::Const0(-3), result::Const_1, result::default("bad".to_string())] {
+                let json = v.to_json();
+                assert_eq!(result::from_json(&json).unwrap().to_json(), json);
+            }
+            assert_eq!(hash::from_json(r#" "01020304" "#).unwrap().0, [1, 2, 3, 4]);
+        }
+
+        #[test]
+        fn malformed() {
+            assert!(kind::from_json(r#""SOCKET""#).is_err());
+            assert!(lookup::from_json(r#"{"FILE":1}"#).is_err());
+            assert!(lookup::from_json(r#"{"PIPE":{}}"#).is_err());
+            assert!(hash::from_json(r#""010203""#).is_err());
+            assert!(entry::from_json(r#"{"name":"x"}"#).is_err());
+            assert!(entry::from_json("[").is_err());
+        }
+    "##;
+
+    if let Err(e) = build_test_with(name, spec, &GenOptions::new(), tests) {
+        panic!("test {} failed: {}", name, e);
+    }
+}
+
+#[cfg(feature = "zerocopy")]
+#[test]
+fn zerocopy_opaque() {
+    let name = "zerocopy_opaque";
+    let spec = r#"
+        const HASHLEN = 32;
+        typedef opaque hash[HASHLEN];
+        typedef opaque key[16];
+        typedef opaque odd[3];
+    "#;
+    let tests = r#"
+        use xdr_codec::{pack, unpack};
+
+        #[test]
+        fn same_as_codec() {
+            let mut h = hash([0; HASHLEN as usize]);
+            for (i, b) in h.0.iter_mut().enumerate() {
+                *b = i as u8;
+            }
+            let mut packed = Vec::new();
+            pack(&h, &mut packed).unwrap();
+            assert_eq!(h.as_bytes(), &packed[..]);
+
+            let k: key = unpack(&mut &packed[..16]).unwrap();
+            assert_eq!(key::from_bytes(&packed[..16]).unwrap(), k);
+            assert!(key::from_bytes(&packed[..15]).is_err());
+        }
+    "#;
+
+    if let Err(e) = build_test_with(name, spec, &GenOptions::new(), tests) {
+        panic!("test {} failed: {}", name, e);
+    }
+}